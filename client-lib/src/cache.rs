@@ -0,0 +1,137 @@
+use bazaar_protocol::{Command, Encoding, Response};
+use nym_sdk::mixnet::Recipient;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, warn};
+
+/// How long cached replies are used before asking the server again, unless
+/// `--cache-ttl` or a profile says otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// Replies to read-only commands, kept on disk under `<root>/<bazaar>/`
+/// (`~/.cache/nymbazaar` for the CLI) so going back to a listing or page doesn't
+/// cost another trip through the mixnet, and so `--offline` has something to
+/// show. Only JSON replies are kept: they don't depend on the language.
+///
+/// Each reply is one file named after the hash of the command line; its age
+/// is the file's modification time.
+pub struct ResponseCache {
+    root: PathBuf,
+    ttl: Duration,
+}
+
+/// A reply `--offline` doesn't have.
+#[derive(Debug)]
+pub struct NotCached(pub String);
+
+impl fmt::Display for NotCached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Offline, and {} isn't cached", self.0)
+    }
+}
+
+impl std::error::Error for NotCached {}
+
+impl ResponseCache {
+    /// A cache kept under `root`, using replies younger than `ttl`.
+    pub fn new(root: PathBuf, ttl: Duration) -> Self {
+        Self { root, ttl }
+    }
+
+    /// Whether replies to `command` can be kept: commands that only read the
+    /// catalog, whole.
+    pub fn caches(command: &Command) -> bool {
+        matches!(
+            command,
+            Command::List { .. }
+                | Command::Get { range: None, .. }
+                | Command::Search { .. }
+                | Command::Categories
+                | Command::Models { .. }
+                | Command::Featured
+        )
+    }
+
+    fn dir(&self, bazaar: &Recipient) -> PathBuf {
+        self.root.join(digest(&bazaar.to_string()))
+    }
+
+    fn path(&self, bazaar: &Recipient, command: &Command) -> PathBuf {
+        self.dir(bazaar).join(digest(&command.to_string()))
+    }
+
+    /// The kept reply to `command`, if it's younger than the TTL or `stale`
+    /// replies are fine.
+    pub fn get(&self, bazaar: &Recipient, command: &Command, stale: bool) -> Option<Vec<u8>> {
+        let path = self.path(bazaar, command);
+        let age = age(&path)?;
+        if !stale && age > self.ttl {
+            return None;
+        }
+        debug!(%command, age_secs = age.as_secs(), "Using cached reply");
+        std::fs::read(&path).ok()
+    }
+
+    /// Whether a reply to `command` younger than the TTL is kept.
+    pub fn is_fresh(&self, bazaar: &Recipient, command: &Command) -> bool {
+        age(&self.path(bazaar, command)).is_some_and(|age| age <= self.ttl)
+    }
+
+    /// Keeps `frame`, a JSON reply, as the reply to `command` unless it's an
+    /// error. Failing to is only logged, the reply has been received either way.
+    pub fn put(&self, bazaar: &Recipient, command: &Command, frame: &[u8]) {
+        if !Response::decode(frame, Encoding::Json).is_ok_and(|response| !matches!(response, Response::Error(_))) {
+            return;
+        }
+        let path = self.path(bazaar, command);
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| {
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, frame)?;
+                std::fs::rename(&tmp, &path)
+            });
+        if let Err(e) = result {
+            warn!("Failed to cache the reply to {}: {}", command.name(), e);
+        }
+    }
+
+    /// Forgets every reply from `bazaar`, after a write changed what it would say.
+    pub fn clear(&self, bazaar: &Recipient) {
+        if let Err(e) = std::fs::remove_dir_all(self.dir(bazaar)) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to clear the reply cache: {}", e);
+            }
+        }
+    }
+
+    /// Removes replies older than a week, or the TTL if that's longer, from
+    /// every bazaar so the cache doesn't grow forever. Stale replies younger
+    /// than that are kept for `--offline`.
+    pub fn prune(&self) {
+        let keep = self.ttl.max(Duration::from_secs(7 * 24 * 60 * 60));
+        let Ok(bazaars) = std::fs::read_dir(&self.root) else {
+            return;
+        };
+        for file in bazaars.flatten().filter_map(|dir| std::fs::read_dir(dir.path()).ok()).flatten().flatten() {
+            let old = file
+                .metadata()
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| SystemTime::now().duration_since(modified).unwrap_or_default() > keep);
+            if old {
+                std::fs::remove_file(file.path()).ok();
+            }
+        }
+    }
+}
+
+fn age(path: &Path) -> Option<Duration> {
+    Some(std::fs::metadata(path).ok()?.modified().ok()?.elapsed().unwrap_or_default())
+}
+
+fn digest(text: &str) -> String {
+    hex::encode(Sha256::digest(text.as_bytes()))
+}
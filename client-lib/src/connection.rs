@@ -0,0 +1,146 @@
+use anyhow::{bail, Result};
+use bazaar_protocol::codec::FrameDecoder;
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{Command, Encoding};
+use nym_sdk::mixnet::MixnetClient;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+
+/// How requests reach the server.
+pub enum Transport {
+    /// Through the local proxy client's socket.
+    Proxy(TcpStream),
+    /// Straight over the mixnet to a server running with `--direct`. Every reply
+    /// message carries one whole response frame.
+    Direct(MixnetClient),
+    /// Nowhere, for browsing offline: replies only come from the cache.
+    Offline,
+}
+
+/// A route to the server along with its response framing state.
+pub struct Connection {
+    pub transport: Transport,
+    pub(crate) decoder: FrameDecoder,
+    /// How the server encodes responses, as agreed with HELLO.
+    pub encoding: Encoding,
+    pub(crate) setup: Setup,
+    /// Whether to show the client's countdown while waiting for a reply; a
+    /// full-screen UI shows that itself.
+    pub countdown: bool,
+}
+
+impl Connection {
+    pub fn new(transport: Transport) -> Self {
+        Self {
+            transport,
+            decoder: FrameDecoder::new(),
+            encoding: Encoding::Text,
+            setup: Setup::default(),
+            countdown: true,
+        }
+    }
+}
+
+/// A command given up on before its reply came, because it timed out or the
+/// user cancelled it.
+#[derive(Debug)]
+pub struct Abandoned {
+    command: &'static str,
+    /// The timeout it ran out of; `None` if it was cancelled.
+    timeout: Option<Duration>,
+    write: bool,
+}
+
+impl Abandoned {
+    pub(crate) fn new(command: &Command, timeout: Option<Duration>) -> Self {
+        Self { command: command.name(), timeout, write: command.is_write() }
+    }
+}
+
+impl fmt::Display for Abandoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.timeout {
+            Some(timeout) => write!(f, "No answer to {} within {}s", self.command, timeout.as_secs())?,
+            None => write!(f, "Cancelled {}", self.command)?,
+        }
+        if self.write {
+            write!(f, "; it may have gone through anyway, check before sending it again")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Abandoned {}
+
+/// Cancels the command in flight, e.g. on Ctrl-C or Esc, failing it with
+/// [`Abandoned`].
+#[derive(Default)]
+pub struct Interrupt {
+    pub(crate) in_flight: AtomicBool,
+    pub(crate) cancel: Notify,
+}
+
+impl Interrupt {
+    /// Whether a command is waiting for its reply.
+    pub fn in_flight(&self) -> bool {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Cancels the command in flight, if there is one.
+    pub fn cancel(&self) {
+        self.cancel.notify_waiters();
+    }
+}
+
+/// How the session was set up, to set it up again after reconnecting.
+#[derive(Default)]
+pub(crate) struct Setup {
+    pub(crate) hello: Option<Command>,
+    /// Token of the session HELLO opened, to resume it.
+    pub(crate) session: Option<String>,
+    pub(crate) lang: Option<Command>,
+    pub(crate) auth: Option<Command>,
+}
+
+impl Setup {
+    /// Notes `command` if it sets the session up and `reply` accepted it.
+    pub(crate) fn record(&mut self, command: &Command, reply: &str) {
+        if parse_error(reply).is_some() {
+            return;
+        }
+        match command {
+            Command::Hello { .. } => {
+                self.hello = Some(command.clone());
+                // HELLO <protocol> <compression> <encoding> <session> <capabilities>,
+                // the session empty if the server doesn't keep them
+                self.session = reply
+                    .trim_end()
+                    .strip_prefix("HELLO ")
+                    .and_then(|fields| fields.split(' ').nth(3))
+                    .filter(|session| !session.is_empty())
+                    .map(str::to_string);
+            },
+            Command::Lang { .. } => self.lang = Some(command.clone()),
+            Command::Auth { .. } => self.auth = Some(command.clone()),
+            _ => {},
+        }
+    }
+}
+
+/// Waits for the reply message carrying the next response frame.
+pub(crate) async fn read_reply(client: &mut MixnetClient, decoder: &mut FrameDecoder) -> Result<Vec<u8>> {
+    loop {
+        if let Some(frame) = decoder.next_frame()? {
+            return Ok(frame);
+        }
+        let Some(messages) = client.wait_for_messages().await else {
+            bail!("Mixnet client stopped");
+        };
+        for message in messages {
+            decoder.extend(&message.message);
+        }
+    }
+}
@@ -0,0 +1,518 @@
+//! Talking to a NymBazaar server through the Nym mixnet, for the CLI and for
+//! anything else that wants to browse a bazaar: GUIs, bots, scripts.
+//!
+//! A [`BazaarClient`] holds the settings (which bazaar, where its proxy
+//! client listens, language, currency, cache) and a [`Connection`] the state
+//! of one route to the server. Commands go through the client with the
+//! connection, which it reconnects and sets up again when it drops:
+//!
+//! ```no_run
+//! # async fn browse(address: nym_sdk::mixnet::Recipient) -> anyhow::Result<()> {
+//! use nymbazaar_client_lib::BazaarClient;
+//!
+//! let client = BazaarClient::new(address, "127.0.0.1", 9050);
+//! let mut conn = client.connect().await?;
+//! for item in client.search(&mut conn, "dx7 price:100..500 sort:price").await?.items {
+//!     println!("{} {}", item.name, item.price);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The proxy client has to be running first, see [`BazaarClient::start_proxy`].
+//! Replies the server answers with an error fail with its [`BazaarError`].
+
+pub mod cache;
+pub mod connection;
+pub mod rates;
+
+pub use cache::{NotCached, ResponseCache};
+pub use connection::{Abandoned, Connection, Interrupt, Transport};
+pub use rates::LocalRates;
+
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::codec::{self, FrameDecoder};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{BazaarError, Command, Compression, Conversion, Encoding, Item, Lang, Model, Response, ServerStatus};
+use connection::read_reply;
+use nym_sdk::mixnet::{IncludedSurbs, MixnetMessageSender, Recipient};
+use nym_sdk::tcp_proxy::NymProxyClient;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::{debug, info, warn};
+
+/// SURBs sent along with each request in direct mode, for the server to reply with.
+const REPLY_SURBS: u32 = 20;
+
+/// How long to wait for a reply unless the client says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Attempts to reconnect to the proxy client after the connection drops.
+const RECONNECT_ATTEMPTS: u32 = 6;
+
+/// Wait before the first reconnect attempt, doubled after each failed one.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Shows that a command is waiting for its reply until `deadline`, for as
+/// long as what it returns is kept.
+pub type Countdown = Box<dyn Fn(Instant) -> Box<dyn Any + Send> + Send + Sync>;
+
+/// Listings, with the prices converted into the currency asked for if they were.
+pub struct Listings {
+    pub items: Vec<Item>,
+    pub conversion: Option<Conversion>,
+}
+
+/// One listing, with its price converted into the currency asked for if it was.
+pub struct Listing {
+    pub item: Item,
+    pub conversion: Option<Conversion>,
+}
+
+/// A client of one bazaar. The settings are fields, to change before use.
+pub struct BazaarClient {
+    pub server_address: Recipient,
+    /// Where the proxy client listens.
+    pub proxy_host: String,
+    pub proxy_port: u16,
+    /// Nym network env file the proxy client is started with.
+    pub nym_env: Option<String>,
+    /// Language text replies are rendered in.
+    pub lang: Option<Lang>,
+    /// Currency listings ask for their prices in.
+    pub currency: Option<String>,
+    /// Rates to convert prices into `currency` with when the bazaar can't.
+    pub rates: Option<LocalRates>,
+    /// Set once the bazaar couldn't convert prices, so they're asked for as
+    /// they are and converted with `rates` from then on.
+    convert_here: AtomicBool,
+    /// Drops listings that don't ship here (a region code like EU) from lists.
+    pub region: Option<String>,
+    /// API token [`BazaarClient::connect`] authenticates with.
+    pub token: Option<String>,
+    pub cache: Option<Arc<ResponseCache>>,
+    /// Answer only from the cache, however old the replies are.
+    pub offline: bool,
+    /// How long to wait for each reply.
+    pub timeout: Duration,
+    pub interrupt: Arc<Interrupt>,
+    pub countdown: Option<Countdown>,
+}
+
+impl BazaarClient {
+    /// A client of the bazaar at `server_address`, through the proxy client on
+    /// `proxy_host:proxy_port`, with no cache and the default timeout.
+    pub fn new(server_address: Recipient, proxy_host: &str, proxy_port: u16) -> Self {
+        Self {
+            server_address,
+            proxy_host: proxy_host.to_string(),
+            proxy_port,
+            nym_env: None,
+            lang: None,
+            currency: None,
+            rates: None,
+            convert_here: AtomicBool::new(false),
+            region: None,
+            token: None,
+            cache: None,
+            offline: false,
+            timeout: DEFAULT_TIMEOUT,
+            interrupt: Arc::default(),
+            countdown: None,
+        }
+    }
+
+    /// Where the proxy client listens.
+    pub fn proxy_addr(&self) -> (&str, u16) {
+        (&self.proxy_host, self.proxy_port)
+    }
+
+    /// Starts a proxy client for the bazaar at `address`, to listen on `port`.
+    /// It serves connections once its `run` is spawned.
+    pub async fn start_proxy(&self, address: Recipient, port: u16) -> Result<NymProxyClient> {
+        info!("Connecting to NYM mixnet...");
+
+        let proxy_client = NymProxyClient::new(
+            address,
+            &self.proxy_host,
+            &port.to_string(),
+            60,      // Timeout in seconds
+            self.nym_env.clone(),
+            1,       // Client pool reserve
+        ).await?;
+
+        info!("Connected to NYM mixnet");
+
+        Ok(proxy_client)
+    }
+
+    /// Connects to the running proxy client, checks the bazaar answers, and
+    /// sets the session up for structured replies, authenticated with the
+    /// token if there is one.
+    pub async fn connect(&self) -> Result<Connection> {
+        let mut stream = TcpStream::connect(self.proxy_addr())
+            .await
+            .with_context(|| format!("The proxy client isn't listening on {}:{}", self.proxy_host, self.proxy_port))?;
+        let response = tokio::time::timeout(self.timeout, probe(&mut stream))
+            .await
+            .map_err(|_| Abandoned::new(&Command::Head, Some(self.timeout)))??;
+        if response != "OK" {
+            bail!("Failed to connect to bazaar server: {}", response);
+        }
+        let mut conn = Connection::new(Transport::Proxy(stream));
+        let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+        if parse_error(&self.send_command(&mut conn, &hello).await?).is_some() {
+            bail!("This server can't answer in JSON");
+        }
+        conn.encoding = Encoding::Json;
+        if let Some(key) = &self.token {
+            self.call(&mut conn, &Command::Auth { key: key.clone() }).await?;
+        }
+        Ok(conn)
+    }
+
+    /// Lists items, from one category or all of them.
+    pub async fn list(&self, conn: &mut Connection, category: Option<&str>) -> Result<Listings> {
+        let command = Command::List { category: category.map(str::to_string), currency: self.currency.clone(), price: None };
+        listings(self.call(conn, &command).await?)
+    }
+
+    /// Searches items, e.g. `dx7 price:100..500 sort:price`.
+    pub async fn search(&self, conn: &mut Connection, query: &str) -> Result<Listings> {
+        let command = Command::Search { query: query.parse()?, currency: self.currency.clone() };
+        listings(self.call(conn, &command).await?)
+    }
+
+    pub async fn featured(&self, conn: &mut Connection) -> Result<Listings> {
+        listings(self.call(conn, &Command::Featured).await?)
+    }
+
+    /// An item by ID or number.
+    pub async fn get(&self, conn: &mut Connection, id: &str) -> Result<Listing> {
+        let command = Command::Get { id: id.to_string(), currency: self.currency.clone(), range: None };
+        listing(self.call(conn, &command).await?)
+    }
+
+    /// A random item, from one category or any.
+    pub async fn random(&self, conn: &mut Connection, category: Option<&str>) -> Result<Listing> {
+        listing(self.call(conn, &Command::Random { category: category.map(str::to_string) }).await?)
+    }
+
+    pub async fn categories(&self, conn: &mut Connection) -> Result<Vec<String>> {
+        match self.call(conn, &Command::Categories).await? {
+            Response::Categories(categories) => Ok(categories),
+            other => bail!("Unexpected reply to CATEGORIES: {}", other),
+        }
+    }
+
+    /// Instrument models matching `query`, or every one.
+    pub async fn models(&self, conn: &mut Connection, query: Option<&str>) -> Result<Vec<Model>> {
+        match self.call(conn, &Command::Models { query: query.map(str::to_string) }).await? {
+            Response::Models(models) => Ok(models),
+            other => bail!("Unexpected reply to MODELS: {}", other),
+        }
+    }
+
+    pub async fn status(&self, conn: &mut Connection) -> Result<ServerStatus> {
+        match self.call(conn, &Command::Status).await? {
+            Response::Status(status) => Ok(status),
+            other => bail!("Unexpected reply to STATUS: {}", other),
+        }
+    }
+
+    /// Fetches listing `id` as the server has it, prices unconverted.
+    pub async fn fetch(&self, conn: &mut Connection, id: &str) -> Result<Item> {
+        match self.call(conn, &Command::Get { id: id.to_string(), currency: None, range: None }).await? {
+            Response::Item(item) => Ok(*item),
+            other => bail!("Unexpected reply to GET: {}", other),
+        }
+    }
+
+    /// Sends `command` and decodes the reply, failing with the server's error
+    /// if it answers with one.
+    pub async fn call(&self, conn: &mut Connection, command: &Command) -> Result<Response> {
+        match self.request(conn, command).await? {
+            Response::Error(e) => Err(e.into()),
+            response => Ok(response),
+        }
+    }
+
+    /// Sends `command` and returns the reply as text to show. A reply that
+    /// isn't cached offline is shown like one.
+    pub async fn send_command(&self, conn: &mut Connection, command: &Command) -> Result<String> {
+        let frame = match self.exchange(conn, command).await {
+            Ok(frame) => frame,
+            // Shown like a reply, so browsing what is cached carries on
+            Err(e) if e.is::<NotCached>() => return Ok(e.to_string()),
+            Err(e) => return Err(e),
+        };
+        self.render(conn.encoding, &frame)
+    }
+
+    /// Sends `command` and decodes the reply. Only works once HELLO has agreed
+    /// on a structured encoding; text replies can't be decoded.
+    pub async fn request(&self, conn: &mut Connection, command: &Command) -> Result<Response> {
+        let frame = self.exchange(conn, command).await?;
+        self.decode(conn.encoding, &frame)
+    }
+
+    /// Sends `command` and waits for the response frame. If the bazaar can't
+    /// convert prices into the currency asked for but the local rates can, the
+    /// prices are asked for as they are instead, for [`BazaarClient::decode`] to convert.
+    pub async fn exchange(&self, conn: &mut Connection, command: &Command) -> Result<Vec<u8>> {
+        // Only structured responses can be converted here
+        let local = match (&self.rates, &self.currency) {
+            (Some(rates), Some(currency)) => conn.encoding != Encoding::Text && rates.knows(currency),
+            _ => false,
+        };
+        let Some(unconverted) = without_currency(command).filter(|_| local) else {
+            return self.exchange_as_is(conn, command).await;
+        };
+        if self.convert_here.load(Ordering::Relaxed) {
+            return self.exchange_as_is(conn, &unconverted).await;
+        }
+        let frame = self.exchange_as_is(conn, command).await?;
+        match Response::decode(&frame, conn.encoding) {
+            Ok(Response::Error(BazaarError::RatesUnavailable | BazaarError::UnsupportedCurrency)) => {
+                info!("The bazaar can't convert prices, converting them with the local rates");
+                self.convert_here.store(true, Ordering::Relaxed);
+                self.exchange_as_is(conn, &unconverted).await
+            },
+            _ => Ok(frame),
+        }
+    }
+
+    /// Sends `command` as it is, or answers it from the cache.
+    async fn exchange_as_is(&self, conn: &mut Connection, command: &Command) -> Result<Vec<u8>> {
+        let cache = self.cache.as_ref().filter(|_| conn.encoding == Encoding::Json);
+        let cached = cache.filter(|_| ResponseCache::caches(command));
+        if let Some(frame) = cached.and_then(|cache| cache.get(&self.server_address, command, self.offline)) {
+            return Ok(frame);
+        }
+
+        debug!(%command, "Sending command");
+
+        let mut result = self.attend(conn, command).await;
+        if let Err(e) = &result {
+            if matches!(conn.transport, Transport::Proxy(_)) && !e.is::<NotCached>() && !e.is::<Abandoned>() {
+                warn!("Lost the connection to the proxy client ({:#}), reconnecting", e);
+                self.reconnect(conn).await?;
+                // A write may have gone through before the connection dropped
+                if command.is_write() {
+                    bail!("The connection dropped while sending {}; check whether it went through before trying again", command.name());
+                }
+                result = self.attend(conn, command).await;
+            }
+        }
+        let frame = match result {
+            Ok(frame) => frame,
+            Err(e) => {
+                if e.is::<Abandoned>() {
+                    self.discard(conn).await?;
+                }
+                return Err(e);
+            },
+        };
+
+        debug!(bytes = frame.len(), "Received response");
+        if matches!(command, Command::Hello { .. } | Command::Lang { .. } | Command::Auth { .. }) {
+            // HELLO is always answered in text
+            let encoding = if matches!(command, Command::Hello { .. }) { Encoding::Text } else { conn.encoding };
+            conn.setup.record(command, &self.render(encoding, &frame)?);
+        }
+        if let Some(cache) = cache {
+            if command.is_write() {
+                cache.clear(&self.server_address);
+            } else if cached.is_some() {
+                cache.put(&self.server_address, command, &frame);
+            }
+        }
+        Ok(frame)
+    }
+
+    /// Sends `command` and waits for the response frame until it times out or
+    /// the interrupt cancels it, showing the countdown unless `conn` says not to.
+    async fn attend(&self, conn: &mut Connection, command: &Command) -> Result<Vec<u8>> {
+        // Listening starts before the command counts as in flight, so no cancel is missed
+        let cancelled = self.interrupt.cancel.notified();
+        tokio::pin!(cancelled);
+        cancelled.as_mut().enable();
+        self.interrupt.in_flight.store(true, Ordering::Relaxed);
+        let _countdown = self.countdown.as_ref().filter(|_| conn.countdown).map(|countdown| countdown(Instant::now() + self.timeout));
+        let result = tokio::select! {
+            result = self.transmit(conn, command) => result,
+            () = &mut cancelled => Err(Abandoned::new(command, None).into()),
+        };
+        self.interrupt.in_flight.store(false, Ordering::Relaxed);
+        result
+    }
+
+    /// Makes sure the reply to a command given up on isn't taken for the reply
+    /// to the next one, should it still come.
+    async fn discard(&self, conn: &mut Connection) -> Result<()> {
+        conn.decoder = FrameDecoder::new();
+        match conn.transport {
+            // A new connection never sees it
+            Transport::Proxy(_) => self.reconnect(conn).await,
+            // Whatever came in the meantime is dropped before sending the next command
+            Transport::Direct(_) | Transport::Offline => Ok(()),
+        }
+    }
+
+    /// Sends `command` over the connection as it is and waits for the response frame.
+    async fn transmit(&self, conn: &mut Connection, command: &Command) -> Result<Vec<u8>> {
+        match &mut conn.transport {
+            Transport::Proxy(stream) => {
+                codec::write_request(stream, command).await?;
+                let frame = tokio::time::timeout(self.timeout, codec::read_frame(stream, &mut conn.decoder))
+                    .await
+                    .map_err(|_| Abandoned::new(command, Some(self.timeout)))??;
+                frame.context("Connection closed by server")
+            },
+            Transport::Direct(client) => {
+                // Late replies to commands given up on
+                while let Ok(Some(_)) = tokio::time::timeout(Duration::ZERO, client.wait_for_messages()).await {}
+                let request = codec::encode_request(command);
+                client.send_message(self.server_address, request, IncludedSurbs::new(REPLY_SURBS)).await?;
+                tokio::time::timeout(self.timeout, read_reply(client, &mut conn.decoder))
+                    .await
+                    .map_err(|_| Abandoned::new(command, Some(self.timeout)))?
+            },
+            Transport::Offline => Err(NotCached(command.name().to_string()).into()),
+        }
+    }
+
+    /// Connects to the proxy client again after the connection dropped, backing
+    /// off between attempts, and sets the session up as it was.
+    async fn reconnect(&self, conn: &mut Connection) -> Result<()> {
+        let mut backoff = RECONNECT_BACKOFF;
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            let result = match TcpStream::connect(self.proxy_addr()).await {
+                Ok(stream) => {
+                    conn.transport = Transport::Proxy(stream);
+                    conn.decoder = FrameDecoder::new();
+                    self.restore(conn).await
+                },
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(()) => {
+                    info!(attempt, "Reconnected");
+                    return Ok(());
+                },
+                Err(e) => warn!(attempt, "Failed to reconnect: {:#}", e),
+            }
+        }
+        bail!("Lost the connection to the proxy client and couldn't reconnect")
+    }
+
+    /// Sets a new connection up like the one it replaces: resumes the session
+    /// if there was one, or opens a new one and sends LANG and AUTH again.
+    async fn restore(&self, conn: &mut Connection) -> Result<()> {
+        let mut resumed = false;
+        if let Some(Command::Hello { compress, encoding, .. }) = &conn.setup.hello {
+            let previous = conn.setup.session.clone();
+            let hello = Command::Hello { compress: compress.clone(), encoding: *encoding, session: previous.clone() };
+            let reply = String::from_utf8_lossy(&self.transmit(conn, &hello).await?).to_string();
+            if let Some((code, message)) = parse_error(&reply) {
+                bail!("HELLO failed: error {} {}", code, message);
+            }
+            conn.setup.record(&hello, &reply);
+            resumed = previous.is_some() && conn.setup.session == previous;
+        }
+        if resumed {
+            return Ok(());
+        }
+        for command in [conn.setup.lang.clone(), conn.setup.auth.clone()].into_iter().flatten() {
+            let reply = self.render(conn.encoding, &self.transmit(conn, &command).await?)?;
+            if let Some((code, message)) = parse_error(&reply) {
+                bail!("{} failed: error {} {}", command.name(), code, message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Turns a response frame into text to show. Structured responses are
+    /// rendered here, in the client's language.
+    pub fn render(&self, encoding: Encoding, frame: &[u8]) -> Result<String> {
+        if encoding == Encoding::Text {
+            return Ok(String::from_utf8_lossy(frame).to_string());
+        }
+        Ok(self.decode(encoding, frame)?.localized(self.lang.unwrap_or_default()).to_string())
+    }
+
+    /// Decodes a structured response frame, filtered by region, with prices the
+    /// bazaar didn't convert converted with the local rates.
+    pub fn decode(&self, encoding: Encoding, frame: &[u8]) -> Result<Response> {
+        let response = Response::decode(frame, encoding).context("Invalid response from server")?;
+        let response = match (&self.rates, &self.currency) {
+            (Some(rates), Some(currency)) => rates.convert(response, currency),
+            _ => response,
+        };
+        Ok(match self.region.as_deref() {
+            Some(region) => filter_region(response, region),
+            None => response,
+        })
+    }
+}
+
+/// Sends HEAD over a new connection to the proxy client and returns the
+/// bazaar's answer through it, `OK` if it's up.
+pub async fn probe(stream: &mut TcpStream) -> Result<String> {
+    let mut decoder = FrameDecoder::new();
+    codec::write_request(stream, &Command::Head).await?;
+    let frame = codec::read_frame(stream, &mut decoder).await?.context("Connection closed by the proxy client")?;
+    Ok(String::from_utf8_lossy(&frame).trim().to_string())
+}
+
+/// A port on `host` nothing listens on, for a proxy client. The system picks
+/// it, so it won't be Tor's 9050 or another client's.
+pub fn free_port(host: &str) -> Result<u16> {
+    let listener = std::net::TcpListener::bind((host, 0))
+        .with_context(|| format!("Failed to find a free port on {} for the proxy client", host))?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn listings(response: Response) -> Result<Listings> {
+    match response {
+        Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => Ok(Listings { items, conversion: None }),
+        Response::Converted(inner, conversion) => Ok(Listings { conversion: Some(conversion), ..listings(*inner)? }),
+        other => bail!("Unexpected reply instead of listings: {}", other),
+    }
+}
+
+fn listing(response: Response) -> Result<Listing> {
+    match response {
+        Response::Item(item) => Ok(Listing { item: *item, conversion: None }),
+        Response::Converted(inner, conversion) => Ok(Listing { conversion: Some(conversion), ..listing(*inner)? }),
+        other => bail!("Unexpected reply instead of a listing: {}", other),
+    }
+}
+
+/// `command` asking for prices as they are listed, if it asks for them converted.
+fn without_currency(command: &Command) -> Option<Command> {
+    match command.clone() {
+        Command::List { category, currency: Some(_), price } => Some(Command::List { category, currency: None, price }),
+        Command::Get { id, currency: Some(_), range } => Some(Command::Get { id, currency: None, range }),
+        Command::Search { query, currency: Some(_) } => Some(Command::Search { query, currency: None }),
+        _ => None,
+    }
+}
+
+/// Drops listings that don't ship to `region` from item lists. Single items
+/// (GET) are shown regardless.
+fn filter_region(response: Response, region: &str) -> Response {
+    let keep = |items: Vec<Item>| items.into_iter().filter(|item| item.shipping.ships_to(region)).collect();
+    match response {
+        Response::Items(items) => Response::Items(keep(items)),
+        Response::SearchResults(items) => Response::SearchResults(keep(items)),
+        Response::PriceFiltered { items, range, currency } => Response::PriceFiltered { items: keep(items), range, currency },
+        Response::Converted(inner, conversion) => Response::Converted(Box::new(filter_region(*inner, region)), conversion),
+        response => response,
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::price::normalize_currency;
+use bazaar_protocol::{Conversion, Item, Price, Response};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Local rates older than this are flagged as stale, like the server's.
+const STALE_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Exchange rates kept by hand, for converting prices when the bazaar has no
+/// rates of its own or doesn't know the currency. Read from a file in the
+/// same format as the server's rates file: `rates["EUR"]` is how many euros
+/// one unit of `base` buys.
+///
+/// ```toml
+/// base = "USD"
+///
+/// [rates]
+/// EUR = 0.92
+/// GBP = 0.79
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalRates {
+    base: String,
+    rates: HashMap<String, f64>,
+    /// How old the file is.
+    #[serde(skip)]
+    age: Duration,
+}
+
+impl LocalRates {
+    /// Reads the rates from `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read rates file {}", path.display()))?;
+        let mut rates: Self = toml::from_str(&contents).with_context(|| format!("Invalid rates file {}", path.display()))?;
+        rates.normalize().with_context(|| format!("Invalid rates file {}", path.display()))?;
+        rates.age = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .unwrap_or_default();
+        Ok(rates)
+    }
+
+    fn normalize(&mut self) -> Result<()> {
+        self.base = normalize_currency(&self.base).context("Invalid base currency")?;
+        let mut rates = HashMap::new();
+        for (code, rate) in self.rates.drain() {
+            let Some(code) = normalize_currency(&code) else {
+                bail!("Invalid currency code '{}'", code);
+            };
+            if !rate.is_finite() || rate <= 0.0 {
+                bail!("Rate for {} must be a positive number", code);
+            }
+            rates.insert(code, rate);
+        }
+        rates.insert(self.base.clone(), 1.0);
+        self.rates = rates;
+        Ok(())
+    }
+
+    /// Whether prices can be converted into `currency`.
+    pub fn knows(&self, currency: &str) -> bool {
+        self.rates.contains_key(currency)
+    }
+
+    /// Units of `to` that one unit of `from` buys, if both currencies are known.
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        Some(self.rates.get(to)? / self.rates.get(from)?)
+    }
+
+    /// Wraps a listing response with its prices converted into `currency`.
+    /// Responses the bazaar converted already are left alone, as are prices in
+    /// currencies these rates don't know.
+    pub fn convert(&self, response: Response, currency: &str) -> Response {
+        if !self.knows(currency) {
+            return response;
+        }
+        let items: Vec<&Item> = match &response {
+            Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => items.iter().collect(),
+            Response::Item(item) => vec![&**item],
+            _ => return response,
+        };
+        let prices = items
+            .into_iter()
+            .filter_map(|item| {
+                let price: Price = item.price.parse().ok()?;
+                let rate = self.rate(&price.currency, currency)?;
+                Some((item.id.clone(), price.convert(currency, rate)))
+            })
+            .collect();
+        let conversion = Conversion {
+            currency: currency.to_string(),
+            prices,
+            rates_age_secs: self.age.as_secs(),
+            stale: self.age > STALE_AFTER,
+        };
+        Response::Converted(Box::new(response), conversion)
+    }
+}
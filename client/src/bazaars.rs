@@ -0,0 +1,97 @@
+use crate::config::config_dir;
+use anyhow::{bail, Context, Result};
+use nym_sdk::mixnet::Recipient;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Bazaars saved by name with `nymbazaar-client bazaar add`, kept in
+/// `~/.config/nymbazaar/bazaars.toml`. A saved name works wherever a bazaar
+/// address does: `--bazaar-id`, `NYMBAZAAR_ID` and profiles.
+///
+/// Nym addresses always contain an `@`, so names can't, and anything with
+/// one is taken as an address.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AddressBook {
+    /// Addresses by name.
+    #[serde(default)]
+    bazaars: BTreeMap<String, String>,
+}
+
+impl AddressBook {
+    fn path() -> Result<PathBuf> {
+        match config_dir() {
+            Some(dir) => Ok(dir.join("bazaars.toml")),
+            None => bail!("Can't find a config directory for saved bazaars; set HOME or XDG_CONFIG_HOME"),
+        }
+    }
+
+    /// Reads the saved bazaars; nothing saved yet yields an empty book.
+    pub fn load() -> Result<Self> {
+        let Ok(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read saved bazaars {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid saved bazaars {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, toml::to_string(self)?).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("Failed to replace saved bazaars {}", path.display()))
+    }
+
+    /// Saves `address` as `name`, replacing whatever was saved under it.
+    pub fn add(&mut self, name: &str, address: &str) -> Result<()> {
+        if name.is_empty() || name.contains('@') || name.contains(char::is_whitespace) {
+            bail!("'{}' can't be a bazaar name; names can't be empty or contain spaces or '@'", name);
+        }
+        Recipient::try_from_base58_string(address).with_context(|| format!("'{}' is not a nym address", address))?;
+        self.bazaars.insert(name.to_string(), address.to_string());
+        self.save()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        if self.bazaars.remove(name).is_none() {
+            bail!("No saved bazaar named '{}'", name);
+        }
+        self.save()
+    }
+
+    /// Saved bazaars as `(name, address)`, by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.bazaars.iter().map(|(name, address)| (name.as_str(), address.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bazaars.is_empty()
+    }
+
+    /// The address `bazaar` stands for: itself if it's an address, the saved
+    /// address if it's a name.
+    pub fn resolve<'a>(&'a self, bazaar: &'a str) -> Result<&'a str> {
+        if bazaar.contains('@') {
+            return Ok(bazaar);
+        }
+        match self.bazaars.get(bazaar) {
+            Some(address) => Ok(address),
+            None => bail!("No saved bazaar named '{}'; add it with `nymbazaar-client bazaar add`", bazaar),
+        }
+    }
+
+    /// The name `address` is saved under, if any.
+    pub fn name_of(&self, address: &Recipient) -> Option<&str> {
+        self.bazaars
+            .iter()
+            .find(|(_, saved)| Recipient::try_from_base58_string(saved.as_str()).is_ok_and(|saved| saved == *address))
+            .map(|(name, _)| name.as_str())
+    }
+}
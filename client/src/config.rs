@@ -0,0 +1,173 @@
+use anyhow::{bail, Context, Result};
+use directories::{BaseDirs, ProjectDirs, UserDirs};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Client settings from `~/.config/nymbazaar/config.toml` (or
+/// `$XDG_CONFIG_HOME/nymbazaar/config.toml`), so the bazaar's address doesn't
+/// have to be pasted on every run. Flags override whatever a profile sets.
+///
+/// ```toml
+/// default_profile = "main"
+///
+/// [profiles.main]
+/// bazaar_id = "8gkQ...base58"
+/// currency = "EUR"
+/// lang = "de"
+///
+/// [profiles.testnet]
+/// bazaar_id = "3xWz...base58"
+/// nym_env = "sandbox.env"
+/// direct = true
+/// gateway = "6qidV..."
+/// log_level = "debug"
+/// log = "/tmp/nymbazaar-testnet.log"
+/// seller = "SynthWave"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Profile used without `--profile`. Without one, a profile named
+    /// `default` is used if there is one.
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Nym address of the bazaar server, base58.
+    pub bazaar_id: Option<String>,
+    /// Local address the proxy client listens on.
+    pub proxy_host: Option<String>,
+    /// Local port of the proxy client, a free one if unset.
+    pub proxy_port: Option<u16>,
+    /// Nym network env file, for testnets and other non-mainnet networks.
+    pub nym_env: Option<String>,
+    /// Talk to the bazaar straight over the mixnet, for servers run with `--direct`.
+    pub direct: bool,
+    /// Identity key of the gateway to connect through. Direct only: the proxy
+    /// client picks its own.
+    pub gateway: Option<String>,
+    /// Skip cover traffic and Poisson send delays. Direct only.
+    pub low_latency: bool,
+    /// Reuse the mixnet client's keys across runs. Direct only.
+    pub persistent: bool,
+    /// Currency to show prices in, e.g. `EUR`.
+    pub currency: Option<String>,
+    /// Exchange rates file to convert prices with when the bazaar can't.
+    pub rates: Option<PathBuf>,
+    /// Language for server messages, e.g. `de`.
+    pub lang: Option<String>,
+    /// Log filter, e.g. `info`.
+    pub log_level: Option<String>,
+    pub log_json: bool,
+    /// Don't save what's typed in the numbered menu.
+    pub no_history: bool,
+    /// Log file; logs go to stderr without one.
+    pub log: Option<PathBuf>,
+    /// Where `image` saves listing images.
+    pub downloads: Option<PathBuf>,
+    /// How long to wait for each reply.
+    pub timeout_secs: Option<u64>,
+    /// How long cached replies are used, 0 to not cache.
+    pub cache_ttl_secs: Option<u64>,
+    /// Listing details fetched at once in the background, 0 to not prefetch.
+    pub prefetch: Option<usize>,
+    /// KiB of replies prefetched per page of listings.
+    pub prefetch_budget_kib: Option<usize>,
+    /// Seller name for `sell` commands.
+    pub seller: Option<String>,
+    /// API token sent with `AUTH` before one-shot commands. Anyone who can
+    /// read the config file can use it, prefer `NYMBAZAAR_TOKEN` on shared machines.
+    pub token: Option<String>,
+}
+
+/// The client's folders where the platform keeps them: the XDG directories
+/// on Linux, `~/Library` on macOS and `%APPDATA%` on Windows.
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "nymbazaar")
+}
+
+/// `~/.config/nymbazaar` (or `$XDG_CONFIG_HOME/nymbazaar`) on Linux,
+/// `~/Library/Application Support/nymbazaar` on macOS and
+/// `%APPDATA%\nymbazaar\config` on Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.config_dir().to_path_buf())
+}
+
+/// `~/.cache/nymbazaar` (or `$XDG_CACHE_HOME/nymbazaar`) on Linux,
+/// `~/Library/Caches/nymbazaar` on macOS and `%LOCALAPPDATA%\nymbazaar\cache`
+/// on Windows.
+pub fn cache_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+/// `~/.local/share/nymbazaar` (or `$XDG_DATA_HOME/nymbazaar`) on Linux,
+/// `~/Library/Application Support/nymbazaar` on macOS and
+/// `%APPDATA%\nymbazaar\data` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.data_dir().to_path_buf())
+}
+
+/// `$XDG_RUNTIME_DIR/nymbazaar`, where the platform has one.
+pub fn runtime_dir() -> Option<PathBuf> {
+    project_dirs().and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+}
+
+/// `~/.config/nymbazaar/rates.toml`, if it's there.
+pub fn default_rates() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("rates.toml")).filter(|path| path.exists())
+}
+
+/// `nymbazaar` in `$XDG_DOWNLOAD_DIR` if that's set, the user's downloads
+/// folder otherwise.
+pub fn downloads_dir() -> Option<PathBuf> {
+    let downloads = std::env::var_os("XDG_DOWNLOAD_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| UserDirs::new().and_then(|dirs| dirs.download_dir().map(Path::to_path_buf)))
+        .or_else(|| BaseDirs::new().map(|dirs| dirs.home_dir().join("Downloads")))?;
+    Some(downloads.join("nymbazaar"))
+}
+
+impl ClientConfig {
+    /// Where the config file is looked for when `--config` isn't given.
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Reads `path`, or the default path if none is given. Only an explicitly
+    /// requested file has to exist.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        if !required && !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// The profile called `name`, which has to exist, or the default one.
+    /// No profile at all yields empty settings.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        let name = match name.or(self.default_profile.as_deref()) {
+            Some(name) => name,
+            None => return Ok(self.profiles.get("default").cloned().unwrap_or_default()),
+        };
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None => bail!("No profile named '{}' in the client config", name),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::{cache_dir, runtime_dir};
+use anyhow::Result;
+#[cfg(unix)]
+use anyhow::{bail, Context};
+use std::path::PathBuf;
+#[cfg(unix)]
+use {
+    crate::Client,
+    nym_sdk::mixnet::Recipient,
+    nymbazaar_client_lib::free_port,
+    std::collections::HashMap,
+    std::process::ExitCode,
+    tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    tokio::net::{UnixListener, UnixStream},
+    tokio::task::JoinHandle,
+    tracing::{error, info, warn},
+};
+
+/// The daemon's control socket: `$XDG_RUNTIME_DIR/nymbazaar/daemon.sock`, or
+/// `daemon.sock` in the cache directory where there's no runtime directory.
+pub fn socket_path() -> Option<PathBuf> {
+    Some(runtime_dir().or_else(cache_dir)?.join("daemon.sock"))
+}
+
+/// Proxy clients the daemon keeps running, by the base58 address of their bazaar.
+#[cfg(unix)]
+struct Proxies {
+    host: String,
+    running: HashMap<String, (u16, JoinHandle<()>)>,
+}
+
+#[cfg(unix)]
+impl Proxies {
+    /// The port of the proxy client for `address`, started on `port` (or a
+    /// free one) if none is running. Proxy clients that stopped are started again.
+    async fn get(&mut self, client: &Client, address: Recipient, port: Option<u16>) -> Result<u16> {
+        let key = address.to_string();
+        if let Some((port, handle)) = self.running.get(&key) {
+            if !handle.is_finished() {
+                return Ok(*port);
+            }
+            warn!(bazaar = %key, "The proxy client stopped, starting it again");
+        }
+        let port = match port {
+            Some(port) => port,
+            None => free_port(&self.host)?,
+        };
+        let proxy_client = client.start_proxy(address, port).await?;
+        let handle = tokio::spawn(async move {
+            if let Err(e) = proxy_client.run().await {
+                error!("Proxy client error: {}", e);
+            }
+        });
+        info!(bazaar = %key, port, "Started a proxy client");
+        self.running.insert(key, (port, handle));
+        Ok(port)
+    }
+}
+
+/// Stops the proxy clients however the daemon exits, a panic included.
+#[cfg(unix)]
+impl Drop for Proxies {
+    fn drop(&mut self) {
+        for (_, handle) in self.running.values() {
+            handle.abort();
+        }
+    }
+}
+
+/// The bound control socket, removed when the daemon exits so the next one
+/// doesn't find it left behind.
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Runs the daemon until `daemon stop`: a proxy client for each bazaar asked
+/// for, kept running so later invocations skip starting their own. The
+/// client's own bazaar gets one straight away, on `--proxy-port` if given.
+///
+/// Invocations ask over the control socket with one line and get one back:
+/// `PROXY <address>` answers `OK <host> <port>`, `STATUS` answers a line per
+/// bazaar, and `STOP` answers `OK` and exits.
+#[cfg(unix)]
+pub async fn run(client: &Client) -> Result<ExitCode> {
+    let Some(path) = socket_path() else {
+        bail!("Can't find a directory for the daemon's socket; set HOME or XDG_RUNTIME_DIR");
+    };
+    if path.exists() {
+        if UnixStream::connect(&path).await.is_ok() {
+            bail!("A daemon is running already; stop it with `nymbazaar-client daemon stop`");
+        }
+        // Left behind by a daemon that didn't get to clean up
+        std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let listener = UnixListener::bind(&path).with_context(|| format!("Failed to listen on {}", path.display()))?;
+    let _socket = SocketFile(path.clone());
+    // Only the user may hand out their proxy clients
+    std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+    let mut proxies = Proxies { host: client.proxy_host.clone(), running: HashMap::new() };
+    let port = proxies.get(client, client.server_address, Some(client.proxy_port)).await?;
+    println!("Daemon listening on {}; {} is on port {}", path.display(), client.server_address, port);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        match answer(client, &mut proxies, stream).await {
+            Ok(true) => break,
+            Ok(false) => {},
+            Err(e) => warn!("Control request failed: {:#}", e),
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Answers one control request; true once asked to stop.
+#[cfg(unix)]
+async fn answer(client: &Client, proxies: &mut Proxies, stream: UnixStream) -> Result<bool> {
+    let (read, mut write) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(read).read_line(&mut line).await?;
+    // Nothing asked: checking whether the daemon is running
+    if line.trim().is_empty() {
+        return Ok(false);
+    }
+    let (reply, stop) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["PROXY", address] => match Recipient::try_from_base58_string(address) {
+            Ok(address) => match proxies.get(client, address, None).await {
+                Ok(port) => (format!("OK {} {}\n", proxies.host, port), false),
+                Err(e) => (format!("ERR {:#}\n", e), false),
+            },
+            Err(_) => ("ERR invalid bazaar address\n".to_string(), false),
+        },
+        ["STATUS"] => {
+            let lines: String = proxies
+                .running
+                .iter()
+                .map(|(address, (port, handle))| {
+                    let state = if handle.is_finished() { "stopped" } else { "running" };
+                    format!("{} {}:{} {}\n", address, proxies.host, port, state)
+                })
+                .collect();
+            (lines, false)
+        },
+        ["STOP"] => ("OK\n".to_string(), true),
+        _ => ("ERR unknown request\n".to_string(), false),
+    };
+    write.write_all(reply.as_bytes()).await?;
+    Ok(stop)
+}
+
+/// Sends the daemon `request` and returns its reply, or `None` if no daemon
+/// is running.
+#[cfg(unix)]
+pub async fn ask(request: &str) -> Result<Option<String>> {
+    let Some(path) = socket_path() else {
+        return Ok(None);
+    };
+    let Ok(mut stream) = UnixStream::connect(&path).await else {
+        return Ok(None);
+    };
+    stream.write_all(format!("{}\n", request).as_bytes()).await?;
+    let mut reply = String::new();
+    tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut reply).await?;
+    Ok(Some(reply))
+}
+
+/// Where the daemon's proxy client for `address` listens, if a daemon is running.
+#[cfg(unix)]
+pub async fn proxy_for(address: Recipient) -> Option<(String, u16)> {
+    match ask(&format!("PROXY {}", address)).await {
+        Ok(Some(reply)) => match reply.split_whitespace().collect::<Vec<_>>()[..] {
+            ["OK", host, port] => Some((host.to_string(), port.parse().ok()?)),
+            _ => {
+                warn!("The daemon has no proxy client for this bazaar: {}", reply.trim());
+                None
+            },
+        },
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to ask the daemon for a proxy client: {:#}", e);
+            None
+        },
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn run(_client: &crate::Client) -> Result<std::process::ExitCode> {
+    anyhow::bail!("The daemon needs unix sockets, which this platform doesn't have")
+}
+
+#[cfg(not(unix))]
+pub async fn ask(_request: &str) -> Result<Option<String>> {
+    Ok(None)
+}
+
+#[cfg(not(unix))]
+pub async fn proxy_for(_address: nym_sdk::mixnet::Recipient) -> Option<(String, u16)> {
+    None
+}
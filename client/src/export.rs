@@ -0,0 +1,35 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::{Encoding, Lang, Response};
+use std::path::Path;
+
+/// What results are exported as, picked by the file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The response's table, with the columns `--output csv` prints.
+    Csv,
+    /// The response as the server's JSON encoding sends it.
+    Json,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("csv") => Ok(Format::Csv),
+            Some("json") => Ok(Format::Json),
+            _ => bail!("Can't tell what to export {} as; name it .csv or .json", path.display()),
+        }
+    }
+}
+
+/// Writes `response` to `path`, for spreadsheets and record keeping. Whatever
+/// is in the file already is replaced.
+pub fn write(path: &Path, response: &Response) -> Result<()> {
+    let contents = match Format::of(path)? {
+        Format::Csv => match response.table() {
+            Some(table) => table.to_csv().into_bytes(),
+            None => bail!("This reply has no CSV form; export it as .json instead"),
+        },
+        Format::Json => response.encode(Encoding::Json, Lang::default())?,
+    };
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,334 @@
+use crate::spinner::Spinner;
+use crate::Client;
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::media::{self, ImageSize, CHUNK_SIZE};
+use bazaar_protocol::{Command, Compression, Encoding, Response};
+use clap::ValueEnum;
+use nymbazaar_client_lib::Connection;
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{debug, warn};
+
+/// Extensions downloaded images are saved with, by what their bytes look like.
+const EXTENSIONS: &[&str] = &["jpg", "png", "gif", "webp", "img"];
+
+/// How `image` shows the images it downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preview {
+    /// Whatever the terminal looks like it supports
+    Auto,
+    /// The kitty graphics protocol (kitty, Ghostty)
+    Kitty,
+    /// Sixel graphics (foot, WezTerm, mlterm, xterm -ti vt340)
+    Sixel,
+    /// Coloured blocks, or plain characters without truecolor
+    Ascii,
+    /// Only save them
+    None,
+}
+
+/// Saves the images of listing `id` under `dir`, one folder per listing, and
+/// previews them on the terminal. Downloads cut short are picked up where
+/// they stopped the next time.
+pub async fn run(client: &Client, mut conn: Connection, id: &str, size: ImageSize, dir: &Path, preview: Preview) -> Result<ExitCode> {
+    let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+    if parse_error(&client.send_command(&mut conn, &hello).await?).is_some() {
+        bail!("This server can't answer in JSON, which downloading images needs");
+    }
+    conn.encoding = Encoding::Json;
+
+    let item = match client.fetch(&mut conn, id).await {
+        Ok(item) => item,
+        Err(e) => return client.fail(e),
+    };
+    if item.media.is_empty() {
+        println!("{} has no images", item.name);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let dir = dir.join(&item.id);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let preview = match preview {
+        _ if !std::io::stdout().is_terminal() => Preview::None,
+        Preview::Auto => detect(),
+        preview => preview,
+    };
+
+    for (number, reference) in (1..).zip(&item.media) {
+        // Bazaars pinning to IPFS list CIDs, which GETIMAGE doesn't serve
+        if !media::is_hash(reference) {
+            println!("Image {} is on IPFS as {}", number, reference);
+            continue;
+        }
+        let path = match download(client, &mut conn, reference, size, &dir, number).await {
+            Ok(path) => path,
+            Err(e) => return client.fail(e),
+        };
+        println!("{}", path.display());
+        if preview != Preview::None {
+            if let Err(e) = show(&path, preview) {
+                warn!("Can't preview {}: {:#}", path.display(), e);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Fetches image `hash` in `size` into `dir` as image `number` of its listing,
+/// chunk by chunk, and returns the file. The chunks are appended to a `.part`
+/// file as they come, so an interrupted download only fetches what's missing.
+async fn download(client: &Client, conn: &mut Connection, hash: &str, size: ImageSize, dir: &Path, number: usize) -> Result<PathBuf> {
+    let stem = match size {
+        ImageSize::Full => number.to_string(),
+        size => format!("{}-{}", number, size.name()),
+    };
+    if let Some(path) = EXTENSIONS.iter().map(|ext| dir.join(format!("{}.{}", stem, ext))).find(|path| path.exists()) {
+        // Scaled images have no hash to check against
+        let intact = size != ImageSize::Full || std::fs::read(&path).is_ok_and(|data| media::verify(hash, &data));
+        if intact {
+            debug!(path = %path.display(), "Downloaded already");
+            return Ok(path);
+        }
+    }
+
+    let part = dir.join(format!("{}.{}.part", stem, &hash[..16]));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&part)
+        .with_context(|| format!("Failed to open {}", part.display()))?;
+    // Only whole chunks count, the last one may have been cut short
+    let mut data = std::fs::read(&part)?;
+    data.truncate(data.len() / CHUNK_SIZE * CHUNK_SIZE);
+    file.set_len(data.len() as u64)?;
+    let mut chunk = data.len() / CHUNK_SIZE;
+    if chunk > 0 {
+        debug!(chunk, "Resuming download");
+    }
+
+    let spinner = Spinner::start(&format!("Downloading image {}...", number));
+    loop {
+        let command = Command::GetImage { hash: hash.to_string(), chunk, size, range: None };
+        let (got, chunks, encoded) = match client.call(conn, &command).await? {
+            Response::Image { chunk, chunks, data, .. } => (chunk, chunks, data),
+            other => bail!("Unexpected reply to GETIMAGE: {}", other),
+        };
+        if got != chunk {
+            bail!("Asked for chunk {} of image {} but got chunk {}", chunk, number, got);
+        }
+        let bytes = media::decode(&encoded).context("Invalid image chunk from server")?;
+        file.write_all(&bytes).with_context(|| format!("Failed to write {}", part.display()))?;
+        data.extend_from_slice(&bytes);
+        chunk += 1;
+        if chunk >= chunks {
+            break;
+        }
+        spinner.set(&format!("Downloading image {}, {} of {} chunks...", number, chunk, chunks));
+    }
+    drop(spinner);
+
+    if size == ImageSize::Full && !media::verify(hash, &data) {
+        std::fs::remove_file(&part).ok();
+        bail!("Image {} doesn't match the hash the listing gives for it", number);
+    }
+    let path = dir.join(format!("{}.{}", stem, extension(&data)));
+    std::fs::rename(&part, &path).with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(path)
+}
+
+/// The extension for an image with `data`, by its magic bytes.
+fn extension(data: &[u8]) -> &'static str {
+    match data {
+        [0xff, 0xd8, 0xff, ..] => "jpg",
+        [0x89, b'P', b'N', b'G', ..] => "png",
+        [b'G', b'I', b'F', b'8', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        _ => "img",
+    }
+}
+
+/// Guesses which graphics the terminal supports from its environment.
+fn detect() -> Preview {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let term = var("TERM");
+    if term == "xterm-kitty" || !var("KITTY_WINDOW_ID").is_empty() || var("TERM_PROGRAM") == "ghostty" {
+        Preview::Kitty
+    } else if var("TERM_PROGRAM") == "WezTerm" || ["foot", "mlterm", "contour"].iter().any(|t| term.starts_with(t)) || term.contains("sixel") {
+        Preview::Sixel
+    } else {
+        Preview::Ascii
+    }
+}
+
+#[cfg(feature = "previews")]
+fn show(path: &Path, preview: Preview) -> Result<()> {
+    use image::imageops::FilterType;
+    use image::{ImageReader, Limits};
+    use ratatui::crossterm::terminal;
+
+    /// Largest width or height of an image that is decoded, so a crafted one
+    /// can't make previewing eat memory.
+    const MAX_SIDE: u32 = 8192;
+    /// Widest preview in pixels, and in columns.
+    const MAX_PIXELS: u32 = 600;
+    const MAX_COLUMNS: u32 = 100;
+
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SIDE);
+    limits.max_image_height = Some(MAX_SIDE);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let size = terminal::window_size().ok().filter(|size| size.columns > 0);
+    let columns = size.as_ref().map_or(80, |size| u32::from(size.columns)).min(MAX_COLUMNS);
+    let mut out = std::io::stdout().lock();
+    match preview {
+        Preview::Kitty | Preview::Sixel => {
+            // Cells are taken to be 8 pixels wide where the terminal won't say
+            let cell = size.filter(|size| size.width >= size.columns).map_or(8, |size| u32::from(size.width / size.columns));
+            let side = (columns * cell).min(MAX_PIXELS);
+            let rgba = image.resize(side, side, FilterType::Triangle).to_rgba8();
+            if preview == Preview::Kitty {
+                write_kitty(&mut out, &rgba)?;
+            } else {
+                write_sixel(&mut out, &rgba)?;
+            }
+        },
+        _ => {
+            // Characters are about twice as tall as they're wide
+            let truecolor = matches!(std::env::var("COLORTERM").as_deref(), Ok("truecolor" | "24bit"));
+            let rows = if truecolor { columns } else { columns / 2 };
+            let rgba = image.resize_exact(columns, (image.height() * rows / image.width().max(1)).max(1), FilterType::Triangle).to_rgba8();
+            if truecolor {
+                write_blocks(&mut out, &rgba)?;
+            } else {
+                write_ascii(&mut out, &rgba)?;
+            }
+        },
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "previews"))]
+fn show(_path: &Path, _preview: Preview) -> Result<()> {
+    bail!("This client was built without image previews (the previews feature)")
+}
+
+/// Sends the image with the kitty graphics protocol, in chunks of at most
+/// 4096 bytes of base64 as it asks for.
+#[cfg(feature = "previews")]
+fn write_kitty(out: &mut impl Write, image: &image::RgbaImage) -> Result<()> {
+    let encoded = media::encode(image.as_raw());
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(out, "\x1b_Gf=32,a=T,s={},v={},m={};", image.width(), image.height(), more)?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Draws the image as sixels, in the 216 colours of a 6×6×6 cube.
+/// Transparent pixels are left alone.
+#[cfg(feature = "previews")]
+fn write_sixel(out: &mut impl Write, image: &image::RgbaImage) -> Result<()> {
+    let level = |c: u8| (u16::from(c) * 5 + 127) / 255;
+    let colour = |x: u32, y: u32| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        (a >= 128).then(|| (level(r) * 36 + level(g) * 6 + level(b)) as usize)
+    };
+    let (width, height) = image.dimensions();
+
+    write!(out, "\x1bPq\"1;1;{};{}", width, height)?;
+    for i in 0..216 {
+        let percent = |l: usize| l * 100 / 5;
+        write!(out, "#{};2;{};{};{}", i, percent(i / 36), percent(i / 6 % 6), percent(i % 6))?;
+    }
+    for top in (0..height).step_by(6) {
+        let rows = top..(top + 6).min(height);
+        let mut used = [false; 216];
+        for y in rows.clone() {
+            for x in 0..width {
+                if let Some(c) = colour(x, y) {
+                    used[c] = true;
+                }
+            }
+        }
+        for c in (0..216).filter(|&c| used[c]) {
+            write!(out, "#{}", c)?;
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let bits = rows.clone().filter(|&y| colour(x, y) == Some(c)).fold(0, |bits, y| bits | 1 << (y - top));
+                let sixel = 63 + bits as u8;
+                run = match run {
+                    Some((last, count)) if last == sixel => Some((last, count + 1)),
+                    Some(last) => {
+                        write_run(out, last)?;
+                        Some((sixel, 1))
+                    },
+                    None => Some((sixel, 1)),
+                };
+            }
+            if let Some(last) = run {
+                write_run(out, last)?;
+            }
+            write!(out, "$")?;
+        }
+        write!(out, "-")?;
+    }
+    write!(out, "\x1b\\")?;
+    Ok(())
+}
+
+#[cfg(feature = "previews")]
+fn write_run(out: &mut impl Write, (sixel, count): (u8, usize)) -> std::io::Result<()> {
+    match count {
+        1..=3 => out.write_all(&vec![sixel; count]),
+        count => write!(out, "!{}{}", count, sixel as char),
+    }
+}
+
+/// Draws the image in half blocks, two pixels to a character, in truecolor.
+#[cfg(feature = "previews")]
+fn write_blocks(out: &mut impl Write, image: &image::RgbaImage) -> Result<()> {
+    for top in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let [r, g, b, _] = image.get_pixel(x, top).0;
+            write!(out, "\x1b[38;2;{};{};{}m", r, g, b)?;
+            if top + 1 < image.height() {
+                let [r, g, b, _] = image.get_pixel(x, top + 1).0;
+                write!(out, "\x1b[48;2;{};{};{}m", r, g, b)?;
+            }
+            write!(out, "▀")?;
+        }
+        writeln!(out, "\x1b[0m")?;
+    }
+    Ok(())
+}
+
+/// Draws the image in characters getting denser as it gets darker.
+#[cfg(feature = "previews")]
+fn write_ascii(out: &mut impl Write, image: &image::RgbaImage) -> Result<()> {
+    const RAMP: &[u8] = b"@%#*+=-:. ";
+    for y in 0..image.height() {
+        let line: Vec<u8> = (0..image.width())
+            .map(|x| {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                let luma = (u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000;
+                RAMP[luma as usize * (RAMP.len() - 1) / 255]
+            })
+            .collect();
+        out.write_all(&line)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use crate::config::config_dir;
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use ed25519_dalek::{Signer, SigningKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Environment variable a passphrase is read from before asking for one, for scripts.
+const PASSPHRASE_ENV: &str = "NYMBAZAAR_PASSPHRASE";
+
+/// Service and user the passphrase is kept under in the OS keyring.
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "nymbazaar";
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "keystore";
+
+/// The user's keys, shared by every subcommand that needs them.
+pub struct Identity {
+    /// Signs seller registrations and listings.
+    signing: SigningKey,
+    /// For end-to-end encrypted shipping details.
+    encryption: StaticSecret,
+}
+
+impl Identity {
+    fn generate() -> Self {
+        let mut secret = [0; 32];
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        let signing = SigningKey::from_bytes(&secret);
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        Self { signing, encryption: StaticSecret::from(secret) }
+    }
+
+    /// The hex ed25519 key to register as a seller with.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.signing.verifying_key().to_bytes())
+    }
+
+    /// The hex x25519 key others encrypt shipping details to.
+    pub fn encryption_key(&self) -> String {
+        hex::encode(PublicKey::from(&self.encryption).to_bytes())
+    }
+
+    /// The x25519 secret matching the registered key, which is what buyers
+    /// can look up and seal shipping addresses to.
+    pub fn shipping_secret(&self) -> StaticSecret {
+        StaticSecret::from(self.signing.to_scalar_bytes())
+    }
+
+    /// A hex ed25519 signature over `message`.
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.signing.sign(message).to_bytes())
+    }
+}
+
+/// The keystore file, `~/.config/nymbazaar/keystore.toml`. The secret keys
+/// are encrypted with XChaCha20-Poly1305 under a key derived from the
+/// passphrase with Argon2id; the public keys are kept in the clear so they
+/// can be shown without unlocking.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Keystore {
+    pub public_key: String,
+    pub encryption_key: String,
+    /// Hex encoded, like the rest.
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    fn path() -> Result<PathBuf> {
+        match config_dir() {
+            Some(dir) => Ok(dir.join("keystore.toml")),
+            None => bail!("Can't find a config directory for the keystore; set HOME or XDG_CONFIG_HOME"),
+        }
+    }
+
+    /// Reads the keystore, failing if there isn't one yet.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            bail!("No keystore yet; create one with `nymbazaar-client identity new`");
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read keystore {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid keystore {}", path.display()))
+    }
+
+    pub fn exists() -> Result<bool> {
+        Ok(Self::path()?.exists())
+    }
+
+    /// Generates a new identity and stores it encrypted with `passphrase`,
+    /// replacing any keystore there was.
+    pub fn create(passphrase: &str) -> Result<Self> {
+        let path = Self::path()?;
+        let identity = Identity::generate();
+        let public_key = identity.public_key();
+        let mut salt = [0; 16];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        let mut nonce = [0; 24];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let mut secrets = identity.signing.to_bytes().to_vec();
+        secrets.extend_from_slice(identity.encryption.as_bytes());
+        let ciphertext = cipher(passphrase, &salt)?
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &secrets, aad: public_key.as_bytes() })
+            .map_err(|_| anyhow!("Failed to encrypt the keystore"))?;
+
+        let keystore = Self {
+            public_key,
+            encryption_key: identity.encryption_key(),
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        };
+        keystore.save(&path)?;
+        // A remembered passphrase was for the keys just replaced
+        #[cfg(feature = "keyring")]
+        if let Err(e) = forget() {
+            tracing::warn!("Failed to forget the old passphrase: {:#}", e);
+        }
+        Ok(keystore)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let tmp = path.with_extension("toml.tmp");
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::io::Write::write_all(&mut file, toml::to_string(self)?.as_bytes())
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace keystore {}", path.display()))
+    }
+
+    /// Decrypts the keys with `passphrase`.
+    pub fn unlock_with(&self, passphrase: &str) -> Result<Identity> {
+        let salt = hex::decode(&self.salt).context("Invalid keystore salt")?;
+        let nonce = hex::decode(&self.nonce).context("Invalid keystore nonce")?;
+        let ciphertext = hex::decode(&self.ciphertext).context("Invalid keystore ciphertext")?;
+        if nonce.len() != 24 {
+            bail!("Invalid keystore nonce");
+        }
+        let secrets = cipher(passphrase, &salt)?
+            .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: self.public_key.as_bytes() })
+            .map_err(|_| anyhow!("Wrong passphrase"))?;
+        let (signing, encryption) = secrets.split_at(32);
+        Ok(Identity {
+            signing: SigningKey::from_bytes(signing.try_into().context("Invalid keystore contents")?),
+            encryption: StaticSecret::from(<[u8; 32]>::try_from(encryption).context("Invalid keystore contents")?),
+        })
+    }
+
+    /// Decrypts the keys with the passphrase from `NYMBAZAAR_PASSPHRASE`, the
+    /// OS keyring (in builds with the `keyring` feature), or asked for.
+    pub fn unlock(&self) -> Result<Identity> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            return self.unlock_with(&passphrase);
+        }
+        #[cfg(feature = "keyring")]
+        if let Some(passphrase) = remembered_passphrase()? {
+            return self.unlock_with(&passphrase);
+        }
+        self.unlock_with(&rpassword::prompt_password("Keystore passphrase: ")?)
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive the keystore key: {}", e))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+/// Asks for a new passphrase twice, unless `NYMBAZAAR_PASSPHRASE` is set.
+pub fn new_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("New keystore passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("The passphrase can't be empty");
+    }
+    if rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+        bail!("The passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+#[cfg(feature = "keyring")]
+fn remembered_passphrase() -> Result<Option<String>> {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.get_password() {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e).context("Failed to read the OS keyring"),
+    }
+}
+
+/// Keeps the keystore's passphrase in the OS keyring, after checking it.
+#[cfg(feature = "keyring")]
+pub fn remember(keystore: &Keystore) -> Result<()> {
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => rpassword::prompt_password("Keystore passphrase: ")?,
+    };
+    keystore.unlock_with(&passphrase)?;
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?
+        .set_password(&passphrase)
+        .context("Failed to write the OS keyring")
+}
+
+/// Removes the passphrase from the OS keyring, if it's there.
+#[cfg(feature = "keyring")]
+pub fn forget() -> Result<()> {
+    match keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e).context("Failed to write the OS keyring"),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn remember(_keystore: &Keystore) -> Result<()> {
+    bail!("This client was built without OS keyring support (the keyring feature)")
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn forget() -> Result<()> {
+    bail!("This client was built without OS keyring support (the keyring feature)")
+}
@@ -1,216 +1,1507 @@
-use anyhow::{Result, Context};
-use clap::Parser;
-use nym_sdk::{mixnet::Recipient, tcp_proxy::NymProxyClient};
+mod bazaars;
+mod config;
+mod daemon;
+mod export;
+mod images;
+mod keystore;
+mod narrow;
+mod prefetch;
+mod qr;
+mod readline;
+mod script;
+mod seller;
+mod shipping;
+mod spinner;
+mod tui;
+mod watchlist;
+
+use anyhow::{anyhow, bail, Result, Context};
+use bazaar_protocol::capability;
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::price::normalize_currency;
+use bazaar_protocol::shipping::is_valid_region;
+use bazaar_protocol::command::{registration_message, ImageData, UploadCommand};
+use bazaar_protocol::media::{self, ImageSize};
+use bazaar_protocol::signing::listing_payload;
+use bazaar_protocol::upload;
+use bazaar_protocol::search::SortKey;
+use bazaar_protocol::{BazaarError, Command, Compression, DropCommand, Encoding, Item, Lang, Response, SearchQuery, SellerCommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use bazaars::AddressBook;
+use config::{cache_dir, data_dir, default_rates, downloads_dir, ClientConfig, Profile};
+use images::Preview;
+use keystore::{Identity, Keystore};
+use script::Script;
+use readline::{Completions, Editor};
+use seller::ListingFile;
+use spinner::Spinner;
+use watchlist::{Watch, Watchlist};
+use nym_sdk::mixnet::{DebugConfig, MixnetClient, MixnetClientBuilder, Recipient, StoragePaths};
+use nym_sdk::NymNetworkDetails;
+use nymbazaar_client_lib::{cache, free_port, probe, Abandoned, BazaarClient, Connection, Interrupt, LocalRates, NotCached, ResponseCache, Transport, DEFAULT_TIMEOUT};
 use std::fs::OpenOptions;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::io::{self, IsTerminal};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
-#[clap(name = "nymbazaar-client", about = "NymBazaar client for shopping vintage collectibles")]
+#[clap(name = "nymbazaar-client", version, about = "NymBazaar client for shopping vintage collectibles")]
 struct Args {
-    /// NYM mixnet address of the NymBazaar server
-    #[clap(long, required = true)]
-    bazaar_id: String,
+    /// NYM mixnet address of the NymBazaar server; taken from the profile if not given
+    #[clap(long, env = "NYMBAZAAR_ID")]
+    bazaar_id: Option<String>,
+    
+    /// Client config file with profiles [default: ~/.config/nymbazaar/config.toml]
+    #[clap(long, env = "NYMBAZAAR_CONFIG")]
+    config: Option<PathBuf>,
+    
+    /// Profile from the client config to use instead of the default one
+    #[clap(long, env = "NYMBAZAAR_PROFILE")]
+    profile: Option<String>,
+    
+    /// Local address for the proxy client to listen on. Anyone who can reach it
+    /// can browse through it, so keep it on loopback [default: 127.0.0.1]
+    #[clap(long)]
+    proxy_host: Option<String>,
     
-    /// Enable verbose logging
+    /// Local port for the proxy client [default: a free one]
+    #[clap(long)]
+    proxy_port: Option<u16>,
+    
+    /// Enable verbose logging (same as --log-level debug)
     #[clap(long)]
     verbose: bool,
     
-    /// Log file path
+    /// Log filter, e.g. `info` or `nymbazaar_client=trace,warn` [default: warn]
+    #[clap(long)]
+    log_level: Option<String>,
+    
+    /// Write logs as JSON lines
+    #[clap(long)]
+    log_json: bool,
+    
+    /// Log file path (logs go to stderr otherwise)
     #[clap(long)]
     log: Option<PathBuf>,
+    
+    /// Language for server messages (en, de, es)
+    #[clap(long, value_parser = parse_lang)]
+    lang: Option<Lang>,
+    
+    /// Show prices converted to this currency (e.g. EUR)
+    #[clap(long, value_parser = parse_currency, global = true)]
+    currency: Option<String>,
+    
+    /// Exchange rates to convert prices with when the bazaar can't, in the
+    /// format of the server's rates file [default: ~/.config/nymbazaar/rates.toml]
+    #[clap(long)]
+    rates: Option<PathBuf>,
+    
+    /// Only show listings that ship to this region (e.g. EU, US). Filtering happens
+    /// here, the server never learns the region
+    #[clap(long, value_parser = parse_region)]
+    region: Option<String>,
+    
+    /// Talk to the server straight over the mixnet, for servers run with `--direct`
+    #[clap(long)]
+    direct: bool,
+    
+    /// Nym network env file, for testnets and other non-mainnet networks
+    #[clap(long)]
+    nym_env: Option<String>,
+    
+    /// Identity key of the gateway to connect through (with --direct)
+    #[clap(long)]
+    gateway: Option<String>,
+    
+    /// Skip cover traffic and Poisson send delays for faster replies, at the cost of
+    /// traffic that is easier to correlate (with --direct)
+    #[clap(long)]
+    low_latency: bool,
+    
+    /// Keep the mixnet client's keys in the data directory and reuse them, which
+    /// starts faster but shows the bazaar the same address every run (with --direct)
+    #[clap(long)]
+    persistent: bool,
+    
+    /// API token to authenticate with (AUTH) before a one-shot command
+    #[clap(long, env = "NYMBAZAAR_TOKEN", global = true, hide_env_values = true)]
+    token: Option<String>,
+    
+    /// Seller name for `sell` commands; taken from the profile if not given
+    #[clap(long, env = "NYMBAZAAR_SELLER", global = true)]
+    seller: Option<String>,
+    
+    /// Seconds to wait for each reply before giving up on it; Ctrl-C gives up
+    /// sooner [default: 60]
+    #[clap(long)]
+    timeout: Option<u64>,
+    
+    /// Seconds to reuse cached listings, searches and categories for before asking
+    /// the server again; 0 turns the cache off [default: 300]
+    #[clap(long)]
+    cache_ttl: Option<u64>,
+    
+    /// Listing details to fetch at once in the background after a list or search,
+    /// each over a proxy connection of its own; 0 turns prefetching off [default: 2]
+    #[clap(long)]
+    prefetch: Option<usize>,
+    
+    /// KiB of listing details to prefetch per page of listings [default: 512]
+    #[clap(long)]
+    prefetch_budget: Option<usize>,
+    
+    /// Browse what's cached without connecting, however old it is
+    #[clap(long)]
+    offline: bool,
+    
+    /// Don't save what's typed in the numbered menu to
+    /// ~/.config/nymbazaar/history
+    #[clap(long)]
+    no_history: bool,
+    
+    /// Use the numbered menu instead of the full-screen interface
+    #[clap(long)]
+    plain: bool,
+    
+    /// How to print the result of a one-shot command
+    #[clap(long, value_enum, default_value_t = Output::Plain, global = true)]
+    output: Output,
+    
+    /// Also write the result of a one-shot command to this file, as CSV or JSON
+    /// by its extension. The full-screen UI offers it when exporting with e or E
+    #[clap(long, value_parser = parse_export, global = true)]
+    export: Option<PathBuf>,
+    
+    /// Run the protocol commands in this file, one per line, with variables and
+    /// assertions, and print their replies; `-` reads them from stdin
+    #[clap(long)]
+    script: Option<PathBuf>,
+    
+    /// Where `image` saves listing images [default: ~/Downloads/nymbazaar]
+    #[clap(long, global = true)]
+    downloads: Option<PathBuf>,
+    
+    /// Run one command, print its result and exit instead of starting the UI.
+    /// Exits with status 1 if the server answers with an error
+    #[clap(subcommand)]
+    command: Option<Commands>,
+}
+
+/// Output formats of one-shot commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Output {
+    /// The server's text, as in the UI
+    Plain,
+    /// The response as the server's JSON encoding sends it
+    Json,
+    /// Listings, categories, models and status as CSV with a header line
+    Csv,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage saved bazaars, whose names work in place of their addresses
+    #[clap(subcommand)]
+    Bazaar(BazaarCommand),
+    /// Manage the keys kept in the local keystore
+    #[clap(subcommand)]
+    Identity(IdentityCommand),
+    /// Run your shop: create, change and remove listings (needs --token)
+    #[clap(subcommand)]
+    Sell(SellCommand),
+    /// Watch listings for price drops and searches for new matches
+    #[clap(subcommand)]
+    Watch(WatchCommand),
+    /// Show an address or payment request as a QR code, to scan with a phone
+    #[clap(subcommand)]
+    Qr(QrCommand),
+    /// Keep a proxy client running for later invocations to use, skipping the
+    /// mixnet startup (with --proxy-port to pin its port)
+    Daemon {
+        #[clap(subcommand)]
+        action: Option<DaemonCommand>,
+    },
+    /// Print a completion script for a shell, e.g.
+    /// `nymbazaar-client completions bash > /etc/bash_completion.d/nymbazaar-client`
+    Completions {
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page, or write one for every subcommand into a directory
+    Man {
+        /// Directory to write nymbazaar-client.1 and the subcommands' pages to
+        #[clap(long)]
+        dir: Option<PathBuf>,
+    },
+    #[clap(flatten)]
+    OneShot(OneShot),
 }
 
+#[derive(Subcommand)]
+enum BazaarCommand {
+    /// Save a bazaar's nym address under a name
+    Add { name: String, address: String },
+    /// List saved bazaars
+    List,
+    /// Forget a saved bazaar
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum WatchCommand {
+    /// Watch a listing on the bazaar for price drops
+    Item { id: String },
+    /// Watch a search on the bazaar for new listings matching it
+    Search {
+        #[clap(required = true)]
+        query: Vec<String>,
+    },
+    /// List what's watched
+    List,
+    /// Stop watching something, by its number in `watch list`
+    Remove { number: usize },
+    /// Poll what's watched on the bazaar and raise desktop notifications
+    Run {
+        /// Seconds between polls
+        #[clap(long, default_value_t = 300)]
+        interval: u64,
+        /// Poll once and exit, e.g. from cron
+        #[clap(long)]
+        once: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum QrCommand {
+    /// The address of the bazaar given with --bazaar-id
+    Bazaar,
+    /// Your public key, which the bazaar knows you by
+    Identity,
+    /// Anything else, e.g. a payment URI like `monero:<address>?tx_amount=0.5`
+    Text { text: String },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommand {
+    /// Show the running daemon's proxy clients
+    Status,
+    /// Stop the running daemon
+    Stop,
+}
+
+#[derive(Subcommand)]
+enum IdentityCommand {
+    /// Generate keys and store them encrypted with a passphrase
+    New {
+        /// Replace an existing keystore, losing its keys
+        #[clap(long)]
+        force: bool,
+    },
+    /// Show the public keys
+    Show,
+    /// Keep the passphrase in the OS keyring so it isn't asked for
+    Remember,
+    /// Remove the passphrase from the OS keyring
+    Forget,
+}
+
+#[derive(Subcommand)]
+enum SellCommand {
+    /// Print a listing file template for create --file and update --file
+    Template,
+    /// List a new item, from a listing file or asked for field by field
+    Create {
+        #[clap(long)]
+        file: Option<PathBuf>,
+    },
+    /// Change a listing, from a listing file or asked for field by field
+    Update {
+        id: String,
+        #[clap(long)]
+        file: Option<PathBuf>,
+    },
+    /// Move a listing to the trash
+    Delete { id: String },
+    /// Put a deleted listing back up
+    Relist { id: String },
+    /// Attach an image to a listing
+    UploadImage { id: String, path: PathBuf },
+    /// List your listings, newest first
+    Mine,
+    /// Show the questions buyers asked about a listing
+    Questions { id: String },
+    /// Read the shipping address a buyer sent, by the drop ID they gave you
+    Address { drop_id: String },
+}
+
+impl SellCommand {
+    /// Builds the request, reading files and asking for input before connecting.
+    fn request(&self, seller: Option<&str>, currency: Option<&String>) -> Result<Request> {
+        let command = match self {
+            SellCommand::Template => bail!("sell template doesn't talk to the server"),
+            SellCommand::Create { file } => {
+                let listing = match file {
+                    Some(path) => ListingFile::load(path)?,
+                    None => ListingFile::prompt(seller)?,
+                };
+                Command::Sell(listing.into_listing(seller)?)
+            },
+            SellCommand::Update { id, file } => {
+                let file = file.as_deref().map(ListingFile::load).transpose()?;
+                return Ok(Request::Update { id: id.clone(), file });
+            },
+            SellCommand::Delete { id } => Command::Delete { id: id.clone() },
+            SellCommand::Relist { id } => Command::Restore { id: id.clone() },
+            SellCommand::UploadImage { id, path } => {
+                let image = std::fs::read(path).with_context(|| format!("Failed to read image {}", path.display()))?;
+                return Ok(Request::PutImage { id: id.clone(), image });
+            },
+            SellCommand::Mine => {
+                let Some(seller) = seller else {
+                    bail!("No seller name: pass --seller or set seller in a profile of the client config");
+                };
+                let query = SearchQuery { seller: Some(seller.to_string()), sort: Some(SortKey::Newest), ..SearchQuery::default() };
+                Command::Search { query, currency: currency.cloned() }
+            },
+            SellCommand::Questions { id } => Command::Questions { id: id.clone() },
+            SellCommand::Address { drop_id } => {
+                return Ok(Request::Address { drop_id: drop_id.clone(), identity: Keystore::load()?.unlock()? });
+            },
+        };
+        Ok(Request::Command(command))
+    }
+}
+
+/// Runs a keystore command; these don't need a connection.
+fn manage_identity(command: &IdentityCommand) -> Result<()> {
+    match command {
+        IdentityCommand::New { force } => {
+            if Keystore::exists()? && !force {
+                bail!("There already is a keystore; pass --force to replace it and lose its keys");
+            }
+            print_keys(&Keystore::create(&keystore::new_passphrase()?)?);
+        },
+        IdentityCommand::Show => print_keys(&Keystore::load()?),
+        IdentityCommand::Remember => keystore::remember(&Keystore::load()?)?,
+        IdentityCommand::Forget => keystore::forget()?,
+    }
+    Ok(())
+}
+
+fn print_keys(keystore: &Keystore) {
+    println!("public_key\t{}", keystore.public_key);
+    println!("encryption_key\t{}", keystore.encryption_key);
+}
+
+/// Changes or lists the watchlist; `bazaar` is what `--bazaar-id` names, which
+/// new watches are on.
+fn manage_watches(command: &WatchCommand, bazaar: Option<&str>) -> Result<()> {
+    let mut watchlist = Watchlist::load()?;
+    let bazaars = AddressBook::load()?;
+    let on_bazaar = |item: Option<&String>, search: Option<String>| -> Result<Watch> {
+        let Some(bazaar) = bazaar else {
+            bail!("No bazaar address: pass --bazaar-id or set bazaar_id in a profile of the client config");
+        };
+        Ok(Watch { bazaar: bazaars.resolve(bazaar)?.to_string(), item: item.cloned(), search })
+    };
+    match command {
+        WatchCommand::Item { id } => watchlist.add(on_bazaar(Some(id), None)?),
+        WatchCommand::Search { query } => watchlist.add(on_bazaar(None, Some(query.join(" ")))?),
+        WatchCommand::Remove { number } => watchlist.remove(*number),
+        WatchCommand::List => {
+            for (i, watch) in watchlist.iter().enumerate() {
+                // Saved bazaars by name
+                let bazaar = bazaars.iter().find(|(_, address)| *address == watch.bazaar).map_or(watch.bazaar.as_str(), |(name, _)| name);
+                match (&watch.item, &watch.search) {
+                    (Some(id), _) => println!("{}. {}	item {}", i + 1, bazaar, id),
+                    (None, search) => println!("{}. {}	search {}", i + 1, bazaar, search.as_deref().unwrap_or_default()),
+                }
+            }
+            Ok(())
+        },
+        WatchCommand::Run { .. } => bail!("watch run needs a connection"),
+    }
+}
+
+/// Writes the man pages into `dir`, or prints the top one.
+fn write_man_pages(dir: Option<&Path>) -> Result<()> {
+    let Some(dir) = dir else {
+        clap_mangen::Man::new(Args::command()).render(&mut io::stdout())?;
+        return Ok(());
+    };
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    clap_mangen::generate_to(Args::command(), dir).with_context(|| format!("Failed to write the man pages to {}", dir.display()))?;
+    println!("Wrote the man pages to {}", dir.display());
+    Ok(())
+}
+
+/// Runs an address book command; these don't need a connection.
+fn manage_bazaars(command: &BazaarCommand) -> Result<()> {
+    let mut bazaars = AddressBook::load()?;
+    match command {
+        BazaarCommand::Add { name, address } => bazaars.add(name, address),
+        BazaarCommand::Remove { name } => bazaars.remove(name),
+        BazaarCommand::List => {
+            for (name, address) in bazaars.iter() {
+                println!("{}\t{}", name, address);
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Prints what `command` names as a QR code with the text under it; `bazaar`
+/// is what `--bazaar-id` names.
+fn print_qr(command: &QrCommand, bazaar: Option<&str>, plain: bool) -> Result<()> {
+    let text = match command {
+        QrCommand::Bazaar => {
+            let Some(bazaar) = bazaar else {
+                bail!("No bazaar address: pass --bazaar-id or set bazaar_id in a profile of the client config");
+            };
+            AddressBook::load()?.resolve(bazaar)?.to_string()
+        },
+        QrCommand::Identity => Keystore::load()?.public_key,
+        QrCommand::Text { text } => text.clone(),
+    };
+    let code = qr::QrCode::encode(text.as_bytes())?;
+    print!("{}", code.render(!plain && io::stdout().is_terminal()));
+    println!("{}", text);
+    Ok(())
+}
+
+/// Asks the running daemon about its proxy clients, or to stop.
+async fn manage_daemon(command: &DaemonCommand) -> Result<()> {
+    let request = match command {
+        DaemonCommand::Status => "STATUS",
+        DaemonCommand::Stop => "STOP",
+    };
+    let Some(reply) = daemon::ask(request).await? else {
+        bail!("No daemon is running; start one with `nymbazaar-client daemon`");
+    };
+    match (command, reply.trim()) {
+        (_, reply) if reply.starts_with("ERR ") => bail!("The daemon refused: {}", &reply[4..]),
+        (DaemonCommand::Stop, _) => println!("Stopped the daemon"),
+        (DaemonCommand::Status, "") => println!("The daemon has no proxy clients"),
+        (DaemonCommand::Status, reply) => println!("{}", reply),
+    }
+    Ok(())
+}
+
+#[derive(Subcommand)]
+enum OneShot {
+    /// List items, optionally from one category
+    List {
+        #[clap(long)]
+        category: Option<String>,
+    },
+    /// Show an item by ID or number
+    Get {
+        id: String,
+    },
+    /// Search items, e.g. `search "dx7 price:100..500 sort:price"`
+    Search {
+        #[clap(required = true)]
+        query: Vec<String>,
+    },
+    /// List categories
+    Categories,
+    /// Look up instrument models, e.g. `models tr-808`
+    Models {
+        query: Vec<String>,
+    },
+    /// Show featured items
+    Featured,
+    /// Show a random item, optionally from one category
+    Random {
+        #[clap(long)]
+        category: Option<String>,
+    },
+    /// Show server status
+    Status,
+    /// Register a seller name with the keystore's key
+    Register {
+        name: String,
+        /// Anonymous credential, for bazaars that require one to register
+        #[clap(long)]
+        credential: Option<String>,
+    },
+    /// Sign a listing's current revision with the keystore's key
+    Sign { id: String },
+    /// Send the seller of a listing your shipping address, encrypted to their
+    /// registered key (needs --token). Read from stdin, never saved
+    Ship { id: String },
+    /// Download a listing's images and preview them on the terminal. Downloads
+    /// cut short pick up where they stopped
+    Image {
+        id: String,
+        /// full, or a thumbnail made by the server: medium or thumb
+        #[clap(long, value_parser = parse_image_size, default_value = "full")]
+        size: ImageSize,
+        /// How to show the images; auto picks from what the terminal looks like
+        #[clap(long, value_enum, default_value_t = Preview::Auto)]
+        preview: Preview,
+    },
+}
+
+/// What a one-shot invocation does once connected.
+enum Request {
+    Command(Command),
+    /// Fetches a listing and sends `SIGN` for it.
+    Sign { id: String, identity: Identity },
+    /// Fetches a listing and sends an `UPDATE` for each field that changes,
+    /// from the listing file or asked for.
+    Update { id: String, file: Option<ListingFile> },
+    /// Sends `PUTIMAGE`, uploading the image first if it's too big to send inline.
+    PutImage { id: String, image: Vec<u8> },
+    /// Fetches a listing and its seller's key and posts `address` sealed to it
+    /// as a dead drop.
+    Ship { id: String, address: String },
+    /// Fetches a dead drop and prints the address in it, see [`shipping::print_drop`].
+    Address { drop_id: String, identity: Identity },
+    /// Fetches a listing and downloads its images, see [`images::run`].
+    Image { id: String, size: ImageSize, preview: Preview },
+    /// Runs a script of commands, see [`script::Script`].
+    Script(Script),
+    /// Polls the watches on `bazaar`, every `interval` or `once`.
+    Watch { bazaar: String, interval: Duration, once: bool },
+}
+
+impl OneShot {
+    /// Builds the request, unlocking the keystore first if it's needed.
+    fn request(&self, currency: Option<&String>) -> Result<Request> {
+        let currency = currency.cloned();
+        let command = match self {
+            OneShot::List { category } => Command::List { category: category.clone(), currency, price: None },
+            OneShot::Get { id } => Command::Get { id: id.clone(), currency, range: None },
+            OneShot::Search { query } => Command::Search { query: query.join(" ").parse::<SearchQuery>()?, currency },
+            OneShot::Categories => Command::Categories,
+            OneShot::Models { query } => Command::Models { query: Some(query.join(" ")).filter(|query| !query.is_empty()) },
+            OneShot::Featured => Command::Featured,
+            OneShot::Random { category } => Command::Random { category: category.clone() },
+            OneShot::Status => Command::Status,
+            OneShot::Register { name, credential } => {
+                let identity = Keystore::load()?.unlock()?;
+                Command::Seller(SellerCommand::Register {
+                    name: name.clone(),
+                    public_key: identity.public_key(),
+                    signature: identity.sign(&registration_message(name)),
+                    credential: credential.clone(),
+                })
+            },
+            OneShot::Sign { id } => return Ok(Request::Sign { id: id.clone(), identity: Keystore::load()?.unlock()? }),
+            OneShot::Ship { id } => return Ok(Request::Ship { id: id.clone(), address: shipping::read_address()? }),
+            OneShot::Image { id, size, preview } => return Ok(Request::Image { id: id.clone(), size: *size, preview: *preview }),
+        };
+        Ok(Request::Command(command))
+    }
+}
+
+fn parse_image_size(size: &str) -> Result<ImageSize, String> {
+    ImageSize::parse(size).ok_or_else(|| format!("'{}' is not an image size: full, medium or thumb", size))
+}
+
+fn parse_region(code: &str) -> Result<String, String> {
+    if !is_valid_region(code) {
+        return Err(format!("'{}' is not a region code such as EU or US", code));
+    }
+    Ok(code.to_uppercase())
+}
+
+fn parse_export(path: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(path);
+    export::Format::of(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+fn parse_currency(code: &str) -> Result<String, String> {
+    normalize_currency(code).ok_or_else(|| format!("'{}' is not a currency code such as EUR or USD", code))
+}
+
+fn parse_lang(code: &str) -> Result<Lang, String> {
+    Lang::from_code(code).ok_or_else(|| format!("unsupported language '{}'", code))
+}
+
+/// Fills in whatever `profile` sets and the flags didn't.
+fn apply_profile(args: &mut Args, profile: Profile) -> Result<()> {
+    args.bazaar_id = args.bazaar_id.take().or(profile.bazaar_id);
+    args.proxy_host = args.proxy_host.take().or(profile.proxy_host);
+    args.proxy_port = args.proxy_port.or(profile.proxy_port);
+    args.nym_env = args.nym_env.take().or(profile.nym_env);
+    args.direct |= profile.direct;
+    args.gateway = args.gateway.take().or(profile.gateway);
+    args.low_latency |= profile.low_latency;
+    args.persistent |= profile.persistent;
+    args.log_level = args.log_level.take().or(profile.log_level);
+    args.log_json |= profile.log_json;
+    args.no_history |= profile.no_history;
+    args.log = args.log.take().or(profile.log);
+    args.timeout = args.timeout.or(profile.timeout_secs);
+    args.cache_ttl = args.cache_ttl.or(profile.cache_ttl_secs);
+    args.prefetch = args.prefetch.or(profile.prefetch);
+    args.prefetch_budget = args.prefetch_budget.or(profile.prefetch_budget_kib);
+    args.token = args.token.take().or(profile.token);
+    args.seller = args.seller.take().or(profile.seller);
+    args.rates = args.rates.take().or(profile.rates);
+    args.downloads = args.downloads.take().or(profile.downloads);
+    if args.lang.is_none() {
+        args.lang = profile.lang.as_deref().map(parse_lang).transpose().map_err(|e| anyhow!("Invalid profile: {}", e))?;
+    }
+    if args.currency.is_none() {
+        args.currency = profile.currency.as_deref().map(parse_currency).transpose().map_err(|e| anyhow!("Invalid profile: {}", e))?;
+    }
+    // Checked here rather than by clap, so `direct` can come from the profile.
+    // The proxy client only takes an env file from the SDK
+    if !args.direct && (args.gateway.is_some() || args.low_latency || args.persistent) {
+        bail!("--gateway, --low-latency and --persistent only work with --direct");
+    }
+    Ok(())
+}
+
+fn init_logging(args: &Args) -> Result<()> {
+    let level = if args.verbose { "debug" } else { args.log_level.as_deref().unwrap_or("warn") };
+    let filter = EnvFilter::try_new(level).with_context(|| format!("Invalid log level '{}'", level))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    
+    match (&args.log, args.log_json) {
+        (Some(path), json) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            let builder = builder.with_ansi(false).with_writer(Mutex::new(file));
+            if json {
+                builder.json().init();
+            } else {
+                builder.init();
+            }
+        },
+        (None, true) => builder.with_writer(io::stderr).json().init(),
+        (None, false) => builder.with_writer(io::stderr).init(),
+    }
+    
+    Ok(())
+}
+
+/// Local port of the proxy client unless `--proxy-port` or a profile says otherwise.
+const DEFAULT_PROXY_HOST: &str = "127.0.0.1";
+
+/// How long the proxy client gets to start listening.
+const PROXY_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait between attempts to connect to the proxy client while it starts.
+const PROXY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// HEADs sent to check the bazaar answers through the proxy before giving up.
+const PROBE_ATTEMPTS: u32 = 3;
+
+/// How long to wait for the answer to each of them.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Ctrl-C cancels the command in flight. With none in flight it quits, as it
+/// would without a handler.
+fn listen_for_interrupts() -> Arc<Interrupt> {
+    let interrupt = Arc::new(Interrupt::default());
+    let handler = interrupt.clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if handler.in_flight() {
+                handler.cancel();
+            } else {
+                std::process::exit(130);
+            }
+        }
+    });
+    interrupt
+}
+
+/// The command line frontend to a [`BazaarClient`], which it derefs to.
 struct Client {
-    verbose: bool,
-    log_file: Option<PathBuf>,
-    server_address: Recipient,
+    bazaar: BazaarClient,
+    bazaars: AddressBook,
+    plain: bool,
+    output: Output,
+    export: Option<PathBuf>,
+    /// Where `image` saves listing images.
+    downloads: Option<PathBuf>,
+    /// Whether to save the menu's history.
+    history: bool,
+    prefetch: usize,
+    /// Bytes.
+    prefetch_budget: usize,
+}
+
+impl Deref for Client {
+    type Target = BazaarClient;
+    
+    fn deref(&self) -> &BazaarClient {
+        &self.bazaar
+    }
+}
+
+impl DerefMut for Client {
+    fn deref_mut(&mut self) -> &mut BazaarClient {
+        &mut self.bazaar
+    }
 }
 
 impl Client {
-    fn new(args: Args) -> Result<Self> {
-        let server_address = Recipient::try_from_base58_string(&args.bazaar_id)
+    fn new(args: &Args, bazaars: AddressBook) -> Result<Self> {
+        let Some(bazaar_id) = &args.bazaar_id else {
+            bail!("No bazaar address: pass --bazaar-id or set bazaar_id in a profile of the client config");
+        };
+        let server_address = Recipient::try_from_base58_string(bazaars.resolve(bazaar_id)?)
             .context("Invalid bazaar server address")?;
+        let proxy_host = args.proxy_host.clone().unwrap_or_else(|| DEFAULT_PROXY_HOST.to_string());
+        let proxy_port = match args.proxy_port {
+            Some(port) => port,
+            None => free_port(&proxy_host)?,
+        };
+        
+        let mut bazaar = BazaarClient::new(server_address, &proxy_host, proxy_port);
+        bazaar.nym_env = args.nym_env.clone();
+        bazaar.lang = args.lang;
+        bazaar.currency = args.currency.clone();
+        bazaar.rates = match args.currency {
+            Some(_) => args.rates.clone().or_else(default_rates).map(|path| LocalRates::load(&path)).transpose()?,
+            None => None,
+        };
+        bazaar.region = args.region.clone();
+        bazaar.token = args.token.clone();
+        bazaar.cache = match args.cache_ttl {
+            Some(0) => None,
+            ttl => cache_dir().map(|dir| Arc::new(ResponseCache::new(dir, ttl.map_or(cache::DEFAULT_TTL, Duration::from_secs)))),
+        };
+        bazaar.offline = args.offline;
+        bazaar.timeout = args.timeout.map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+        bazaar.interrupt = listen_for_interrupts();
+        bazaar.countdown = Some(Box::new(|deadline| Box::new(Spinner::countdown("Waiting for the bazaar, Ctrl-C to cancel...", deadline))));
         
         Ok(Self {
-            verbose: args.verbose,
-            log_file: args.log,
-            server_address,
+            bazaar,
+            bazaars,
+            // The full-screen UI needs a terminal to draw on
+            plain: args.plain || !io::stdout().is_terminal(),
+            output: args.output,
+            export: args.export.clone(),
+            downloads: args.downloads.clone().or_else(downloads_dir),
+            history: !args.no_history,
+            prefetch: args.prefetch.unwrap_or(prefetch::DEFAULT_CONCURRENCY),
+            prefetch_budget: args.prefetch_budget.map_or(prefetch::DEFAULT_BUDGET, |kib| kib.saturating_mul(1024)),
         })
     }
     
-    fn log(&self, message: &str) {
-        if self.verbose {
-            println!("[LOG] {}", message);
-        }
-        
-        if let Some(log_path) = &self.log_file {
-            if let Ok(mut file) = OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(log_path) 
-            {
-                let _ = writeln!(file, "{}", message);
+    async fn wait_for_proxy(&self, proxy: Option<&JoinHandle<()>>) -> Result<TcpStream> {
+        let spinner = Spinner::start("Starting the proxy client...");
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            if proxy.is_some_and(JoinHandle::is_finished) {
+                bail!("The proxy client stopped before it was ready");
             }
+            let mut stream = match TcpStream::connect(self.proxy_addr()).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if started.elapsed() > PROXY_START_TIMEOUT {
+                        bail!("The proxy client isn't listening on {}:{} after {}s: {}", self.proxy_host, self.proxy_port, PROXY_START_TIMEOUT.as_secs(), e);
+                    }
+                    tokio::time::sleep(PROXY_POLL_INTERVAL).await;
+                    continue;
+                },
+            };
+            
+            spinner.set(&format!("Reaching the bazaar through the mixnet (try {} of {})...", attempt, PROBE_ATTEMPTS));
+            match tokio::time::timeout(PROBE_TIMEOUT, probe(&mut stream)).await {
+                Ok(Ok(response)) => {
+                    if response != "OK" {
+                        bail!("Failed to connect to bazaar server: {}", response);
+                    }
+                    debug!(elapsed_ms = started.elapsed().as_millis() as u64, "Proxy client ready");
+                    return Ok(stream);
+                },
+                Ok(Err(e)) => warn!(attempt, "No answer to HEAD: {:#}", e),
+                Err(_) => warn!(attempt, "No answer to HEAD within {}s", PROBE_TIMEOUT.as_secs()),
+            }
+            if attempt == PROBE_ATTEMPTS {
+                bail!("The bazaar didn't answer through the mixnet after {} tries; it may be offline, or the mixnet unreachable", PROBE_ATTEMPTS);
+            }
+            // A new connection, so a late answer can't be taken for the next one
+            attempt += 1;
         }
     }
     
-    async fn connect_to_mixnet(&self, temp_dir: &str) -> Result<NymProxyClient> {
-        self.log("Connecting to NYM mixnet...");
+    /// Runs a single command for a one-shot invocation and prints its result,
+    /// failing if the server answers with an error.
+    async fn run_once(&self, transport: Transport, request: &Request) -> Result<ExitCode> {
+        let mut conn = Connection::new(transport);
         
-        let proxy_client = NymProxyClient::new(
-            self.server_address,
-            "127.0.0.1",
-            "9050",  // Local port for SOCKS proxy
-            60,      // Timeout in seconds
-            None,    // Env path (None for default network)
-            1,       // Client pool reserve
-        ).await?;
+        // Skip HELLO and LANG unless they're needed, each is a trip through the mixnet.
+        // Requests of several steps read the replies in between, so they need JSON
+        let mut capabilities = None;
+        let steps = !matches!(request, Request::Command(_));
+        if self.offline {
+            conn.encoding = Encoding::Json;
+        } else if self.output != Output::Plain || self.export.is_some() || self.region.is_some() || steps {
+            let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+            let response = self.send_command(&mut conn, &hello).await?;
+            if parse_error(&response).is_some() {
+                bail!("This server can't answer in JSON, which --output json, --output csv, --export, --region, sign, sell update and sell upload-image need");
+            }
+            capabilities = capability::from_hello(&response);
+            conn.encoding = Encoding::Json;
+        } else if self.rates.is_some() {
+            // Converting prices with the local rates needs JSON, but can do without
+            let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+            let response = self.send_command(&mut conn, &hello).await?;
+            if parse_error(&response).is_none() {
+                capabilities = capability::from_hello(&response);
+                conn.encoding = Encoding::Json;
+            }
+        }
+        if let Some(lang) = self.lang.filter(|_| conn.encoding == Encoding::Text && !self.offline) {
+            let response = self.send_command(&mut conn, &Command::Lang { lang }).await?;
+            if let Some((code, message)) = parse_error(&response) {
+                bail!("Failed to set the language: error {} {}", code, message);
+            }
+        }
+        
+        if let Some(key) = self.token.as_ref().filter(|_| !self.offline) {
+            let text = self.send_command(&mut conn, &Command::Auth { key: key.clone() }).await?;
+            if parse_error(&text).is_some() {
+                self.print_error(&text);
+                return Ok(ExitCode::FAILURE);
+            }
+        }
         
-        self.log("Connected to NYM mixnet");
+        let command = match self.prepare(&mut conn, request, capabilities.as_deref()).await {
+            Ok(Some(command)) => command,
+            Ok(None) => {
+                println!("Nothing to change");
+                return Ok(ExitCode::SUCCESS);
+            },
+            Err(e) => return self.fail(e),
+        };
+        
+        let frame = self.exchange(&mut conn, &command).await?;
+        if let Some(path) = &self.export {
+            // Errors are reported below, as without --export
+            let response = self.decode(conn.encoding, &frame)?;
+            if !matches!(response, Response::Error(_)) {
+                export::write(path, &response)?;
+                info!(path = %path.display(), "Exported the result");
+            }
+        }
+        if self.output == Output::Plain {
+            let text = self.render(conn.encoding, &frame)?;
+            if parse_error(&text).is_some() {
+                self.print_error(&text);
+                return Ok(ExitCode::FAILURE);
+            }
+            println!("{}", text.trim_end());
+            return Ok(ExitCode::SUCCESS);
+        }
         
-        Ok(proxy_client)
+        let response = self.decode(conn.encoding, &frame)?;
+        if let Response::Error(e) = &response {
+            // Scripts reading JSON get the error as JSON too
+            if self.output == Output::Json {
+                println!("{}", String::from_utf8_lossy(&response.encode(Encoding::Json, Lang::default())?));
+            }
+            self.print_error(&e.to_string());
+            return Ok(ExitCode::FAILURE);
+        }
+        match (self.output, response.table()) {
+            (Output::Csv, Some(table)) => print!("{}", table.to_csv()),
+            (Output::Csv, None) => bail!("The reply to {} has no CSV form", command.name()),
+            _ => println!("{}", String::from_utf8_lossy(&response.encode(Encoding::Json, Lang::default())?)),
+        }
+        Ok(ExitCode::SUCCESS)
+    }
+    
+    /// Runs the steps of `request` before its last command and returns that
+    /// command, or `None` if there's nothing left to send.
+    async fn prepare(&self, conn: &mut Connection, request: &Request, capabilities: Option<&[String]>) -> Result<Option<Command>> {
+        match request {
+            Request::Command(command) => Ok(Some(command.clone())),
+            // Run on their own, see `run`
+            Request::Watch { .. } => bail!("watch run isn't a one-shot command"),
+            Request::Image { .. } => bail!("image isn't a single command"),
+            Request::Address { .. } => bail!("sell address isn't a single command"),
+            Request::Script(_) => bail!("a script isn't a single command"),
+            Request::Sign { id, identity } => {
+                // The signature covers the listing as the server has it now
+                let item = self.fetch(conn, id).await?;
+                Ok(Some(Command::Sign { id: item.id.clone(), signature: identity.sign(&listing_payload(&item)) }))
+            },
+            Request::Ship { id, address } => {
+                let item = self.fetch(conn, id).await?;
+                let seller = match self.call(conn, &Command::Seller(SellerCommand::Info { name: item.seller.clone() })).await? {
+                    Response::Seller(info) => info,
+                    other => bail!("Unexpected reply to SELLER INFO: {}", other),
+                };
+                let drop_id = uuid::Uuid::new_v4().simple().to_string();
+                let ciphertext = shipping::seal(&seller.public_key, address)?;
+                println!("Sealed to {}'s registered key; give them the drop ID {}", seller.name, drop_id);
+                Ok(Some(Command::Drop(DropCommand::Put { id: drop_id, ciphertext })))
+            },
+            Request::Update { id, file } => {
+                let item = self.fetch(conn, id).await?;
+                let mut changes = match file {
+                    Some(file) => file.changes(&item)?,
+                    None => seller::prompt_changes(&item)?,
+                };
+                let Some((field, value)) = changes.pop() else {
+                    return Ok(None);
+                };
+                // Every edit names the revision it applies to
+                let mut revision = item.revision;
+                for (field, value) in changes {
+                    match self.call(conn, &Command::Update { id: item.id.clone(), revision, field, value }).await? {
+                        Response::Updated { revision: updated, .. } => revision = updated,
+                        other => bail!("Unexpected reply to UPDATE: {}", other),
+                    }
+                }
+                Ok(Some(Command::Update { id: item.id, revision, field, value }))
+            },
+            Request::PutImage { id, image } => {
+                let uploads = capabilities.is_some_and(|list| list.iter().any(|c| c == capability::UPLOADS));
+                if image.len() <= upload::CHUNK_SIZE || !uploads {
+                    return Ok(Some(Command::PutImage { id: id.clone(), data: ImageData::Inline(media::encode(image)) }));
+                }
+                
+                let size = image.len() as u64;
+                let begin = UploadCommand::Begin { size, hash: media::hash(image) };
+                let status = match self.call(conn, &Command::Upload(begin)).await? {
+                    Response::Upload(status) => status,
+                    other => bail!("Unexpected reply to UPLOAD: {}", other),
+                };
+                for chunk in status.missing {
+                    let start = chunk * upload::CHUNK_SIZE;
+                    let len = upload::chunk_len(size, chunk).context("Server asked for a chunk past the end")?;
+                    info!(chunk, chunks = status.chunks, "Uploading image");
+                    let data = media::encode(&image[start..start + len]);
+                    self.call(conn, &Command::Upload(UploadCommand::Chunk { id: status.id.clone(), chunk, data })).await?;
+                }
+                Ok(Some(Command::PutImage { id: id.clone(), data: ImageData::Upload(status.id) }))
+            },
+        }
+    }
+    
+    /// Fetches listing `id` as the server has it.
+    async fn fetch(&self, conn: &mut Connection, id: &str) -> Result<Item> {
+        match self.call(conn, &Command::Get { id: id.to_string(), currency: None, range: None }).await? {
+            Response::Item(item) => Ok(*item),
+            other => bail!("Unexpected reply to GET: {}", other),
+        }
+    }
+    
+    /// Sends `command` and decodes the reply, failing with the server's error
+    /// if it answers with one.
+    async fn call(&self, conn: &mut Connection, command: &Command) -> Result<Response> {
+        match self.request(conn, command).await? {
+            Response::Error(e) => Err(e.into()),
+            response => Ok(response),
+        }
+    }
+    
+    /// Prints a server error from `e` and fails with status 1; other errors
+    /// are passed on.
+    fn fail(&self, e: anyhow::Error) -> Result<ExitCode> {
+        match e.downcast_ref::<BazaarError>() {
+            Some(e) => {
+                self.print_error(&e.to_string());
+                Ok(ExitCode::FAILURE)
+            },
+            None => Err(e),
+        }
+    }
+    
+    /// Prints an `ERR <code> <message>` reply to stderr.
+    fn print_error(&self, response: &str) {
+        if let Some((code, message)) = parse_error(response) {
+            eprintln!("Error {}: {}", code, message.replace('_', " "));
+        }
+    }
+    
+    /// Prints a server response, turning `ERR <code> <message>` replies into readable errors.
+    fn print_response(&self, response: &str) {
+        match parse_error(response) {
+            Some((code, message)) => println!("Error {}: {}", code, message.replace('_', " ")),
+            None => println!("{}", response),
+        }
     }
     
-    async fn send_command(&self, stream: &mut TcpStream, command: &str) -> Result<String> {
-        self.log(&format!("Sending command: {}", command.trim()));
+    /// Runs the UI until the user quits, or picks another saved bazaar to
+    /// switch to, whose address is returned.
+    async fn run_ui(&self, transport: Transport) -> Result<Option<Recipient>> {
+        let mut conn = Connection::new(transport);
         
-        stream.write_all(command.as_bytes()).await?;
+        if self.offline {
+            conn.encoding = Encoding::Json;
+            if self.plain {
+                return self.run_menu(&mut conn, None).await;
+            }
+            return tui::run(self, &mut conn, None).await;
+        }
         
-        let mut buffer = vec![0u8; 4096];
-        let n = stream.read(&mut buffer).await?;
+        // Initial connection check, which waiting for the proxy client did already
+        if !matches!(conn.transport, Transport::Proxy(_)) {
+            let response = self.send_command(&mut conn, &Command::Head).await?;
+            if response.trim() != "OK" {
+                println!("Failed to connect to bazaar server: {}", response);
+                return Ok(None);
+            }
+        }
         
-        let response = String::from_utf8_lossy(&buffer[..n]).to_string();
-        self.log(&format!("Received response: {} bytes", response.len()));
+        // Ask for compressed responses; servers that predate HELLO just reject it.
+        // The full-screen UI and filtering by region need the listings themselves,
+        // so ask for JSON then. So do the cache, which only keeps JSON, and
+        // converting prices with local rates
+        let mut plain = self.plain;
+        let encoding = (!plain || self.region.is_some() || self.cache.is_some() || self.rates.is_some()).then_some(Encoding::Json);
+        let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding, session: None };
+        let response = self.send_command(&mut conn, &hello).await?;
+        debug!(response = response.trim(), "Negotiated session");
+        match (encoding, parse_error(&response)) {
+            (Some(encoding), None) => conn.encoding = encoding,
+            (Some(_), Some(_)) => {
+                if self.region.is_some() {
+                    println!("This server can't filter by region, showing every listing");
+                }
+                if !plain {
+                    println!("This server only answers in text, using the plain menu");
+                    plain = true;
+                }
+            },
+            (None, _) => {},
+        }
+        let capabilities = capability::from_hello(&response);
+        
+        if let Some(lang) = self.lang {
+            let response = self.send_command(&mut conn, &Command::Lang { lang }).await?;
+            if parse_error(&response).is_some() {
+                self.print_response(&response);
+            }
+        }
         
-        Ok(response)
+        if plain {
+            self.run_menu(&mut conn, capabilities.as_deref()).await
+        } else {
+            tui::run(self, &mut conn, capabilities.as_deref()).await
+        }
     }
     
-    async fn run_ui(&self, mut stream: TcpStream) -> Result<()> {
-        // Initial connection check
-        let response = self.send_command(&mut stream, "HEAD\n").await?;
-        if response.trim() != "OK" {
-            println!("Failed to connect to bazaar server: {}", response);
-            return Ok(());
-        }
+    /// The numbered menu of `--plain`.
+    async fn run_menu(&self, conn: &mut Connection, capabilities: Option<&[String]>) -> Result<Option<Recipient>> {
+        // Servers that don't advertise capabilities get every menu entry
+        let offers = |name: &str| capabilities.is_none_or(|list| list.iter().any(|c| c == name));
         
         println!("\n🏪 Welcome to NymBazaar - Vintage Collectibles Marketplace 🏪");
         println!("Connected to server via NYM mixnet");
+        let mut editor = Editor::new(self.history);
+        // Categories cached by an earlier run complete before any are asked for
+        let mut seen = Completions::default();
+        let cached = self.cache.as_ref().and_then(|cache| cache.get(&self.server_address, &Command::Categories, true));
+        if let Some(response) = cached.and_then(|frame| Response::decode(&frame, Encoding::Json).ok()) {
+            seen.note(&response);
+        }
         
         // Main UI loop
         loop {
             println!("\n📋 Menu:");
             println!("1. List all items");
             println!("2. List by category");
-            println!("3. Search items");
+            if offers(capability::SEARCH) {
+                println!("3. Search items");
+            }
             println!("4. View item details");
             println!("5. Show categories");
-            println!("6. Exit");
-            
-            print!("\nSelect an option: ");
-            io::stdout().flush()?;
+            println!("6. Server status");
+            println!("7. Featured items");
+            println!("8. Random item");
+            println!("9. Exit");
+            if !self.bazaars.is_empty() {
+                println!("b. Switch bazaar");
+            }
             
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            let Some(input) = editor.read_line("\nSelect an option: ")? else {
+                break;
+            };
             
             match input.trim() {
                 "1" => {
                     println!("\n📦 All Items:");
-                    let response = self.send_command(&mut stream, "LIST\n").await?;
-                    println!("{}", response);
+                    self.show(conn, &Command::List { category: None, currency: self.currency.clone(), price: None }, &mut seen).await?;
                 },
                 "2" => {
                     println!("\nFirst, let's get available categories:");
-                    let cats = self.send_command(&mut stream, "CATEGORIES\n").await?;
-                    println!("{}", cats);
+                    self.show(conn, &Command::Categories, &mut seen).await?;
                     
-                    print!("Enter category: ");
-                    io::stdout().flush()?;
-                    let mut cat = String::new();
-                    io::stdin().read_line(&mut cat)?;
+                    let cat = editor.read_line_with("Enter category: ", &seen.categories())?.unwrap_or_default();
+                    editor.add_history(&cat);
                     
                     println!("\n📦 Items in category '{}':", cat.trim());
-                    let response = self.send_command(&mut stream, &format!("LIST {}\n", cat.trim())).await?;
-                    println!("{}", response);
+                    self.show(conn, &Command::List { category: Some(cat.trim().to_string()), currency: self.currency.clone(), price: None }, &mut seen).await?;
                 },
-                "3" => {
-                    print!("Enter search term: ");
-                    io::stdout().flush()?;
-                    let mut term = String::new();
-                    io::stdin().read_line(&mut term)?;
+                "3" if offers(capability::SEARCH) => {
+                    let categories: Vec<String> = seen.categories().iter().map(|category| format!("category:{}", category)).collect();
+                    let term = editor.read_line_with("Enter search (e.g. synth category:synthesizer price:100..500 sort:price): ", &categories)?.unwrap_or_default();
+                    editor.add_history(&term);
                     
                     println!("\n🔍 Search results for '{}':", term.trim());
-                    let response = self.send_command(&mut stream, &format!("SEARCH {}\n", term.trim())).await?;
-                    println!("{}", response);
+                    let query = match term.parse() {
+                        Ok(query) => query,
+                        Err(e) => {
+                            self.print_response(&Response::Error(e).to_string());
+                            continue;
+                        }
+                    };
+                    self.show(conn, &Command::Search { query, currency: self.currency.clone() }, &mut seen).await?;
                 },
                 "4" => {
-                    print!("Enter item ID: ");
-                    io::stdout().flush()?;
-                    let mut id = String::new();
-                    io::stdin().read_line(&mut id)?;
+                    let id = editor.read_line_with("Enter item ID or number: ", &seen.items())?.unwrap_or_default();
+                    editor.add_history(&id);
                     
                     println!("\n📋 Item details:");
-                    let response = self.send_command(&mut stream, &format!("GET {}\n", id.trim())).await?;
-                    println!("{}", response);
+                    self.show(conn, &Command::Get { id: id.trim().to_string(), currency: self.currency.clone(), range: None }, &mut seen).await?;
                 },
                 "5" => {
                     println!("\n🏷️ Categories:");
-                    let response = self.send_command(&mut stream, "CATEGORIES\n").await?;
-                    println!("{}", response);
+                    self.show(conn, &Command::Categories, &mut seen).await?;
                 },
                 "6" => {
+                    println!("\n📡 Server status:");
+                    self.show(conn, &Command::Status, &mut seen).await?;
+                },
+                "7" => {
+                    println!("\n⭐ Featured:");
+                    self.show(conn, &Command::Featured, &mut seen).await?;
+                },
+                "8" => {
+                    println!("\n🎲 Random pick:");
+                    self.show(conn, &Command::Random { category: None }, &mut seen).await?;
+                },
+                "9" => {
                     println!("Thank you for using NymBazaar! Goodbye.");
                     break;
                 },
+                "b" if !self.bazaars.is_empty() => {
+                    if let Some(address) = self.pick_bazaar(&editor)? {
+                        return Ok(Some(address));
+                    }
+                },
                 _ => println!("Invalid option. Please try again."),
             }
         }
         
+        Ok(None)
+    }
+    
+    /// Sends `command` for the menu and prints the reply, noting the categories
+    /// and items in it in `seen`. A command that timed out or was cancelled, or
+    /// isn't cached offline, is only reported, so the menu carries on.
+    async fn show(&self, conn: &mut Connection, command: &Command, seen: &mut Completions) -> Result<()> {
+        let frame = match self.exchange(conn, command).await {
+            Ok(frame) => frame,
+            Err(e) if e.is::<Abandoned>() || e.is::<NotCached>() => {
+                println!("{}", e);
+                return Ok(());
+            },
+            Err(e) => return Err(e),
+        };
+        if conn.encoding != Encoding::Text {
+            if let Ok(response) = self.decode(conn.encoding, &frame) {
+                seen.note(&response);
+            }
+        }
+        self.print_response(&self.render(conn.encoding, &frame)?);
         Ok(())
     }
+    
+    /// Asks which saved bazaar to switch to; nothing if the user picks none.
+    fn pick_bazaar(&self, editor: &Editor) -> Result<Option<Recipient>> {
+        let bazaars: Vec<(&str, &str)> = self.bazaars.iter().collect();
+        println!("\n🏪 Saved bazaars:");
+        for (i, (name, _)) in bazaars.iter().enumerate() {
+            println!("{}. {}", i + 1, name);
+        }
+        let input = editor.read_line("Switch to (empty to stay): ")?.unwrap_or_default();
+        
+        let Some((_, address)) = input.trim().parse::<usize>().ok().and_then(|n| bazaars.get(n.checked_sub(1)?)) else {
+            return Ok(None);
+        };
+        Ok(Some(Recipient::try_from_base58_string(*address).context("Invalid saved bazaar address")?))
+    }
+}
+
+/// A mixnet client for `--direct`, set up from the nym flags. It's ephemeral
+/// unless `--persistent` asks to keep its keys in `<data_dir>/mixnet-client`.
+async fn connect_direct(args: &Args) -> Result<MixnetClient> {
+    let mut debug = DebugConfig::default();
+    if args.low_latency {
+        debug.cover_traffic.disable_loop_cover_traffic_stream = true;
+        debug.traffic.disable_main_poisson_packet_distribution = true;
+    }
+    // main has loaded the env file by now
+    let network = args.nym_env.as_ref().map(|_| NymNetworkDetails::new_from_env());
+    // The builders differ in type with their storage, so each is set up on its own
+    if args.persistent {
+        let mut builder = MixnetClientBuilder::new_with_default_storage(StoragePaths::new_from_dir(identity_dir()?)?)
+            .await?
+            .debug_config(debug);
+        if let Some(network) = network {
+            builder = builder.network_details(network);
+        }
+        if let Some(gateway) = &args.gateway {
+            builder = builder.request_gateway(gateway.clone());
+        }
+        return Ok(builder.build()?.connect_to_mixnet().await?);
+    }
+    let mut builder = MixnetClientBuilder::new_ephemeral().debug_config(debug);
+    if let Some(network) = network {
+        builder = builder.network_details(network);
+    }
+    if let Some(gateway) = &args.gateway {
+        builder = builder.request_gateway(gateway.clone());
+    }
+    Ok(builder.build()?.connect_to_mixnet().await?)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+/// Where `--persistent` keeps the mixnet client's keys, created readable only
+/// by the user.
+fn identity_dir() -> Result<PathBuf> {
+    let Some(dir) = data_dir().map(|dir| dir.join("mixnet-client")) else {
+        bail!("Can't find a data directory for the mixnet client's keys; set HOME");
+    };
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    #[cfg(unix)]
+    std::fs::set_permissions(&dir, std::os::unix::fs::PermissionsExt::from_mode(0o700))?;
+    Ok(dir)
+}
+
+/// Sets the `KEY=value` lines of a network env file as environment variables, as
+/// the proxy client does with its env path. Only call it before the runtime
+/// starts, while nothing else can be reading the environment.
+fn load_env(path: &str) -> Result<()> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read nym env file {}", path))?;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("Invalid line in nym env file {}: {}", path, line);
+        };
+        std::env::set_var(key.trim(), value.trim().trim_matches('"'));
+    }
+    Ok(())
+}
+
+fn main() -> Result<ExitCode> {
+    let mut args = Args::parse();
+    let profile = ClientConfig::load(args.config.as_deref())?.profile(args.profile.as_deref())?;
+    apply_profile(&mut args, profile)?;
+    // Sets environment variables, which is only safe before the runtime starts its threads
+    if let (true, Some(env_path)) = (args.direct, &args.nym_env) {
+        load_env(env_path)?;
+    }
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the async runtime")?
+        .block_on(start(args))
+}
+
+/// Everything after argument parsing, on the runtime.
+async fn start(args: Args) -> Result<ExitCode> {
+    init_logging(&args)?;
     
-    let client = Client::new(args)?;
+    match &args.command {
+        Some(Commands::Bazaar(command)) => {
+            manage_bazaars(command)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Identity(command)) => {
+            manage_identity(command)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Qr(command)) => {
+            print_qr(command, args.bazaar_id.as_deref(), args.plain)?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Completions { shell }) => {
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut Args::command(), "nymbazaar-client", &mut script);
+            // Piped into head and the like, which stop reading early
+            match io::Write::write_all(&mut io::stdout(), &script) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+                _ => return Ok(ExitCode::SUCCESS),
+            }
+        },
+        Some(Commands::Man { dir }) => {
+            write_man_pages(dir.as_deref())?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Daemon { action: Some(command) }) => {
+            manage_daemon(command).await?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Sell(SellCommand::Template)) => {
+            print!("{}", seller::TEMPLATE);
+            return Ok(ExitCode::SUCCESS);
+        },
+        Some(Commands::Watch(command)) if !matches!(command, WatchCommand::Run { .. }) => {
+            manage_watches(command, args.bazaar_id.as_deref())?;
+            return Ok(ExitCode::SUCCESS);
+        },
+        _ => {},
+    }
     
-    // Use a temporary directory for the client
-    let temp_dir = format!("/tmp/nymbazaar-client-{}", uuid::Uuid::new_v4());
-    std::fs::create_dir_all(&temp_dir)?;
+    let bazaars = AddressBook::load()?;
+    let watching = match (&args.command, &args.bazaar_id) {
+        (Some(Commands::Watch(WatchCommand::Run { interval, once })), Some(bazaar_id)) => Some(Request::Watch {
+            bazaar: bazaars.resolve(bazaar_id)?.to_string(),
+            interval: Duration::from_secs(*interval),
+            once: *once,
+        }),
+        _ => None,
+    };
+    let mut client = Client::new(&args, bazaars)?;
+    if let Some(Commands::Daemon { action: None }) = &args.command {
+        if args.direct || args.offline {
+            bail!("The daemon keeps proxy clients running, which --direct and --offline don't use");
+        }
+        return daemon::run(&client).await;
+    }
+    // Polling has to ask the server, not the cache
+    if watching.is_some() {
+        client.cache = None;
+    }
     
-    // Start the proxy client
-    let proxy_client = client.connect_to_mixnet(&temp_dir).await?;
+    // Catch bad arguments, read files, ask for input and unlock keys before
+    // spending time on the mixnet
+    let request = match &args.command {
+        Some(Commands::OneShot(one_shot)) => Some(one_shot.request(args.currency.as_ref())),
+        Some(Commands::Sell(sell)) => Some(sell.request(args.seller.as_deref(), args.currency.as_ref())),
+        _ => watching.map(Ok),
+    };
+    let request = match (&args.script, request) {
+        (Some(_), Some(_)) => bail!("--script runs instead of a command, not with one"),
+        (Some(path), None) => Some(Script::load(path).map(Request::Script)),
+        (None, request) => request,
+    };
+    let request = match request.transpose() {
+        Ok(request) => request,
+        Err(e) => return client.fail(e),
+    };
+    if client.offline && !request.as_ref().is_none_or(|request| matches!(request, Request::Command(command) if ResponseCache::caches(command))) {
+        bail!("Only listings, searches, categories and models can be shown --offline");
+    }
+    if let Some(cache) = &client.cache {
+        cache.prune();
+    }
     
-    // Run proxy client in background
-    let _proxy_handle = tokio::spawn(async move {
-        if let Err(e) = proxy_client.run().await {
-            eprintln!("Proxy client error: {}", e);
+    // Switching bazaars in the UI starts over with the new address
+    loop {
+        match connect_and_run(&mut client, &args, request.as_ref()).await? {
+            Outcome::Done(status) => return Ok(status),
+            Outcome::Switch(address) => client.server_address = address,
         }
-    });
+    }
+}
+
+/// How a session with one bazaar ended.
+enum Outcome {
+    Done(ExitCode),
+    /// The user picked another saved bazaar.
+    Switch(Recipient),
+}
+
+/// Connects to the client's bazaar and runs the one-shot command or the UI.
+async fn connect_and_run(client: &mut Client, args: &Args, request: Option<&Request>) -> Result<Outcome> {
+    if client.offline {
+        return Ok(run(client, Transport::Offline, request).await);
+    }
     
-    // Wait for proxy to start
-    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+    if args.direct {
+        info!("Connecting to NYM mixnet...");
+        let mixnet_client = connect_direct(args).await.context("Failed to connect to the mixnet")?;
+        info!("Connected to NYM mixnet");
+        return Ok(run(client, Transport::Direct(mixnet_client), request).await);
+    }
     
-    // Connect to local proxy socket
-    let stream = match TcpStream::connect("127.0.0.1:9050").await {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Failed to connect to proxy: {}", e);
-            return Ok(());
+    // A running daemon's proxy client is already through the mixnet startup,
+    // unless a port was asked for
+    if args.proxy_port.is_none() {
+        if let Some((host, port)) = daemon::proxy_for(client.server_address).await {
+            debug!(host, port, "Using the daemon's proxy client");
+            client.proxy_host = host;
+            client.proxy_port = port;
+            return Ok(match client.wait_for_proxy(None).await {
+                Ok(stream) => run(client, Transport::Proxy(stream), request).await,
+                Err(e) => {
+                    error!("{:#}", e);
+                    Outcome::Done(ExitCode::FAILURE)
+                }
+            });
         }
-    };
-    
-    // Run the UI
-    if let Err(e) = client.run_ui(stream).await {
-        eprintln!("UI error: {}", e);
     }
     
-    // Clean up
-    std::fs::remove_dir_all(temp_dir).ok();
+    // Start the proxy client and run it in the background, stopped however
+    // this returns so the port is free for the next bazaar
+    let proxy_client = client.start_proxy(client.server_address, client.proxy_port).await?;
+    let proxy = ProxyTask(tokio::spawn(async move {
+        if let Err(e) = proxy_client.run().await {
+            error!("Proxy client error: {}", e);
+        }
+    }));
     
-    Ok(())
+    Ok(match client.wait_for_proxy(Some(&proxy.0)).await {
+        Ok(stream) => run(client, Transport::Proxy(stream), request).await,
+        Err(e) => {
+            error!("{:#}", e);
+            Outcome::Done(ExitCode::FAILURE)
+        }
+    })
+}
+
+/// The background task running a proxy client, aborted when dropped, a panic
+/// unwinding included.
+struct ProxyTask(JoinHandle<()>);
+
+impl Drop for ProxyTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Runs the one-shot command if there is one, the UI otherwise.
+async fn run(client: &Client, transport: Transport, request: Option<&Request>) -> Outcome {
+    let result = match request {
+        Some(Request::Watch { bazaar, interval, once }) => {
+            watchlist::run(client, Connection::new(transport), bazaar, *interval, *once).await.map(Outcome::Done)
+        },
+        Some(Request::Image { id, size, preview }) => match &client.downloads {
+            Some(dir) => images::run(client, Connection::new(transport), id, *size, dir, *preview).await.map(Outcome::Done),
+            None => Err(anyhow!("Can't find a downloads directory for the images; pass --downloads or set HOME")),
+        },
+        Some(Request::Script(script)) => script::run(client, Connection::new(transport), script).await.map(Outcome::Done),
+        Some(Request::Address { drop_id, identity }) => {
+            shipping::print_drop(client, Connection::new(transport), drop_id, identity).await.map(Outcome::Done)
+        },
+        Some(request) => client.run_once(transport, request).await.map(Outcome::Done),
+        None => client.run_ui(transport).await.map(|switch| switch.map_or(Outcome::Done(ExitCode::SUCCESS), Outcome::Switch)),
+    };
+    result.unwrap_or_else(|e| {
+        error!("{:#}", e);
+        Outcome::Done(ExitCode::FAILURE)
+    })
 }
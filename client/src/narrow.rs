@@ -0,0 +1,75 @@
+use bazaar_protocol::{Item, SortKey};
+use std::cmp::Reverse;
+
+/// Picks the listings of a page that match `filter` and orders them by `sort`,
+/// without asking the server again, and returns their positions in `items`.
+/// Every word of `filter` has to fuzzily match the name, category or seller;
+/// without a sort, the closest matches come first. `price_of` gives a
+/// listing's price in minor units, or `None` if it can't be read; such
+/// listings sort last by price.
+pub fn narrow(items: &[Item], filter: &str, sort: Option<SortKey>, price_of: impl Fn(&Item) -> Option<u64>) -> Vec<usize> {
+    let terms: Vec<String> = filter.split_whitespace().map(str::to_lowercase).collect();
+
+    let mut shown: Vec<(i64, usize)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            let fields = [item.name.to_lowercase(), item.category.to_lowercase(), item.seller.to_lowercase()];
+            let score = terms
+                .iter()
+                .map(|term| fields.iter().filter_map(|field| fuzzy_score(term, field)).max())
+                .sum::<Option<i64>>()?;
+            Some((score, i))
+        })
+        .collect();
+
+    let item = |&(_, i): &(i64, usize)| &items[i];
+    match sort {
+        Some(SortKey::Price) => shown.sort_by_cached_key(|entry| {
+            let price = price_of(item(entry));
+            (price.is_none(), price)
+        }),
+        Some(SortKey::PriceDesc) => shown.sort_by_cached_key(|entry| {
+            let price = price_of(item(entry));
+            (price.is_none(), Reverse(price))
+        }),
+        Some(SortKey::Newest) => shown.sort_by_key(|entry| Reverse(item(entry).created_at)),
+        Some(SortKey::Oldest) => shown.sort_by_key(|entry| item(entry).created_at),
+        Some(SortKey::Name) => shown.sort_by_cached_key(|entry| item(entry).name.to_lowercase()),
+        None => shown.sort_by_key(|&(score, _)| Reverse(score)),
+    }
+    shown.into_iter().map(|(_, i)| i).collect()
+}
+
+/// How well `pattern` matches `text`, both lowercase, if its characters appear
+/// in `text` in order. Characters next to each other and at the start of
+/// words score higher, gaps between them lower.
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    for c in pattern.chars() {
+        let at = next + text.get(next..)?.iter().position(|&t| t == c)?;
+        score += 1;
+        if at == 0 || !text[at - 1].is_alphanumeric() {
+            score += 3;
+        }
+        if next > 0 {
+            match at - next {
+                0 => score += 4,
+                gap => score -= gap.min(3) as i64,
+            }
+        }
+        next = at + 1;
+    }
+    Some(score)
+}
+
+/// The sort after `sort`, going through every [`SortKey`] and back to the
+/// order the server sent.
+pub fn next_sort(sort: Option<SortKey>) -> Option<SortKey> {
+    match sort {
+        None => SortKey::ALL.first().copied(),
+        Some(key) => SortKey::ALL.iter().skip_while(|&&k| k != key).nth(1).copied(),
+    }
+}
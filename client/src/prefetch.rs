@@ -0,0 +1,146 @@
+use anyhow::{bail, Result};
+use bazaar_protocol::codec::{self, FrameDecoder};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{Command, Compression, Encoding};
+use nym_sdk::mixnet::Recipient;
+use nymbazaar_client_lib::ResponseCache;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpStream;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+/// Listing details fetched at once in the background, unless `--prefetch` or a
+/// profile says otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 2;
+
+/// Bytes of replies prefetched per page of listings, unless `--prefetch-budget`
+/// or a profile says otherwise.
+pub const DEFAULT_BUDGET: usize = 512 * 1024;
+
+/// Fetches the details of listings the UI shows before they're opened, so
+/// opening one is answered from the [`ResponseCache`] instead of waiting on
+/// the mixnet.
+///
+/// Each worker has a connection of its own through the local proxy client,
+/// which the proxy sends over a mixnet session of its own, so fetches run
+/// alongside each other and alongside the UI's. Workers connect on their
+/// first fetch and stop with the prefetcher.
+pub struct Prefetcher {
+    shared: Arc<Shared>,
+    budget: usize,
+    _workers: JoinSet<()>,
+}
+
+struct Shared {
+    /// Host and port of the proxy client.
+    proxy: (String, u16),
+    bazaar: Recipient,
+    cache: Arc<ResponseCache>,
+    queue: Mutex<VecDeque<Command>>,
+    wake: Notify,
+    /// Bytes the current page may still fetch.
+    budget: AtomicUsize,
+}
+
+impl Prefetcher {
+    pub fn start(proxy: (&str, u16), bazaar: Recipient, cache: Arc<ResponseCache>, concurrency: usize, budget: usize) -> Self {
+        let shared = Arc::new(Shared {
+            proxy: (proxy.0.to_string(), proxy.1),
+            bazaar,
+            cache,
+            queue: Mutex::new(VecDeque::new()),
+            wake: Notify::new(),
+            budget: AtomicUsize::new(budget),
+        });
+        let mut workers = JoinSet::new();
+        for _ in 0..concurrency {
+            workers.spawn(worker(shared.clone()));
+        }
+        Self { shared, budget, _workers: workers }
+    }
+
+    /// Starts a new page of listings with a full budget.
+    pub fn new_page(&self) {
+        self.shared.budget.store(self.budget, Ordering::Relaxed);
+    }
+
+    /// Replaces whatever is still waiting to be fetched with `commands`. Ones
+    /// whose replies are cached already are skipped.
+    pub fn fetch(&self, commands: impl IntoIterator<Item = Command>) {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.clear();
+        queue.extend(commands.into_iter().filter(|command| !self.shared.cache.is_fresh(&self.shared.bazaar, command)));
+        drop(queue);
+        self.shared.wake.notify_waiters();
+    }
+}
+
+/// A connection to the proxy agreed on JSON replies, which the cache keeps.
+struct Connection {
+    stream: TcpStream,
+    decoder: FrameDecoder,
+}
+
+impl Connection {
+    async fn open(proxy: &(String, u16)) -> Result<Self> {
+        let mut conn = Self {
+            stream: TcpStream::connect((proxy.0.as_str(), proxy.1)).await?,
+            decoder: FrameDecoder::new(),
+        };
+        let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+        let reply = conn.exchange(&hello).await?;
+        if parse_error(&String::from_utf8_lossy(&reply)).is_some() {
+            bail!("The server can't answer in JSON");
+        }
+        Ok(conn)
+    }
+
+    async fn exchange(&mut self, command: &Command) -> Result<Vec<u8>> {
+        codec::write_request(&mut self.stream, command).await?;
+        match codec::read_frame(&mut self.stream, &mut self.decoder).await? {
+            Some(frame) => Ok(frame),
+            None => bail!("Connection closed by server"),
+        }
+    }
+}
+
+async fn worker(shared: Arc<Shared>) {
+    let mut conn = None;
+    loop {
+        // Waiting is registered before looking, so a fetch queued in between isn't missed
+        let wake = shared.wake.notified();
+        let next = shared.queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+        let Some(command) = next else {
+            wake.await;
+            continue;
+        };
+        if shared.budget.load(Ordering::Relaxed) == 0 || shared.cache.is_fresh(&shared.bazaar, &command) {
+            continue;
+        }
+
+        let mut open = match conn.take() {
+            Some(open) => open,
+            None => match Connection::open(&shared.proxy).await {
+                Ok(open) => open,
+                Err(e) => {
+                    debug!("Prefetching stopped: {:#}", e);
+                    return;
+                },
+            },
+        };
+        match open.exchange(&command).await {
+            Ok(frame) => {
+                let len = frame.len();
+                let _ = shared.budget.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| Some(left.saturating_sub(len)));
+                debug!(%command, bytes = len, "Prefetched");
+                shared.cache.put(&shared.bazaar, &command, &frame);
+                conn = Some(open);
+            },
+            // Dropped, the next fetch reconnects
+            Err(e) => debug!("Prefetch of {} failed: {:#}", command.name(), e),
+        }
+    }
+}
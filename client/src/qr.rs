@@ -0,0 +1,426 @@
+use anyhow::{bail, Result};
+
+/// Error correction codewords per block at level M, by version.
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28,
+];
+
+/// Error correction blocks at level M, by version.
+const ERROR_CORRECTION_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40,
+    43, 45, 47, 49,
+];
+
+/// Light modules around the code, as the standard asks for.
+const QUIET_ZONE: usize = 4;
+
+/// A QR code, for addresses and payment requests that are easier to scan with
+/// a phone than to copy across. Only what that takes is implemented: byte mode
+/// at error correction level M, in the smallest version the data fits.
+pub struct QrCode {
+    version: usize,
+    size: usize,
+    /// Row by row, `true` for dark.
+    modules: Vec<bool>,
+    /// Finder, timing, alignment, format and version modules, which masks skip.
+    function: Vec<bool>,
+}
+
+impl QrCode {
+    pub fn encode(data: &[u8]) -> Result<Self> {
+        let Some(version) = (1..=40).find(|&version| bits_needed(version, data.len()) <= data_codewords(version) * 8) else {
+            bail!("{} bytes are too many for a QR code", data.len());
+        };
+
+        let mut bits = Bits::default();
+        bits.push(0b0100, 4);
+        bits.push(data.len() as u32, if version < 10 { 8 } else { 16 });
+        for &byte in data {
+            bits.push(u32::from(byte), 8);
+        }
+        let capacity = data_codewords(version) * 8;
+        bits.push(0, (capacity - bits.len).min(4));
+        bits.push(0, (8 - bits.len % 8) % 8);
+        let mut codewords = bits.bytes;
+        for pad in [0xec, 0x11].into_iter().cycle() {
+            if codewords.len() * 8 >= capacity {
+                break;
+            }
+            codewords.push(pad);
+        }
+
+        let size = version * 4 + 17;
+        let mut qr = QrCode { version, size, modules: vec![false; size * size], function: vec![false; size * size] };
+        qr.draw_function_patterns();
+        qr.draw_codewords(&qr.add_error_correction(&codewords));
+
+        // Each mask is tried and undone again, the least penalised one stays
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                qr.apply_mask(mask);
+                qr.draw_format_bits(mask);
+                let penalty = qr.penalty();
+                qr.apply_mask(mask);
+                penalty
+            })
+            .unwrap_or_default();
+        qr.apply_mask(mask);
+        qr.draw_format_bits(mask);
+        Ok(qr)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.function[y * self.size + x] = true;
+    }
+
+    fn draw_function_patterns(&mut self) {
+        for i in 0..self.size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        let far = self.size - 4;
+        for (x, y) in [(3, 3), (far, 3), (3, far)] {
+            self.draw_finder(x, y);
+        }
+        let positions = alignment_positions(self.version);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // Not over the finders
+                if ![(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    self.draw_alignment(x, y);
+                }
+            }
+        }
+        // Reserved until a mask is picked
+        self.draw_format_bits(0);
+        self.draw_version();
+    }
+
+    /// A finder pattern centred on `x`, `y`, with its light separator.
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4..=4_isize {
+            for dx in -4..=4_isize {
+                let (Some(xx), Some(yy)) = (x.checked_add_signed(dx), y.checked_add_signed(dy)) else {
+                    continue;
+                };
+                if xx < self.size && yy < self.size {
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_function(xx, yy, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2..=2_isize {
+            for dx in -2..=2_isize {
+                self.set_function(x.wrapping_add_signed(dx), y.wrapping_add_signed(dy), dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    /// Level M and `mask`, BCH protected, next to the top left finder and
+    /// split between the other two.
+    fn draw_format_bits(&mut self, mask: usize) {
+        // Level M is 00
+        let data = mask as u32;
+        let mut rem = data;
+        for _ in 0..10 {
+            rem = (rem << 1) ^ ((rem >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | rem) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(self.size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, self.size - 15 + i, bit(i));
+        }
+        self.set_function(8, self.size - 8, true);
+    }
+
+    /// The version, BCH protected, for versions 7 and up.
+    fn draw_version(&mut self) {
+        if self.version < 7 {
+            return;
+        }
+        let mut rem = self.version as u32;
+        for _ in 0..12 {
+            rem = (rem << 1) ^ ((rem >> 11) * 0x1f25);
+        }
+        let bits = ((self.version as u32) << 12) | rem;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Splits the data codewords into blocks, appends each block's
+    /// Reed-Solomon codewords and interleaves them.
+    fn add_error_correction(&self, data: &[u8]) -> Vec<u8> {
+        let blocks = ERROR_CORRECTION_BLOCKS[self.version];
+        let ecc_len = ECC_CODEWORDS_PER_BLOCK[self.version];
+        let raw = raw_data_modules(self.version) / 8;
+        let short_blocks = blocks - raw % blocks;
+        let short_len = raw / blocks;
+        let divisor = reed_solomon_divisor(ecc_len);
+
+        let mut rest = data;
+        let mut all = Vec::new();
+        for i in 0..blocks {
+            let data_len = short_len - ecc_len + usize::from(i >= short_blocks);
+            let (block, after) = rest.split_at(data_len);
+            rest = after;
+            let mut block = block.to_vec();
+            let ecc = reed_solomon_remainder(&block, &divisor);
+            // Short blocks are padded to line up with the long ones
+            if i < short_blocks {
+                block.push(0);
+            }
+            block.extend(ecc);
+            all.push(block);
+        }
+
+        let mut interleaved = Vec::with_capacity(raw);
+        for i in 0..all[0].len() {
+            for (j, block) in all.iter().enumerate() {
+                if i != short_len - ecc_len || j >= short_blocks {
+                    interleaved.push(block[i]);
+                }
+            }
+        }
+        interleaved
+    }
+
+    /// Places the codewords in two-module columns zigzagging up and down
+    /// from the bottom right, around the function patterns.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        loop {
+            // The vertical timing pattern takes a column of its own
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vert in 0..self.size {
+                for x in [right, right - 1] {
+                    let y = if upward { self.size - 1 - vert } else { vert };
+                    if !self.function[y * self.size + x] && i < codewords.len() * 8 {
+                        self.modules[y * self.size + x] = (codewords[i / 8] >> (7 - i % 8)) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 3 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Flips the data modules `mask` picks; applying it twice undoes it.
+    fn apply_mask(&mut self, mask: usize) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if flip && !self.function[y * self.size + x] {
+                    self.modules[y * self.size + x] ^= true;
+                }
+            }
+        }
+    }
+
+    /// How hard the code is to read, by the standard's four rules: runs of
+    /// one colour, 2×2 blocks, finder-like patterns, and imbalance.
+    fn penalty(&self) -> usize {
+        let n = self.size;
+        let mut penalty = 0;
+        let lines = (0..n).flat_map(|i| {
+            let row: Vec<bool> = (0..n).map(|x| self.get(x, i)).collect();
+            let column: Vec<bool> = (0..n).map(|y| self.get(i, y)).collect();
+            [row, column]
+        });
+        const FINDER: [bool; 11] = [true, false, true, true, true, false, true, false, false, false, false];
+        for line in lines {
+            for run in line.chunk_by(|a, b| a == b).map(<[bool]>::len).filter(|&len| len >= 5) {
+                penalty += run - 2;
+            }
+            for window in line.windows(11) {
+                if window == FINDER || window.iter().rev().eq(FINDER.iter()) {
+                    penalty += 40;
+                }
+            }
+        }
+        for y in 0..n - 1 {
+            for x in 0..n - 1 {
+                let dark = self.get(x, y);
+                if self.get(x + 1, y) == dark && self.get(x, y + 1) == dark && self.get(x + 1, y + 1) == dark {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let total = n * n;
+        // 10 for every 5% away from half dark
+        penalty += (dark * 20).abs_diff(total * 10) / total * 10;
+        penalty
+    }
+
+    /// The code in half blocks, two rows of modules per line, dark on light.
+    /// With `colour`, light is painted white so it scans on dark terminals too.
+    pub fn render(&self, colour: bool) -> String {
+        let side = self.size + QUIET_ZONE * 2;
+        let dark = |x: usize, y: usize| {
+            let inside = |i: usize| (QUIET_ZONE..QUIET_ZONE + self.size).contains(&i);
+            inside(x) && inside(y) && self.get(x - QUIET_ZONE, y - QUIET_ZONE)
+        };
+        let mut out = String::new();
+        for y in (0..side).step_by(2) {
+            if colour {
+                out.push_str("\x1b[30;107m");
+            }
+            for x in 0..side {
+                out.push(match (dark(x, y), y + 1 < side && dark(x, y + 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            if colour {
+                out.push_str("\x1b[0m");
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if let Some(last) = self.bytes.last_mut().filter(|_| (value >> i) & 1 != 0) {
+                *last |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+}
+
+fn bits_needed(version: usize, len: usize) -> usize {
+    let count_bits = if version < 10 { 8 } else { 16 };
+    if len >= 1 << count_bits {
+        return usize::MAX;
+    }
+    4 + count_bits + len * 8
+}
+
+/// Modules left for data and error correction once the function patterns
+/// are drawn.
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * ERROR_CORRECTION_BLOCKS[version]
+}
+
+/// Centres of the alignment patterns along either axis.
+fn alignment_positions(version: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let size = version * 4 + 17;
+    let step = if version == 32 { 26 } else { (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2 };
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Coefficients of the generator polynomial for `degree` error correction
+/// codewords, highest first without the leading 1.
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1.
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1d);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
@@ -0,0 +1,359 @@
+use crate::config::config_dir;
+use anyhow::Result;
+use bazaar_protocol::{Item, Response};
+use ratatui::crossterm::cursor::MoveToColumn;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::queue;
+use ratatui::crossterm::terminal::{self, Clear, ClearType};
+use std::collections::BTreeSet;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Entries kept in the history file; older ones are dropped when it's loaded.
+const HISTORY_LEN: usize = 1000;
+
+/// Item IDs kept to complete, the most recently seen ones.
+const SEEN_ITEMS: usize = 500;
+
+/// Completions listed under the line at once, when Tab can't pick one.
+const LISTED: usize = 40;
+
+/// Reads lines for the numbered menu with the usual editing keys: arrows and
+/// Home/End (Ctrl-A/Ctrl-E) move, Up and Down go through earlier entries,
+/// Ctrl-R searches them, Ctrl-U clears the line and Tab completes the word
+/// before the cursor.
+///
+/// History is kept in `~/.config/nymbazaar/history` across runs, readable by
+/// the user only, unless it's turned off; then it only lasts the session.
+/// Without a terminal, lines are read as they come.
+pub struct Editor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+/// How editing a line ended.
+enum Input {
+    Line(String),
+    /// Ctrl-D on an empty line.
+    Eof,
+    /// Ctrl-C.
+    Interrupted,
+}
+
+/// Categories and item IDs seen in replies, to complete at the menu's prompts
+/// so a mistyped category doesn't just come back with no items.
+#[derive(Default)]
+pub struct Completions {
+    categories: BTreeSet<String>,
+    /// Oldest first.
+    items: Vec<String>,
+}
+
+impl Completions {
+    pub fn note(&mut self, response: &Response) {
+        let items: &[Item] = match response {
+            Response::Categories(categories) => {
+                self.categories.extend(categories.iter().cloned());
+                return;
+            },
+            Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => items,
+            Response::Item(item) => std::slice::from_ref(&**item),
+            _ => return,
+        };
+        for item in items {
+            self.categories.insert(item.category.clone());
+            self.items.retain(|id| *id != item.id);
+            self.items.push(item.id.clone());
+        }
+        self.items.drain(..self.items.len().saturating_sub(SEEN_ITEMS));
+    }
+
+    pub fn categories(&self) -> Vec<String> {
+        self.categories.iter().cloned().collect()
+    }
+
+    /// Most recently seen first.
+    pub fn items(&self) -> Vec<String> {
+        self.items.iter().rev().cloned().collect()
+    }
+}
+
+/// A line being edited; `cursor` counts chars.
+#[derive(Default)]
+struct Line {
+    text: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn set(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+
+    fn to_text(&self) -> String {
+        self.text.iter().collect()
+    }
+}
+
+impl Editor {
+    /// An editor whose history is saved if `save` is set.
+    pub fn new(save: bool) -> Self {
+        let path = config_dir().filter(|_| save).map(|dir| dir.join("history"));
+        let mut history: Vec<String> = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| contents.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        let dropped = history.len().saturating_sub(HISTORY_LEN);
+        if dropped > 0 {
+            history.drain(..dropped);
+            if let Some(path) = &path {
+                if let Err(e) = rewrite(path, &history) {
+                    warn!("Failed to trim the history in {}: {}", path.display(), e);
+                }
+            }
+        }
+        Self { history, path }
+    }
+
+    /// Adds `line` to the history, unless it's empty or the same as the last
+    /// entry. Failing to save it is only logged.
+    pub fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.path {
+            if let Err(e) = append(path, line) {
+                warn!("Failed to save the history to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Reads a line after showing `prompt`; `None` at the end of input. Ctrl-C
+    /// quits, as it does without the editor.
+    pub fn read_line(&self, prompt: &str) -> Result<Option<String>> {
+        self.read_line_with(prompt, &[])
+    }
+
+    /// Reads a line like [`Editor::read_line`], completing words from `candidates`.
+    pub fn read_line_with(&self, prompt: &str, candidates: &[String]) -> Result<Option<String>> {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            let mut input = String::new();
+            if io::stdin().read_line(&mut input)? == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(input.trim_end_matches(['\r', '\n']).to_string()));
+        }
+
+        // Only the prompt's last line is redrawn
+        let prompt = prompt.rsplit('\n').next().unwrap_or_default();
+        terminal::enable_raw_mode()?;
+        // Reading keys blocks, which the proxy task mustn't be stuck behind
+        let input = tokio::task::block_in_place(|| self.edit(prompt, candidates));
+        terminal::disable_raw_mode()?;
+        println!();
+        match input? {
+            Input::Line(line) => Ok(Some(line)),
+            Input::Eof => Ok(None),
+            Input::Interrupted => std::process::exit(130),
+        }
+    }
+
+    fn edit(&self, prompt: &str, candidates: &[String]) -> Result<Input> {
+        let mut line = Line::default();
+        // Up and Down move through the history; its end is the line being typed
+        let mut entry = self.history.len();
+        let mut typed = String::new();
+        // The Ctrl-R query and the entry it found
+        let mut search: Option<(String, Option<usize>)> = None;
+
+        loop {
+            draw(prompt, &line, search.as_ref().map(|(query, found)| (query.as_str(), found.map(|i| self.history[i].as_str()))))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+            if let Some((query, found)) = &mut search {
+                match key.code {
+                    // An older match
+                    KeyCode::Char('r') if ctrl => *found = self.find(query, found.unwrap_or(self.history.len())).or(*found),
+                    KeyCode::Char('c') if ctrl => return Ok(Input::Interrupted),
+                    KeyCode::Char('g') if ctrl => search = None,
+                    KeyCode::Esc => search = None,
+                    KeyCode::Char(c) if !ctrl => {
+                        query.push(c);
+                        *found = self.find(query, self.history.len());
+                    },
+                    KeyCode::Backspace => {
+                        query.pop();
+                        *found = self.find(query, self.history.len());
+                    },
+                    KeyCode::Enter => {
+                        if let Some(i) = found {
+                            return Ok(Input::Line(self.history[*i].clone()));
+                        }
+                        search = None;
+                    },
+                    // Anything else edits the match
+                    _ => {
+                        if let Some(i) = found {
+                            line.set(&self.history[*i]);
+                            entry = *i;
+                        }
+                        search = None;
+                    },
+                }
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Enter => return Ok(Input::Line(line.to_text())),
+                KeyCode::Char('c') if ctrl => return Ok(Input::Interrupted),
+                KeyCode::Char('d') if ctrl && line.text.is_empty() => return Ok(Input::Eof),
+                KeyCode::Char('d') if ctrl && line.cursor < line.text.len() => {
+                    line.text.remove(line.cursor);
+                },
+                KeyCode::Char('r') if ctrl => search = Some((String::new(), None)),
+                KeyCode::Char('a') if ctrl => line.cursor = 0,
+                KeyCode::Char('e') if ctrl => line.cursor = line.text.len(),
+                KeyCode::Char('u') if ctrl => {
+                    line.text.drain(..line.cursor);
+                    line.cursor = 0;
+                },
+                KeyCode::Char(c) if !ctrl => {
+                    line.text.insert(line.cursor, c);
+                    line.cursor += 1;
+                },
+                KeyCode::Backspace if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    line.text.remove(line.cursor);
+                },
+                KeyCode::Delete if line.cursor < line.text.len() => {
+                    line.text.remove(line.cursor);
+                },
+                KeyCode::Tab => complete(&mut line, candidates)?,
+                KeyCode::Left => line.cursor = line.cursor.saturating_sub(1),
+                KeyCode::Right => line.cursor = (line.cursor + 1).min(line.text.len()),
+                KeyCode::Home => line.cursor = 0,
+                KeyCode::End => line.cursor = line.text.len(),
+                KeyCode::Up if entry > 0 => {
+                    if entry == self.history.len() {
+                        typed = line.to_text();
+                    }
+                    entry -= 1;
+                    line.set(&self.history[entry]);
+                },
+                KeyCode::Down if entry < self.history.len() => {
+                    entry += 1;
+                    match self.history.get(entry) {
+                        Some(earlier) => line.set(earlier),
+                        None => line.set(&typed),
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// The latest entry before `before` that contains `query`.
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        self.history[..before].iter().rposition(|entry| entry.contains(query))
+    }
+}
+
+/// Completes the word before the cursor from `candidates`, ignoring case: as
+/// far as all that match agree, or lists them if that's no further.
+fn complete(line: &mut Line, candidates: &[String]) -> Result<()> {
+    let start = line.text[..line.cursor].iter().rposition(|c| c.is_whitespace()).map_or(0, |i| i + 1);
+    let word: String = line.text[start..line.cursor].iter().collect::<String>().to_lowercase();
+    let matches: Vec<&str> = candidates
+        .iter()
+        .map(String::as_str)
+        .filter(|candidate| candidate.to_lowercase().starts_with(&word))
+        .collect();
+    let Some(first) = matches.first() else {
+        return Ok(());
+    };
+    // As far as every match agrees
+    let mut common: Vec<char> = first.chars().collect();
+    for candidate in &matches[1..] {
+        let agree = common.iter().zip(candidate.chars()).take_while(|(a, b)| **a == *b).count();
+        common.truncate(agree);
+    }
+
+    if common.len() > line.cursor - start || matches.len() == 1 {
+        line.text.splice(start..line.cursor, common.iter().copied());
+        line.cursor = start + common.len();
+        return Ok(());
+    }
+    let mut stdout = io::stdout();
+    write!(stdout, "\r\n{}", matches.iter().take(LISTED).copied().collect::<Vec<_>>().join("  "))?;
+    if matches.len() > LISTED {
+        write!(stdout, "  ...and {} more", matches.len() - LISTED)?;
+    }
+    write!(stdout, "\r\n")?;
+    Ok(())
+}
+
+/// Redraws the line being edited, or the Ctrl-R search with what it found.
+fn draw(prompt: &str, line: &Line, search: Option<(&str, Option<&str>)>) -> Result<()> {
+    let mut stdout = io::stdout();
+    queue!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine))?;
+    let column = match search {
+        Some((query, found)) => {
+            let text = format!("(history search)'{}': {}", query, found.unwrap_or_default());
+            write!(stdout, "{}", text)?;
+            text.chars().count()
+        },
+        None => {
+            write!(stdout, "{}{}", prompt, line.to_text())?;
+            prompt.chars().count() + line.cursor
+        },
+    };
+    queue!(stdout, MoveToColumn(u16::try_from(column).unwrap_or(u16::MAX)))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Adds `line` to the history file.
+fn append(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    writeln!(open(&mut options, path)?, "{}", line)
+}
+
+/// Replaces the history file with `history`.
+fn rewrite(path: &Path, history: &[String]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    let mut file = open(&mut options, &tmp)?;
+    for line in history {
+        writeln!(file, "{}", line)?;
+    }
+    std::fs::rename(&tmp, path)
+}
+
+/// Opens a history file the way `options` say, readable by the user only.
+fn open(options: &mut OpenOptions, path: &Path) -> io::Result<std::fs::File> {
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(options, 0o600);
+    options.open(path)
+}
@@ -0,0 +1,252 @@
+use crate::{Client, Output};
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{Command, Compression, Encoding, Lang, Response};
+use nymbazaar_client_lib::Connection;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+
+/// A script for `--script`: protocol commands, one per line as the server
+/// reads them, run in order and their replies printed. Between them:
+///
+/// ```text
+/// # comments and blank lines are skipped
+/// set id 01HX4Z8K3M9Q              # a variable, used as $id or ${id}
+/// GET $id
+/// assert .item.seller == synthshop # a field of the last reply, jq style
+/// UPDATE $id ${.item.revision} price €400
+/// assert ok                        # or `assert error [code]`
+/// echo lowered ${.updated.id}
+/// ```
+///
+/// Variables not set in the script come from the environment, e.g. `$HOME`.
+/// A reply that is an error stops the script, unless the next line asserts it.
+pub struct Script {
+    /// Where the script came from, for messages.
+    name: String,
+    statements: Vec<(usize, Statement)>,
+}
+
+enum Statement {
+    Set { name: String, value: String },
+    Echo(String),
+    Assert(Assertion),
+    /// A protocol command, before its variables are filled in.
+    Command(String),
+}
+
+enum Assertion {
+    Ok,
+    /// The last reply is an error, with this code if one is given.
+    Error(Option<String>),
+    /// A field of the last reply is there and not `null` or `false`.
+    Present(String),
+    /// A field of the last reply has this value, or doesn't with `negate`.
+    Equals { path: String, value: String, negate: bool },
+}
+
+impl Script {
+    /// Reads and parses the script in `path`, or stdin for `-`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let (name, source) = if path.as_os_str() == "-" {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source).context("Failed to read the script from stdin")?;
+            ("stdin".to_string(), source)
+        } else {
+            let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read script {}", path.display()))?;
+            (path.display().to_string(), source)
+        };
+        let mut statements = Vec::new();
+        for (number, line) in (1..).zip(source.lines()) {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let statement = parse_statement(line).with_context(|| format!("{}:{}: can't read `{}`", name, number, line))?;
+            statements.push((number, statement));
+        }
+        Ok(Self { name, statements })
+    }
+}
+
+fn parse_statement(line: &str) -> Result<Statement> {
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    Ok(match word {
+        "set" => {
+            let (name, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("variable names are letters, digits and _");
+            }
+            Statement::Set { name: name.to_string(), value: value.trim().to_string() }
+        },
+        "echo" => Statement::Echo(rest.to_string()),
+        "assert" => Statement::Assert(parse_assertion(rest)?),
+        _ if word.eq_ignore_ascii_case("HELLO") => bail!("the client says HELLO itself"),
+        _ => {
+            // Lines without variables can be checked before connecting
+            if let (false, Err(e)) = (line.contains('$'), Command::parse(line)) {
+                bail!("{}", e.message().replace('_', " "));
+            }
+            Statement::Command(line.to_string())
+        },
+    })
+}
+
+fn parse_assertion(text: &str) -> Result<Assertion> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    Ok(match words[..] {
+        ["ok"] => Assertion::Ok,
+        ["error"] => Assertion::Error(None),
+        ["error", code] => Assertion::Error(Some(code.to_string())),
+        [path] if path.starts_with('.') => Assertion::Present(path.to_string()),
+        [path, op @ ("==" | "!="), ..] if path.starts_with('.') => {
+            // Everything after the operator, spaces and all
+            let value = text.split_once(op).map_or("", |(_, value)| value).trim();
+            Assertion::Equals { path: path.to_string(), value: value.to_string(), negate: op == "!=" }
+        },
+        _ => bail!("assert takes ok, error [code], .path, or .path == value"),
+    })
+}
+
+/// Runs `script`, printing each reply as `--output` asks. Fails with status 1
+/// when an assertion doesn't hold or the server answers with an error the
+/// script didn't expect.
+pub async fn run(client: &Client, mut conn: Connection, script: &Script) -> Result<ExitCode> {
+    let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+    if parse_error(&client.send_command(&mut conn, &hello).await?).is_some() {
+        bail!("This server can't answer in JSON, which scripts need");
+    }
+    conn.encoding = Encoding::Json;
+    if let Some(key) = &client.token {
+        if let Response::Error(e) = client.request(&mut conn, &Command::Auth { key: key.clone() }).await? {
+            client.print_error(&e.to_string());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    let mut variables = HashMap::new();
+    let mut last = Value::Null;
+    for (i, (number, statement)) in script.statements.iter().enumerate() {
+        let at = || format!("{}:{}", script.name, number);
+        let fill = |text: &str| substitute(text, &variables, &last).with_context(at);
+        match statement {
+            Statement::Set { name, value } => {
+                let value = fill(value)?;
+                variables.insert(name.clone(), value);
+            },
+            Statement::Echo(text) => println!("{}", fill(text)?),
+            Statement::Assert(assertion) => {
+                if let Err(e) = check(assertion, &last, &fill) {
+                    eprintln!("{}: assertion failed: {:#}", at(), e);
+                    return Ok(ExitCode::FAILURE);
+                }
+            },
+            Statement::Command(line) => {
+                let line = fill(line)?;
+                let command = match Command::parse(&line) {
+                    Ok(command) => command,
+                    Err(e) => bail!("{}: can't send `{}`: {}", at(), line, e.message().replace('_', " ")),
+                };
+                let response = client.request(&mut conn, &command).await?;
+                print(client, &response)?;
+                last = serde_json::to_value(&response)?;
+                // Only an assertion right after it may expect an error
+                let asserted = matches!(script.statements.get(i + 1), Some((_, Statement::Assert(Assertion::Error(_)))));
+                if matches!(response, Response::Error(_)) && !asserted {
+                    return Ok(ExitCode::FAILURE);
+                }
+            },
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print(client: &Client, response: &Response) -> Result<()> {
+    match (client.output, response.table()) {
+        (Output::Json, _) => println!("{}", String::from_utf8_lossy(&response.encode(Encoding::Json, Lang::default())?)),
+        (Output::Csv, Some(table)) => print!("{}", table.to_csv()),
+        _ => match response {
+            Response::Error(e) => client.print_error(&e.to_string()),
+            response => print!("{}", response.localized(client.lang.unwrap_or_default())),
+        },
+    }
+    Ok(())
+}
+
+fn check(assertion: &Assertion, last: &Value, fill: &dyn Fn(&str) -> Result<String>) -> Result<()> {
+    let error = last.get("error").and_then(Value::as_str);
+    match assertion {
+        Assertion::Ok if error.is_some() => bail!("the reply is error {}", error.unwrap_or_default()),
+        Assertion::Ok => Ok(()),
+        Assertion::Error(code) => match (error, code) {
+            (None, _) => bail!("the reply isn't an error"),
+            (Some(error), Some(code)) if error != code => bail!("the reply is error {}, not {}", error, code),
+            _ => Ok(()),
+        },
+        Assertion::Present(path) => match lookup(last, path) {
+            None | Some(Value::Null | Value::Bool(false)) => bail!("the reply has no {}", path),
+            Some(_) => Ok(()),
+        },
+        Assertion::Equals { path, value, negate } => {
+            let expected = fill(value)?;
+            let actual = lookup(last, path).map(text);
+            if (actual.as_deref() == Some(expected.as_str())) == *negate {
+                let actual = actual.unwrap_or_else(|| "missing".to_string());
+                bail!("{} is {}", path, actual);
+            }
+            Ok(())
+        },
+    }
+}
+
+/// Fills `$name`, `${name}` and `${.path}` into `text`: variables, the
+/// environment, and fields of the last reply. `$$` is a lone `$`.
+fn substitute(line: &str, variables: &HashMap<String, String>, last: &Value) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(at) = rest.find('$') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        let (name, after) = if let Some(braced) = rest.strip_prefix('{') {
+            braced.split_once('}').context("a ${ isn't closed")?
+        } else if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        } else {
+            let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            rest.split_at(end)
+        };
+        let value = if name.starts_with('.') {
+            lookup(last, name).map(text).with_context(|| format!("the last reply has no {}", name))?
+        } else if let Some(value) = variables.get(name) {
+            value.clone()
+        } else {
+            std::env::var(name).ok().with_context(|| format!("${} isn't set", name))?
+        };
+        out.push_str(&value);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The value at a jq style `path` like `.items.0.id`.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').skip(1).filter(|key| !key.is_empty()).try_fold(value, |value, key| match value {
+        Value::Array(values) => values.get(key.parse::<usize>().ok()?),
+        value => value.get(key),
+    })
+}
+
+/// How a value reads in a command or comparison: strings bare, the rest as JSON.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::command::{ItemField, NewListing};
+use bazaar_protocol::shipping::ShippingOptions;
+use bazaar_protocol::Item;
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Printed by `sell template`, to fill in and pass to `sell create --file`.
+pub const TEMPLATE: &str = r#"# A listing for `nymbazaar-client sell create --file <this file>`.
+# `sell update <id> --file <this file>` changes whichever fields are set here
+# and differ from the listing.
+
+category = "synthesizer"
+# Defaults to --seller or the profile's seller
+seller = "SynthWave"
+price = "€450"
+name = "Yamaha DX7"
+description = """
+Works, all keys and sliders fine. Comes with the original manual.
+"""
+# method:regions:cost:days, options separated by `;`
+shipping = "tracked:EU,UK:€8:3-5; letter:WORLD:€25:10-20"
+
+# Reference model from `models`, only set by `sell update`
+# model = "yamaha-dx7"
+# Unix time to go live at, for timed drops (create only)
+# publish_at = 1767225600
+# Anonymous credential, for bazaars that require one to list (create only)
+# credential = "..."
+"#;
+
+/// A listing as written in a file for `sell create` and `sell update`, see
+/// [`TEMPLATE`]. Fields left out keep their current value on update.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListingFile {
+    pub category: Option<String>,
+    pub seller: Option<String>,
+    pub price: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub shipping: Option<String>,
+    pub model: Option<String>,
+    pub publish_at: Option<u64>,
+    pub credential: Option<String>,
+}
+
+impl ListingFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read listing file {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid listing file {}", path.display()))
+    }
+
+    /// Asks for a new listing field by field.
+    pub fn prompt(seller: Option<&str>) -> Result<Self> {
+        let optional = |answer: String| Some(answer).filter(|answer| !answer.is_empty());
+        Ok(Self {
+            seller: optional(prompt("Seller", seller)?),
+            category: optional(prompt("Category", None)?),
+            name: optional(prompt("Name", None)?),
+            price: optional(prompt("Price (e.g. €450)", None)?),
+            description: optional(prompt("Description", None)?),
+            shipping: optional(prompt("Shipping (method:regions:cost:days; ...)", None)?),
+            ..Self::default()
+        })
+    }
+
+    /// The listing to send with `SELL`; `seller` is used if the file names none.
+    pub fn into_listing(self, seller: Option<&str>) -> Result<NewListing> {
+        let required = |value: Option<String>, field: &str| match value.filter(|value| !value.trim().is_empty()) {
+            Some(value) => Ok(value.trim().to_string()),
+            None => bail!("The listing needs a {}", field),
+        };
+        if self.model.is_some() {
+            bail!("A model can only be set with `sell update`, once the listing exists");
+        }
+        Ok(NewListing {
+            category: required(self.category, "category")?,
+            seller: required(self.seller.or(seller.map(str::to_string)), "seller (set it in the file, with --seller or in the profile)")?,
+            price: required(self.price, "price")?,
+            name: required(self.name, "name")?,
+            description: self.description.unwrap_or_default().trim().to_string(),
+            credential: self.credential,
+            publish_at: self.publish_at,
+            shipping: parse_shipping(self.shipping.as_deref().unwrap_or_default())?,
+        })
+    }
+
+    /// The `UPDATE`s that bring `item` in line with the file.
+    pub fn changes(&self, item: &Item) -> Result<Vec<(ItemField, String)>> {
+        if self.seller.as_ref().is_some_and(|seller| *seller != item.seller) {
+            bail!("The listing file is for seller '{}', but {} is listed by '{}'", self.seller.as_deref().unwrap_or_default(), item.id, item.seller);
+        }
+        if self.publish_at.is_some() || self.credential.is_some() {
+            bail!("publish_at and credential only apply to `sell create`");
+        }
+        let mut changes = Vec::new();
+        let fields = [
+            (ItemField::Name, &self.name),
+            (ItemField::Category, &self.category),
+            (ItemField::Price, &self.price),
+            (ItemField::Description, &self.description),
+            (ItemField::Shipping, &self.shipping),
+            (ItemField::Model, &self.model),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                push_change(&mut changes, item, field, value.trim())?;
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Asks for a new value of every field of `item`, keeping the ones left empty.
+pub fn prompt_changes(item: &Item) -> Result<Vec<(ItemField, String)>> {
+    println!("Editing {} (revision {}); leave a field empty to keep it", item.id, item.revision);
+    let mut changes = Vec::new();
+    for field in [ItemField::Name, ItemField::Category, ItemField::Price, ItemField::Description, ItemField::Shipping, ItemField::Model] {
+        let value = prompt(field.name(), Some(&current(item, field)))?;
+        if !value.is_empty() {
+            push_change(&mut changes, item, field, &value)?;
+        }
+    }
+    Ok(changes)
+}
+
+/// Adds `field = value` to `changes` unless that's what `item` already has.
+fn push_change(changes: &mut Vec<(ItemField, String)>, item: &Item, field: ItemField, value: &str) -> Result<()> {
+    let unchanged = match field {
+        // Compared parsed, so spacing and case in the file don't count as changes
+        ItemField::Shipping => parse_shipping(value)? == item.shipping,
+        _ => current(item, field) == value,
+    };
+    if !unchanged {
+        changes.push((field, value.to_string()));
+    }
+    Ok(())
+}
+
+fn current(item: &Item, field: ItemField) -> String {
+    match field {
+        ItemField::Name => item.name.clone(),
+        ItemField::Category => item.category.clone(),
+        ItemField::Price => item.price.clone(),
+        ItemField::Description => item.description.clone(),
+        ItemField::Shipping => item.shipping.to_string(),
+        ItemField::Model => item.model.clone().unwrap_or_default(),
+    }
+}
+
+fn parse_shipping(value: &str) -> Result<ShippingOptions> {
+    value.parse().map_err(|_| anyhow::anyhow!("Invalid shipping options '{}'; write them as method:regions:cost:days, separated by ';'", value))
+}
+
+/// Reads one line for `label`, showing `current` as the value an empty answer keeps.
+fn prompt(label: &str, current: Option<&str>) -> Result<String> {
+    match current.filter(|current| !current.is_empty()) {
+        Some(current) => print!("{} [{}]: ", label, current),
+        None => print!("{}: ", label),
+    }
+    io::stdout().flush()?;
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        bail!("No answer for {}", label);
+    }
+    Ok(input.trim().to_string())
+}
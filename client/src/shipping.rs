@@ -0,0 +1,116 @@
+use crate::keystore::Identity;
+use crate::Client;
+use anyhow::{anyhow, bail, Context, Result};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{Command, Compression, DropCommand, Encoding, Response};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use ed25519_dalek::VerifyingKey;
+use nymbazaar_client_lib::Connection;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// Mixed into the key a sealed address is encrypted with, so the key is only
+/// ever used for this.
+const CONTEXT: &[u8] = b"nym-bazaar shipping address";
+
+/// Reads a shipping address from stdin: line by line up to an empty line when
+/// typed, all of it when piped. It's only ever kept in memory.
+pub fn read_address() -> Result<String> {
+    let stdin = io::stdin();
+    let typed = stdin.is_terminal();
+    if typed {
+        println!("Shipping address, ending with an empty line:");
+        io::stdout().flush()?;
+    }
+    let mut lines = Vec::new();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if typed && line.trim().is_empty() {
+            break;
+        }
+        lines.push(line.trim_end().to_string());
+    }
+    let address = lines.join("\n").trim().to_string();
+    if address.is_empty() {
+        bail!("No shipping address given");
+    }
+    Ok(address)
+}
+
+/// Encrypts `address` to the x25519 form of a seller's registered ed25519
+/// `public_key`, under a key agreed with a throwaway one. Returns the hex of
+/// the throwaway public key, the nonce and the ciphertext, which only the
+/// seller's keystore can open.
+pub fn seal(public_key: &str, address: &str) -> Result<String> {
+    let bytes: [u8; 32] = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .context("The seller's registered key isn't an ed25519 key")?;
+    let recipient = PublicKey::from(VerifyingKey::from_bytes(&bytes)?.to_montgomery().to_bytes());
+
+    let mut secret = [0; 32];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    let ephemeral = StaticSecret::from(secret);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let mut nonce = [0; 24];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(&ephemeral.diffie_hellman(&recipient).to_bytes(), &ephemeral_public, &recipient);
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: address.as_bytes(), aad: CONTEXT })
+        .map_err(|_| anyhow!("Failed to encrypt the address"))?;
+
+    let mut sealed = ephemeral_public.to_bytes().to_vec();
+    sealed.extend_from_slice(&nonce);
+    sealed.extend(ciphertext);
+    Ok(hex::encode(sealed))
+}
+
+/// Decrypts an address [`seal`]ed to `identity`'s registered key.
+pub fn open(identity: &Identity, sealed: &str) -> Result<String> {
+    let sealed = hex::decode(sealed.trim()).context("The drop isn't a sealed address")?;
+    if sealed.len() < 32 + 24 {
+        bail!("The drop isn't a sealed address");
+    }
+    let (ephemeral_public, rest) = sealed.split_at(32);
+    let (nonce, ciphertext) = rest.split_at(24);
+    let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(ephemeral_public)?);
+
+    let secret = identity.shipping_secret();
+    let key = derive_key(&secret.diffie_hellman(&ephemeral_public).to_bytes(), &ephemeral_public, &PublicKey::from(&secret));
+    let address = XChaCha20Poly1305::new(&key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: CONTEXT })
+        .map_err(|_| anyhow!("The drop wasn't sealed to this keystore's key"))?;
+    String::from_utf8(address).context("The address isn't text")
+}
+
+fn derive_key(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(CONTEXT);
+    hasher.update(shared);
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(recipient.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Fetches dead drop `id` and prints the address sealed in it. The address
+/// goes to stdout only, never to the cache or the logs.
+pub async fn print_drop(client: &Client, mut conn: Connection, id: &str, identity: &Identity) -> Result<ExitCode> {
+    let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+    if parse_error(&client.send_command(&mut conn, &hello).await?).is_some() {
+        bail!("This server can't answer in JSON, which reading drops needs");
+    }
+    conn.encoding = Encoding::Json;
+
+    let ciphertext = match client.call(&mut conn, &Command::Drop(DropCommand::Get { id: id.to_string() })).await {
+        Ok(Response::Drop { ciphertext, .. }) => ciphertext,
+        Ok(other) => bail!("Unexpected reply to DROP GET: {}", other),
+        Err(e) => return client.fail(e),
+    };
+    println!("{}", open(identity, &ciphertext)?);
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,59 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// A spinner on stderr with a line saying what's being waited on, for waits
+/// that would otherwise look like a hang. Nothing is shown unless stderr is a
+/// terminal, and the line is cleared when the spinner is dropped.
+pub struct Spinner {
+    message: Arc<Mutex<String>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        Self::spin(message, None)
+    }
+
+    /// A spinner counting down the seconds left until `deadline`.
+    pub fn countdown(message: &str, deadline: Instant) -> Self {
+        Self::spin(message, Some(deadline))
+    }
+
+    fn spin(message: &str, deadline: Option<Instant>) -> Self {
+        let message = Arc::new(Mutex::new(message.to_string()));
+        let task = io::stderr().is_terminal().then(|| {
+            let message = message.clone();
+            tokio::spawn(async move {
+                for frame in FRAMES.iter().cycle() {
+                    let line = message.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    match deadline {
+                        Some(deadline) => eprint!("\r\x1b[2K{} {} {}s", frame, line, deadline.saturating_duration_since(Instant::now()).as_secs()),
+                        None => eprint!("\r\x1b[2K{} {}", frame, line),
+                    }
+                    io::stderr().flush().ok();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            })
+        });
+        Self { message, task }
+    }
+
+    /// Changes the line next to the spinner.
+    pub fn set(&self, message: &str) {
+        *self.message.lock().unwrap_or_else(|e| e.into_inner()) = message.to_string();
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+            eprint!("\r\x1b[2K");
+            io::stderr().flush().ok();
+        }
+    }
+}
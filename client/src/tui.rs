@@ -0,0 +1,759 @@
+use crate::export;
+use crate::narrow::{narrow, next_sort};
+use crate::prefetch::Prefetcher;
+use crate::Client;
+use anyhow::Result;
+use bazaar_protocol::response::Conversion;
+use bazaar_protocol::signing::Trust;
+use bazaar_protocol::{capability, Command, Item, Price, Response, SearchQuery, SellerCommand, SortKey};
+use nym_sdk::mixnet::Recipient;
+use nymbazaar_client_lib::{Abandoned, Connection, NotCached, Transport};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Flex, Layout, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Lines the detail pane scrolls by with PageUp and PageDown.
+const PAGE: u16 = 10;
+
+/// Which pane keys go to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Categories,
+    Items,
+    Search,
+    /// Typing narrows the listings on screen.
+    Filter,
+    /// Asking where to export to.
+    Export,
+    /// The saved bazaars popup.
+    Bazaars,
+}
+
+/// A request for the server, made between redraws.
+#[derive(Debug, Clone)]
+enum Action {
+    Categories,
+    List(Option<String>),
+    Search(String, SearchQuery),
+    Get(String),
+    Featured,
+    Random,
+    Status,
+    /// Fetches a listing whole and writes it to a file.
+    Export(String, PathBuf),
+}
+
+/// What's being exported while asking where to.
+#[derive(Debug, Clone)]
+enum Export {
+    /// The listings on screen.
+    Listings,
+    /// A listing with all its details.
+    Item(String),
+}
+
+impl Action {
+    fn command(&self, currency: Option<&String>) -> Command {
+        let currency = currency.cloned();
+        match self {
+            Action::Categories => Command::Categories,
+            Action::List(category) => Command::List { category: category.clone(), currency, price: None },
+            Action::Search(_, query) => Command::Search { query: query.clone(), currency },
+            Action::Get(id) | Action::Export(id, _) => Command::Get { id: id.clone(), currency, range: None },
+            Action::Featured => Command::Featured,
+            Action::Random => Command::Random { category: None },
+            Action::Status => Command::Status,
+        }
+    }
+
+    /// What the item pane is titled after this action.
+    fn title(&self) -> String {
+        match self {
+            Action::List(None) => "All items".to_string(),
+            Action::List(Some(category)) => category.clone(),
+            Action::Search(text, _) => format!("Search: {}", text),
+            Action::Featured => "Featured".to_string(),
+            Action::Random => "Random pick".to_string(),
+            Action::Categories | Action::Get(_) | Action::Status | Action::Export(..) => String::new(),
+        }
+    }
+}
+
+struct App<'a> {
+    client: &'a Client,
+    /// `None` stands for all categories and comes first.
+    categories: Vec<Option<String>>,
+    category: ListState,
+    title: String,
+    items: Vec<Item>,
+    /// Positions in `items` of the listings shown, narrowed by `filter` and
+    /// ordered by `sort`; `item` selects among these.
+    shown: Vec<usize>,
+    filter: String,
+    sort: Option<SortKey>,
+    item: ListState,
+    /// Converted prices of every listing seen, with `--currency`.
+    conversion: Option<Conversion>,
+    /// Text of the detail pane, and how far it's scrolled.
+    detail: String,
+    scroll: u16,
+    /// The listing the detail pane shows, once it was opened whole.
+    opened: Option<Item>,
+    /// Registered keys of the sellers looked up so far, `None` for sellers
+    /// without one.
+    seller_keys: HashMap<String, Option<String>>,
+    /// What checking the listings opened found, by ID, with the revision checked.
+    trust: HashMap<String, (u64, Trust)>,
+    focus: Focus,
+    search: String,
+    can_search: bool,
+    /// What's being exported, and the file typed in for it.
+    export: Option<Export>,
+    export_path: String,
+    /// Selection in the saved bazaars popup.
+    bazaar: ListState,
+    /// Saved bazaar to switch to once the UI closes.
+    switch: Option<Recipient>,
+    /// Errors and progress, shown above the key help.
+    status: String,
+    quit: bool,
+    /// Rows of listings in view, as last drawn.
+    visible: usize,
+    prefetcher: Option<Prefetcher>,
+}
+
+/// Runs the full-screen UI until the user quits, or picks another saved
+/// bazaar, whose address is returned. The connection must have agreed on a
+/// structured encoding with HELLO.
+pub async fn run(client: &Client, conn: &mut Connection, capabilities: Option<&[String]>) -> Result<Option<Recipient>> {
+    let can_search = capabilities.is_none_or(|list| list.iter().any(|c| c == capability::SEARCH));
+    let mut app = App {
+        client,
+        categories: vec![None],
+        category: ListState::default().with_selected(Some(0)),
+        title: String::new(),
+        items: Vec::new(),
+        shown: Vec::new(),
+        filter: String::new(),
+        sort: None,
+        item: ListState::default(),
+        conversion: None,
+        detail: String::new(),
+        scroll: 0,
+        opened: None,
+        seller_keys: HashMap::new(),
+        trust: HashMap::new(),
+        focus: Focus::Items,
+        search: String::new(),
+        can_search,
+        export: None,
+        export_path: String::new(),
+        bazaar: ListState::default().with_selected(Some(0)),
+        switch: None,
+        status: String::new(),
+        quit: false,
+        visible: 0,
+        // Fetching ahead needs connections of its own, which only the proxy hands out
+        prefetcher: match (&conn.transport, &client.cache) {
+            (Transport::Proxy(_), Some(cache)) if client.prefetch > 0 => Some(Prefetcher::start(
+                client.proxy_addr(),
+                client.server_address,
+                cache.clone(),
+                client.prefetch,
+                client.prefetch_budget,
+            )),
+            _ => None,
+        },
+    };
+
+    // The status line counts down instead
+    conn.countdown = false;
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal, conn).await;
+    ratatui::restore();
+    result.map(|()| app.switch)
+}
+
+impl App<'_> {
+    async fn run(&mut self, terminal: &mut DefaultTerminal, conn: &mut Connection) -> Result<()> {
+        let mut pending = vec![Action::Categories, Action::List(None)];
+        while !self.quit {
+            for action in pending.drain(..) {
+                let response = self.request(terminal, conn, &action.command(self.client.currency.as_ref())).await;
+                self.status.clear();
+                let response = match response {
+                    Ok(response) => response,
+                    // Offline, whatever isn't cached just can't be shown
+                    Err(e) if e.is::<NotCached>() => {
+                        self.status = e.to_string();
+                        continue;
+                    },
+                    // The rest of what was asked for goes with it
+                    Err(e) if e.is::<Abandoned>() => {
+                        self.status = e.to_string();
+                        break;
+                    },
+                    Err(e) => return Err(e),
+                };
+                match &action {
+                    Action::Export(_, path) => self.export_to(path, &response),
+                    Action::Get(_) => {
+                        self.apply(action, response);
+                        self.check_trust(terminal, conn).await?;
+                    },
+                    _ => self.apply(action, response),
+                }
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            self.prefetch_visible();
+
+            // Reading keys blocks, which the proxy task mustn't be stuck behind
+            if let Event::Key(key) = tokio::task::block_in_place(event::read)? {
+                if key.kind == KeyEventKind::Press {
+                    pending.extend(self.on_key(key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `command` and waits for the reply, counting down in the status
+    /// line. Esc or Ctrl-C cancels it; other keys pressed meanwhile are dropped.
+    async fn request(&mut self, terminal: &mut DefaultTerminal, conn: &mut Connection, command: &Command) -> Result<Response> {
+        let client = self.client;
+        let deadline = Instant::now() + client.timeout;
+        let request = client.request(conn, command);
+        tokio::pin!(request);
+        let mut tick = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                response = &mut request => return response,
+                _ = tick.tick() => {
+                    let left = deadline.saturating_duration_since(Instant::now()).as_secs();
+                    self.status = format!("Waiting for the server, {}s left (Esc to cancel)", left);
+                    terminal.draw(|frame| self.draw(frame))?;
+                    while event::poll(Duration::ZERO)? {
+                        if let Event::Key(key) = event::read()? {
+                            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                            if key.kind == KeyEventKind::Press && (key.code == KeyCode::Esc || ctrl_c) {
+                                client.interrupt.cancel();
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// Checks the opened listing against its seller's registered key and the
+    /// bazaar's catalog proof for it, for the indicator over the detail pane.
+    /// Each is a trip through the mixnet, so seller keys are only asked for once.
+    async fn check_trust(&mut self, terminal: &mut DefaultTerminal, conn: &mut Connection) -> Result<()> {
+        let Some(item) = self.opened.clone() else {
+            return Ok(());
+        };
+        let key = match self.seller_keys.get(&item.seller) {
+            Some(key) => key.clone(),
+            None => {
+                let command = Command::Seller(SellerCommand::Info { name: item.seller.clone() });
+                let Some(response) = self.request_check(terminal, conn, &command).await? else {
+                    return Ok(());
+                };
+                // A seller who never registered has no key to check against
+                let key = match response {
+                    Response::Seller(info) => Some(info.public_key),
+                    _ => None,
+                };
+                self.seller_keys.insert(item.seller.clone(), key.clone());
+                key
+            },
+        };
+        let Some(response) = self.request_check(terminal, conn, &Command::Proof { id: item.id.clone() }).await? else {
+            return Ok(());
+        };
+        // Bazaars that can't prove listings are taken at their word
+        let proof = match response {
+            Response::Proof(proof) => Some(proof),
+            _ => None,
+        };
+        self.trust.insert(item.id.clone(), (item.revision, item.trust(key.as_deref(), proof.as_ref())));
+        Ok(())
+    }
+
+    /// [`App::request`] for checks, which are given up on quietly if the reply
+    /// isn't cached or the user cancels.
+    async fn request_check(&mut self, terminal: &mut DefaultTerminal, conn: &mut Connection, command: &Command) -> Result<Option<Response>> {
+        let response = self.request(terminal, conn, command).await;
+        self.status.clear();
+        match response {
+            Ok(response) => Ok(Some(response)),
+            Err(e) if e.is::<NotCached>() || e.is::<Abandoned>() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if self.focus == Focus::Bazaars {
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.bazaar.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.bazaar.select_next(),
+                KeyCode::Enter => {
+                    let picked = self.bazaar.selected().and_then(|i| self.client.bazaars.iter().nth(i));
+                    match picked.map(|(_, address)| Recipient::try_from_base58_string(address)) {
+                        Some(Ok(address)) => {
+                            self.switch = Some(address);
+                            self.quit = true;
+                        },
+                        Some(Err(_)) => self.status = "That saved bazaar's address is invalid".to_string(),
+                        None => {},
+                    }
+                    self.focus = Focus::Items;
+                },
+                KeyCode::Esc | KeyCode::Char('b') => self.focus = Focus::Items,
+                _ => {},
+            }
+            return None;
+        }
+
+        if self.focus == Focus::Search {
+            match key.code {
+                KeyCode::Enter => {
+                    self.focus = Focus::Items;
+                    let text = self.search.trim().to_string();
+                    if text.is_empty() {
+                        return None;
+                    }
+                    match text.parse() {
+                        Ok(query) => return Some(Action::Search(text, query)),
+                        Err(e) => self.status = self.render(&Response::Error(e)).trim().to_string(),
+                    }
+                },
+                KeyCode::Esc => self.focus = Focus::Items,
+                KeyCode::Backspace => {
+                    self.search.pop();
+                },
+                KeyCode::Char(c) => self.search.push(c),
+                _ => {},
+            }
+            return None;
+        }
+
+        if self.focus == Focus::Export {
+            match key.code {
+                KeyCode::Enter => {
+                    self.focus = Focus::Items;
+                    let path = PathBuf::from(self.export_path.trim());
+                    match self.export.take() {
+                        Some(Export::Listings) => self.export_to(&path, &self.listings()),
+                        Some(Export::Item(id)) => return Some(Action::Export(id, path)),
+                        None => {},
+                    }
+                },
+                KeyCode::Esc => {
+                    self.export = None;
+                    self.focus = Focus::Items;
+                },
+                KeyCode::Backspace => {
+                    self.export_path.pop();
+                },
+                KeyCode::Char(c) => self.export_path.push(c),
+                _ => {},
+            }
+            return None;
+        }
+
+        // Narrowing and sorting only rearrange what's on screen, no request is made
+        if self.focus == Focus::Filter {
+            match key.code {
+                KeyCode::Enter => self.focus = Focus::Items,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.focus = Focus::Items;
+                    self.renarrow();
+                },
+                KeyCode::Up => self.select(-1),
+                KeyCode::Down => self.select(1),
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.renarrow();
+                },
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.renarrow();
+                },
+                _ => {},
+            }
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+                self.focus = match self.focus {
+                    Focus::Categories => Focus::Items,
+                    _ => Focus::Categories,
+                };
+            },
+            KeyCode::Char('/') if self.can_search => self.focus = Focus::Search,
+            KeyCode::Char('b') if !self.client.bazaars.is_empty() => self.focus = Focus::Bazaars,
+            KeyCode::Char('n') => self.focus = Focus::Filter,
+            KeyCode::Char('e') if !self.shown.is_empty() => self.ask_export(Export::Listings),
+            KeyCode::Char('E') => {
+                if let Some(id) = self.selected_item().map(|item| item.id.clone()) {
+                    self.ask_export(Export::Item(id));
+                }
+            },
+            KeyCode::Char('o') => {
+                self.sort = next_sort(self.sort);
+                self.renarrow();
+            },
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(PAGE),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(PAGE),
+            KeyCode::Enter => match self.focus {
+                Focus::Categories => {
+                    let category = self.category.selected().and_then(|i| self.categories.get(i));
+                    return Some(Action::List(category.cloned().flatten()));
+                },
+                // Listings come without questions and attestations; fetch it whole
+                _ => return self.selected_item().map(|item| Action::Get(item.id.clone())),
+            },
+            KeyCode::Char('f') => return Some(Action::Featured),
+            KeyCode::Char('r') => return Some(Action::Random),
+            KeyCode::Char('s') => return Some(Action::Status),
+            _ => {},
+        }
+        None
+    }
+
+    /// Asks where to export `what` to, suggesting `--export` if it was given.
+    fn ask_export(&mut self, what: Export) {
+        self.export_path = match (&self.client.export, &what) {
+            (Some(path), _) => path.display().to_string(),
+            (None, Export::Listings) => "listings.csv".to_string(),
+            (None, Export::Item(id)) => format!("{}.json", id),
+        };
+        self.export = Some(what);
+        self.focus = Focus::Export;
+    }
+
+    /// The listings on screen as a response, with their converted prices.
+    fn listings(&self) -> Response {
+        let items: Vec<Item> = self.shown.iter().map(|&i| self.items[i].clone()).collect();
+        let Some(conversion) = &self.conversion else {
+            return Response::Items(items);
+        };
+        let mut conversion = conversion.clone();
+        conversion.prices.retain(|id, _| items.iter().any(|item| &item.id == id));
+        Response::Converted(Box::new(Response::Items(items)), conversion)
+    }
+
+    /// Writes `response` to `path` and says how that went in the status line.
+    fn export_to(&mut self, path: &Path, response: &Response) {
+        self.status = match response {
+            Response::Error(_) => self.render(response).trim().to_string(),
+            response => match export::write(path, response) {
+                Ok(()) => format!("Exported to {}", path.display()),
+                Err(e) => format!("{:#}", e),
+            },
+        };
+    }
+
+    /// Moves the selection of the focused pane by `step`.
+    fn select(&mut self, step: isize) {
+        let (state, len) = match self.focus {
+            Focus::Categories => (&mut self.category, self.categories.len()),
+            _ => (&mut self.item, self.shown.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0);
+        state.select(Some(current.saturating_add_signed(step).min(len - 1)));
+        if self.focus != Focus::Categories {
+            self.show_selected();
+        }
+    }
+
+    fn selected_item(&self) -> Option<&Item> {
+        self.item.selected().and_then(|i| self.shown.get(i)).map(|&i| &self.items[i])
+    }
+
+    /// Narrows and orders the listings again, keeping the selected one
+    /// selected if it's still shown.
+    fn renarrow(&mut self) {
+        let selected = self.selected_item().map(|item| item.id.clone());
+        let conversion = self.conversion.as_ref();
+        self.shown = narrow(&self.items, &self.filter, self.sort, |item| {
+            let converted = conversion.and_then(|conversion| conversion.prices.get(&item.id));
+            converted.map(|price| price.minor).or_else(|| item.price.parse::<Price>().ok().map(|price| price.minor))
+        });
+        let position = self.shown.iter().position(|&i| Some(&self.items[i].id) == selected.as_ref());
+        self.item.select(position.or((!self.shown.is_empty()).then_some(0)));
+        if self.selected_item().map(|item| &item.id) != selected.as_ref() {
+            self.show_selected();
+        }
+    }
+
+    fn show_selected(&mut self) {
+        self.detail = self.selected_item().map(|item| self.render_item(item)).unwrap_or_default();
+        self.scroll = 0;
+        self.opened = None;
+    }
+
+    fn render_item(&self, item: &Item) -> String {
+        let response = Response::Item(Box::new(item.clone()));
+        match &self.conversion {
+            Some(conversion) => self.render(&Response::Converted(Box::new(response), conversion.clone())),
+            None => self.render(&response),
+        }
+    }
+
+    fn price(&self, item: &Item) -> String {
+        match self.conversion.as_ref().and_then(|conversion| conversion.prices.get(&item.id)) {
+            Some(converted) => format!("{} (~{})", item.price, converted),
+            None => item.price.clone(),
+        }
+    }
+
+    fn render(&self, response: &Response) -> String {
+        response.localized(self.client.lang.unwrap_or_default()).to_string()
+    }
+
+    fn apply(&mut self, action: Action, response: Response) {
+        match response {
+            Response::Error(_) => self.status = self.render(&response).trim().to_string(),
+            Response::Converted(inner, mut conversion) => {
+                // Keep the prices converted earlier, at the latest rates' age
+                if let Some(known) = self.conversion.take() {
+                    for (id, price) in known.prices {
+                        conversion.prices.entry(id).or_insert(price);
+                    }
+                }
+                self.conversion = Some(conversion);
+                self.apply(action, *inner)
+            },
+            Response::Categories(categories) => {
+                self.categories = std::iter::once(None).chain(categories.into_iter().map(Some)).collect();
+                self.category.select(Some(0));
+            },
+            Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => {
+                self.show_items(action.title(), items);
+            },
+            Response::Item(item) => match action {
+                // Refresh the listing in place, keeping the list around it
+                Action::Get(_) => {
+                    if let Some(i) = self.item.selected().and_then(|i| self.shown.get(i).copied()).filter(|&i| self.items[i].id == item.id) {
+                        self.items[i] = (*item).clone();
+                    }
+                    self.detail = self.render_item(&item);
+                    self.scroll = 0;
+                    self.opened = Some(*item);
+                },
+                _ => self.show_items(action.title(), vec![*item]),
+            },
+            response => {
+                self.detail = self.render(&response);
+                self.scroll = 0;
+                self.opened = None;
+            },
+        }
+    }
+
+    fn show_items(&mut self, title: String, items: Vec<Item>) {
+        if let Some(prefetcher) = &self.prefetcher {
+            prefetcher.new_page();
+        }
+        self.title = title;
+        self.items = items;
+        // A new page starts unfiltered, in the order asked for last
+        self.filter.clear();
+        self.item.select(None);
+        self.renarrow();
+        self.focus = Focus::Items;
+        self.show_selected();
+        if self.items.is_empty() {
+            self.status = "Nothing found".to_string();
+        }
+    }
+
+    /// Has the details of the listings in view fetched ahead.
+    fn prefetch_visible(&self) {
+        if let Some(prefetcher) = &self.prefetcher {
+            let currency = self.client.currency.as_ref();
+            let in_view = self.shown.iter().skip(self.item.offset()).take(self.visible).map(|&i| &self.items[i]);
+            prefetcher.fetch(in_view.map(|item| Action::Get(item.id.clone()).command(currency)));
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search, main, status, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [sidebar, list, detail] = Layout::horizontal([
+            Constraint::Length(24),
+            Constraint::Percentage(40),
+            Constraint::Min(0),
+        ])
+        .areas(main);
+
+        self.draw_search(frame, search);
+
+        let categories: Vec<ListItem> = self
+            .categories
+            .iter()
+            .map(|category| ListItem::new(category.as_deref().unwrap_or("All items")))
+            .collect();
+        let categories = List::new(categories)
+            .block(self.pane("Categories", Focus::Categories))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(categories, sidebar, &mut self.category);
+
+        let items: Vec<ListItem> = self
+            .shown
+            .iter()
+            .map(|&i| &self.items[i])
+            .map(|item| Line::from(vec![Span::raw(item.name.clone()), Span::raw("  "), self.price(item).dim()]).into())
+            .collect();
+        let title = if self.shown.len() == self.items.len() {
+            format!("{} ({})", self.title, self.items.len())
+        } else {
+            format!("{} ({} of {})", self.title, self.shown.len(), self.items.len())
+        };
+        let mut block = self.pane(&title, if self.focus == Focus::Filter { Focus::Filter } else { Focus::Items });
+        if let Some(sort) = self.sort {
+            block = block.title_top(Line::from(format!("sort:{}", sort.name())).right_aligned());
+        }
+        if self.focus == Focus::Filter || !self.filter.is_empty() {
+            block = block.title_bottom(format!("narrow: {}", self.filter));
+        }
+        let items = List::new(items)
+            .block(block)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(items, list, &mut self.item);
+        self.visible = usize::from(list.height.saturating_sub(2));
+        if self.focus == Focus::Filter {
+            let x = list.x + 1 + "narrow: ".len() as u16 + self.filter.chars().count() as u16;
+            frame.set_cursor_position(Position::new(x.min(list.right().saturating_sub(2)), list.bottom().saturating_sub(1)));
+        }
+
+        let mut block = Block::bordered().title("Details");
+        if let Some(trust) = self.opened_trust() {
+            let style = match trust {
+                Trust::Verified => Style::new().fg(Color::Green),
+                Trust::Unsigned => Style::new().dim(),
+                Trust::Invalid => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+            };
+            block = block.title_top(Line::from(Span::styled(trust.name(), style)).right_aligned());
+        }
+        let details = Paragraph::new(self.detail.as_str())
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(details, detail);
+
+        if self.focus == Focus::Export {
+            let prompt = "Export to: ";
+            frame.render_widget(Paragraph::new(format!("{}{}", prompt, self.export_path)), status);
+            let x = status.x + (prompt.len() + self.export_path.chars().count()) as u16;
+            frame.set_cursor_position(Position::new(x.min(status.right().saturating_sub(1)), status.y));
+        } else {
+            frame.render_widget(Paragraph::new(self.status.as_str()).fg(Color::Yellow), status);
+        }
+        frame.render_widget(Paragraph::new(self.help()).dim(), help);
+
+        if self.focus == Focus::Bazaars {
+            self.draw_bazaars(frame, main);
+        }
+    }
+
+    /// What checking the listing in the detail pane found, if it was checked
+    /// as it is now.
+    fn opened_trust(&self) -> Option<Trust> {
+        let item = self.opened.as_ref()?;
+        self.trust.get(&item.id).filter(|(revision, _)| *revision == item.revision).map(|&(_, trust)| trust)
+    }
+
+    fn draw_search(&self, frame: &mut Frame, area: Rect) {
+        let (text, style) = match (self.can_search, self.focus) {
+            (false, _) => ("This server doesn't offer search", Style::new().dim()),
+            (true, Focus::Search) => (self.search.as_str(), Style::new()),
+            (true, _) if self.search.is_empty() => ("Press / to search, e.g. synth category:synthesizer price:100..500", Style::new().dim()),
+            (true, _) => (self.search.as_str(), Style::new().dim()),
+        };
+        let mut block = self.pane("Search", Focus::Search);
+        if let Some(name) = self.client.bazaars.name_of(&self.client.server_address) {
+            block = block.title_top(Line::from(name).right_aligned());
+        }
+        frame.render_widget(Paragraph::new(text).style(style).block(block), area);
+        if self.focus == Focus::Search {
+            let x = area.x + 1 + self.search.chars().count() as u16;
+            frame.set_cursor_position(Position::new(x.min(area.right().saturating_sub(2)), area.y + 1));
+        }
+    }
+
+    fn pane<'t>(&self, title: &'t str, focus: Focus) -> Block<'t> {
+        let block = Block::bordered().title(title);
+        if self.focus == focus {
+            block.border_style(Style::new().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+
+    /// The saved bazaars popup, over `area`.
+    fn draw_bazaars(&mut self, frame: &mut Frame, area: Rect) {
+        let client = self.client;
+        let current = client.bazaars.name_of(&client.server_address);
+        let names: Vec<ListItem> = client
+            .bazaars
+            .iter()
+            .map(|(name, _)| {
+                if current == Some(name) {
+                    ListItem::new(format!("{} (current)", name))
+                } else {
+                    ListItem::new(name)
+                }
+            })
+            .collect();
+        let [popup] = Layout::vertical([Constraint::Length(names.len() as u16 + 2)]).flex(Flex::Center).areas(area);
+        let [popup] = Layout::horizontal([Constraint::Length(40)]).flex(Flex::Center).areas(popup);
+        let list = List::new(names)
+            .block(self.pane("Switch bazaar", Focus::Bazaars))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_widget(Clear, popup);
+        frame.render_stateful_widget(list, popup, &mut self.bazaar);
+    }
+
+    fn help(&self) -> String {
+        match self.focus {
+            Focus::Search => "Enter search  Esc cancel".to_string(),
+            Focus::Export => "Enter export (.csv or .json)  Esc cancel".to_string(),
+            Focus::Filter => "Type to narrow the listings  ↑↓ move  Enter done  Esc clear".to_string(),
+            Focus::Bazaars => "↑↓ move  Enter switch  Esc cancel".to_string(),
+            Focus::Categories | Focus::Items => {
+                let mut keys = vec!["↑↓ move", "Tab switch pane", "Enter open"];
+                if self.can_search {
+                    keys.push("/ search");
+                }
+                keys.extend(["n narrow", "o sort", "e/E export", "f featured", "r random", "s status"]);
+                if !self.client.bazaars.is_empty() {
+                    keys.push("b bazaars");
+                }
+                keys.extend(["PgUp/PgDn scroll", "q quit"]);
+                keys.join("  ")
+            },
+        }
+    }
+}
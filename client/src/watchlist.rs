@@ -0,0 +1,260 @@
+use crate::config::{cache_dir, config_dir};
+use crate::Client;
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::price::Price;
+use bazaar_protocol::{Command, Compression, Encoding, Item, Response, SearchQuery};
+use nymbazaar_client_lib::{Abandoned, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Once;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Listings and searches watched with `nymbazaar-client watch`, kept in
+/// `~/.config/nymbazaar/watchlist.toml`. `watch run` polls them and raises a
+/// desktop notification when a watched listing gets cheaper or a new listing
+/// matches a watched search.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Watchlist {
+    #[serde(default)]
+    watches: Vec<Watch>,
+}
+
+/// A listing or a search on one bazaar; exactly one of `item` and `search` is set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Watch {
+    /// Nym address of the bazaar, base58.
+    pub bazaar: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+}
+
+/// What `watch run` last saw of each watch, in `~/.cache/nymbazaar/watched.toml`.
+/// Losing it only means the next poll sets a new baseline without notifying.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Seen {
+    #[serde(default)]
+    watches: BTreeMap<String, Observed>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Observed {
+    /// Price of a watched listing, as the server showed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    price: Option<String>,
+    /// Listings that matched a watched search.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    ids: BTreeSet<String>,
+}
+
+impl Watch {
+    /// Identifies the watch in what `watch run` saw.
+    fn key(&self) -> String {
+        match (&self.item, &self.search) {
+            (Some(id), _) => format!("{} item {}", self.bazaar, id),
+            (None, search) => format!("{} search {}", self.bazaar, search.as_deref().unwrap_or_default()),
+        }
+    }
+
+    fn command(&self, currency: Option<&String>) -> Result<Command> {
+        let currency = currency.cloned();
+        match (&self.item, &self.search) {
+            (Some(id), None) => Ok(Command::Get { id: id.clone(), currency, range: None }),
+            (None, Some(search)) => Ok(Command::Search { query: search.parse::<SearchQuery>()?, currency }),
+            _ => bail!("A watch needs either an item or a search"),
+        }
+    }
+
+    /// What `response` shows, compared to what was seen `before`, with the
+    /// notifications that calls for. Nothing is notified without anything
+    /// seen before: the first poll sets the baseline.
+    fn observe(&self, response: &Response, before: Option<Observed>) -> (Observed, Vec<(String, String)>) {
+        let mut now = Observed::default();
+        let mut alerts = Vec::new();
+        match response {
+            Response::Item(item) => {
+                if let Some(before) = before.and_then(|before| before.price).filter(|before| is_drop(before, &item.price)) {
+                    alerts.push((format!("Price drop: {}", item.name), format!("{} → {} ({})", before, item.price, item.id)));
+                }
+                now.price = Some(item.price.clone());
+            },
+            Response::SearchResults(items) | Response::Items(items) => {
+                let search = self.search.as_deref().unwrap_or_default();
+                let new: Vec<&Item> = match &before {
+                    Some(before) => items.iter().filter(|item| !before.ids.contains(&item.id)).collect(),
+                    None => Vec::new(),
+                };
+                match new.as_slice() {
+                    [] => {},
+                    [item] => alerts.push((format!("New match for '{}'", search), format!("{}, {} ({})", item.name, item.price, item.id))),
+                    items => alerts.push((
+                        format!("{} new matches for '{}'", items.len(), search),
+                        items.iter().map(|item| item.name.as_str()).collect::<Vec<_>>().join(", "),
+                    )),
+                }
+                // Listings that drop out of the results and come back aren't new
+                now.ids = before.map(|before| before.ids).unwrap_or_default();
+                now.ids.extend(items.iter().map(|item| item.id.clone()));
+            },
+            _ => {},
+        }
+        (now, alerts)
+    }
+}
+
+/// Whether `now` is lower than `before`, both prices in the same currency.
+fn is_drop(before: &str, now: &str) -> bool {
+    match (before.parse::<Price>(), now.parse::<Price>()) {
+        (Ok(before), Ok(now)) => before.currency == now.currency && now.minor < before.minor,
+        _ => false,
+    }
+}
+
+impl Watchlist {
+    fn path() -> Result<PathBuf> {
+        match config_dir() {
+            Some(dir) => Ok(dir.join("watchlist.toml")),
+            None => bail!("Can't find a config directory for the watchlist; set HOME or XDG_CONFIG_HOME"),
+        }
+    }
+
+    /// Reads the watchlist; nothing watched yet yields an empty one.
+    pub fn load() -> Result<Self> {
+        let Ok(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(&path).with_context(|| format!("Failed to read the watchlist {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid watchlist {}", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        write_toml(&Self::path()?, self)
+    }
+
+    /// Adds `watch` unless it's watched already.
+    pub fn add(&mut self, watch: Watch) -> Result<()> {
+        watch.command(None)?;
+        if self.watches.contains(&watch) {
+            bail!("Already watched");
+        }
+        self.watches.push(watch);
+        self.save()
+    }
+
+    /// Removes the watch numbered `number` by [`Watchlist::iter`], from 1.
+    pub fn remove(&mut self, number: usize) -> Result<()> {
+        if number == 0 || number > self.watches.len() {
+            bail!("No watch numbered {}; see `watch list`", number);
+        }
+        self.watches.remove(number - 1);
+        self.save()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watch> {
+        self.watches.iter()
+    }
+}
+
+impl Seen {
+    fn path() -> Option<PathBuf> {
+        cache_dir().map(|dir| dir.join("watched.toml"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Failing to save is only logged; the next run starts from a new baseline.
+    fn save(&self) {
+        if let Some(path) = Self::path() {
+            if let Err(e) = write_toml(&path, self) {
+                warn!("Failed to save what the watches saw: {:#}", e);
+            }
+        }
+    }
+}
+
+fn write_toml(path: &Path, value: &impl Serialize) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, toml::to_string(value)?).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+/// Polls the watches on `bazaar` every `interval`, or once, notifying of
+/// price drops and new matches. The watchlist is read again before each
+/// round, so watches added meanwhile are picked up.
+pub async fn run(client: &Client, mut conn: Connection, bazaar: &str, interval: Duration, once: bool) -> Result<ExitCode> {
+    let hello = Command::Hello { compress: Compression::supported().to_vec(), encoding: Some(Encoding::Json), session: None };
+    if parse_error(&client.send_command(&mut conn, &hello).await?).is_some() {
+        bail!("This server can't answer in JSON, which watching needs");
+    }
+    conn.encoding = Encoding::Json;
+
+    let mut seen = Seen::load();
+    loop {
+        let watchlist = Watchlist::load()?;
+        let watches: Vec<&Watch> = watchlist.watches.iter().filter(|watch| watch.bazaar == bazaar).collect();
+        if watches.is_empty() {
+            bail!("Nothing watched on this bazaar; add something with `watch item` or `watch search`");
+        }
+        for watch in watches {
+            let response = match client.request(&mut conn, &watch.command(client.currency.as_ref())?).await {
+                Ok(response) => response,
+                Err(e) if e.is::<Abandoned>() => {
+                    warn!("{:#}", e);
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
+            if let Response::Error(e) = &response {
+                warn!("Polling {} failed: {}", watch.key(), e);
+                continue;
+            }
+            let key = watch.key();
+            let (now, alerts) = watch.observe(&response, seen.watches.remove(&key));
+            for (summary, body) in alerts {
+                notify(&summary, &body).await;
+            }
+            seen.watches.insert(key, now);
+        }
+        seen.save();
+
+        if once {
+            return Ok(ExitCode::SUCCESS);
+        }
+        debug!(secs = interval.as_secs(), "Waiting for the next poll");
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Prints a notification and raises it on the desktop.
+async fn notify(summary: &str, body: &str) {
+    println!("{}: {}", summary, body);
+
+    let mut notification = notify_rust::Notification::new();
+    notification.appname("NymBazaar").summary(summary).body(body);
+    // Showing one is a blocking round trip to the notification service
+    let result = tokio::task::spawn_blocking(move || notification.show().map(drop)).await;
+
+    static WARNED: Once = Once::new();
+    match result {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => WARNED.call_once(|| warn!("Can't raise desktop notifications: {}", e)),
+        Err(e) => WARNED.call_once(|| warn!("Can't raise desktop notifications: {}", e)),
+    }
+}
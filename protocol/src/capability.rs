@@ -0,0 +1,56 @@
+//! Names of the subsystems a server advertises in `STATUS` and `HELLO`.
+//!
+//! Most subsystems can be switched off in the server's config, and older
+//! servers lack some altogether, so clients should check for a capability
+//! before offering the commands that need it rather than waiting for them to
+//! fail. Unknown names are to be ignored.
+
+/// `SEARCH` is enabled.
+pub const SEARCH: &str = "search";
+/// Listings can carry images, fetched with `GETIMAGE` or out-of-band.
+pub const IMAGES: &str = "images";
+/// Images are pinned to IPFS; listings only carry their CIDs.
+pub const IPFS: &str = "ipfs";
+/// Scaled down images are available with `GETIMAGE --size`.
+pub const THUMBNAILS: &str = "thumbnails";
+/// Images can be uploaded in chunks with `UPLOAD`.
+pub const UPLOADS: &str = "uploads";
+/// Buyers can `ASK` sellers about listings.
+pub const QUESTIONS: &str = "questions";
+/// Prices can be converted with `--currency` on `LIST`, `GET` and `SEARCH`.
+pub const CURRENCY: &str = "currency";
+/// Writes need a blind `PASS`, issued for solving a proof-of-work challenge.
+pub const PASSES: &str = "passes";
+/// `SELL` needs a credential.
+pub const CREDENTIALS: &str = "credentials";
+/// Responses can be compressed with `HELLO --compress`.
+pub const COMPRESSION: &str = "compression";
+/// The server mirrors another bazaar; listings are changed there, not here.
+pub const MIRROR: &str = "mirror";
+/// Sellers' listings are capped by their standing.
+pub const SELLER_TIERS: &str = "seller-tiers";
+/// `MODELS` and `model:` searches.
+pub const MODELS: &str = "models";
+/// `BUNDLE CREATE`.
+pub const BUNDLES: &str = "bundles";
+/// `ATTEST` and `AUTHENTICATOR`.
+pub const ATTESTATIONS: &str = "attestations";
+/// Signed operator announcements with `NEWS`.
+pub const NEWS: &str = "news";
+/// Seller signatures with `SIGN` and the transparency log with `TLOG` and `PROOF`.
+pub const SIGNING: &str = "signing";
+/// `AWAY` notices on sellers' listings.
+pub const AWAY: &str = "away";
+/// `SAVEDSEARCH` and `NEW`.
+pub const SAVED_SEARCHES: &str = "saved-searches";
+
+/// The capabilities listed in a text `HELLO` reply, or `None` for servers
+/// that predate them (or replies that aren't `HELLO` at all).
+pub fn from_hello(reply: &str) -> Option<Vec<String>> {
+    // Split on single spaces: the session field is empty without sessions
+    let fields: Vec<&str> = reply.trim_end().strip_prefix("HELLO ")?.split(' ').collect();
+    match fields.get(4)? {
+        &"-" => Some(Vec::new()),
+        list => Some(list.split(',').map(str::to_string).collect()),
+    }
+}
@@ -0,0 +1,244 @@
+//! Framing for requests and responses.
+//!
+//! A request is one line of text ending in `\n`, or a batch of lines between
+//! `BATCH` and `END` that is answered with a single frame. A response frame is
+//! a header line holding the payload length in bytes, followed by exactly that
+//! payload:
+//!
+//! ```text
+//! 3\nOK\n
+//! ```
+//!
+//! A compressed frame names its encoding after the length, which is then the
+//! compressed length. Only clients that asked for compression receive these:
+//!
+//! ```text
+//! 812 zstd\n<812 bytes of zstd data>
+//! ```
+
+use crate::command::Command;
+use crate::compression::Compression;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Default longest request line accepted, including the trailing newline.
+pub const MAX_LINE_LEN: usize = 4096;
+
+/// Largest response payload a decoder will accept.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Default smallest payload worth compressing; below this the header overhead wins.
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn encode_request(command: &Command) -> Vec<u8> {
+    format!("{}\n", command).into_bytes()
+}
+
+/// Encodes commands as one `BATCH` request.
+pub fn encode_batch(commands: &[Command]) -> Vec<u8> {
+    let mut batch = b"BATCH\n".to_vec();
+    for command in commands {
+        batch.extend_from_slice(&encode_request(command));
+    }
+    batch.extend_from_slice(b"END\n");
+    batch
+}
+
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = format!("{}\n", payload.len()).into_bytes();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Encodes a frame, compressing payloads of at least `threshold` bytes when
+/// `compression` is set and the result actually comes out smaller.
+pub fn encode_frame_compressed(payload: &[u8], compression: Option<Compression>, threshold: usize) -> io::Result<Vec<u8>> {
+    let Some(compression) = compression.filter(|_| payload.len() >= threshold) else {
+        return Ok(encode_frame(payload));
+    };
+    let compressed = compression.compress(payload)?;
+    if compressed.len() >= payload.len() {
+        return Ok(encode_frame(payload));
+    }
+    let mut frame = format!("{} {}\n", compressed.len(), compression.name()).into_bytes();
+    frame.extend_from_slice(&compressed);
+    Ok(frame)
+}
+
+/// Splits a byte stream into request lines.
+///
+/// Lines over the length limit fail with [`io::ErrorKind::InvalidData`].
+#[derive(Debug)]
+pub struct LineDecoder {
+    buf: Vec<u8>,
+    max_len: usize,
+}
+
+impl Default for LineDecoder {
+    fn default() -> Self {
+        Self::with_max_len(MAX_LINE_LEN)
+    }
+}
+
+impl LineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_len(max_len: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_len,
+        }
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns the next complete line without its terminator, if one is buffered.
+    pub fn next_line(&mut self) -> io::Result<Option<String>> {
+        match self.buf.iter().position(|&b| b == b'\n') {
+            Some(pos) if pos < self.max_len => {
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                Ok(Some(String::from_utf8_lossy(&line).trim_end().to_string()))
+            },
+            Some(_) => Err(invalid_data("request line too long")),
+            None if self.buf.len() >= self.max_len => Err(invalid_data("request line too long")),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Splits a byte stream into length-prefixed response frames.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Returns the next complete frame payload, if one is buffered. Compressed
+    /// frames are decompressed before they're returned.
+    pub fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some((start, len, compression)) = self.next_header()? else {
+            return Ok(None);
+        };
+        let payload: Vec<u8> = self.buf.drain(..start + len).skip(start).collect();
+        match compression {
+            Some(compression) => compression.decompress(&payload, MAX_FRAME_LEN).map(Some),
+            None => Ok(Some(payload)),
+        }
+    }
+
+    /// Returns the next complete frame exactly as it was sent, header included
+    /// and still compressed, for passing frames on without looking inside.
+    pub fn next_encoded_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let Some((start, len, _)) = self.next_header()? else {
+            return Ok(None);
+        };
+        Ok(Some(self.buf.drain(..start + len).collect()))
+    }
+
+    /// The payload offset, length and compression of the first buffered frame,
+    /// once all of it has arrived.
+    fn next_header(&self) -> io::Result<Option<(usize, usize, Option<Compression>)>> {
+        let Some(header_end) = self.buf.iter().position(|&b| b == b'\n') else {
+            if self.buf.len() > 20 {
+                return Err(invalid_data("malformed frame header"));
+            }
+            return Ok(None);
+        };
+
+        let header = std::str::from_utf8(&self.buf[..header_end]).map_err(|_| invalid_data("malformed frame header"))?;
+        let mut fields = header.split_whitespace();
+        let len: usize = fields
+            .next()
+            .and_then(|len| len.parse().ok())
+            .ok_or_else(|| invalid_data("malformed frame header"))?;
+        let compression = match fields.next() {
+            Some(name) => Some(Compression::from_name(name).ok_or_else(|| invalid_data("unknown frame encoding"))?),
+            None => None,
+        };
+        if fields.next().is_some() {
+            return Err(invalid_data("malformed frame header"));
+        }
+        if len > MAX_FRAME_LEN {
+            return Err(invalid_data("frame too large"));
+        }
+
+        let start = header_end + 1;
+        if self.buf.len() < start + len {
+            return Ok(None);
+        }
+        Ok(Some((start, len, compression)))
+    }
+}
+
+/// Reads the next request line, or `None` once the peer has closed the stream.
+pub async fn read_line<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    decoder: &mut LineDecoder,
+) -> io::Result<Option<String>> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        if let Some(line) = decoder.next_line()? {
+            return Ok(Some(line));
+        }
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        decoder.extend(&buffer[..n]);
+    }
+}
+
+/// Reads the next response frame, or `None` once the peer has closed the stream.
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    decoder: &mut FrameDecoder,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        if let Some(frame) = decoder.next_frame()? {
+            return Ok(Some(frame));
+        }
+        let n = reader.read(&mut buffer).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        decoder.extend(&buffer[..n]);
+    }
+}
+
+pub async fn write_request<W: AsyncWrite + Unpin>(writer: &mut W, command: &Command) -> io::Result<()> {
+    writer.write_all(&encode_request(command)).await
+}
+
+pub async fn write_batch<W: AsyncWrite + Unpin>(writer: &mut W, commands: &[Command]) -> io::Result<()> {
+    writer.write_all(&encode_batch(commands)).await
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&encode_frame(payload)).await
+}
+
+pub async fn write_frame_compressed<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+    compression: Option<Compression>,
+    threshold: usize,
+) -> io::Result<()> {
+    writer.write_all(&encode_frame_compressed(payload, compression, threshold)?).await
+}
@@ -0,0 +1,1277 @@
+use crate::compression::Compression;
+use crate::encoding::Encoding;
+use crate::error::BazaarError;
+use crate::i18n::Lang;
+use crate::media::{self, ImageSize};
+use crate::pass::Pass;
+use crate::price::{format_amount, normalize_currency};
+use crate::search::{PriceRange, SearchQuery};
+use crate::shipping::ShippingOptions;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+/// A request sent from a client to the bazaar server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Head,
+    Help,
+    List { category: Option<String>, currency: Option<String>, price: Option<PriceRange> },
+    Get { id: String, currency: Option<String>, range: Option<ByteRange> },
+    Search { query: SearchQuery, currency: Option<String> },
+    /// Listings created within the last `since_secs`, newest first.
+    New { since_secs: Option<u64> },
+    Categories,
+    /// Reference models whose brand or name start with the query's words, see [`crate::model`].
+    Models { query: Option<String> },
+    /// Listings picked by the operator, most recently featured first.
+    Featured,
+    Feature { id: String },
+    Unfeature { id: String },
+    /// One listing picked at random, optionally from a category.
+    Random { category: Option<String> },
+    Status,
+    Auth { key: String },
+    Reload { catalog: bool },
+    Lang { lang: Lang },
+    Sell(NewListing),
+    /// Lists existing listings of one seller together as a lot, at `price`.
+    /// Without a name, the bundle is named after its parts.
+    BundleCreate { items: Vec<String>, price: String, name: Option<String>, credential: Option<String> },
+    Update { id: String, revision: u64, field: ItemField, value: String },
+    Delete { id: String },
+    Restore { id: String },
+    AuditTail { count: Option<usize> },
+    History { id: String },
+    Token(TokenCommand),
+    SavedSearch(SavedSearchCommand),
+    Seller(SellerCommand),
+    Drop(DropCommand),
+    Pass(PassCommand),
+    Upload(UploadCommand),
+    Away(AwayCommand),
+    Attest(AttestCommand),
+    /// Publishes a signed operator announcement, shown by `NEWS`.
+    Announce { text: String },
+    News,
+    Authenticator(AuthenticatorCommand),
+    /// Attaches the seller's signature to the current revision of a listing.
+    Sign { id: String, signature: String },
+    /// Attaches an image to a listing.
+    PutImage { id: String, data: ImageData },
+    /// One chunk of an image by hash, see [`crate::media`], or the bytes of the image in `range`.
+    GetImage { hash: String, chunk: usize, size: ImageSize, range: Option<ByteRange> },
+    DelImage { id: String, hash: String },
+    /// Asks the seller a question about a listing, answered publicly.
+    Ask { id: String, question: String },
+    Answer { question_id: String, answer: String },
+    /// Every question about a listing, answered or not, for its seller.
+    Questions { id: String },
+    /// Inclusion proof for a listing against the catalog root in `STATUS`.
+    Proof { id: String },
+    /// Transparency log entries `from` through `to`, inclusive.
+    TlogRange { from: u64, to: u64 },
+    Export { range: Option<ByteRange> },
+    /// Opens a session, listing the response compression schemes the client
+    /// accepts and optionally switching responses to a structured encoding.
+    /// Passing the token of an earlier session resumes it.
+    Hello { compress: Vec<Compression>, encoding: Option<Encoding>, session: Option<String> },
+}
+
+/// Management of seller API tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenCommand {
+    Create { seller: String, scopes: Vec<TokenScope>, expires_secs: Option<u64> },
+    Revoke { id: String },
+    List,
+}
+
+/// Blind write passes, see [`crate::pass`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassCommand {
+    Challenge,
+    Issue { challenge: String, solution: u64, blinded: Vec<String> },
+    Redeem { passes: Vec<Pass> },
+}
+
+/// A seller's away mode, marking their listings while they can't ship.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AwayCommand {
+    /// Away until turned off, or for `for_secs`.
+    On { for_secs: Option<u64> },
+    Off,
+    Status,
+}
+
+/// Attestations about listings by authenticators, see [`crate::signing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttestCommand {
+    /// Records `authenticator`'s signed `statement` about listing `id`.
+    Add { id: String, authenticator: String, signature: String, statement: String },
+    /// Withdraws an attestation, signed by its authenticator; operators may
+    /// revoke without a signature.
+    Revoke { id: String, signature: Option<String> },
+}
+
+/// The authenticators whose attestations the bazaar accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthenticatorCommand {
+    Add { name: String, public_key: String },
+    /// Stops accepting the authenticator's attestations and revokes the ones it made.
+    Remove { name: String },
+    List,
+}
+
+/// Resumable uploads, see [`crate::upload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadCommand {
+    Begin { size: u64, hash: String },
+    Chunk { id: String, chunk: usize, data: String },
+    Status { id: String },
+    Abort { id: String },
+}
+
+/// Bytes `offset..offset + len` of a payload, asked for with `--range <offset> <len>`
+/// by clients with small buffers or that lost the end of a transfer. For `GET`
+/// and `EXPORT` the payload is the response as it would be sent whole in the
+/// connection's encoding, before compression; for `GETIMAGE` it is the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl ByteRange {
+    /// The part of `data` in range, cut short at its end, or `None` if the range
+    /// starts past the end.
+    pub fn slice<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start = usize::try_from(self.offset).ok().filter(|start| *start <= data.len())?;
+        let end = start.saturating_add(usize::try_from(self.len).unwrap_or(usize::MAX)).min(data.len());
+        Some(&data[start..end])
+    }
+}
+
+/// Where `PUTIMAGE` takes the image from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageData {
+    /// The image itself, base64 encoded.
+    Inline(String),
+    /// A complete upload, by id.
+    Upload(String),
+}
+
+/// Searches an authenticated user keeps on the server to run again later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SavedSearchCommand {
+    Add { name: String, query: SearchQuery },
+    /// Runs a saved search. `new_only` limits results to listings created since its previous run.
+    Run { name: String, new_only: bool },
+    List,
+    Delete { name: String },
+}
+
+/// The seller-name registry. Names are unique ignoring case and bound to the
+/// ed25519 key that registered them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SellerCommand {
+    /// Claims a name. `signature` is over [`registration_message`] with the key's secret half.
+    Register { name: String, public_key: String, signature: String, credential: Option<String> },
+    Info { name: String },
+    Verify { name: String },
+    Unverify { name: String },
+    /// Stops the seller from writing, for `expires_secs` or until lifted.
+    Ban { name: String, expires_secs: Option<u64> },
+    Unban { name: String },
+    /// Records a report against the seller; enough of them ban it for a while.
+    Strike { name: String },
+    /// Revokes every API token issued for the seller.
+    Revoke { name: String },
+}
+
+/// Delivery instructions a seller leaves for a buyer, encrypted end to end. The
+/// server only ever sees ciphertext; the drop ID is the only thing a buyer needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropCommand {
+    Put { id: String, ciphertext: String },
+    Get { id: String },
+}
+
+/// Whether `id` can name a drop: 16 to 64 letters, digits, `-` or `_`. Anyone who
+/// knows an ID can fetch the drop, so it should be random.
+pub fn is_valid_drop_id(id: &str) -> bool {
+    (16..=64).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// What a key signs to register `name`, so a signature can't be replayed for another name.
+pub fn registration_message(name: &str) -> Vec<u8> {
+    format!("nym-bazaar seller registration\n{}", name).into_bytes()
+}
+
+/// Whether `name` can be registered: 3 to 32 letters, digits, `.`, `-` or `_`.
+pub fn is_valid_seller_name(name: &str) -> bool {
+    (3..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+/// What a seller API token may be used for. `Delete` also covers `RESTORE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Sell,
+    Update,
+    Delete,
+}
+
+impl TokenScope {
+    pub const ALL: &'static [TokenScope] = &[TokenScope::Sell, TokenScope::Update, TokenScope::Delete];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenScope::Sell => "sell",
+            TokenScope::Update => "update",
+            TokenScope::Delete => "delete",
+        }
+    }
+
+    /// Parses a comma separated scope list such as `sell,update`.
+    pub fn parse_list(list: &str) -> Option<Vec<TokenScope>> {
+        list.split(',')
+            .map(|name| TokenScope::ALL.iter().copied().find(|s| s.name().eq_ignore_ascii_case(name.trim())))
+            .collect()
+    }
+}
+
+/// Formats scopes the way [`TokenScope::parse_list`] reads them.
+pub fn format_scopes(scopes: &[TokenScope]) -> String {
+    scopes.iter().map(|s| s.name()).collect::<Vec<_>>().join(",")
+}
+
+/// The fields of a listing as submitted with `SELL`. The server assigns the ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewListing {
+    pub category: String,
+    pub seller: String,
+    pub price: String,
+    pub name: String,
+    pub description: String,
+    /// Anonymous credential (zk-nym) for servers that require one to list.
+    pub credential: Option<String>,
+    /// Unix time to put the listing live at, for timed drops. Until then it isn't shown.
+    pub publish_at: Option<u64>,
+    /// How the item can be delivered, see [`crate::shipping`].
+    pub shipping: ShippingOptions,
+}
+
+/// A listing field that can be changed with `UPDATE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemField {
+    Name,
+    Category,
+    Price,
+    Description,
+    Shipping,
+    Model,
+}
+
+impl ItemField {
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemField::Name => "name",
+            ItemField::Category => "category",
+            ItemField::Price => "price",
+            ItemField::Description => "description",
+            ItemField::Shipping => "shipping",
+            ItemField::Model => "model",
+        }
+    }
+
+    fn parse(name: &str) -> Option<ItemField> {
+        [ItemField::Name, ItemField::Category, ItemField::Price, ItemField::Description, ItemField::Shipping, ItemField::Model]
+            .into_iter()
+            .find(|field| field.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl Command {
+    pub const USAGE: &'static [&'static str] = &[
+        "HEAD",
+        "HELP",
+        "LIST [category] [--min <amount>] [--max <amount>] [--currency <code>]",
+        "GET <id> [--currency <code>] [--range <offset> <len>]",
+        "SEARCH <words...> [category:<name>] [seller:<name>] [model:<model-id>] [price:<min>..<max>] [sort:price|-price|newest|oldest|name] [--currency <code>]",
+        "NEW [since <duration, e.g. 12h or 7d>]",
+        "CATEGORIES",
+        "MODELS [brand or model...]",
+        "FEATURED",
+        "FEATURE <id>",
+        "UNFEATURE <id>",
+        "RANDOM [category]",
+        "STATUS",
+        "AUTH <key>",
+        "RELOAD [CATALOG]",
+        "LANG <en|de|es>",
+        "SELL [--credential <zk-nym>] [--publish-at <unix-time>] [--shipping <options>] <category> <seller> <price> <name...> [-- <description...>]",
+        "BUNDLE CREATE [--credential <zk-nym>] <id1,id2,...> <price> [name...]",
+        "UPDATE <id> <rev> <name|category|price|description|shipping|model> <value...>",
+        "DELETE <id>",
+        "RESTORE <id>",
+        "AUDIT TAIL [count]",
+        "HISTORY <id>",
+        "TOKEN CREATE <seller> --scope <sell,update,delete> [--expires <secs>]",
+        "TOKEN REVOKE <token-id>",
+        "TOKEN LIST",
+        "SAVEDSEARCH ADD <name> <query...>",
+        "SAVEDSEARCH RUN <name> [--new]",
+        "SAVEDSEARCH LIST",
+        "SAVEDSEARCH DELETE <name>",
+        "SELLER REGISTER [--credential <zk-nym>] <name> <public-key-hex> <signature-hex>",
+        "SELLER INFO <name>",
+        "SELLER VERIFY <name>",
+        "SELLER UNVERIFY <name>",
+        "SELLER BAN <name> [--expires <secs>]",
+        "SELLER UNBAN <name>",
+        "SELLER STRIKE <name>",
+        "SELLER REVOKE <name>",
+        "SIGN <id> <signature-hex>",
+        "PUTIMAGE <id> <base64>",
+        "PUTIMAGE <id> --upload <upload-id>",
+        "GETIMAGE <hash> [--chunk <n> | --range <offset> <len>] [--size <full|medium|thumb>]",
+        "DELIMAGE <id> <hash>",
+        "ASK <id> <question...>",
+        "ANSWER <question-id> <answer...>",
+        "QUESTIONS <id>",
+        "PROOF <id>",
+        "TLOG RANGE <from> <to>",
+        "DROP PUT <drop-id> <ciphertext>",
+        "DROP GET <drop-id>",
+        "PASS CHALLENGE",
+        "PASS ISSUE <challenge> <solution> <blinded-pass...>",
+        "PASS REDEEM <pass...>",
+        "AWAY [ON [duration, e.g. 14d] | OFF]",
+        "NEWS",
+        "ANNOUNCE <text...>",
+        "ATTEST <id> <authenticator> <signature> <statement...>",
+        "ATTEST REVOKE <attestation-id> [signature]",
+        "AUTHENTICATOR ADD <name> <public-key>",
+        "AUTHENTICATOR REMOVE <name>",
+        "AUTHENTICATOR LIST",
+        "UPLOAD BEGIN <size> <sha256-hex>",
+        "UPLOAD CHUNK <upload-id> <n> <base64>",
+        "UPLOAD STATUS <upload-id>",
+        "UPLOAD ABORT <upload-id>",
+        "EXPORT [--range <offset> <len>]",
+        "HELLO [--compress <zstd,gzip>] [--encoding <text|json|cbor>] [--session <token>]",
+        "BATCH (then one command per line, then END)",
+        "BATCH --upload <upload-id>",
+    ];
+
+    /// The command keyword, as used on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Head => "HEAD",
+            Command::Help => "HELP",
+            Command::List { .. } => "LIST",
+            Command::Get { .. } => "GET",
+            Command::Search { .. } => "SEARCH",
+            Command::New { .. } => "NEW",
+            Command::Categories => "CATEGORIES",
+            Command::Models { .. } => "MODELS",
+            Command::Featured => "FEATURED",
+            Command::Feature { .. } => "FEATURE",
+            Command::Unfeature { .. } => "UNFEATURE",
+            Command::Random { .. } => "RANDOM",
+            Command::Status => "STATUS",
+            Command::Auth { .. } => "AUTH",
+            Command::Reload { .. } => "RELOAD",
+            Command::Lang { .. } => "LANG",
+            Command::Sell(_) => "SELL",
+            Command::BundleCreate { .. } => "BUNDLE",
+            Command::Update { .. } => "UPDATE",
+            Command::Delete { .. } => "DELETE",
+            Command::Restore { .. } => "RESTORE",
+            Command::AuditTail { .. } => "AUDIT",
+            Command::History { .. } => "HISTORY",
+            Command::Token(_) => "TOKEN",
+            Command::SavedSearch(_) => "SAVEDSEARCH",
+            Command::Seller(_) => "SELLER",
+            Command::Drop(_) => "DROP",
+            Command::Pass(_) => "PASS",
+            Command::Upload(_) => "UPLOAD",
+            Command::Away(_) => "AWAY",
+            Command::Attest(_) => "ATTEST",
+            Command::Announce { .. } => "ANNOUNCE",
+            Command::News => "NEWS",
+            Command::Authenticator(_) => "AUTHENTICATOR",
+            Command::Sign { .. } => "SIGN",
+            Command::PutImage { .. } => "PUTIMAGE",
+            Command::GetImage { .. } => "GETIMAGE",
+            Command::DelImage { .. } => "DELIMAGE",
+            Command::Ask { .. } => "ASK",
+            Command::Answer { .. } => "ANSWER",
+            Command::Questions { .. } => "QUESTIONS",
+            Command::Proof { .. } => "PROOF",
+            Command::TlogRange { .. } => "TLOG",
+            Command::Export { .. } => "EXPORT",
+            Command::Hello { .. } => "HELLO",
+        }
+    }
+
+    /// Whether the command changes server state, which read-only mirrors refuse.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Sell(_)
+                | Command::BundleCreate { .. }
+                | Command::Update { .. }
+                | Command::Delete { .. }
+                | Command::Restore { .. }
+                | Command::Token(_)
+                | Command::Feature { .. }
+                | Command::Unfeature { .. }
+                | Command::Seller(
+                    SellerCommand::Register { .. }
+                        | SellerCommand::Verify { .. }
+                        | SellerCommand::Unverify { .. }
+                        | SellerCommand::Ban { .. }
+                        | SellerCommand::Unban { .. }
+                        | SellerCommand::Strike { .. }
+                        | SellerCommand::Revoke { .. }
+                )
+                | Command::Drop(DropCommand::Put { .. })
+                | Command::Sign { .. }
+                | Command::PutImage { .. }
+                | Command::DelImage { .. }
+                | Command::Away(AwayCommand::On { .. } | AwayCommand::Off)
+                | Command::Attest(_)
+                | Command::Announce { .. }
+                | Command::Authenticator(AuthenticatorCommand::Add { .. } | AuthenticatorCommand::Remove { .. })
+                | Command::Ask { .. }
+                | Command::Answer { .. }
+        )
+    }
+
+    /// Parses a single request line. Command names are case-insensitive, and
+    /// arguments may be double-quoted to include spaces (see [`tokenize`]).
+    pub fn parse(line: &str) -> Result<Command, BazaarError> {
+        let tokens = tokenize(line)?;
+        let mut parts: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        let arg = |n: usize| parts.get(n).map(|s| s.to_string());
+
+        match parts.first().map(|s| s.to_uppercase()).as_deref() {
+            Some("HEAD") => Ok(Command::Head),
+            Some("HELP") => Ok(Command::Help),
+            Some("LIST") => {
+                let bound = |amount: Option<String>| amount.map(|a| PriceRange::parse_amount(&a)).transpose();
+                let min = bound(take_flag(&mut parts, "--min")?)?;
+                let max = bound(take_flag(&mut parts, "--max")?)?;
+                let currency = take_currency(&mut parts)?;
+                let price = match (min, max) {
+                    (None, None) => None,
+                    (min, max) => Some(PriceRange::new(min, max)?),
+                };
+                let category = Some(parts[1..].join(" ")).filter(|c| !c.is_empty());
+                Ok(Command::List { category, currency, price })
+            },
+            Some("GET") => {
+                let range = take_range(&mut parts)?;
+                let currency = take_currency(&mut parts)?;
+                Ok(Command::Get {
+                    id: parts.get(1).ok_or(BazaarError::MissingArgument)?.to_string(),
+                    currency,
+                    range,
+                })
+            },
+            Some("SEARCH") => {
+                let currency = take_currency(&mut parts)?;
+                Ok(Command::Search {
+                    query: SearchQuery::parse(parts.get(1..).unwrap_or_default())?,
+                    currency,
+                })
+            },
+            Some("NEW") => {
+                // `since` is optional sugar: `NEW since 7d` and `NEW 7d` are the same
+                let duration = match parts.get(1) {
+                    Some(word) if word.eq_ignore_ascii_case("since") => parts.get(2).ok_or(BazaarError::MissingArgument)?,
+                    Some(word) => word,
+                    None => return Ok(Command::New { since_secs: None }),
+                };
+                let since_secs = parse_duration(duration).ok_or(BazaarError::InvalidCommand)?;
+                Ok(Command::New { since_secs: Some(since_secs) })
+            },
+            Some("CATEGORIES") => Ok(Command::Categories),
+            Some("MODELS") => Ok(Command::Models {
+                query: Some(parts[1..].join(" ")).filter(|query| !query.is_empty()),
+            }),
+            Some("FEATURED") => Ok(Command::Featured),
+            Some("FEATURE") => Ok(Command::Feature {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("UNFEATURE") => Ok(Command::Unfeature {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("RANDOM") => Ok(Command::Random {
+                category: Some(parts[1..].join(" ")).filter(|c| !c.is_empty()),
+            }),
+            Some("STATUS") => Ok(Command::Status),
+            Some("AUTH") => Ok(Command::Auth {
+                key: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("RELOAD") => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                None => Ok(Command::Reload { catalog: false }),
+                Some("CATALOG") => Ok(Command::Reload { catalog: true }),
+                Some(_) => Err(BazaarError::InvalidCommand),
+            },
+            Some("LANG") => {
+                let code = parts.get(1).ok_or(BazaarError::MissingArgument)?;
+                let lang = Lang::from_code(code).ok_or(BazaarError::UnsupportedLanguage)?;
+                Ok(Command::Lang { lang })
+            },
+            Some("SELL") => {
+                let [credential, publish_at, shipping] = take_leading_flags(&mut parts, 1, ["--credential", "--publish-at", "--shipping"])?;
+                let publish_at = publish_at.map(|at| at.parse().map_err(|_| BazaarError::InvalidCommand)).transpose()?;
+                let shipping = shipping.map(|s| s.parse()).transpose()?.unwrap_or_default();
+                let [_, category, seller, price, rest @ ..] = parts.as_slice() else {
+                    return Err(BazaarError::MissingArgument);
+                };
+                let (name, description) = match rest.iter().position(|p| *p == "--") {
+                    Some(pos) => (&rest[..pos], &rest[pos + 1..]),
+                    None => (rest, &[][..]),
+                };
+                if name.is_empty() {
+                    return Err(BazaarError::MissingArgument);
+                }
+                Ok(Command::Sell(NewListing {
+                    category: category.to_string(),
+                    seller: seller.to_string(),
+                    price: price.to_string(),
+                    name: name.join(" "),
+                    description: description.join(" "),
+                    credential,
+                    publish_at,
+                    shipping,
+                }))
+            },
+            Some("UPDATE") => {
+                let [_, id, revision, field, value @ ..] = parts.as_slice() else {
+                    return Err(BazaarError::MissingArgument);
+                };
+                let revision = revision.parse().map_err(|_| BazaarError::InvalidCommand)?;
+                let field = ItemField::parse(field).ok_or(BazaarError::InvalidCommand)?;
+                let value = value.join(" ");
+                // Descriptions, shipping options and models may be cleared, everything else needs a value
+                if value.is_empty() && !matches!(field, ItemField::Description | ItemField::Shipping | ItemField::Model) {
+                    return Err(BazaarError::MissingArgument);
+                }
+                if field == ItemField::Shipping {
+                    value.parse::<ShippingOptions>()?;
+                }
+                Ok(Command::Update {
+                    id: id.to_string(),
+                    revision,
+                    field,
+                    value,
+                })
+            },
+            Some("DELETE") => Ok(Command::Delete {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("RESTORE") => Ok(Command::Restore {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("AUDIT") => {
+                if !parts.get(1).is_some_and(|sub| sub.eq_ignore_ascii_case("TAIL")) {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                let count = parts
+                    .get(2)
+                    .map(|n| n.parse().map_err(|_| BazaarError::InvalidCommand))
+                    .transpose()?;
+                Ok(Command::AuditTail { count })
+            },
+            Some("HISTORY") => Ok(Command::History {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("TOKEN") => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                Some("CREATE") => {
+                    let scopes = take_flag(&mut parts, "--scope")?.ok_or(BazaarError::MissingArgument)?;
+                    let scopes = TokenScope::parse_list(&scopes).ok_or(BazaarError::InvalidCommand)?;
+                    let expires_secs = take_flag(&mut parts, "--expires")?
+                        .map(|secs| secs.parse().map_err(|_| BazaarError::InvalidCommand))
+                        .transpose()?;
+                    let seller = parts.get(2).ok_or(BazaarError::MissingArgument)?.to_string();
+                    Ok(Command::Token(TokenCommand::Create { seller, scopes, expires_secs }))
+                },
+                Some("REVOKE") => Ok(Command::Token(TokenCommand::Revoke {
+                    id: parts.get(2).ok_or(BazaarError::MissingArgument)?.to_string(),
+                })),
+                Some("LIST") => Ok(Command::Token(TokenCommand::List)),
+                Some(_) => Err(BazaarError::InvalidCommand),
+                None => Err(BazaarError::MissingArgument),
+            },
+            Some("PASS") => match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                Some("CHALLENGE") => Ok(Command::Pass(PassCommand::Challenge)),
+                Some("ISSUE") => {
+                    let challenge = parts.get(2).ok_or(BazaarError::MissingArgument)?.to_string();
+                    let solution = parts.get(3).ok_or(BazaarError::MissingArgument)?;
+                    let solution = solution.parse().map_err(|_| BazaarError::InvalidCommand)?;
+                    let blinded: Vec<String> = parts.get(4..).unwrap_or_default().iter().map(|s| s.to_string()).collect();
+                    if blinded.is_empty() {
+                        return Err(BazaarError::MissingArgument);
+                    }
+                    Ok(Command::Pass(PassCommand::Issue { challenge, solution, blinded }))
+                },
+                Some("REDEEM") => {
+                    let passes = parts.get(2..).unwrap_or_default().iter().map(|s| s.parse()).collect::<Result<Vec<Pass>, _>>()?;
+                    if passes.is_empty() {
+                        return Err(BazaarError::MissingArgument);
+                    }
+                    Ok(Command::Pass(PassCommand::Redeem { passes }))
+                },
+                Some(_) => Err(BazaarError::InvalidCommand),
+                None => Err(BazaarError::MissingArgument),
+            },
+            Some("SAVEDSEARCH") => {
+                let name = || parts.get(2).map(|s| s.to_string()).ok_or(BazaarError::MissingArgument);
+                let command = match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    Some("ADD") => SavedSearchCommand::Add {
+                        name: name()?,
+                        query: SearchQuery::parse(parts.get(3..).unwrap_or_default())?,
+                    },
+                    Some("RUN") => SavedSearchCommand::Run {
+                        name: name()?,
+                        new_only: parts.get(3).is_some_and(|flag| flag.eq_ignore_ascii_case("--new")),
+                    },
+                    Some("LIST") => SavedSearchCommand::List,
+                    Some("DELETE") => SavedSearchCommand::Delete { name: name()? },
+                    Some(_) => return Err(BazaarError::InvalidCommand),
+                    None => return Err(BazaarError::MissingArgument),
+                };
+                Ok(Command::SavedSearch(command))
+            },
+            Some("SELLER") => {
+                let expires_secs = take_flag(&mut parts, "--expires")?
+                    .map(|secs| secs.parse().map_err(|_| BazaarError::InvalidCommand))
+                    .transpose()?;
+                let [credential] = match parts.get(1) {
+                    Some(sub) if sub.eq_ignore_ascii_case("REGISTER") => take_leading_flags(&mut parts, 2, ["--credential"])?,
+                    _ => [None],
+                };
+                let name = parts.get(2).ok_or(BazaarError::MissingArgument)?.to_string();
+                let command = match parts[1].to_uppercase().as_str() {
+                    "REGISTER" => {
+                        let [public_key, signature] = parts[3..] else {
+                            return Err(BazaarError::MissingArgument);
+                        };
+                        if !is_valid_seller_name(&name) {
+                            return Err(BazaarError::InvalidCommand);
+                        }
+                        SellerCommand::Register {
+                            name,
+                            public_key: public_key.to_string(),
+                            signature: signature.to_string(),
+                            credential,
+                        }
+                    },
+                    "INFO" => SellerCommand::Info { name },
+                    "VERIFY" => SellerCommand::Verify { name },
+                    "UNVERIFY" => SellerCommand::Unverify { name },
+                    "BAN" => SellerCommand::Ban { name, expires_secs },
+                    "UNBAN" => SellerCommand::Unban { name },
+                    "STRIKE" => SellerCommand::Strike { name },
+                    "REVOKE" => SellerCommand::Revoke { name },
+                    _ => return Err(BazaarError::InvalidCommand),
+                };
+                Ok(Command::Seller(command))
+            },
+            Some("PUTIMAGE") => {
+                let upload = take_flag(&mut parts, "--upload")?;
+                let id = parts.get(1).ok_or(BazaarError::MissingArgument)?.to_string();
+                let data = match upload {
+                    Some(upload) => ImageData::Upload(upload),
+                    None => ImageData::Inline(parts.get(2).ok_or(BazaarError::MissingArgument)?.to_string()),
+                };
+                Ok(Command::PutImage { id, data })
+            },
+            Some("BUNDLE") => {
+                if !parts.get(1).is_some_and(|sub| sub.eq_ignore_ascii_case("CREATE")) {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                let [credential] = take_leading_flags(&mut parts, 2, ["--credential"])?;
+                let items: Vec<String> = parts
+                    .get(2)
+                    .ok_or(BazaarError::MissingArgument)?
+                    .split(',')
+                    .filter(|id| !id.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                let price = parts.get(3).ok_or(BazaarError::MissingArgument)?.to_string();
+                let name = Some(parts.get(4..).unwrap_or_default().join(" ")).filter(|name| !name.is_empty());
+                Ok(Command::BundleCreate { items, price, name, credential })
+            },
+            Some("AWAY") => {
+                let command = match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    None => AwayCommand::Status,
+                    Some("ON") => AwayCommand::On {
+                        for_secs: parts.get(2).map(|d| parse_duration(d).filter(|secs| *secs > 0).ok_or(BazaarError::InvalidCommand)).transpose()?,
+                    },
+                    Some("OFF") => AwayCommand::Off,
+                    Some(_) => return Err(BazaarError::InvalidCommand),
+                };
+                Ok(Command::Away(command))
+            },
+            Some("NEWS") => Ok(Command::News),
+            Some("ANNOUNCE") => {
+                let text = parts.get(1..).unwrap_or_default().join(" ");
+                if text.is_empty() {
+                    return Err(BazaarError::MissingArgument);
+                }
+                Ok(Command::Announce { text })
+            },
+            Some("ATTEST") => {
+                let command = match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    Some("REVOKE") => AttestCommand::Revoke {
+                        id: arg(2).ok_or(BazaarError::MissingArgument)?.to_lowercase(),
+                        signature: arg(3),
+                    },
+                    _ => {
+                        let [_, id, authenticator, signature, statement @ ..] = parts.as_slice() else {
+                            return Err(BazaarError::MissingArgument);
+                        };
+                        if statement.is_empty() {
+                            return Err(BazaarError::MissingArgument);
+                        }
+                        AttestCommand::Add {
+                            id: id.to_string(),
+                            authenticator: authenticator.to_string(),
+                            signature: signature.to_string(),
+                            statement: statement.join(" "),
+                        }
+                    },
+                };
+                Ok(Command::Attest(command))
+            },
+            Some("AUTHENTICATOR") => {
+                let command = match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    Some("ADD") => {
+                        let [_, _, name, public_key] = parts.as_slice() else {
+                            return Err(BazaarError::MissingArgument);
+                        };
+                        if !is_valid_seller_name(name) {
+                            return Err(BazaarError::InvalidCommand);
+                        }
+                        AuthenticatorCommand::Add { name: name.to_string(), public_key: public_key.to_lowercase() }
+                    },
+                    Some("REMOVE") => AuthenticatorCommand::Remove { name: arg(2).ok_or(BazaarError::MissingArgument)? },
+                    Some("LIST") => AuthenticatorCommand::List,
+                    _ => return Err(BazaarError::InvalidCommand),
+                };
+                Ok(Command::Authenticator(command))
+            },
+            Some("UPLOAD") => {
+                let id = || parts.get(2).map(|id| id.to_lowercase()).ok_or(BazaarError::MissingArgument);
+                let number = |i: usize| -> Result<u64, BazaarError> {
+                    parts.get(i).ok_or(BazaarError::MissingArgument)?.parse().map_err(|_| BazaarError::InvalidCommand)
+                };
+                let command = match parts.get(1).map(|s| s.to_uppercase()).as_deref() {
+                    Some("BEGIN") => {
+                        let size = number(2)?;
+                        let hash = parts.get(3).ok_or(BazaarError::MissingArgument)?.to_lowercase();
+                        if !media::is_hash(&hash) {
+                            return Err(BazaarError::InvalidCommand);
+                        }
+                        UploadCommand::Begin { size, hash }
+                    },
+                    Some("CHUNK") => UploadCommand::Chunk {
+                        id: id()?,
+                        chunk: number(3)? as usize,
+                        data: parts.get(4).ok_or(BazaarError::MissingArgument)?.to_string(),
+                    },
+                    Some("STATUS") => UploadCommand::Status { id: id()? },
+                    Some("ABORT") => UploadCommand::Abort { id: id()? },
+                    Some(_) => return Err(BazaarError::InvalidCommand),
+                    None => return Err(BazaarError::MissingArgument),
+                };
+                Ok(Command::Upload(command))
+            },
+            Some("GETIMAGE") => {
+                let chunk = take_flag(&mut parts, "--chunk")?
+                    .map(|n| n.parse().map_err(|_| BazaarError::InvalidCommand))
+                    .transpose()?;
+                let size = take_flag(&mut parts, "--size")?
+                    .map(|size| ImageSize::parse(&size).ok_or(BazaarError::InvalidCommand))
+                    .transpose()?;
+                let range = take_range(&mut parts)?;
+                let hash = parts.get(1).ok_or(BazaarError::MissingArgument)?.to_lowercase();
+                if !media::is_hash(&hash) {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                if chunk.is_some() && range.is_some() {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                Ok(Command::GetImage { hash, chunk: chunk.unwrap_or_default(), size: size.unwrap_or_default(), range })
+            },
+            Some("DELIMAGE") => Ok(Command::DelImage {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+                hash: arg(2).ok_or(BazaarError::MissingArgument)?.to_lowercase(),
+            }),
+            Some("SIGN") => Ok(Command::Sign {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+                signature: arg(2).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("ASK") => {
+                let question = parts.get(2..).unwrap_or_default().join(" ");
+                if question.is_empty() {
+                    return Err(BazaarError::MissingArgument);
+                }
+                Ok(Command::Ask { id: arg(1).ok_or(BazaarError::MissingArgument)?, question })
+            },
+            Some("ANSWER") => {
+                let answer = parts.get(2..).unwrap_or_default().join(" ");
+                if answer.is_empty() {
+                    return Err(BazaarError::MissingArgument);
+                }
+                Ok(Command::Answer { question_id: arg(1).ok_or(BazaarError::MissingArgument)?.to_lowercase(), answer })
+            },
+            Some("QUESTIONS") => Ok(Command::Questions {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("PROOF") => Ok(Command::Proof {
+                id: arg(1).ok_or(BazaarError::MissingArgument)?,
+            }),
+            Some("TLOG") => {
+                if !parts.get(1).is_some_and(|sub| sub.eq_ignore_ascii_case("RANGE")) {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                let seq = |i: usize| -> Result<u64, BazaarError> {
+                    parts.get(i).ok_or(BazaarError::MissingArgument)?.parse().map_err(|_| BazaarError::InvalidCommand)
+                };
+                let (from, to) = (seq(2)?, seq(3)?);
+                if from > to {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                Ok(Command::TlogRange { from, to })
+            },
+            Some("DROP") => {
+                let id = arg(2).ok_or(BazaarError::MissingArgument)?;
+                if !is_valid_drop_id(&id) {
+                    return Err(BazaarError::InvalidCommand);
+                }
+                let command = match parts[1].to_uppercase().as_str() {
+                    "PUT" => DropCommand::Put {
+                        id,
+                        ciphertext: arg(3).ok_or(BazaarError::MissingArgument)?,
+                    },
+                    "GET" => DropCommand::Get { id },
+                    _ => return Err(BazaarError::InvalidCommand),
+                };
+                Ok(Command::Drop(command))
+            },
+            Some("EXPORT") => Ok(Command::Export { range: take_range(&mut parts)? }),
+            Some("HELLO") => {
+                // Unknown schemes are skipped so clients can offer newer ones to older servers
+                let compress = take_flag(&mut parts, "--compress")?
+                    .map(|list| list.split(',').filter_map(|name| Compression::from_name(name.trim())).collect())
+                    .unwrap_or_default();
+                let encoding = take_flag(&mut parts, "--encoding")?
+                    .map(|name| Encoding::from_name(&name).ok_or(BazaarError::InvalidCommand))
+                    .transpose()?;
+                let session = take_flag(&mut parts, "--session")?;
+                Ok(Command::Hello { compress, encoding, session })
+            },
+            _ => Err(BazaarError::InvalidCommand),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Head => write!(f, "HEAD"),
+            Command::Help => write!(f, "HELP"),
+            Command::List { category, currency, price } => {
+                write!(f, "LIST")?;
+                if let Some(category) = category {
+                    write!(f, " {}", quote(category))?;
+                }
+                if let Some(price) = price {
+                    write_flag(f, "--min", &price.min.map(format_amount))?;
+                    write_flag(f, "--max", &price.max.map(format_amount))?;
+                }
+                write_flag(f, "--currency", currency)
+            },
+            Command::Get { id, currency, range } => {
+                write!(f, "GET {}", id)?;
+                write_flag(f, "--currency", currency)?;
+                write_range(f, range)
+            },
+            Command::Search { query, currency } => {
+                write!(f, "SEARCH {}", query)?;
+                write_flag(f, "--currency", currency)
+            },
+            Command::New { since_secs: None } => write!(f, "NEW"),
+            Command::New { since_secs: Some(secs) } => write!(f, "NEW since {}", format_duration(*secs)),
+            Command::Categories => write!(f, "CATEGORIES"),
+            Command::Models { query: None } => write!(f, "MODELS"),
+            Command::Models { query: Some(query) } => write!(f, "MODELS {}", query),
+            Command::Featured => write!(f, "FEATURED"),
+            Command::Feature { id } => write!(f, "FEATURE {}", id),
+            Command::Unfeature { id } => write!(f, "UNFEATURE {}", id),
+            Command::Random { category: None } => write!(f, "RANDOM"),
+            Command::Random { category: Some(category) } => write!(f, "RANDOM {}", quote(category)),
+            Command::Status => write!(f, "STATUS"),
+            Command::Auth { key } => write!(f, "AUTH {}", quote(key)),
+            Command::Reload { catalog: false } => write!(f, "RELOAD"),
+            Command::Reload { catalog: true } => write!(f, "RELOAD CATALOG"),
+            Command::Lang { lang } => write!(f, "LANG {}", lang.code()),
+            Command::Sell(listing) => {
+                write!(f, "SELL")?;
+                write_flag(f, "--credential", &listing.credential)?;
+                write_flag(f, "--publish-at", &listing.publish_at.map(|at| at.to_string()))?;
+                write_flag(f, "--shipping", &(!listing.shipping.is_empty()).then(|| listing.shipping.to_string()))?;
+                write!(
+                    f,
+                    " {} {} {} {}",
+                    quote(&listing.category),
+                    quote(&listing.seller),
+                    quote(&listing.price),
+                    quote(&listing.name)
+                )?;
+                if !listing.description.is_empty() {
+                    write!(f, " -- {}", quote(&listing.description))?;
+                }
+                Ok(())
+            },
+            Command::Update { id, revision, field, value } => {
+                write!(f, "UPDATE {} {} {} {}", id, revision, field.name(), quote(value))
+            },
+            Command::Delete { id } => write!(f, "DELETE {}", id),
+            Command::Restore { id } => write!(f, "RESTORE {}", id),
+            Command::AuditTail { count: None } => write!(f, "AUDIT TAIL"),
+            Command::AuditTail { count: Some(count) } => write!(f, "AUDIT TAIL {}", count),
+            Command::History { id } => write!(f, "HISTORY {}", id),
+            Command::Token(TokenCommand::Create { seller, scopes, expires_secs }) => {
+                write!(f, "TOKEN CREATE {} --scope {}", quote(seller), format_scopes(scopes))?;
+                write_flag(f, "--expires", &expires_secs.map(|secs| secs.to_string()))
+            },
+            Command::Token(TokenCommand::Revoke { id }) => write!(f, "TOKEN REVOKE {}", id),
+            Command::Token(TokenCommand::List) => write!(f, "TOKEN LIST"),
+            Command::SavedSearch(SavedSearchCommand::Add { name, query }) => {
+                write!(f, "SAVEDSEARCH ADD {} {}", quote(name), query)
+            },
+            Command::SavedSearch(SavedSearchCommand::Run { name, new_only }) => {
+                write!(f, "SAVEDSEARCH RUN {}{}", quote(name), if *new_only { " --new" } else { "" })
+            },
+            Command::SavedSearch(SavedSearchCommand::List) => write!(f, "SAVEDSEARCH LIST"),
+            Command::Seller(SellerCommand::Register { name, public_key, signature, credential }) => {
+                write!(f, "SELLER REGISTER")?;
+                write_flag(f, "--credential", credential)?;
+                write!(f, " {} {} {}", name, public_key, signature)
+            },
+            Command::Sign { id, signature } => write!(f, "SIGN {} {}", id, signature),
+            Command::PutImage { id, data: ImageData::Inline(data) } => write!(f, "PUTIMAGE {} {}", id, data),
+            Command::PutImage { id, data: ImageData::Upload(upload) } => write!(f, "PUTIMAGE {} --upload {}", id, upload),
+            Command::GetImage { hash, chunk, size, range } => {
+                write!(f, "GETIMAGE {}", hash)?;
+                match range {
+                    Some(_) => write_range(f, range)?,
+                    None => write!(f, " --chunk {}", chunk)?,
+                }
+                if *size != ImageSize::Full {
+                    write!(f, " --size {}", size.name())?;
+                }
+                Ok(())
+            },
+            Command::DelImage { id, hash } => write!(f, "DELIMAGE {} {}", id, hash),
+            Command::Ask { id, question } => write!(f, "ASK {} {}", id, quote(question)),
+            Command::Answer { question_id, answer } => write!(f, "ANSWER {} {}", question_id, quote(answer)),
+            Command::Questions { id } => write!(f, "QUESTIONS {}", id),
+            Command::Proof { id } => write!(f, "PROOF {}", id),
+            Command::TlogRange { from, to } => write!(f, "TLOG RANGE {} {}", from, to),
+            Command::Drop(DropCommand::Put { id, ciphertext }) => write!(f, "DROP PUT {} {}", id, ciphertext),
+            Command::Drop(DropCommand::Get { id }) => write!(f, "DROP GET {}", id),
+            Command::Pass(PassCommand::Challenge) => write!(f, "PASS CHALLENGE"),
+            Command::Pass(PassCommand::Issue { challenge, solution, blinded }) => {
+                write!(f, "PASS ISSUE {} {} {}", challenge, solution, blinded.join(" "))
+            },
+            Command::Pass(PassCommand::Redeem { passes }) => {
+                write!(f, "PASS REDEEM")?;
+                passes.iter().try_for_each(|pass| write!(f, " {}", pass))
+            },
+            Command::BundleCreate { items, price, name, credential } => {
+                write!(f, "BUNDLE CREATE")?;
+                write_flag(f, "--credential", credential)?;
+                write!(f, " {} {}", items.join(","), price)?;
+                match name {
+                    Some(name) => write!(f, " {}", quote(name)),
+                    None => Ok(()),
+                }
+            },
+            Command::Away(AwayCommand::On { for_secs: None }) => write!(f, "AWAY ON"),
+            Command::Away(AwayCommand::On { for_secs: Some(secs) }) => write!(f, "AWAY ON {}", format_duration(*secs)),
+            Command::Away(AwayCommand::Off) => write!(f, "AWAY OFF"),
+            Command::Away(AwayCommand::Status) => write!(f, "AWAY"),
+            Command::News => write!(f, "NEWS"),
+            Command::Announce { text } => write!(f, "ANNOUNCE {}", quote(text)),
+            Command::Attest(AttestCommand::Add { id, authenticator, signature, statement }) => {
+                write!(f, "ATTEST {} {} {} {}", id, authenticator, signature, quote(statement))
+            },
+            Command::Attest(AttestCommand::Revoke { id, signature: None }) => write!(f, "ATTEST REVOKE {}", id),
+            Command::Attest(AttestCommand::Revoke { id, signature: Some(signature) }) => {
+                write!(f, "ATTEST REVOKE {} {}", id, signature)
+            },
+            Command::Authenticator(AuthenticatorCommand::Add { name, public_key }) => {
+                write!(f, "AUTHENTICATOR ADD {} {}", name, public_key)
+            },
+            Command::Authenticator(AuthenticatorCommand::Remove { name }) => write!(f, "AUTHENTICATOR REMOVE {}", name),
+            Command::Authenticator(AuthenticatorCommand::List) => write!(f, "AUTHENTICATOR LIST"),
+            Command::Upload(UploadCommand::Begin { size, hash }) => write!(f, "UPLOAD BEGIN {} {}", size, hash),
+            Command::Upload(UploadCommand::Chunk { id, chunk, data }) => write!(f, "UPLOAD CHUNK {} {} {}", id, chunk, data),
+            Command::Upload(UploadCommand::Status { id }) => write!(f, "UPLOAD STATUS {}", id),
+            Command::Upload(UploadCommand::Abort { id }) => write!(f, "UPLOAD ABORT {}", id),
+            Command::Seller(SellerCommand::Info { name }) => write!(f, "SELLER INFO {}", name),
+            Command::Seller(SellerCommand::Verify { name }) => write!(f, "SELLER VERIFY {}", name),
+            Command::Seller(SellerCommand::Unverify { name }) => write!(f, "SELLER UNVERIFY {}", name),
+            Command::Seller(SellerCommand::Ban { name, expires_secs }) => {
+                write!(f, "SELLER BAN {}", name)?;
+                write_flag(f, "--expires", &expires_secs.map(|secs| secs.to_string()))
+            },
+            Command::Seller(SellerCommand::Unban { name }) => write!(f, "SELLER UNBAN {}", name),
+            Command::Seller(SellerCommand::Strike { name }) => write!(f, "SELLER STRIKE {}", name),
+            Command::Seller(SellerCommand::Revoke { name }) => write!(f, "SELLER REVOKE {}", name),
+            Command::SavedSearch(SavedSearchCommand::Delete { name }) => write!(f, "SAVEDSEARCH DELETE {}", quote(name)),
+            Command::Export { range } => {
+                write!(f, "EXPORT")?;
+                write_range(f, range)
+            },
+            Command::Hello { compress, encoding, session } => {
+                write!(f, "HELLO")?;
+                if !compress.is_empty() {
+                    let names: Vec<&str> = compress.iter().map(|c| c.name()).collect();
+                    write!(f, " --compress {}", names.join(","))?;
+                }
+                write_flag(f, "--encoding", &encoding.map(|e| e.name().to_string()))?;
+                write_flag(f, "--session", session)
+            },
+        }
+    }
+}
+
+/// Removes `--flag <value>` from `parts`, returning the value if the flag was present.
+fn take_flag(parts: &mut Vec<&str>, flag: &str) -> Result<Option<String>, BazaarError> {
+    let Some(pos) = parts.iter().position(|p| p.eq_ignore_ascii_case(flag)) else {
+        return Ok(None);
+    };
+    if pos + 1 >= parts.len() {
+        return Err(BazaarError::MissingArgument);
+    }
+    let value = parts.remove(pos + 1).to_string();
+    parts.remove(pos);
+    Ok(Some(value))
+}
+
+/// Removes `flags` from directly after `parts[start]`, in any order, returning
+/// their values in the order asked for. Flags are only looked for there, so
+/// free text later in the line is kept as written even if it looks like one.
+fn take_leading_flags<const N: usize>(parts: &mut Vec<&str>, start: usize, flags: [&str; N]) -> Result<[Option<String>; N], BazaarError> {
+    let mut values = [const { None }; N];
+    while let Some(n) = parts.get(start).and_then(|part| flags.iter().position(|flag| part.eq_ignore_ascii_case(flag))) {
+        let value = parts.get(start + 1).ok_or(BazaarError::MissingArgument)?.to_string();
+        if values[n].replace(value).is_some() {
+            return Err(BazaarError::InvalidCommand);
+        }
+        parts.drain(start..start + 2);
+    }
+    Ok(values)
+}
+
+/// Takes `--currency <code>` out of `parts`. Only commands that print prices
+/// take it, so free text elsewhere keeps it as written.
+fn take_currency(parts: &mut Vec<&str>) -> Result<Option<String>, BazaarError> {
+    take_flag(parts, "--currency")?
+        .map(|code| normalize_currency(&code).ok_or(BazaarError::UnsupportedCurrency))
+        .transpose()
+}
+
+/// Takes `--range <offset> <len>` out of `parts`.
+fn take_range(parts: &mut Vec<&str>) -> Result<Option<ByteRange>, BazaarError> {
+    let Some(pos) = parts.iter().position(|p| p.eq_ignore_ascii_case("--range")) else {
+        return Ok(None);
+    };
+    let [offset, len] = parts.get(pos + 1..pos + 3).ok_or(BazaarError::MissingArgument)? else {
+        return Err(BazaarError::MissingArgument);
+    };
+    let offset = offset.parse().map_err(|_| BazaarError::InvalidCommand)?;
+    let len = len.parse().map_err(|_| BazaarError::InvalidCommand)?;
+    if len == 0 {
+        return Err(BazaarError::InvalidCommand);
+    }
+    parts.drain(pos..pos + 3);
+    Ok(Some(ByteRange { offset, len }))
+}
+
+fn write_range(f: &mut fmt::Formatter<'_>, range: &Option<ByteRange>) -> fmt::Result {
+    match range {
+        Some(range) => write!(f, " --range {} {}", range.offset, range.len),
+        None => Ok(()),
+    }
+}
+
+fn write_flag(f: &mut fmt::Formatter<'_>, flag: &str, value: &Option<String>) -> fmt::Result {
+    match value {
+        Some(value) => write!(f, " {} {}", flag, quote(value)),
+        None => Ok(()),
+    }
+}
+
+/// Splits a request line into arguments. Words are separated by whitespace; a
+/// word starting with `"` runs to the closing quote and may contain spaces,
+/// with `\"` and `\\` escaping a quote or backslash. Quotes inside a bare word
+/// are kept as they are, so `don't` needs no quoting.
+pub fn tokenize(line: &str) -> Result<Vec<String>, BazaarError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+
+        let mut token = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => token.push(chars.next().ok_or(BazaarError::InvalidCommand)?),
+                    Some(c) => token.push(c),
+                    None => return Err(BazaarError::InvalidCommand),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push(token);
+    }
+}
+
+/// Quotes an argument for [`tokenize`] if it would otherwise be split or misread.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && !arg.starts_with('"') && !arg.contains(char::is_whitespace) {
+        return Cow::Borrowed(arg);
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Parses a duration such as `90m`, `12h`, `7d` or `2w` into seconds. A bare
+/// number is taken as seconds.
+pub fn parse_duration(duration: &str) -> Option<u64> {
+    let split = duration.find(|c: char| !c.is_ascii_digit()).unwrap_or(duration.len());
+    let (amount, unit) = duration.split_at(split);
+    let multiplier = match unit.to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => return None,
+    };
+    amount.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Formats seconds in the largest unit [`parse_duration`] reads back exactly.
+pub fn format_duration(secs: u64) -> String {
+    [("w", 7 * 24 * 3600), ("d", 24 * 3600), ("h", 3600), ("m", 60)]
+        .into_iter()
+        .find(|(_, unit)| secs > 0 && secs.is_multiple_of(*unit))
+        .map(|(suffix, unit)| format!("{}{}", secs / unit, suffix))
+        .unwrap_or_else(|| format!("{}s", secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sell(line: &str) -> Result<NewListing, BazaarError> {
+        match Command::parse(line)? {
+            Command::Sell(listing) => Ok(listing),
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quote_round_trips_through_tokenize() {
+        for arg in ["plain", "", "two words", "\"quoted\"", "a\"b", "back\\slash and \"quote\"", "tab\there", " padded ", "--"] {
+            assert_eq!(tokenize(&quote(arg)).unwrap(), [arg], "{:?}", arg);
+        }
+        let args = ["SELL", "two words", "", "€5", "\\"];
+        let line = args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ");
+        assert_eq!(tokenize(&line).unwrap(), args);
+    }
+
+    #[test]
+    fn tokenize_splits_on_any_whitespace() {
+        assert_eq!(tokenize("  GET\t42 \n").unwrap(), ["GET", "42"]);
+        assert_eq!(tokenize("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn tokenize_rejects_unterminated_quotes() {
+        assert_eq!(tokenize("SELL \"open"), Err(BazaarError::InvalidCommand));
+        assert_eq!(tokenize("SELL \"ends in \\"), Err(BazaarError::InvalidCommand));
+    }
+
+    #[test]
+    fn sell_takes_leading_flags() {
+        let listing = sell("SELL --credential zk --publish-at 100 --shipping post:DE:€5:2-4 synths alice €10 Juno 60 -- Great synth").unwrap();
+        assert_eq!(listing.credential.as_deref(), Some("zk"));
+        assert_eq!(listing.publish_at, Some(100));
+        assert_eq!(listing.shipping, "post:DE:€5:2-4".parse().unwrap());
+        assert_eq!((listing.category.as_str(), listing.seller.as_str(), listing.price.as_str()), ("synths", "alice", "€10"));
+        assert_eq!(listing.name, "Juno 60");
+        assert_eq!(listing.description, "Great synth");
+    }
+
+    #[test]
+    fn sell_keeps_flags_in_the_listing_text() {
+        let listing = sell("SELL synths alice €10 Juno --credential x -- ships with --shipping post:DE:€5:2-4 --publish-at 5").unwrap();
+        assert_eq!(listing.credential, None);
+        assert_eq!(listing.publish_at, None);
+        assert!(listing.shipping.is_empty());
+        assert_eq!(listing.name, "Juno --credential x");
+        assert_eq!(listing.description, "ships with --shipping post:DE:€5:2-4 --publish-at 5");
+    }
+
+    #[test]
+    fn sell_rejects_bad_flags() {
+        assert_eq!(sell("SELL --credential a --credential b synths alice €10 Juno"), Err(BazaarError::InvalidCommand));
+        assert_eq!(sell("SELL --publish-at soon synths alice €10 Juno"), Err(BazaarError::InvalidCommand));
+        assert_eq!(sell("SELL --credential"), Err(BazaarError::MissingArgument));
+        assert_eq!(sell("SELL --credential zk synths alice €10"), Err(BazaarError::MissingArgument));
+    }
+
+    #[test]
+    fn sell_round_trips_through_display() {
+        let listing = NewListing {
+            category: "synths".into(),
+            seller: "alice".into(),
+            price: "€10".into(),
+            name: "Juno \"60\" --shipping".into(),
+            description: "comes with -- a case and --publish-at 5".into(),
+            credential: Some("zk nym".into()),
+            publish_at: Some(1_800_000_000),
+            shipping: "post:DE:€5:2-4".parse().unwrap(),
+        };
+        let command = Command::Sell(listing);
+        assert_eq!(Command::parse(&command.to_string()), Ok(command));
+    }
+}
@@ -0,0 +1,75 @@
+//! Response frame compression, negotiated per connection with `HELLO --compress`.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// A compression scheme for response payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// Schemes this build can encode and decode, most preferred first.
+    pub fn supported() -> &'static [Compression] {
+        #[cfg(feature = "zstd")]
+        {
+            &[Compression::Zstd, Compression::Gzip]
+        }
+        #[cfg(not(feature = "zstd"))]
+        {
+            &[Compression::Gzip]
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Compression> {
+        [Compression::Zstd, Compression::Gzip]
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::bulk::compress(data, 3),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => Err(unsupported()),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+
+    /// Decompresses `data`, refusing output larger than `max_len`.
+    pub fn decompress(self, data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let limit = max_len as u64 + 1;
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => zstd::stream::read::Decoder::new(data)?.take(limit).read_to_end(&mut out)?,
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => return Err(unsupported()),
+            Compression::Gzip => flate2::read::GzDecoder::new(data).take(limit).read_to_end(&mut out)?,
+        };
+        if out.len() > max_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "decompressed frame too large"));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "built without zstd support")
+}
@@ -0,0 +1,65 @@
+//! Response payload encodings, negotiated per connection with `HELLO --encoding`.
+//!
+//! `text` is the human-readable rendering every client gets by default. `json`
+//! and `cbor` carry the [`Response`] itself, for programs that would otherwise
+//! have to scrape the text; CBOR is the compact binary form, worth it for big
+//! catalogs crossing the mixnet. The reply to `HELLO` is always text, so a
+//! client can read it before switching.
+
+use crate::i18n::Lang;
+use crate::response::Response;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Text,
+    Json,
+    Cbor,
+}
+
+impl Encoding {
+    pub const ALL: &'static [Encoding] = &[Encoding::Text, Encoding::Json, Encoding::Cbor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Text => "text",
+            Encoding::Json => "json",
+            Encoding::Cbor => "cbor",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        Encoding::ALL.iter().copied().find(|e| e.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl Response {
+    /// Encodes the response as a frame payload. Text is rendered in `lang`;
+    /// structured encodings aren't localized.
+    pub fn encode(&self, encoding: Encoding, lang: Lang) -> io::Result<Vec<u8>> {
+        match (self, encoding) {
+            (Response::Hello { .. }, _) | (_, Encoding::Text) => Ok(self.localized(lang).to_string().into_bytes()),
+            (_, Encoding::Json) => serde_json::to_vec(self).map_err(io::Error::from),
+            (_, Encoding::Cbor) => {
+                let mut payload = Vec::new();
+                ciborium::into_writer(self, &mut payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                Ok(payload)
+            },
+        }
+    }
+
+    /// Decodes a payload sent in a structured encoding. Text responses can't be
+    /// turned back into a [`Response`].
+    pub fn decode(payload: &[u8], encoding: Encoding) -> io::Result<Response> {
+        match encoding {
+            Encoding::Text => Err(io::Error::new(io::ErrorKind::Unsupported, "text responses can't be decoded")),
+            Encoding::Json => serde_json::from_slice(payload).map_err(io::Error::from),
+            Encoding::Cbor => {
+                ciborium::from_reader(payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            },
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Failures reported to clients as `ERR <code> <message>`. Structured
+/// encodings carry the message part, e.g. `"item_not_found"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BazaarError {
+    InvalidCommand,
+    MissingArgument,
+    ItemNotFound,
+    FeatureDisabled,
+    ServerBusy,
+    IdleTimeout,
+    CommandTooLong,
+    Unauthorized,
+    Forbidden,
+    ReloadFailed,
+    UnsupportedLanguage,
+    UnsupportedCurrency,
+    RatesUnavailable,
+    RevisionConflict,
+    TokenNotFound,
+    #[serde(rename = "internal_error")]
+    Internal,
+    CredentialRequired,
+    InvalidCredential,
+    #[serde(rename = "read_only_mirror")]
+    ReadOnly,
+    BatchTooLarge,
+    SavedSearchNotFound,
+    LimitReached,
+    SellerNameTaken,
+    SellerNotFound,
+    InvalidSignature,
+    ContentRejected,
+    DropNotFound,
+    PassRequired,
+    InvalidPass,
+    Banned,
+    DuplicateListing,
+    MediaNotFound,
+    InvalidMedia,
+    UploadNotFound,
+    UploadIncomplete,
+    UploadCorrupt,
+    QuestionNotFound,
+    ModelNotFound,
+    AuthenticatorNotFound,
+    AttestationNotFound,
+    AttestationRevoked,
+    InBundle,
+}
+
+impl BazaarError {
+    pub const ALL: &'static [BazaarError] = &[
+        BazaarError::InvalidCommand,
+        BazaarError::MissingArgument,
+        BazaarError::ItemNotFound,
+        BazaarError::FeatureDisabled,
+        BazaarError::ServerBusy,
+        BazaarError::IdleTimeout,
+        BazaarError::CommandTooLong,
+        BazaarError::Unauthorized,
+        BazaarError::Forbidden,
+        BazaarError::ReloadFailed,
+        BazaarError::UnsupportedLanguage,
+        BazaarError::UnsupportedCurrency,
+        BazaarError::RatesUnavailable,
+        BazaarError::RevisionConflict,
+        BazaarError::TokenNotFound,
+        BazaarError::Internal,
+        BazaarError::CredentialRequired,
+        BazaarError::InvalidCredential,
+        BazaarError::ReadOnly,
+        BazaarError::BatchTooLarge,
+        BazaarError::SavedSearchNotFound,
+        BazaarError::LimitReached,
+        BazaarError::SellerNameTaken,
+        BazaarError::SellerNotFound,
+        BazaarError::InvalidSignature,
+        BazaarError::ContentRejected,
+        BazaarError::DropNotFound,
+        BazaarError::PassRequired,
+        BazaarError::InvalidPass,
+        BazaarError::Banned,
+        BazaarError::DuplicateListing,
+        BazaarError::MediaNotFound,
+        BazaarError::InvalidMedia,
+        BazaarError::UploadNotFound,
+        BazaarError::UploadIncomplete,
+        BazaarError::UploadCorrupt,
+        BazaarError::QuestionNotFound,
+        BazaarError::ModelNotFound,
+        BazaarError::AuthenticatorNotFound,
+        BazaarError::AttestationNotFound,
+        BazaarError::AttestationRevoked,
+        BazaarError::InBundle,
+    ];
+
+    pub fn code(self) -> u16 {
+        match self {
+            BazaarError::InvalidCommand => 400,
+            BazaarError::MissingArgument => 400,
+            BazaarError::ItemNotFound => 404,
+            BazaarError::FeatureDisabled => 403,
+            BazaarError::ServerBusy => 503,
+            BazaarError::IdleTimeout => 408,
+            BazaarError::CommandTooLong => 413,
+            BazaarError::Unauthorized => 401,
+            BazaarError::Forbidden => 403,
+            BazaarError::ReloadFailed => 500,
+            BazaarError::UnsupportedLanguage => 400,
+            BazaarError::UnsupportedCurrency => 400,
+            BazaarError::RatesUnavailable => 503,
+            BazaarError::RevisionConflict => 409,
+            BazaarError::TokenNotFound => 404,
+            BazaarError::Internal => 500,
+            BazaarError::CredentialRequired => 402,
+            BazaarError::InvalidCredential => 403,
+            BazaarError::ReadOnly => 405,
+            BazaarError::BatchTooLarge => 413,
+            BazaarError::SavedSearchNotFound => 404,
+            BazaarError::LimitReached => 403,
+            BazaarError::SellerNameTaken => 409,
+            BazaarError::SellerNotFound => 404,
+            BazaarError::InvalidSignature => 403,
+            BazaarError::ContentRejected => 422,
+            BazaarError::DropNotFound => 404,
+            BazaarError::PassRequired => 402,
+            BazaarError::InvalidPass => 403,
+            BazaarError::Banned => 403,
+            BazaarError::DuplicateListing => 409,
+            BazaarError::MediaNotFound => 404,
+            BazaarError::InvalidMedia => 422,
+            BazaarError::UploadNotFound => 404,
+            BazaarError::UploadIncomplete => 409,
+            BazaarError::UploadCorrupt => 422,
+            BazaarError::QuestionNotFound => 404,
+            BazaarError::ModelNotFound => 404,
+            BazaarError::AuthenticatorNotFound => 404,
+            BazaarError::AttestationNotFound => 404,
+            BazaarError::AttestationRevoked => 409,
+            BazaarError::InBundle => 409,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            BazaarError::InvalidCommand => "invalid_command",
+            BazaarError::MissingArgument => "missing_argument",
+            BazaarError::ItemNotFound => "item_not_found",
+            BazaarError::FeatureDisabled => "feature_disabled",
+            BazaarError::ServerBusy => "server_busy",
+            BazaarError::IdleTimeout => "idle_timeout",
+            BazaarError::CommandTooLong => "command_too_long",
+            BazaarError::Unauthorized => "unauthorized",
+            BazaarError::Forbidden => "forbidden",
+            BazaarError::ReloadFailed => "reload_failed",
+            BazaarError::UnsupportedLanguage => "unsupported_language",
+            BazaarError::UnsupportedCurrency => "unsupported_currency",
+            BazaarError::RatesUnavailable => "rates_unavailable",
+            BazaarError::RevisionConflict => "revision_conflict",
+            BazaarError::TokenNotFound => "token_not_found",
+            BazaarError::Internal => "internal_error",
+            BazaarError::CredentialRequired => "credential_required",
+            BazaarError::InvalidCredential => "invalid_credential",
+            BazaarError::ReadOnly => "read_only_mirror",
+            BazaarError::BatchTooLarge => "batch_too_large",
+            BazaarError::SavedSearchNotFound => "saved_search_not_found",
+            BazaarError::LimitReached => "limit_reached",
+            BazaarError::SellerNameTaken => "seller_name_taken",
+            BazaarError::SellerNotFound => "seller_not_found",
+            BazaarError::InvalidSignature => "invalid_signature",
+            BazaarError::ContentRejected => "content_rejected",
+            BazaarError::DropNotFound => "drop_not_found",
+            BazaarError::PassRequired => "pass_required",
+            BazaarError::InvalidPass => "invalid_pass",
+            BazaarError::Banned => "banned",
+            BazaarError::DuplicateListing => "duplicate_listing",
+            BazaarError::MediaNotFound => "media_not_found",
+            BazaarError::InvalidMedia => "invalid_media",
+            BazaarError::UploadNotFound => "upload_not_found",
+            BazaarError::UploadIncomplete => "upload_incomplete",
+            BazaarError::UploadCorrupt => "upload_corrupt",
+            BazaarError::QuestionNotFound => "question_not_found",
+            BazaarError::ModelNotFound => "model_not_found",
+            BazaarError::AuthenticatorNotFound => "authenticator_not_found",
+            BazaarError::AttestationNotFound => "attestation_not_found",
+            BazaarError::AttestationRevoked => "attestation_revoked",
+            BazaarError::InBundle => "in_bundle",
+        }
+    }
+}
+
+impl fmt::Display for BazaarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ERR {} {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for BazaarError {}
+
+impl FromStr for BazaarError {
+    type Err = ();
+
+    /// Parses a full `ERR <code> <message>` line into a known error.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, message) = parse_error(s).ok_or(())?;
+        BazaarError::ALL
+            .iter()
+            .copied()
+            .find(|e| e.code() == code && e.message() == message)
+            .ok_or(())
+    }
+}
+
+/// Splits an `ERR <code> <message>` response into its code and message.
+///
+/// Works for codes this crate doesn't know about, so older clients can still
+/// report errors from newer servers.
+pub fn parse_error(response: &str) -> Option<(u16, &str)> {
+    let rest = response.trim().strip_prefix("ERR ")?;
+    let (code, message) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some((code.parse().ok()?, message))
+}
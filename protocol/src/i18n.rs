@@ -0,0 +1,421 @@
+//! Message catalog for the human-readable parts of responses.
+//!
+//! Error lines (`ERR <code> <message>`) are machine-readable and never translated.
+
+use serde::{Deserialize, Serialize};
+
+/// A language the bazaar can answer in, negotiated per connection with `LANG <code>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+    Es,
+}
+
+impl Lang {
+    pub const ALL: &'static [Lang] = &[Lang::En, Lang::De, Lang::Es];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::De => "de",
+            Lang::Es => "es",
+        }
+    }
+
+    /// Accepts bare codes as well as locale tags like `de-AT` or `es_MX`.
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let primary = code.split(['-', '_']).next()?.to_lowercase();
+        Lang::ALL.iter().copied().find(|lang| lang.code() == primary)
+    }
+
+    pub fn messages(self) -> &'static Messages {
+        match self {
+            Lang::En => &EN,
+            Lang::De => &DE,
+            Lang::Es => &ES,
+        }
+    }
+}
+
+/// Every user-facing string the server sends. `{}` placeholders are filled in
+/// order by [`Messages::fill`].
+pub struct Messages {
+    pub available_commands: &'static str,
+    pub available_categories: &'static str,
+    pub no_items: &'static str,
+    pub no_search_results: &'static str,
+    pub id: &'static str,
+    pub name: &'static str,
+    pub category: &'static str,
+    pub price: &'static str,
+    pub seller: &'static str,
+    pub protocol: &'static str,
+    pub address: &'static str,
+    pub uptime: &'static str,
+    pub items: &'static str,
+    pub categories: &'static str,
+    pub sessions: &'static str,
+    pub reloaded: &'static str,
+    pub language_set: &'static str,
+    pub converted: &'static str,
+    pub stale_rates: &'static str,
+    pub created: &'static str,
+    pub updated: &'static str,
+    pub revision: &'static str,
+    pub deleted: &'static str,
+    pub restored: &'static str,
+    pub no_audit_entries: &'static str,
+    pub history: &'static str,
+    pub listed_at: &'static str,
+    pub history_deleted: &'static str,
+    pub history_restored: &'static str,
+    pub token_created: &'static str,
+    pub token_expires: &'static str,
+    pub token_revoked: &'static str,
+    pub no_tokens: &'static str,
+    pub price_range: &'static str,
+    pub no_saved_searches: &'static str,
+    pub last_run: &'static str,
+    pub scheduled: &'static str,
+    pub verified_seller: &'static str,
+    pub public_key: &'static str,
+    pub registered: &'static str,
+    pub drop_posted: &'static str,
+    pub shipping: &'static str,
+    pub shipping_option: &'static str,
+    pub signature: &'static str,
+    pub catalog_root: &'static str,
+    pub proof_leaf: &'static str,
+    pub no_tlog_entries: &'static str,
+    pub tlog_head: &'static str,
+    pub also_reachable_at: &'static str,
+    pub passes_left: &'static str,
+    pub strikes: &'static str,
+    pub banned: &'static str,
+    pub banned_until: &'static str,
+    pub possible_duplicate: &'static str,
+    pub images_of: &'static str,
+    pub no_images: &'static str,
+    pub images: &'static str,
+    pub questions: &'static str,
+    pub question_line: &'static str,
+    pub answer_line: &'static str,
+    pub unanswered: &'static str,
+    pub questions_about: &'static str,
+    pub no_questions: &'static str,
+    pub question_sent: &'static str,
+    pub seller_away: &'static str,
+    pub seller_away_until: &'static str,
+    pub away_on: &'static str,
+    pub away_on_until: &'static str,
+    pub away_off: &'static str,
+    pub bundle_of: &'static str,
+    pub reference_models: &'static str,
+    pub no_models: &'static str,
+    pub model: &'static str,
+    pub model_listings: &'static str,
+    pub attestations: &'static str,
+    pub attestation_revoked: &'static str,
+    pub authenticators: &'static str,
+    pub no_authenticators: &'static str,
+    pub attested: &'static str,
+    pub authenticator_removed: &'static str,
+    pub tier: &'static str,
+    pub news: &'static str,
+    pub no_news: &'static str,
+    pub news_signed_by: &'static str,
+    pub announced: &'static str,
+    pub capabilities: &'static str,
+    pub pass_key: &'static str,
+}
+
+impl Messages {
+    /// Substitutes `args` for the `{}` placeholders in `template`, left to right.
+    pub fn fill(template: &str, args: &[&dyn std::fmt::Display]) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut args = args.iter();
+        let mut rest = template;
+        while let Some(pos) = rest.find("{}") {
+            out.push_str(&rest[..pos]);
+            if let Some(arg) = args.next() {
+                out.push_str(&arg.to_string());
+            }
+            rest = &rest[pos + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+static EN: Messages = Messages {
+    available_commands: "Available commands:",
+    available_categories: "Available categories:",
+    no_items: "No items found",
+    no_search_results: "No items found matching your search",
+    id: "ID",
+    name: "Name",
+    category: "Category",
+    price: "Price",
+    seller: "Seller",
+    protocol: "Protocol",
+    address: "Address",
+    uptime: "Uptime",
+    items: "Items",
+    categories: "Categories",
+    sessions: "Sessions",
+    reloaded: "Reloaded configuration ({} items in catalog)",
+    language_set: "Language set to English",
+    converted: "Prices converted to {} using rates from {} min ago",
+    stale_rates: "Exchange rates are out of date, converted prices may be off",
+    created: "Listed as {} (#{})",
+    updated: "Updated {} to revision {}",
+    revision: "Revision",
+    deleted: "Deleted {}, RESTORE brings it back within {} h",
+    restored: "Restored {}",
+    no_audit_entries: "The audit log is empty",
+    history: "History of {}:",
+    listed_at: "listed at {}",
+    history_deleted: "deleted",
+    history_restored: "restored",
+    token_created: "Token: {} (store it now, it is not shown again)",
+    token_expires: "expires",
+    token_revoked: "revoked",
+    no_tokens: "No API tokens",
+    price_range: "Price range: {}",
+    no_saved_searches: "No saved searches",
+    last_run: "last run",
+    scheduled: "Listed as {} (#{}), goes live at {}",
+    verified_seller: "{} ✓ verified",
+    public_key: "Public key",
+    registered: "Registered",
+    drop_posted: "Drop {}, posted at {}:",
+    shipping: "Shipping",
+    shipping_option: "{} to {}: {}, {} days",
+    signature: "Seller signature",
+    catalog_root: "Catalog root",
+    proof_leaf: "Leaf {} of {}",
+    no_tlog_entries: "No transparency log entries in that range",
+    tlog_head: "Transparency log",
+    also_reachable_at: "Also reachable at",
+    passes_left: "Write passes left in this session: {}",
+    strikes: "Strikes",
+    banned: "Banned",
+    banned_until: "Banned until {}",
+    possible_duplicate: "This looks like a copy of your listing {}",
+    images_of: "Images of {}:",
+    no_images: "{} has no images",
+    images: "Images",
+    questions: "Questions",
+    question_line: "Q: {}",
+    answer_line: "A: {}",
+    unanswered: "(not answered yet)",
+    questions_about: "Questions about {}:",
+    no_questions: "No questions about {}",
+    question_sent: "Question {} sent to the seller",
+    seller_away: "Seller away, expect shipping delays",
+    seller_away_until: "Seller away until {}, expect shipping delays",
+    away_on: "{} is away",
+    away_on_until: "{} is away until {}",
+    away_off: "{} is not away",
+    bundle_of: "Bundle of",
+    reference_models: "Reference models:",
+    no_models: "No models found",
+    model: "Model",
+    model_listings: "{} listings",
+    attestations: "Attestations",
+    attestation_revoked: "revoked",
+    authenticators: "Authenticators:",
+    no_authenticators: "No authenticators",
+    attested: "Attestation {} recorded",
+    authenticator_removed: "(removed)",
+    tier: "Tier",
+    news: "News",
+    no_news: "No announcements",
+    news_signed_by: "signed by {}",
+    announced: "Announcement {} published",
+    capabilities: "Capabilities",
+    pass_key: "Pass key",
+};
+
+static DE: Messages = Messages {
+    available_commands: "Verfügbare Befehle:",
+    available_categories: "Verfügbare Kategorien:",
+    no_items: "Keine Artikel gefunden",
+    no_search_results: "Keine Artikel passend zu deiner Suche gefunden",
+    id: "ID",
+    name: "Name",
+    category: "Kategorie",
+    price: "Preis",
+    seller: "Verkäufer",
+    protocol: "Protokoll",
+    address: "Adresse",
+    uptime: "Laufzeit",
+    items: "Artikel",
+    categories: "Kategorien",
+    sessions: "Sitzungen",
+    reloaded: "Konfiguration neu geladen ({} Artikel im Katalog)",
+    language_set: "Sprache auf Deutsch gestellt",
+    converted: "Preise in {} umgerechnet, Kurse von vor {} Min.",
+    stale_rates: "Wechselkurse sind veraltet, umgerechnete Preise können abweichen",
+    created: "Eingestellt als {} (#{})",
+    updated: "{} auf Revision {} aktualisiert",
+    revision: "Revision",
+    deleted: "{} gelöscht, mit RESTORE innerhalb von {} Std. wiederherstellbar",
+    restored: "{} wiederhergestellt",
+    no_audit_entries: "Das Audit-Log ist leer",
+    history: "Verlauf von {}:",
+    listed_at: "eingestellt für {}",
+    history_deleted: "gelöscht",
+    history_restored: "wiederhergestellt",
+    token_created: "Token: {} (jetzt sichern, es wird nicht erneut angezeigt)",
+    token_expires: "läuft ab",
+    token_revoked: "widerrufen",
+    no_tokens: "Keine API-Tokens",
+    price_range: "Preisspanne: {}",
+    no_saved_searches: "Keine gespeicherten Suchen",
+    last_run: "zuletzt ausgeführt",
+    scheduled: "Eingestellt als {} (#{}), sichtbar ab {}",
+    verified_seller: "{} ✓ verifiziert",
+    public_key: "Öffentlicher Schlüssel",
+    registered: "Registriert",
+    drop_posted: "Ablage {}, hinterlegt um {}:",
+    shipping: "Versand",
+    shipping_option: "{} nach {}: {}, {} Tage",
+    signature: "Signatur des Verkäufers",
+    catalog_root: "Katalog-Wurzel",
+    proof_leaf: "Blatt {} von {}",
+    no_tlog_entries: "Keine Einträge im Transparenzlog in diesem Bereich",
+    tlog_head: "Transparenzlog",
+    also_reachable_at: "Auch erreichbar unter",
+    passes_left: "Verbleibende Schreibpässe für diese Sitzung: {}",
+    strikes: "Verwarnungen",
+    banned: "Gesperrt",
+    banned_until: "Gesperrt bis {}",
+    possible_duplicate: "Das sieht aus wie eine Kopie deines Angebots {}",
+    images_of: "Bilder von {}:",
+    no_images: "{} hat keine Bilder",
+    images: "Bilder",
+    questions: "Fragen",
+    question_line: "F: {}",
+    answer_line: "A: {}",
+    unanswered: "(noch nicht beantwortet)",
+    questions_about: "Fragen zu {}:",
+    no_questions: "Keine Fragen zu {}",
+    question_sent: "Frage {} an den Verkäufer gesendet",
+    seller_away: "Verkäufer abwesend, Versand verzögert sich",
+    seller_away_until: "Verkäufer abwesend bis {}, Versand verzögert sich",
+    away_on: "{} ist abwesend",
+    away_on_until: "{} ist abwesend bis {}",
+    away_off: "{} ist nicht abwesend",
+    bundle_of: "Paket aus",
+    reference_models: "Referenzmodelle:",
+    no_models: "Keine Modelle gefunden",
+    model: "Modell",
+    model_listings: "{} Angebote",
+    attestations: "Bestätigungen",
+    attestation_revoked: "widerrufen",
+    authenticators: "Prüfer:",
+    no_authenticators: "Keine Prüfer",
+    attested: "Bestätigung {} gespeichert",
+    authenticator_removed: "(entfernt)",
+    tier: "Stufe",
+    news: "Neuigkeiten",
+    no_news: "Keine Ankündigungen",
+    news_signed_by: "signiert von {}",
+    announced: "Ankündigung {} veröffentlicht",
+    capabilities: "Funktionen",
+    pass_key: "Pass-Schlüssel",
+};
+
+static ES: Messages = Messages {
+    available_commands: "Comandos disponibles:",
+    available_categories: "Categorías disponibles:",
+    no_items: "No se encontraron artículos",
+    no_search_results: "No se encontraron artículos que coincidan con tu búsqueda",
+    id: "ID",
+    name: "Nombre",
+    category: "Categoría",
+    price: "Precio",
+    seller: "Vendedor",
+    protocol: "Protocolo",
+    address: "Dirección",
+    uptime: "Tiempo activo",
+    items: "Artículos",
+    categories: "Categorías",
+    sessions: "Sesiones",
+    reloaded: "Configuración recargada ({} artículos en el catálogo)",
+    language_set: "Idioma cambiado a español",
+    converted: "Precios convertidos a {} con tipos de cambio de hace {} min",
+    stale_rates: "Los tipos de cambio están desactualizados, los precios convertidos pueden variar",
+    created: "Publicado como {} (#{})",
+    updated: "{} actualizado a la revisión {}",
+    revision: "Revisión",
+    deleted: "{} eliminado, RESTORE lo recupera durante {} h",
+    restored: "{} restaurado",
+    no_audit_entries: "El registro de auditoría está vacío",
+    history: "Historial de {}:",
+    listed_at: "publicado por {}",
+    history_deleted: "eliminado",
+    history_restored: "restaurado",
+    token_created: "Token: {} (guárdalo ahora, no se volverá a mostrar)",
+    token_expires: "caduca",
+    token_revoked: "revocado",
+    no_tokens: "No hay tokens de API",
+    price_range: "Rango de precios: {}",
+    no_saved_searches: "No hay búsquedas guardadas",
+    last_run: "última ejecución",
+    scheduled: "Publicado como {} (#{}), visible desde {}",
+    verified_seller: "{} ✓ verificado",
+    public_key: "Clave pública",
+    registered: "Registrado",
+    drop_posted: "Entrega {}, publicada el {}:",
+    shipping: "Envío",
+    shipping_option: "{} a {}: {}, {} días",
+    signature: "Firma del vendedor",
+    catalog_root: "Raíz del catálogo",
+    proof_leaf: "Hoja {} de {}",
+    no_tlog_entries: "No hay entradas del registro de transparencia en ese rango",
+    tlog_head: "Registro de transparencia",
+    also_reachable_at: "También accesible en",
+    passes_left: "Pases de escritura restantes en esta sesión: {}",
+    strikes: "Advertencias",
+    banned: "Bloqueado",
+    banned_until: "Bloqueado hasta {}",
+    possible_duplicate: "Parece una copia de tu anuncio {}",
+    images_of: "Imágenes de {}:",
+    no_images: "{} no tiene imágenes",
+    images: "Imágenes",
+    questions: "Preguntas",
+    question_line: "P: {}",
+    answer_line: "R: {}",
+    unanswered: "(aún sin respuesta)",
+    questions_about: "Preguntas sobre {}:",
+    no_questions: "No hay preguntas sobre {}",
+    question_sent: "Pregunta {} enviada al vendedor",
+    seller_away: "Vendedor ausente, el envío puede retrasarse",
+    seller_away_until: "Vendedor ausente hasta {}, el envío puede retrasarse",
+    away_on: "{} está ausente",
+    away_on_until: "{} está ausente hasta {}",
+    away_off: "{} no está ausente",
+    bundle_of: "Lote de",
+    reference_models: "Modelos de referencia:",
+    no_models: "No se encontraron modelos",
+    model: "Modelo",
+    model_listings: "{} anuncios",
+    attestations: "Certificaciones",
+    attestation_revoked: "revocada",
+    authenticators: "Autenticadores:",
+    no_authenticators: "No hay autenticadores",
+    attested: "Certificación {} registrada",
+    authenticator_removed: "(eliminado)",
+    tier: "Nivel",
+    news: "Noticias",
+    no_news: "No hay anuncios",
+    news_signed_by: "firmado por {}",
+    announced: "Anuncio {} publicado",
+    capabilities: "Capacidades",
+    pass_key: "Clave de pases",
+};
@@ -0,0 +1,141 @@
+use crate::command::ItemField;
+use crate::shipping::ShippingOptions;
+use serde::{Deserialize, Serialize};
+
+/// A listing in the bazaar catalog.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item {
+    /// ULID assigned when the item was listed.
+    pub id: String,
+    /// Short number that also identifies the item on the server that assigned it.
+    #[serde(default)]
+    pub alias: u64,
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    pub price: String,
+    pub seller: String,
+    /// Unix time the item was listed, taken from its ULID, or the time it went
+    /// live for listings scheduled with `--publish-at`.
+    #[serde(default)]
+    pub created_at: u64,
+    /// Bumped on every edit; `UPDATE` must name the revision it was based on.
+    #[serde(default = "first_revision")]
+    pub revision: u64,
+    /// Whether the seller's registered name carries an operator's verified badge.
+    /// Filled in by the server when it serves a listing, never stored.
+    #[serde(default)]
+    pub seller_verified: bool,
+    /// Whether the seller set `AWAY`, so shipping will be late, and until when
+    /// if they said. Filled in by the server when it serves a listing, never stored.
+    #[serde(default)]
+    pub seller_away: bool,
+    #[serde(default)]
+    pub seller_back_at: Option<u64>,
+    #[serde(default)]
+    pub shipping: ShippingOptions,
+    /// The seller's signature over this revision, see [`crate::signing`].
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Hashes of the listing's images, fetched with `GETIMAGE` (see [`crate::media`]),
+    /// or IPFS CIDs on bazaars that pin images to IPFS, fetched out-of-band.
+    #[serde(default)]
+    pub media: Vec<String>,
+    /// ID of the reference model (see [`crate::model`]) the seller linked the
+    /// listing to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Answered questions about the listing, oldest first. Filled in by the
+    /// server when it serves a listing with `GET`, never stored.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub questions: Vec<Question>,
+    /// ULIDs of the listings a bundle made with `BUNDLE CREATE` is made of.
+    /// Empty for ordinary listings.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundle: Vec<String>,
+    /// Condition and authenticity attestations by authenticators, revoked ones
+    /// included. Filled in by the server when it serves a listing with `GET`,
+    /// never stored.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attestations: Vec<Attestation>,
+}
+
+impl Item {
+    /// A fresh listing at its first revision, with no shipping options, images,
+    /// signature or other extras. Set those with struct update syntax.
+    pub fn new(id: String, created_at: u64, name: String, category: String, description: String, price: String, seller: String) -> Self {
+        Self {
+            id,
+            alias: 0,
+            name,
+            category,
+            description,
+            price,
+            seller,
+            created_at,
+            revision: first_revision(),
+            seller_verified: false,
+            seller_away: false,
+            seller_back_at: None,
+            shipping: ShippingOptions::default(),
+            signature: None,
+            media: Vec::new(),
+            model: None,
+            questions: Vec::new(),
+            bundle: Vec::new(),
+            attestations: Vec::new(),
+        }
+    }
+}
+
+fn first_revision() -> u64 {
+    1
+}
+
+/// A buyer's question about a listing, asked with `ASK` and answered by the
+/// seller with `ANSWER`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Question {
+    pub id: String,
+    pub question: String,
+    pub asked_at: u64,
+    #[serde(default)]
+    pub answer: Option<String>,
+    #[serde(default)]
+    pub answered_at: Option<u64>,
+}
+
+/// A statement about an item's condition or authenticity, signed by an
+/// authenticator the operator trusts (see [`crate::signing::attestation_payload`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub id: String,
+    /// Name of the authenticator, see `AUTHENTICATOR LIST` for their key.
+    pub authenticator: String,
+    pub statement: String,
+    pub attested_at: u64,
+    /// The authenticator's signature, hex encoded.
+    pub signature: String,
+    /// Set once the authenticator or an operator withdrew the attestation.
+    #[serde(default)]
+    pub revoked_at: Option<u64>,
+}
+
+/// A change to a listing, as reported by `HISTORY`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEvent {
+    /// Unix time of the change.
+    pub timestamp: u64,
+    /// The listing's revision after the change.
+    pub revision: u64,
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum Change {
+    Listed { price: String },
+    Updated { field: ItemField, old: String, new: String },
+    Deleted,
+    Restored,
+}
@@ -0,0 +1,45 @@
+//! Wire protocol shared by the NymBazaar server, client and stress test.
+//!
+//! Requests are single text lines terminated by `\n`. Every response is sent
+//! as one frame: a decimal length header line followed by that many bytes of
+//! payload (see [`codec`]). Large frames may be compressed once a client
+//! opts in with `HELLO --compress` (see [`compression`]), and sent as JSON or
+//! CBOR instead of text with `HELLO --encoding` (see [`encoding`]).
+
+pub mod capability;
+pub mod codec;
+pub mod command;
+pub mod compression;
+pub mod encoding;
+pub mod error;
+pub mod i18n;
+pub mod item;
+pub mod media;
+pub mod merkle;
+pub mod model;
+pub mod pass;
+pub mod price;
+pub mod response;
+pub mod search;
+pub mod shipping;
+pub mod signing;
+pub mod table;
+pub mod tlog;
+pub mod upload;
+
+pub use compression::Compression;
+pub use encoding::Encoding;
+pub use command::{AttestCommand, AuthenticatorCommand, AwayCommand, ByteRange, Command, ImageData, ItemField, DropCommand, NewListing, PassCommand, SavedSearchCommand, SellerCommand, TokenCommand, TokenScope, UploadCommand};
+pub use error::BazaarError;
+pub use i18n::Lang;
+pub use item::{Attestation, Change, HistoryEvent, Item, Question};
+pub use model::Model;
+pub use price::Price;
+pub use response::{Announcement, AuditRecord, AuthenticatorInfo, Conversion, Response, SavedSearchInfo, SellerInfo, ServerStatus, TokenInfo};
+pub use search::{PriceRange, SearchQuery, SortKey};
+pub use shipping::{ShippingOption, ShippingOptions};
+pub use tlog::TlogEntry;
+pub use upload::UploadStatus;
+
+/// Bumped whenever the command grammar or response format changes incompatibly.
+pub const PROTOCOL_VERSION: u32 = 1;
@@ -0,0 +1,81 @@
+//! Listing images, stored by the server under the SHA-256 of their bytes.
+//!
+//! Images are uploaded whole with `PUTIMAGE` and fetched in chunks of
+//! [`CHUNK_SIZE`] bytes with `GETIMAGE`, base64 encoded on the wire. Listings
+//! refer to their images by hash, so a client can check the bytes it put back
+//! together against the listing without trusting the server.
+//!
+//! Bazaars may pin images to IPFS instead. Their listings carry IPFS CIDs,
+//! which are content addresses too, and clients fetch the images from IPFS
+//! through whatever transport they trust rather than with `GETIMAGE`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+/// Bytes of an image sent per `GETIMAGE` response.
+pub const CHUNK_SIZE: usize = 32 * 1024;
+
+/// The hex SHA-256 an image is stored and referred to by.
+pub fn hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Whether `data` is the image `hash` refers to.
+pub fn verify(hash: &str, data: &[u8]) -> bool {
+    self::hash(data).eq_ignore_ascii_case(hash)
+}
+
+/// Whether `value` looks like an image hash.
+pub fn is_hash(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+pub fn encode(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+pub fn decode(data: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(data).ok()
+}
+
+/// Which version of an image `GETIMAGE --size` asks for. Smaller sizes are
+/// JPEG thumbnails made by the server, handy for browsing over the mixnet;
+/// only [`ImageSize::Full`] can be checked against the image hash.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageSize {
+    #[default]
+    Full,
+    Medium,
+    Thumb,
+}
+
+impl ImageSize {
+    pub const SCALED: &'static [ImageSize] = &[ImageSize::Medium, ImageSize::Thumb];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageSize::Full => "full",
+            ImageSize::Medium => "medium",
+            ImageSize::Thumb => "thumb",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "full" => Some(ImageSize::Full),
+            "medium" => Some(ImageSize::Medium),
+            "thumb" => Some(ImageSize::Thumb),
+            _ => None,
+        }
+    }
+
+    /// Longest side of a scaled image in pixels, `None` for the original.
+    pub fn max_side(self) -> Option<u32> {
+        match self {
+            ImageSize::Full => None,
+            ImageSize::Medium => Some(640),
+            ImageSize::Thumb => Some(160),
+        }
+    }
+}
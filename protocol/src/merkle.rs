@@ -0,0 +1,203 @@
+//! Merkle tree over the live catalog.
+//!
+//! Leaves are the [`listing_payload`] of every live listing in ULID order. A
+//! server that shows different catalogs to different users has to publish
+//! different roots, which clients and mirrors can compare out of band.
+//! `PROOF <id>` shows that one listing is part of the tree behind a root.
+
+use crate::item::Item;
+use crate::signing::listing_payload;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+/// Which side of the path a sibling hash sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Shows that a listing is a leaf of the catalog tree with root `root`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub id: String,
+    /// Position of the listing among `leaf_count` leaves.
+    pub index: usize,
+    pub leaf_count: usize,
+    /// Sibling hashes from the leaf up, hex encoded.
+    pub path: Vec<(Side, String)>,
+    pub root: String,
+}
+
+/// The leaf hash of a listing. Leaves and nodes are domain separated so a node
+/// can't pass for a leaf.
+pub fn leaf_hash(item: &Item) -> Hash {
+    Sha256::new().chain_update([0]).chain_update(listing_payload(item)).finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new().chain_update([1]).chain_update(left).chain_update(right).finalize().into()
+}
+
+/// Hashes one level into the next. An odd last node moves up unpaired.
+fn next_level(level: &[Hash]) -> Vec<Hash> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks(2) yields one or two hashes"),
+        })
+        .collect()
+}
+
+/// The root over `leaves`. An empty catalog has the hash of nothing as its root.
+pub fn root(leaves: &[Hash]) -> Hash {
+    if leaves.is_empty() {
+        return Sha256::digest([]).into();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Builds the proof for the leaf at `index`, or `None` if there is no such leaf.
+pub fn prove(id: &str, leaves: &[Hash], index: usize) -> Option<InclusionProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            let side = if sibling < position { Side::Left } else { Side::Right };
+            path.push((side, hex::encode(hash)));
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(InclusionProof {
+        id: id.to_string(),
+        index,
+        leaf_count: leaves.len(),
+        path,
+        root: hex::encode(level[0]),
+    })
+}
+
+impl InclusionProof {
+    /// Whether `item` is the proven leaf under `root` (hex), e.g. a root from `STATUS`.
+    pub fn verify(&self, item: &Item, root: &str) -> bool {
+        let mut hash = leaf_hash(item);
+        for (side, sibling) in &self.path {
+            let mut sibling_hash = [0; 32];
+            if hex::decode_to_slice(sibling, &mut sibling_hash).is_err() {
+                return false;
+            }
+            hash = match side {
+                Side::Left => node_hash(&sibling_hash, &hash),
+                Side::Right => node_hash(&hash, &sibling_hash),
+            };
+        }
+        item.id == self.id && hex::encode(hash).eq_ignore_ascii_case(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(count: usize) -> Vec<Item> {
+        (0..count)
+            .map(|n| Item::new(format!("01JB2Q5X4N{:016}", n), 0, format!("Item {}", n), "synths".into(), String::new(), "€10".into(), "alice".into()))
+            .collect()
+    }
+
+    fn leaves(items: &[Item]) -> Vec<Hash> {
+        items.iter().map(leaf_hash).collect()
+    }
+
+    #[test]
+    fn every_leaf_proves_against_the_root() {
+        // Odd counts leave unpaired nodes on some levels
+        for count in 1..=9 {
+            let items = items(count);
+            let leaves = leaves(&items);
+            let root = hex::encode(root(&leaves));
+            for (index, item) in items.iter().enumerate() {
+                let proof = prove(&item.id, &leaves, index).unwrap();
+                assert_eq!(proof.root, root);
+                assert!(proof.verify(item, &root), "leaf {} of {}", index, count);
+            }
+        }
+    }
+
+    #[test]
+    fn a_tampered_leaf_fails() {
+        let items = items(5);
+        let leaves = leaves(&items);
+        let proof = prove(&items[2].id, &leaves, 2).unwrap();
+
+        let mut changed = items[2].clone();
+        changed.price = "€1".into();
+        assert!(!proof.verify(&changed, &proof.root));
+        // Another listing can't borrow the proof, nor one under another ID
+        assert!(!proof.verify(&items[3], &proof.root));
+        let mut renamed = items[2].clone();
+        renamed.id = items[3].id.clone();
+        assert!(!proof.verify(&renamed, &proof.root));
+    }
+
+    #[test]
+    fn a_tampered_proof_or_root_fails() {
+        let items = items(5);
+        let leaves = leaves(&items);
+        let proof = prove(&items[2].id, &leaves, 2).unwrap();
+
+        let other_root = hex::encode(root(&leaves[..4]));
+        assert!(!proof.verify(&items[2], &other_root));
+
+        let mut sibling = proof.clone();
+        sibling.path[0].1 = hex::encode(leaves[4]);
+        assert!(!sibling.verify(&items[2], &proof.root));
+
+        let mut flipped = proof.clone();
+        flipped.path[0].0 = match flipped.path[0].0 {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        };
+        assert!(!flipped.verify(&items[2], &proof.root));
+
+        let mut short = proof.clone();
+        short.path.pop();
+        assert!(!short.verify(&items[2], &proof.root));
+
+        let mut garbled = proof.clone();
+        garbled.path[0].1 = "not hex".into();
+        assert!(!garbled.verify(&items[2], &proof.root));
+    }
+
+    #[test]
+    fn leaves_and_nodes_are_domain_separated() {
+        let leaves = leaves(&items(2));
+        let as_leaf: Hash = Sha256::new().chain_update([0]).chain_update(leaves.concat()).finalize().into();
+        assert_eq!(root(&leaves), node_hash(&leaves[0], &leaves[1]));
+        assert_ne!(root(&leaves), as_leaf);
+    }
+
+    #[test]
+    fn empty_and_single_leaf_trees() {
+        assert_eq!(root(&[]), <Hash>::from(Sha256::digest([])));
+        let items = items(3);
+        assert!(prove(&items[0].id, &leaves(&items), 3).is_none());
+        assert_eq!(root(&leaves(&items[..1])), leaf_hash(&items[0]));
+    }
+}
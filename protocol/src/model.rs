@@ -0,0 +1,72 @@
+//! The reference table of known gear (make, model, years) that listings can
+//! be linked to with `UPDATE <id> <rev> model <model-id>`, browsed with
+//! `MODELS` and searched with `model:<model-id>`.
+//!
+//! A model's ID is derived from its brand and model name, so the same piece
+//! of gear has the same ID on every bazaar:
+//!
+//! ```text
+//! Roland TR-808  ->  roland-tr-808
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Model {
+    pub id: String,
+    pub brand: String,
+    pub model: String,
+    /// Production years as written in the table, e.g. `1983-1986`.
+    #[serde(default)]
+    pub years: Option<String>,
+    /// Live listings linked to the model. Filled in by the server for `MODELS`.
+    #[serde(default)]
+    pub listings: usize,
+}
+
+impl Model {
+    pub fn new(brand: &str, model: &str, years: Option<String>) -> Model {
+        Model {
+            id: model_id(brand, model),
+            brand: brand.to_string(),
+            model: model.to_string(),
+            years,
+            listings: 0,
+        }
+    }
+
+    /// Whether every word of `query` starts a word of the brand or model name,
+    /// ignoring case, so `yam dx` finds the Yamaha DX7. An empty query matches
+    /// everything.
+    pub fn matches(&self, query: &str) -> bool {
+        let words = words(&format!("{} {}", self.brand, self.model));
+        let query = query.to_lowercase();
+        let matches = words_of(&query).all(|prefix| words.iter().any(|word| word.starts_with(prefix)));
+        matches
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.brand, self.model)?;
+        match &self.years {
+            Some(years) => write!(f, " ({})", years),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The ID of a brand's model: both lowercased, with every run of other
+/// characters than letters and digits turned into a single `-`.
+pub fn model_id(brand: &str, model: &str) -> String {
+    words(&format!("{} {}", brand, model)).join("-")
+}
+
+fn words(text: &str) -> Vec<String> {
+    words_of(&text.to_lowercase()).map(str::to_string).collect()
+}
+
+fn words_of(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty())
+}
@@ -0,0 +1,297 @@
+//! Blind write passes, for servers that make writes cost something.
+//!
+//! A client solves one proof-of-work challenge (`PASS CHALLENGE`) and gets a
+//! batch of passes signed for it (`PASS ISSUE`), then redeems them on any later
+//! connection (`PASS REDEEM`), one per write. The server signs the passes
+//! blinded, so it can't link a redeemed pass to the challenge it was issued
+//! for, and the work is paid once per batch instead of once per command.
+//!
+//! A pass is a random nonce together with `k·H(nonce)`, where `k` is the
+//! server's secret key and `H` hashes onto the ristretto255 group. The client
+//! sends `r·H(nonce)` for a random `r`, the server multiplies it by `k`, and
+//! the client divides `r` back out.
+//!
+//! Blinding alone doesn't stop a server signing one client's passes with a key
+//! of their own and recognising them on redemption. So the server publishes
+//! its public key `K = k·G` in `STATUS` and with every challenge, and proves
+//! each signature was made with the `k` behind it: a Chaum-Pedersen proof that
+//! `K` and the signature have the same discrete log relative to `G` and the
+//! blinded point. A client's passes are as anonymous as the key it checks them
+//! against is shared, so clients should compare it with what other
+//! connections see.
+
+use crate::error::BazaarError;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::Scalar;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::str::FromStr;
+
+/// The server's signing key for passes.
+#[derive(Clone)]
+pub struct PassKey(Scalar);
+
+impl PassKey {
+    /// A key from 64 uniformly random bytes.
+    pub fn from_random(bytes: &[u8; 64]) -> Self {
+        Self(Scalar::from_bytes_mod_order_wide(bytes))
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        Option::from(Scalar::from_canonical_bytes(bytes)).map(Self)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    /// The public key `k·G` issued passes are proven against, in hex.
+    pub fn public_key(&self) -> String {
+        hex::encode(RistrettoPoint::mul_base(&self.0).compress().as_bytes())
+    }
+
+    /// Signs one hex blinded pass from `PASS ISSUE`, returning the hex signature
+    /// and the proof it was made with this key, as `<signature>:<proof>`.
+    pub fn sign_blinded(&self, blinded: &str) -> Result<String, BazaarError> {
+        let point = decode_point(blinded)?;
+        let signature = self.0 * point;
+
+        // Derived from the key and the point, so it is secret and never reused
+        // for another point without needing a random source
+        let mut hasher = Sha512::new();
+        hasher.update(b"nym-bazaar pass proof nonce v1\n");
+        hasher.update(self.0.as_bytes());
+        hasher.update(point.compress().as_bytes());
+        let nonce = Scalar::from_bytes_mod_order_wide(&hasher.finalize().into());
+
+        let public = RistrettoPoint::mul_base(&self.0);
+        let challenge = proof_challenge(&public, &point, &signature, &RistrettoPoint::mul_base(&nonce), &(nonce * point));
+        let response = nonce - challenge * self.0;
+        Ok(format!(
+            "{}:{}{}",
+            hex::encode(signature.compress().as_bytes()),
+            hex::encode(challenge.as_bytes()),
+            hex::encode(response.as_bytes())
+        ))
+    }
+
+    /// Whether `pass` was signed with this key. Spent passes still verify; the
+    /// server has to remember which ones it has seen.
+    pub fn verify(&self, pass: &Pass) -> bool {
+        (self.0 * hash_nonce(&pass.nonce)).compress().as_bytes() == &pass.signature
+    }
+}
+
+/// A pass before the server signed it, kept by the client until `PASS ISSUE` answers.
+pub struct BlindedPass {
+    nonce: [u8; 32],
+    blind: Scalar,
+}
+
+impl BlindedPass {
+    /// A new pass from a random nonce and 64 random bytes to blind it with.
+    pub fn new(nonce: [u8; 32], blind: &[u8; 64]) -> Self {
+        Self {
+            nonce,
+            blind: Scalar::from_bytes_mod_order_wide(blind),
+        }
+    }
+
+    /// What to send with `PASS ISSUE`, in hex.
+    pub fn blinded(&self) -> String {
+        hex::encode((self.blind * hash_nonce(&self.nonce)).compress().as_bytes())
+    }
+
+    /// Checks the server's signature from `PASS ISSUE` against its hex public
+    /// key and unblinds it into a pass that can be redeemed.
+    pub fn finish(&self, signed: &str, public_key: &str) -> Result<Pass, BazaarError> {
+        let (signature, proof) = signed.split_once(':').ok_or(BazaarError::InvalidPass)?;
+        let public = decode_point(public_key)?;
+        let signature = decode_point(signature)?;
+        let mut proof_bytes = [0; 64];
+        hex::decode_to_slice(proof, &mut proof_bytes).map_err(|_| BazaarError::InvalidPass)?;
+        let scalar = |bytes: &[u8]| {
+            let bytes: [u8; 32] = bytes.try_into().map_err(|_| BazaarError::InvalidPass)?;
+            Option::from(Scalar::from_canonical_bytes(bytes)).ok_or(BazaarError::InvalidPass)
+        };
+        let (challenge, response) = (scalar(&proof_bytes[..32])?, scalar(&proof_bytes[32..])?);
+
+        // The commitments the server must have made for the proof to hold
+        let blinded = self.blind * hash_nonce(&self.nonce);
+        let base_commitment = RistrettoPoint::vartime_double_scalar_mul_basepoint(&challenge, &public, &response);
+        let point_commitment = response * blinded + challenge * signature;
+        if proof_challenge(&public, &blinded, &signature, &base_commitment, &point_commitment) != challenge {
+            return Err(BazaarError::InvalidPass);
+        }
+
+        Ok(Pass {
+            nonce: self.nonce,
+            signature: (self.blind.invert() * signature).compress().to_bytes(),
+        })
+    }
+}
+
+/// A signed pass, written `<nonce>:<signature>` in hex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pass {
+    nonce: [u8; 32],
+    signature: [u8; 32],
+}
+
+impl Pass {
+    /// Identifies the pass for double-spend checks without keeping the pass itself.
+    pub fn spend_id(&self) -> [u8; 32] {
+        Sha256::digest(self.nonce).into()
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", hex::encode(self.nonce), hex::encode(self.signature))
+    }
+}
+
+impl FromStr for Pass {
+    type Err = BazaarError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (nonce, signature) = s.split_once(':').ok_or(BazaarError::InvalidPass)?;
+        let mut pass = Pass {
+            nonce: [0; 32],
+            signature: [0; 32],
+        };
+        hex::decode_to_slice(nonce, &mut pass.nonce).map_err(|_| BazaarError::InvalidPass)?;
+        hex::decode_to_slice(signature, &mut pass.signature).map_err(|_| BazaarError::InvalidPass)?;
+        Ok(pass)
+    }
+}
+
+/// Whether `solution` solves `challenge`: the hash of the two has to start with
+/// at least `difficulty` zero bits.
+pub fn check_solution(challenge: &str, difficulty: u32, solution: u64) -> bool {
+    leading_zero_bits(&solution_hash(challenge, solution)) >= difficulty
+}
+
+/// Finds a solution to a `PASS CHALLENGE`, taking about `2^difficulty` hashes.
+pub fn solve(challenge: &str, difficulty: u32) -> u64 {
+    (0..).find(|&solution| check_solution(challenge, difficulty, solution)).unwrap_or_default()
+}
+
+fn solution_hash(challenge: &str, solution: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"nym-bazaar pass challenge v1\n");
+    hasher.update(challenge.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(solution.to_string().as_bytes());
+    hasher.finalize().into()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+fn hash_nonce(nonce: &[u8; 32]) -> RistrettoPoint {
+    let mut hasher = Sha512::new();
+    hasher.update(b"nym-bazaar pass v1\n");
+    hasher.update(nonce);
+    RistrettoPoint::from_uniform_bytes(&hasher.finalize().into())
+}
+
+/// The Fiat-Shamir challenge of a proof that `signature = k·blinded` for the
+/// `k` in `public = k·G`, given the prover's commitments.
+fn proof_challenge(
+    public: &RistrettoPoint,
+    blinded: &RistrettoPoint,
+    signature: &RistrettoPoint,
+    base_commitment: &RistrettoPoint,
+    point_commitment: &RistrettoPoint,
+) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(b"nym-bazaar pass proof v1\n");
+    for point in [public, blinded, signature, base_commitment, point_commitment] {
+        hasher.update(point.compress().as_bytes());
+    }
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+fn decode_point(value: &str) -> Result<RistrettoPoint, BazaarError> {
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(value, &mut bytes).map_err(|_| BazaarError::InvalidPass)?;
+    CompressedRistretto(bytes).decompress().ok_or(BazaarError::InvalidPass)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blinded(seed: u8) -> BlindedPass {
+        BlindedPass::new([seed; 32], &[seed.wrapping_add(1); 64])
+    }
+
+    #[test]
+    fn blind_signed_pass_verifies() {
+        let key = PassKey::from_random(&[7; 64]);
+        let request = blinded(1);
+        let signed = key.sign_blinded(&request.blinded()).unwrap();
+        let pass = request.finish(&signed, &key.public_key()).unwrap();
+
+        assert!(key.verify(&pass));
+        assert_eq!(pass.to_string().parse::<Pass>(), Ok(pass.clone()));
+        // Unblinded, so the server can't match it to what it signed
+        assert_ne!(signed.split_once(':').unwrap().0, hex::encode(pass.signature));
+    }
+
+    #[test]
+    fn passes_from_another_key_dont_verify() {
+        let key = PassKey::from_random(&[7; 64]);
+        let other = PassKey::from_random(&[8; 64]);
+        let request = blinded(1);
+        let pass = request.finish(&other.sign_blinded(&request.blinded()).unwrap(), &other.public_key()).unwrap();
+        assert!(!key.verify(&pass));
+    }
+
+    #[test]
+    fn finish_rejects_a_signature_from_another_key() {
+        let key = PassKey::from_random(&[7; 64]);
+        let tagging = PassKey::from_random(&[8; 64]);
+        let request = blinded(1);
+        // A server signing with a key of its own can't prove it against the published one
+        let signed = tagging.sign_blinded(&request.blinded()).unwrap();
+        assert_eq!(request.finish(&signed, &key.public_key()).err(), Some(BazaarError::InvalidPass));
+    }
+
+    #[test]
+    fn finish_rejects_a_tampered_proof() {
+        let key = PassKey::from_random(&[7; 64]);
+        let request = blinded(1);
+        let signed = key.sign_blinded(&request.blinded()).unwrap();
+        let (signature, proof) = signed.split_once(':').unwrap();
+
+        let mut response = hex::decode(proof).unwrap();
+        response[40] ^= 1;
+        let tampered = format!("{}:{}", signature, hex::encode(response));
+        assert_eq!(request.finish(&tampered, &key.public_key()).err(), Some(BazaarError::InvalidPass));
+        assert_eq!(request.finish(signature, &key.public_key()).err(), Some(BazaarError::InvalidPass));
+
+        // A proof for another pass doesn't carry over
+        let other = key.sign_blinded(&blinded(2).blinded()).unwrap();
+        let (_, other_proof) = other.split_once(':').unwrap();
+        let swapped = format!("{}:{}", signature, other_proof);
+        assert_eq!(request.finish(&swapped, &key.public_key()).err(), Some(BazaarError::InvalidPass));
+    }
+
+    #[test]
+    fn solutions_meet_the_difficulty() {
+        let solution = solve("challenge", 8);
+        assert!(check_solution("challenge", 8, solution));
+        assert!(leading_zero_bits(&solution_hash("challenge", solution)) >= 8);
+        assert!(!check_solution("challenge", 256, solution));
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Currency symbols understood in listing prices, with their ISO 4217 codes.
+const SYMBOLS: &[(&str, &str)] = &[("$", "USD"), ("€", "EUR"), ("£", "GBP"), ("¥", "JPY")];
+
+/// A monetary amount in minor units (cents) of an ISO 4217 currency.
+///
+/// Listing prices are free text. `$150`, `$1,200.50`, `150 EUR` and `CHF 99.90`
+/// parse; decimal commas such as `€1.200,50` don't.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Price {
+    pub minor: u64,
+    pub currency: String,
+}
+
+impl Price {
+    pub fn new(minor: u64, currency: &str) -> Self {
+        Self {
+            minor,
+            currency: currency.to_uppercase(),
+        }
+    }
+
+    /// Converts to `currency` given how many units of it one unit of this price's currency buys.
+    pub fn convert(&self, currency: &str, rate: f64) -> Price {
+        Price::new((self.minor as f64 * rate).round() as u64, currency)
+    }
+}
+
+/// Checks that `code` looks like an ISO 4217 code and returns it uppercased.
+pub fn normalize_currency(code: &str) -> Option<String> {
+    let code = code.trim();
+    (code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())).then(|| code.to_uppercase())
+}
+
+/// Parses a plain amount such as `1,200.50` into minor units.
+pub(crate) fn parse_amount(amount: &str) -> Option<u64> {
+    let amount = amount.trim().replace(',', "");
+    let (whole, fraction) = amount.split_once('.').unwrap_or((&amount, ""));
+    if whole.is_empty() || fraction.len() > 2 || !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let cents = format!("{:0<2}", fraction).parse::<u64>().ok()?;
+    whole.parse::<u64>().ok()?.checked_mul(100)?.checked_add(cents)
+}
+
+impl FromStr for Price {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        for (symbol, code) in SYMBOLS {
+            if let Some(amount) = s.strip_prefix(symbol) {
+                return Ok(Price::new(parse_amount(amount).ok_or(())?, code));
+            }
+        }
+
+        let (first, second) = s.split_once(' ').ok_or(())?;
+        if let Some(code) = normalize_currency(second) {
+            return Ok(Price::new(parse_amount(first).ok_or(())?, &code));
+        }
+        let code = normalize_currency(first).ok_or(())?;
+        Ok(Price::new(parse_amount(second).ok_or(())?, &code))
+    }
+}
+
+/// Formats minor units as a plain amount, leaving off zero cents.
+pub(crate) fn format_amount(minor: u64) -> String {
+    let whole = minor / 100;
+    let cents = minor % 100;
+    if cents == 0 {
+        whole.to_string()
+    } else {
+        format!("{}.{:02}", whole, cents)
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = format_amount(self.minor);
+
+        match SYMBOLS.iter().find(|(_, code)| *code == self.currency) {
+            Some((symbol, _)) => write!(f, "{}{}", symbol, amount),
+            None => write!(f, "{} {}", amount, self.currency),
+        }
+    }
+}
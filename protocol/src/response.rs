@@ -0,0 +1,637 @@
+use crate::command::{format_scopes, TokenScope};
+use crate::compression::Compression;
+use crate::encoding::Encoding;
+use crate::error::BazaarError;
+use crate::i18n::{Lang, Messages};
+use crate::item::{Attestation, Change, HistoryEvent, Item, Question};
+use crate::merkle::{InclusionProof, Side};
+use crate::model::Model;
+use crate::price::Price;
+use crate::search::PriceRange;
+use crate::tlog::TlogEntry;
+use crate::upload::UploadStatus;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// A reply from the bazaar server. `Display` renders the English text payload;
+/// use [`Response::localized`] for other languages, or [`Response::encode`]
+/// for the structured encodings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    /// Usage lines for every command the server answers.
+    Help(Vec<String>),
+    Items(Vec<Item>),
+    SearchResults(Vec<Item>),
+    /// `LIST` results within a price range, in `currency` or each listing's own.
+    PriceFiltered { items: Vec<Item>, range: PriceRange, currency: Option<String> },
+    Item(Box<Item>),
+    Categories(Vec<String>),
+    /// `MODELS` results, by brand and model name.
+    Models(Vec<Model>),
+    Status(ServerStatus),
+    Reloaded { items: usize },
+    Language(Lang),
+    /// A new listing was accepted under this ULID and alias. `duplicate_of`
+    /// names an earlier listing by the same seller it closely resembles.
+    Created {
+        id: String,
+        alias: u64,
+        #[serde(default)]
+        duplicate_of: Option<String>,
+    },
+    /// A new listing was accepted and goes live at `publish_at` (Unix time).
+    Scheduled {
+        id: String,
+        alias: u64,
+        publish_at: u64,
+        #[serde(default)]
+        duplicate_of: Option<String>,
+    },
+    /// An edit was applied and the listing is now at this revision.
+    Updated { id: String, revision: u64 },
+    /// A listing was moved to the trash and can be restored for `restore_secs`.
+    Deleted { id: String, restore_secs: u64 },
+    Restored { id: String },
+    AuditTail(Vec<AuditRecord>),
+    /// Changes to one listing, oldest first.
+    History { id: String, events: Vec<HistoryEvent> },
+    /// A new API token. The secret is only ever shown in this response.
+    TokenCreated { info: TokenInfo, token: String },
+    Tokens(Vec<TokenInfo>),
+    SavedSearches(Vec<SavedSearchInfo>),
+    Seller(SellerInfo),
+    Proof(InclusionProof),
+    /// Transparency log entries, oldest first (see [`crate::tlog`]).
+    Tlog(Vec<TlogEntry>),
+    /// A dead drop's ciphertext, as the seller posted it.
+    Drop { id: String, ciphertext: String, posted_at: u64 },
+    /// Every live listing, rendered as a JSON array of [`Item`]s for mirrors and backups.
+    Export(Vec<Item>),
+    /// The images now attached to a listing, by hash.
+    Media { id: String, media: Vec<String> },
+    /// Chunk `chunk` of `chunks` of an image, base64 encoded.
+    Image { hash: String, chunk: usize, chunks: usize, data: String },
+    /// Work to do before `PASS ISSUE` signs up to `batch` passes, and the hex
+    /// public key they will be signed with.
+    PassChallenge { challenge: String, difficulty: u32, batch: usize, key: String },
+    /// Signed blinded passes with their proofs, in the order they were sent.
+    PassesIssued(Vec<String>),
+    /// Writes the connection can still make with the passes it redeemed.
+    PassBalance(u32),
+    Upload(UploadStatus),
+    /// A seller's away mode after `AWAY`.
+    Away { seller: String, away: bool, until: Option<u64> },
+    /// A question sent with `ASK`, by id.
+    Asked { id: String },
+    /// Questions about a listing, oldest first.
+    Questions { id: String, questions: Vec<Question> },
+    /// Recent announcements, newest first, and the hex ed25519 key they are signed with.
+    News { public_key: String, announcements: Vec<Announcement> },
+    /// An announcement published with `ANNOUNCE`.
+    Announced { seq: u64 },
+    /// An attestation recorded with `ATTEST`, by id.
+    Attested { id: String },
+    Authenticators(Vec<AuthenticatorInfo>),
+    /// Bytes `offset..offset + len` of a `total`-byte payload asked for with
+    /// `--range`, base64 encoded (see [`crate::command::ByteRange`]).
+    Partial { offset: u64, total: u64, data: String },
+    /// Session parameters agreed by `HELLO`:
+    /// `HELLO <protocol> <compression|none> <encoding> <session> <capability,...>`.
+    /// The session is resumable with `HELLO --session <token>` until it idles out.
+    Hello {
+        protocol_version: u32,
+        compression: Option<Compression>,
+        encoding: Encoding,
+        #[serde(default)]
+        session: String,
+        /// Subsystems this server has enabled (see [`crate::capability`]).
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
+    /// Replies to the commands of a `BATCH`, in order. As text, each reply is a
+    /// nested length-prefixed frame that [`crate::codec::FrameDecoder`] splits.
+    Batch(Vec<Response>),
+    /// A listing response with prices converted into the currency the client asked for.
+    Converted(Box<Response>, Conversion),
+    Error(BazaarError),
+}
+
+/// Converted prices for the items in a response, keyed by item ID. Items whose
+/// price couldn't be parsed are left out and shown unconverted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Conversion {
+    pub currency: String,
+    pub prices: HashMap<String, Price>,
+    /// How old the exchange rates used are.
+    pub rates_age_secs: u64,
+    /// Whether the rates are older than the server's staleness threshold.
+    pub stale: bool,
+}
+
+/// Health and statistics returned by `STATUS`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub protocol_version: u32,
+    pub address: String,
+    /// Further nym addresses serving the same bazaar.
+    #[serde(default)]
+    pub other_addresses: Vec<String>,
+    pub uptime_secs: u64,
+    pub items: usize,
+    pub categories: usize,
+    pub sessions: usize,
+    /// Merkle root over the live catalog, hex encoded (see [`crate::merkle`]).
+    #[serde(default)]
+    pub catalog_root: String,
+    /// Number of transparency log entries and the hash of the last one.
+    #[serde(default)]
+    pub tlog_size: u64,
+    #[serde(default)]
+    pub tlog_head: String,
+    /// Subsystems this server has enabled (see [`crate::capability`]).
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Public key write passes are signed with, hex encoded, when the server
+    /// requires them (see [`crate::pass`]).
+    #[serde(default)]
+    pub pass_key: String,
+}
+
+/// An operator's announcement, as returned by `NEWS`. Signed with the
+/// bazaar's announcement key over [`crate::signing::announcement_payload`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    /// Numbered from 1 in the order they were published.
+    pub seq: u64,
+    pub published_at: u64,
+    pub text: String,
+    /// Hex encoded.
+    pub signature: String,
+}
+
+/// An entry from the server's audit log, as returned by `AUDIT TAIL`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub actor: String,
+    pub action: String,
+    pub detail: String,
+    /// Chain hash of this entry, hex encoded.
+    pub hash: String,
+}
+
+/// A seller API token as listed by `TOKEN LIST`, without its secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub seller: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    pub revoked: bool,
+}
+
+/// A search saved with `SAVEDSEARCH ADD`, as listed by `SAVEDSEARCH LIST`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedSearchInfo {
+    pub name: String,
+    pub query: String,
+    pub created_at: u64,
+    pub last_run_at: Option<u64>,
+}
+
+/// An authenticator trusted to attest listings, as returned by `AUTHENTICATOR LIST`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticatorInfo {
+    pub name: String,
+    /// The ed25519 key attestations are signed with, hex encoded.
+    pub public_key: String,
+    pub added_at: u64,
+    /// Removed authenticators are kept so their revoked attestations can still be checked.
+    #[serde(default)]
+    pub removed: bool,
+}
+
+/// A registered seller name, as returned by `SELLER INFO`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SellerInfo {
+    pub name: String,
+    /// The ed25519 key the name is bound to, hex encoded.
+    pub public_key: String,
+    pub registered_at: u64,
+    pub verified: bool,
+    /// Reports moderators recorded since the last automatic ban.
+    #[serde(default)]
+    pub strikes: u32,
+    #[serde(default)]
+    pub banned: bool,
+    /// When a temporary ban ends.
+    #[serde(default)]
+    pub banned_until: Option<u64>,
+    /// The seller's tier on bazaars that limit sellers by standing.
+    #[serde(default)]
+    pub tier: Option<String>,
+}
+
+fn write_token(f: &mut fmt::Formatter<'_>, m: &Messages, info: &TokenInfo) -> fmt::Result {
+    write!(f, "{} {} {}", info.id, info.seller, format_scopes(&info.scopes))?;
+    if let Some(expires_at) = info.expires_at {
+        write!(f, " {} {}", m.token_expires, expires_at)?;
+    }
+    if info.revoked {
+        write!(f, " {}", m.token_revoked)?;
+    }
+    writeln!(f)
+}
+
+impl From<BazaarError> for Response {
+    fn from(e: BazaarError) -> Self {
+        Response::Error(e)
+    }
+}
+
+impl Response {
+    /// Renders the response text in `lang`.
+    pub fn localized(&self, lang: Lang) -> Localized<'_> {
+        Localized { response: self, lang }
+    }
+}
+
+/// A response paired with the language to render it in.
+pub struct Localized<'a> {
+    response: &'a Response,
+    lang: Lang,
+}
+
+fn converted_price(item: &Item, conversion: Option<&Conversion>) -> String {
+    match conversion.and_then(|c| c.prices.get(&item.id)) {
+        Some(converted) => format!("{} (~{})", item.price, converted),
+        None => item.price.clone(),
+    }
+}
+
+fn write_summaries(f: &mut fmt::Formatter<'_>, items: &[Item], conversion: Option<&Conversion>) -> fmt::Result {
+    for item in items {
+        writeln!(f, "{}. {} - {}", item.alias, item.name, converted_price(item, conversion))?;
+    }
+    Ok(())
+}
+
+fn write_price_range(f: &mut fmt::Formatter<'_>, m: &Messages, range: &PriceRange, currency: &Option<String>) -> fmt::Result {
+    let range = match currency {
+        Some(currency) => format!("{} {}", range, currency),
+        None => range.to_string(),
+    };
+    writeln!(f, "{}", Messages::fill(m.price_range, &[&range]))
+}
+
+fn write_item(f: &mut fmt::Formatter<'_>, m: &Messages, item: &Item, conversion: Option<&Conversion>) -> fmt::Result {
+    writeln!(
+        f,
+        "{}: {} (#{})\n{}: {}\n{}: {}\n{}: {}\n{}: {}\n{}: {}\n\n{}",
+        m.id, item.id, item.alias,
+        m.revision, item.revision,
+        m.name, item.name,
+        m.category, item.category,
+        m.price, converted_price(item, conversion),
+        m.seller, seller_name(m, &item.seller, item.seller_verified),
+        item.description
+    )?;
+    match (item.seller_away, item.seller_back_at) {
+        (true, Some(back_at)) => writeln!(f, "\n{}", Messages::fill(m.seller_away_until, &[&back_at]))?,
+        (true, None) => writeln!(f, "\n{}", m.seller_away)?,
+        (false, _) => {},
+    }
+    if let Some(model) = &item.model {
+        writeln!(f, "\n{}: {}", m.model, model)?;
+    }
+    if let Some(signature) = &item.signature {
+        writeln!(f, "\n{}: {}", m.signature, signature)?;
+    }
+    if !item.bundle.is_empty() {
+        writeln!(f, "\n{}:", m.bundle_of)?;
+        for id in &item.bundle {
+            writeln!(f, "  {}", id)?;
+        }
+    }
+    if !item.media.is_empty() {
+        writeln!(f, "\n{}:", m.images)?;
+        for hash in &item.media {
+            writeln!(f, "  {}", hash)?;
+        }
+    }
+    if !item.shipping.is_empty() {
+        writeln!(f, "\n{}:", m.shipping)?;
+        for option in &item.shipping.0 {
+            let days = if option.min_days == option.max_days {
+                option.min_days.to_string()
+            } else {
+                format!("{}-{}", option.min_days, option.max_days)
+            };
+            writeln!(f, "  {}", Messages::fill(m.shipping_option, &[&option.method, &option.regions.join(", "), &option.cost, &days]))?;
+        }
+    }
+    if !item.attestations.is_empty() {
+        writeln!(f, "\n{}:", m.attestations)?;
+        item.attestations.iter().try_for_each(|attestation| write_attestation(f, m, attestation))?;
+    }
+    if !item.questions.is_empty() {
+        writeln!(f, "\n{}:", m.questions)?;
+        item.questions.iter().try_for_each(|question| write_question(f, m, question))?;
+    }
+    Ok(())
+}
+
+fn write_question(f: &mut fmt::Formatter<'_>, m: &Messages, question: &Question) -> fmt::Result {
+    writeln!(f, "  {}", Messages::fill(m.question_line, &[&question.question]))?;
+    match &question.answer {
+        Some(answer) => writeln!(f, "  {}", Messages::fill(m.answer_line, &[answer])),
+        None => writeln!(f, "  {}", m.unanswered),
+    }
+}
+
+fn write_attestation(f: &mut fmt::Formatter<'_>, m: &Messages, attestation: &Attestation) -> fmt::Result {
+    write!(f, "  [{}] {}: {}", attestation.id, attestation.authenticator, attestation.statement)?;
+    if attestation.revoked_at.is_some() {
+        write!(f, " ({})", m.attestation_revoked)?;
+    }
+    writeln!(f)
+}
+
+fn write_duplicate(f: &mut fmt::Formatter<'_>, m: &Messages, duplicate_of: &Option<String>) -> fmt::Result {
+    match duplicate_of {
+        Some(id) => writeln!(f, "{}", Messages::fill(m.possible_duplicate, &[id])),
+        None => Ok(()),
+    }
+}
+
+fn seller_name(m: &Messages, name: &str, verified: bool) -> String {
+    if verified {
+        Messages::fill(m.verified_seller, &[&name])
+    } else {
+        name.to_string()
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.localized(Lang::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Localized<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.lang.messages();
+        match self.response {
+            Response::Ok => writeln!(f, "OK"),
+            Response::Help(usage) => {
+                writeln!(f, "{}", m.available_commands)?;
+                for usage in usage {
+                    writeln!(f, "{}", usage)?;
+                }
+                Ok(())
+            },
+            Response::Items(items) if items.is_empty() => writeln!(f, "{}", m.no_items),
+            Response::Items(items) => write_summaries(f, items, None),
+            Response::SearchResults(items) if items.is_empty() => {
+                writeln!(f, "{}", m.no_search_results)
+            },
+            Response::SearchResults(items) => write_summaries(f, items, None),
+            Response::PriceFiltered { items, range, currency } => {
+                write_price_range(f, m, range, currency)?;
+                if items.is_empty() {
+                    writeln!(f, "{}", m.no_items)
+                } else {
+                    write_summaries(f, items, None)
+                }
+            },
+            Response::Item(item) => write_item(f, m, item, None),
+            Response::Models(models) => {
+                if models.is_empty() {
+                    return writeln!(f, "{}", m.no_models);
+                }
+                writeln!(f, "{}", m.reference_models)?;
+                for model in models {
+                    let listings = Messages::fill(m.model_listings, &[&model.listings]);
+                    writeln!(f, "- {}  {}  [{}]", model.id, model, listings)?;
+                }
+                Ok(())
+            },
+            Response::Categories(categories) => {
+                writeln!(f, "{}", m.available_categories)?;
+                for category in categories {
+                    writeln!(f, "- {}", category)?;
+                }
+                Ok(())
+            },
+            Response::Status(status) => {
+                writeln!(f, "{}: {}", m.protocol, status.protocol_version)?;
+                writeln!(f, "{}: {}", m.address, status.address)?;
+                for address in &status.other_addresses {
+                    writeln!(f, "{}: {}", m.also_reachable_at, address)?;
+                }
+                writeln!(f, "{}: {}s", m.uptime, status.uptime_secs)?;
+                writeln!(f, "{}: {}", m.items, status.items)?;
+                writeln!(f, "{}: {}", m.categories, status.categories)?;
+                writeln!(f, "{}: {}", m.sessions, status.sessions)?;
+                writeln!(f, "{}: {}", m.catalog_root, status.catalog_root)?;
+                writeln!(f, "{}: {} {}", m.tlog_head, status.tlog_size, status.tlog_head)?;
+                writeln!(f, "{}: {}", m.capabilities, status.capabilities.join(", "))?;
+                if !status.pass_key.is_empty() {
+                    writeln!(f, "{}: {}", m.pass_key, status.pass_key)?;
+                }
+                Ok(())
+            },
+            Response::Reloaded { items } => writeln!(f, "{}", Messages::fill(m.reloaded, &[items])),
+            Response::Language(lang) => writeln!(f, "{}", lang.messages().language_set),
+            Response::Created { id, alias, duplicate_of } => {
+                writeln!(f, "{}", Messages::fill(m.created, &[id, alias]))?;
+                write_duplicate(f, m, duplicate_of)
+            },
+            Response::Scheduled { id, alias, publish_at, duplicate_of } => {
+                writeln!(f, "{}", Messages::fill(m.scheduled, &[id, alias, publish_at]))?;
+                write_duplicate(f, m, duplicate_of)
+            },
+            Response::Updated { id, revision } => {
+                writeln!(f, "{}", Messages::fill(m.updated, &[id, revision]))
+            },
+            Response::Deleted { id, restore_secs } => {
+                let hours = restore_secs / 3600;
+                writeln!(f, "{}", Messages::fill(m.deleted, &[id, &hours]))
+            },
+            Response::Restored { id } => writeln!(f, "{}", Messages::fill(m.restored, &[id])),
+            Response::AuditTail(records) if records.is_empty() => writeln!(f, "{}", m.no_audit_entries),
+            Response::AuditTail(records) => {
+                for r in records {
+                    let hash = r.hash.get(..16).unwrap_or(&r.hash);
+                    writeln!(f, "{} {} {} {} {} {}", r.seq, r.timestamp, hash, r.actor, r.action, r.detail)?;
+                }
+                Ok(())
+            },
+            Response::Converted(inner, conversion) => {
+                match inner.as_ref() {
+                    Response::Items(items) | Response::SearchResults(items) if !items.is_empty() => {
+                        write_summaries(f, items, Some(conversion))?
+                    },
+                    Response::Item(item) => write_item(f, m, item, Some(conversion))?,
+                    Response::PriceFiltered { items, range, currency } if !items.is_empty() => {
+                        write_price_range(f, m, range, currency)?;
+                        write_summaries(f, items, Some(conversion))?
+                    },
+                    other => return other.localized(self.lang).fmt(f),
+                }
+                writeln!(f)?;
+                let minutes = conversion.rates_age_secs / 60;
+                writeln!(f, "{}", Messages::fill(m.converted, &[&conversion.currency, &minutes]))?;
+                if conversion.stale {
+                    writeln!(f, "{}", m.stale_rates)?;
+                }
+                Ok(())
+            },
+            Response::History { id, events } => {
+                writeln!(f, "{}", Messages::fill(m.history, &[id]))?;
+                for event in events {
+                    write!(f, "{} {} {}: ", event.timestamp, m.revision, event.revision)?;
+                    match &event.change {
+                        Change::Listed { price } => writeln!(f, "{}", Messages::fill(m.listed_at, &[price]))?,
+                        Change::Updated { field, old, new } => writeln!(f, "{} {} -> {}", field.name(), old, new)?,
+                        Change::Deleted => writeln!(f, "{}", m.history_deleted)?,
+                        Change::Restored => writeln!(f, "{}", m.history_restored)?,
+                    }
+                }
+                Ok(())
+            },
+            Response::TokenCreated { info, token } => {
+                write_token(f, m, info)?;
+                writeln!(f, "{}", Messages::fill(m.token_created, &[token]))
+            },
+            Response::Tokens(tokens) if tokens.is_empty() => writeln!(f, "{}", m.no_tokens),
+            Response::Tokens(tokens) => tokens.iter().try_for_each(|info| write_token(f, m, info)),
+            Response::SavedSearches(searches) if searches.is_empty() => writeln!(f, "{}", m.no_saved_searches),
+            Response::SavedSearches(searches) => searches.iter().try_for_each(|search| {
+                write!(f, "{}: {}", search.name, search.query)?;
+                if let Some(at) = search.last_run_at {
+                    write!(f, " ({} {})", m.last_run, at)?;
+                }
+                writeln!(f)
+            }),
+            Response::Seller(info) => {
+                writeln!(f, "{}: {}", m.seller, seller_name(m, &info.name, info.verified))?;
+                writeln!(f, "{}: {}", m.public_key, info.public_key)?;
+                writeln!(f, "{}: {}", m.registered, info.registered_at)?;
+                if let Some(tier) = &info.tier {
+                    writeln!(f, "{}: {}", m.tier, tier)?;
+                }
+                if info.strikes > 0 {
+                    writeln!(f, "{}: {}", m.strikes, info.strikes)?;
+                }
+                match (info.banned, info.banned_until) {
+                    (true, Some(until)) => writeln!(f, "{}", Messages::fill(m.banned_until, &[&until])),
+                    (true, None) => writeln!(f, "{}", m.banned),
+                    (false, _) => Ok(()),
+                }
+            },
+            Response::Proof(proof) => {
+                writeln!(f, "{}: {}", m.id, proof.id)?;
+                writeln!(f, "{}", Messages::fill(m.proof_leaf, &[&(proof.index + 1), &proof.leaf_count]))?;
+                writeln!(f, "{}: {}", m.catalog_root, proof.root)?;
+                for (side, hash) in &proof.path {
+                    let side = match side {
+                        Side::Left => "left",
+                        Side::Right => "right",
+                    };
+                    writeln!(f, "{} {}", side, hash)?;
+                }
+                Ok(())
+            },
+            Response::Tlog(entries) if entries.is_empty() => writeln!(f, "{}", m.no_tlog_entries),
+            Response::Tlog(entries) => entries.iter().try_for_each(|entry| writeln!(f, "{}", entry)),
+            Response::Drop { id, ciphertext, posted_at } => {
+                writeln!(f, "{}", Messages::fill(m.drop_posted, &[id, posted_at]))?;
+                writeln!(f, "{}", ciphertext)
+            },
+            Response::Export(items) => {
+                let json = serde_json::to_string(items).map_err(|_| fmt::Error)?;
+                writeln!(f, "{}", json)
+            },
+            Response::Media { id, media } if media.is_empty() => writeln!(f, "{}", Messages::fill(m.no_images, &[id])),
+            Response::Media { id, media } => {
+                writeln!(f, "{}", Messages::fill(m.images_of, &[id]))?;
+                media.iter().try_for_each(|hash| writeln!(f, "{}", hash))
+            },
+            Response::Image { hash, chunk, chunks, data } => {
+                writeln!(f, "IMAGE {} {} {}", hash, chunk, chunks)?;
+                writeln!(f, "{}", data)
+            },
+            Response::PassChallenge { challenge, difficulty, batch, key } => {
+                writeln!(f, "CHALLENGE {} {} {} {}", challenge, difficulty, batch, key)
+            },
+            Response::PassesIssued(signed) => signed.iter().try_for_each(|signed| writeln!(f, "{}", signed)),
+            Response::PassBalance(passes) => writeln!(f, "{}", Messages::fill(m.passes_left, &[passes])),
+            Response::Away { seller, away: false, .. } => writeln!(f, "{}", Messages::fill(m.away_off, &[seller])),
+            Response::Away { seller, until: None, .. } => writeln!(f, "{}", Messages::fill(m.away_on, &[seller])),
+            Response::Away { seller, until: Some(until), .. } => {
+                writeln!(f, "{}", Messages::fill(m.away_on_until, &[seller, until]))
+            },
+            Response::Asked { id } => writeln!(f, "{}", Messages::fill(m.question_sent, &[id])),
+            Response::Announced { seq } => writeln!(f, "{}", Messages::fill(m.announced, &[seq])),
+            Response::News { announcements, .. } if announcements.is_empty() => writeln!(f, "{}", m.no_news),
+            Response::News { public_key, announcements } => {
+                writeln!(f, "{} ({})", m.news, Messages::fill(m.news_signed_by, &[public_key]))?;
+                for announcement in announcements {
+                    writeln!(f, "\n#{} {}\n{}", announcement.seq, announcement.published_at, announcement.text)?;
+                }
+                Ok(())
+            },
+            Response::Attested { id } => writeln!(f, "{}", Messages::fill(m.attested, &[id])),
+            Response::Authenticators(authenticators) if authenticators.is_empty() => writeln!(f, "{}", m.no_authenticators),
+            Response::Authenticators(authenticators) => {
+                writeln!(f, "{}", m.authenticators)?;
+                for authenticator in authenticators {
+                    write!(f, "- {}  {}", authenticator.name, authenticator.public_key)?;
+                    if authenticator.removed {
+                        write!(f, " {}", m.authenticator_removed)?;
+                    }
+                    writeln!(f)?;
+                }
+                Ok(())
+            },
+            Response::Questions { id, questions } if questions.is_empty() => {
+                writeln!(f, "{}", Messages::fill(m.no_questions, &[id]))
+            },
+            Response::Questions { id, questions } => {
+                writeln!(f, "{}", Messages::fill(m.questions_about, &[id]))?;
+                questions.iter().try_for_each(|question| {
+                    writeln!(f, "[{}]", question.id)?;
+                    write_question(f, m, question)
+                })
+            },
+            Response::Partial { offset, total, data } => {
+                writeln!(f, "PARTIAL {} {}", offset, total)?;
+                writeln!(f, "{}", data)
+            },
+            Response::Upload(status) => {
+                writeln!(f, "UPLOAD {} {} {} {}", status.id, status.size, status.hash, status.chunks)?;
+                if !status.is_complete() {
+                    let missing: Vec<String> = status.missing.iter().map(|chunk| chunk.to_string()).collect();
+                    writeln!(f, "MISSING {}", missing.join(" "))?;
+                }
+                Ok(())
+            },
+            Response::Batch(responses) => responses.iter().try_for_each(|response| {
+                let text = response.localized(self.lang).to_string();
+                write!(f, "{}\n{}", text.len(), text)
+            }),
+            Response::Hello { protocol_version, compression, encoding, session, capabilities } => {
+                let compression = compression.map_or("none", |c| c.name());
+                let capabilities = if capabilities.is_empty() { "-".to_string() } else { capabilities.join(",") };
+                writeln!(f, "HELLO {} {} {} {} {}", protocol_version, compression, encoding.name(), session, capabilities)
+            },
+            Response::Error(e) => writeln!(f, "{}", e),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! `SEARCH` query syntax: free-text words mixed with `key:value` filters.
+//!
+//! ```text
+//! SEARCH synth price:100..500 category:synthesizer seller:SynthWave sort:price
+//! SEARCH model:roland-juno-60
+//! ```
+//!
+//! Every word has to appear in a listing's name, description or category; a
+//! quoted phrase has to appear as written. Unknown keys are searched as words.
+
+use crate::command::{quote, tokenize};
+use crate::error::BazaarError;
+use crate::price::{format_amount, parse_amount};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub category: Option<String>,
+    pub seller: Option<String>,
+    /// ID of a reference model listings are linked to, see [`crate::model`].
+    pub model: Option<String>,
+    pub price: Option<PriceRange>,
+    pub sort: Option<SortKey>,
+}
+
+/// An inclusive price range in minor units. Either end may be open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceRange {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+/// Result order for `sort:`. Without one, listings come oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Price,
+    PriceDesc,
+    Newest,
+    Oldest,
+    Name,
+}
+
+impl SortKey {
+    pub const ALL: &'static [SortKey] =
+        &[SortKey::Price, SortKey::PriceDesc, SortKey::Newest, SortKey::Oldest, SortKey::Name];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Price => "price",
+            SortKey::PriceDesc => "-price",
+            SortKey::Newest => "newest",
+            SortKey::Oldest => "oldest",
+            SortKey::Name => "name",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SortKey> {
+        SortKey::ALL.iter().copied().find(|key| key.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl PriceRange {
+    /// A range from optional bounds, refusing one whose minimum is above its maximum.
+    pub fn new(min: Option<u64>, max: Option<u64>) -> Result<PriceRange, BazaarError> {
+        if min.zip(max).is_some_and(|(min, max)| min > max) {
+            return Err(BazaarError::InvalidCommand);
+        }
+        Ok(PriceRange { min, max })
+    }
+
+    /// Parses one bound such as `99.50`.
+    pub fn parse_amount(amount: &str) -> Result<u64, BazaarError> {
+        parse_amount(amount).ok_or(BazaarError::InvalidCommand)
+    }
+
+    pub fn contains(&self, minor: u64) -> bool {
+        self.min.is_none_or(|min| minor >= min) && self.max.is_none_or(|max| minor <= max)
+    }
+}
+
+impl FromStr for PriceRange {
+    type Err = BazaarError;
+
+    /// Parses `100..500`, `100..`, `..500`, or a single amount for an exact price.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let amount = |s: &str| match s {
+            "" => Ok(None),
+            s => PriceRange::parse_amount(s).map(Some),
+        };
+        match s.split_once("..") {
+            Some((min, max)) => PriceRange::new(amount(min)?, amount(max)?),
+            None => {
+                let exact = PriceRange::parse_amount(s)?;
+                PriceRange::new(Some(exact), Some(exact))
+            },
+        }
+    }
+}
+
+impl fmt::Display for PriceRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min == max => write!(f, "{}", format_amount(min)),
+            (min, max) => write!(
+                f,
+                "{}..{}",
+                min.map(format_amount).unwrap_or_default(),
+                max.map(format_amount).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+impl SearchQuery {
+    /// Builds a query from already tokenized words.
+    pub fn parse(words: &[&str]) -> Result<SearchQuery, BazaarError> {
+        let mut query = SearchQuery::default();
+        for word in words {
+            let (key, value) = word.split_once(':').unwrap_or(("", word));
+            match key.to_lowercase().as_str() {
+                "category" => query.category = Some(value.to_string()),
+                "seller" => query.seller = Some(value.to_string()),
+                "model" => query.model = Some(value.to_lowercase()),
+                "price" => query.price = Some(value.parse()?),
+                "sort" => query.sort = Some(SortKey::from_name(value).ok_or(BazaarError::InvalidCommand)?),
+                _ => query.terms.push(word.to_string()),
+            }
+        }
+        if query == SearchQuery::default() {
+            return Err(BazaarError::MissingArgument);
+        }
+        Ok(query)
+    }
+}
+
+impl FromStr for SearchQuery {
+    type Err = BazaarError;
+
+    /// Parses a query as typed after `SEARCH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        SearchQuery::parse(&tokens.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+}
+
+impl fmt::Display for SearchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words: Vec<String> = self.terms.iter().map(|term| quote(term).into_owned()).collect();
+        if let Some(category) = &self.category {
+            words.push(quote(&format!("category:{}", category)).into_owned());
+        }
+        if let Some(seller) = &self.seller {
+            words.push(quote(&format!("seller:{}", seller)).into_owned());
+        }
+        if let Some(model) = &self.model {
+            words.push(quote(&format!("model:{}", model)).into_owned());
+        }
+        if let Some(price) = &self.price {
+            words.push(format!("price:{}", price));
+        }
+        if let Some(sort) = self.sort {
+            words.push(format!("sort:{}", sort.name()));
+        }
+        write!(f, "{}", words.join(" "))
+    }
+}
@@ -0,0 +1,138 @@
+//! Shipping options a seller offers on a listing.
+//!
+//! On the wire a listing's options are one argument, each option written as
+//! `method:regions:cost:days` and separated by `;`:
+//!
+//! ```text
+//! UPDATE 42 3 shipping "tracked:EU,UK:€8:3-5; letter:WORLD:€4:10-20"
+//! ```
+
+use crate::error::BazaarError;
+use crate::price::Price;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Region code for options that ship anywhere.
+pub const WORLDWIDE: &str = "WORLD";
+
+/// One way a listing can be delivered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShippingOption {
+    pub method: String,
+    /// Coarse region codes such as `EU`, `US` or [`WORLDWIDE`], uppercased.
+    pub regions: Vec<String>,
+    pub cost: Price,
+    /// Estimated delivery time in days, as a range.
+    pub min_days: u32,
+    pub max_days: u32,
+}
+
+/// A listing's shipping options. Empty means the seller hasn't said.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ShippingOptions(pub Vec<ShippingOption>);
+
+/// Whether `code` can name a region: 2 to 5 letters or digits.
+pub fn is_valid_region(code: &str) -> bool {
+    (2..=5).contains(&code.len()) && code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+impl ShippingOption {
+    /// Whether this option delivers to `region`.
+    pub fn ships_to(&self, region: &str) -> bool {
+        self.regions
+            .iter()
+            .any(|r| r == WORLDWIDE || r.eq_ignore_ascii_case(region))
+    }
+}
+
+impl ShippingOptions {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether any option delivers to `region`. Listings without options might, so they count.
+    pub fn ships_to(&self, region: &str) -> bool {
+        self.is_empty() || self.0.iter().any(|option| option.ships_to(region))
+    }
+
+    /// The option called `method`, ignoring case.
+    pub fn get(&self, method: &str) -> Option<&ShippingOption> {
+        self.0.iter().find(|option| option.method.eq_ignore_ascii_case(method))
+    }
+}
+
+impl FromStr for ShippingOption {
+    type Err = BazaarError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [method, regions, cost, days] = s.trim().splitn(4, ':').collect::<Vec<_>>()[..] else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let regions: Vec<String> = regions.split(',').map(|r| r.trim().to_uppercase()).collect();
+        if method.trim().is_empty() || !regions.iter().all(|r| is_valid_region(r)) {
+            return Err(BazaarError::InvalidCommand);
+        }
+        let cost = cost.parse().map_err(|_| BazaarError::InvalidCommand)?;
+        let day = |d: &str| d.trim().parse::<u32>().map_err(|_| BazaarError::InvalidCommand);
+        let (min_days, max_days) = match days.split_once('-') {
+            Some((min, max)) => (day(min)?, day(max)?),
+            None => (day(days)?, day(days)?),
+        };
+        if min_days > max_days {
+            return Err(BazaarError::InvalidCommand);
+        }
+
+        Ok(ShippingOption {
+            method: method.trim().to_string(),
+            regions,
+            cost,
+            min_days,
+            max_days,
+        })
+    }
+}
+
+impl fmt::Display for ShippingOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:", self.method, self.regions.join(","), self.cost)?;
+        if self.min_days == self.max_days {
+            write!(f, "{}", self.min_days)
+        } else {
+            write!(f, "{}-{}", self.min_days, self.max_days)
+        }
+    }
+}
+
+impl FromStr for ShippingOptions {
+    type Err = BazaarError;
+
+    /// Parses `;`-separated options. An empty string clears them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let options: Vec<ShippingOption> = s
+            .split(';')
+            .filter(|option| !option.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        let mut methods: Vec<String> = options.iter().map(|option| option.method.to_lowercase()).collect();
+        methods.sort();
+        methods.dedup();
+        if methods.len() != options.len() {
+            return Err(BazaarError::InvalidCommand);
+        }
+        Ok(ShippingOptions(options))
+    }
+}
+
+impl fmt::Display for ShippingOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, option) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", option)?;
+        }
+        Ok(())
+    }
+}
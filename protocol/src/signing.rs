@@ -0,0 +1,253 @@
+//! ed25519 signatures from sellers' registered keys, authenticators and the server.
+//!
+//! A seller signs [`listing_payload`] of a listing and submits it with `SIGN`.
+//! The signature is served with the listing, so a client holding the seller's
+//! key can tell whether the server or a mirror changed it.
+//!
+//! Authenticators sign [`attestation_payload`] to vouch for an item with
+//! `ATTEST`, and [`revocation_payload`] to withdraw it again.
+//!
+//! The server signs [`announcement_payload`] of every `ANNOUNCE` with its own
+//! announcement key, so announcements relayed by others can be checked.
+//!
+//! Clients sum up what they could check about a listing as a [`Trust`].
+
+use crate::error::BazaarError;
+use crate::item::{Attestation, Item};
+use crate::merkle::InclusionProof;
+use crate::response::Announcement;
+use ed25519_dalek::{Signature, VerifyingKey};
+
+/// Checks a hex `signature` over `message` against a hex ed25519 `public_key`.
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), BazaarError> {
+    let key = VerifyingKey::from_bytes(&decode_hex(public_key)?).map_err(|_| BazaarError::InvalidSignature)?;
+    let signature = Signature::from_bytes(&decode_hex(signature)?);
+    key.verify_strict(message, &signature).map_err(|_| BazaarError::InvalidSignature)
+}
+
+/// Whether `public_key` is a hex encoded ed25519 public key.
+pub fn is_public_key(public_key: &str) -> bool {
+    decode_hex(public_key).is_ok_and(|key| VerifyingKey::from_bytes(&key).is_ok())
+}
+
+fn decode_hex<const N: usize>(value: &str) -> Result<[u8; N], BazaarError> {
+    let mut bytes = [0; N];
+    hex::decode_to_slice(value, &mut bytes).map_err(|_| BazaarError::InvalidSignature)?;
+    Ok(bytes)
+}
+
+/// How far a listing as served can be trusted, for clients to show next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    /// Signed by the seller's registered key, and the listing the catalog
+    /// proof is for.
+    Verified,
+    /// Not signed, or signed by a seller with no registered key to check it with.
+    Unsigned,
+    /// The signature doesn't match, or the catalog proof is for a different
+    /// listing: the server or a mirror changed it.
+    Invalid,
+}
+
+impl Trust {
+    pub fn name(self) -> &'static str {
+        match self {
+            Trust::Verified => "verified",
+            Trust::Unsigned => "unsigned",
+            Trust::Invalid => "INVALID",
+        }
+    }
+}
+
+/// The bytes a seller signs for one revision of a listing. Every field a buyer
+/// relies on is included, length-prefixed so no two listings share a payload.
+pub fn listing_payload(item: &Item) -> Vec<u8> {
+    let shipping = item.shipping.to_string();
+    let revision = item.revision.to_string();
+    let fields = [
+        item.id.as_str(),
+        revision.as_str(),
+        item.seller.as_str(),
+        item.name.as_str(),
+        item.category.as_str(),
+        item.price.as_str(),
+        item.description.as_str(),
+        shipping.as_str(),
+    ];
+
+    let mut payload = b"nym-bazaar listing v1\n".to_vec();
+    for field in fields {
+        payload.extend_from_slice(format!("{}:", field.len()).as_bytes());
+        payload.extend_from_slice(field.as_bytes());
+        payload.push(b'\n');
+    }
+    payload
+}
+
+/// The bytes an authenticator signs to attest `statement` about the listing
+/// with ULID `id`. The statement is about the item itself, so it doesn't
+/// depend on the listing's revision.
+pub fn attestation_payload(id: &str, authenticator: &str, statement: &str) -> Vec<u8> {
+    let mut payload = b"nym-bazaar attestation v1\n".to_vec();
+    for field in [id, authenticator, statement] {
+        payload.extend_from_slice(format!("{}:", field.len()).as_bytes());
+        payload.extend_from_slice(field.as_bytes());
+        payload.push(b'\n');
+    }
+    payload
+}
+
+/// The bytes an authenticator signs to revoke attestation `id`.
+pub fn revocation_payload(id: &str) -> Vec<u8> {
+    format!("nym-bazaar attestation revocation v1\n{}", id).into_bytes()
+}
+
+/// The bytes the server signs for an announcement.
+pub fn announcement_payload(seq: u64, published_at: u64, text: &str) -> Vec<u8> {
+    format!("nym-bazaar announcement v1\n{}\n{}\n{}", seq, published_at, text).into_bytes()
+}
+
+impl Announcement {
+    /// Checks the announcement's signature against the bazaar's hex announcement key.
+    pub fn verify(&self, public_key: &str) -> Result<(), BazaarError> {
+        verify(public_key, &announcement_payload(self.seq, self.published_at, &self.text), &self.signature)
+    }
+}
+
+impl Attestation {
+    /// Checks the attestation's signature for listing `id` against its
+    /// authenticator's hex public key.
+    pub fn verify(&self, id: &str, public_key: &str) -> Result<(), BazaarError> {
+        verify(public_key, &attestation_payload(id, &self.authenticator, &self.statement), &self.signature)
+    }
+}
+
+impl Item {
+    /// Checks the listing's seller signature against the seller's hex public key.
+    /// Unsigned listings fail with `InvalidSignature` too.
+    pub fn verify_signature(&self, public_key: &str) -> Result<(), BazaarError> {
+        let signature = self.signature.as_deref().ok_or(BazaarError::InvalidSignature)?;
+        verify(public_key, &listing_payload(self), signature)
+    }
+
+    /// Checks the listing against its seller's hex public key, if the seller
+    /// registered one, and against the bazaar's catalog `proof` for it, if
+    /// there is one. A proof that doesn't hold makes even a well signed
+    /// listing invalid.
+    pub fn trust(&self, seller_key: Option<&str>, proof: Option<&InclusionProof>) -> Trust {
+        if proof.is_some_and(|proof| !proof.verify(self, &proof.root)) {
+            return Trust::Invalid;
+        }
+        match (&self.signature, seller_key) {
+            (None, _) | (_, None) => Trust::Unsigned,
+            (Some(_), Some(key)) if self.verify_signature(key).is_ok() => Trust::Verified,
+            (Some(_), Some(_)) => Trust::Invalid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn listing() -> Item {
+        Item {
+            shipping: "post:DE:€5:2-4".parse().unwrap(),
+            ..Item::new("01JB2Q5X4N0000000000000001".into(), 0, "Juno 60".into(), "synths".into(), "Serviced".into(), "€900".into(), "alice".into())
+        }
+    }
+
+    fn signed(key: &SigningKey) -> Item {
+        let mut item = listing();
+        item.signature = Some(hex::encode(key.sign(&listing_payload(&item)).to_bytes()));
+        item
+    }
+
+    fn public(key: &SigningKey) -> String {
+        hex::encode(key.verifying_key().as_bytes())
+    }
+
+    #[test]
+    fn payload_covers_every_signed_field() {
+        let item = listing();
+        let changes: [fn(&mut Item); 8] = [
+            |item| item.id.push('X'),
+            |item| item.revision += 1,
+            |item| item.seller.push('X'),
+            |item| item.name.push('X'),
+            |item| item.category.push('X'),
+            |item| item.price.push('0'),
+            |item| item.description.push('X'),
+            |item| item.shipping = "pickup:DE:€0:0-0".parse().unwrap(),
+        ];
+        for change in changes {
+            let mut changed = item.clone();
+            change(&mut changed);
+            assert_ne!(listing_payload(&changed), listing_payload(&item), "{:?}", changed);
+        }
+    }
+
+    #[test]
+    fn payload_fields_cant_run_into_each_other() {
+        let mut split = listing();
+        (split.name, split.category) = ("Juno 6".into(), "0synths".into());
+        assert_ne!(listing_payload(&split), listing_payload(&listing()));
+    }
+
+    #[test]
+    fn signed_listing_verifies() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let item = signed(&key);
+        assert_eq!(item.verify_signature(&public(&key)), Ok(()));
+        assert_eq!(item.trust(Some(&public(&key)), None), Trust::Verified);
+    }
+
+    #[test]
+    fn tampered_listing_fails() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let mut item = signed(&key);
+        item.price = "€90".into();
+        assert_eq!(item.verify_signature(&public(&key)), Err(BazaarError::InvalidSignature));
+        assert_eq!(item.trust(Some(&public(&key)), None), Trust::Invalid);
+    }
+
+    #[test]
+    fn wrong_key_or_signature_fails() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let other = SigningKey::from_bytes(&[4; 32]);
+        let item = signed(&key);
+        assert_eq!(item.verify_signature(&public(&other)), Err(BazaarError::InvalidSignature));
+        assert_eq!(item.verify_signature("not a key"), Err(BazaarError::InvalidSignature));
+
+        let mut garbled = item.clone();
+        garbled.signature = Some("00".repeat(64));
+        assert_eq!(garbled.verify_signature(&public(&key)), Err(BazaarError::InvalidSignature));
+    }
+
+    #[test]
+    fn unsigned_listings_are_unsigned_not_invalid() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let item = listing();
+        assert_eq!(item.verify_signature(&public(&key)), Err(BazaarError::InvalidSignature));
+        assert_eq!(item.trust(Some(&public(&key)), None), Trust::Unsigned);
+        // Signed, but the seller has no registered key to check it with
+        assert_eq!(signed(&key).trust(None, None), Trust::Unsigned);
+    }
+
+    #[test]
+    fn a_proof_for_another_listing_is_invalid() {
+        let key = SigningKey::from_bytes(&[3; 32]);
+        let item = signed(&key);
+        let leaves = [merkle::leaf_hash(&item)];
+        let proof = merkle::prove(&item.id, &leaves, 0).unwrap();
+        assert_eq!(item.trust(Some(&public(&key)), Some(&proof)), Trust::Verified);
+
+        // Re-signed by the seller, but not what the catalog holds
+        let mut changed = item.clone();
+        changed.description = "Needs repair".into();
+        changed.signature = Some(hex::encode(key.sign(&listing_payload(&changed)).to_bytes()));
+        assert_eq!(changed.trust(Some(&public(&key)), Some(&proof)), Trust::Invalid);
+    }
+}
@@ -0,0 +1,149 @@
+//! Tabular views of responses, for clients that print CSV.
+//!
+//! Column names match the JSON field names and are part of the protocol:
+//! columns may be added at the end, but existing ones aren't renamed,
+//! reordered or dropped without bumping [`crate::PROTOCOL_VERSION`].
+
+use crate::item::Item;
+use crate::model::Model;
+use crate::response::{Conversion, Response, ServerStatus};
+
+/// Columns of a listing row. `converted_price` is only filled in when the
+/// client asked for a currency.
+pub const ITEM_COLUMNS: &[&str] = &[
+    "id",
+    "alias",
+    "name",
+    "category",
+    "price",
+    "converted_price",
+    "seller",
+    "seller_verified",
+    "seller_away",
+    "created_at",
+    "revision",
+    "model",
+    "shipping",
+    "bundle",
+    "description",
+];
+
+pub const CATEGORY_COLUMNS: &[&str] = &["category"];
+
+pub const MODEL_COLUMNS: &[&str] = &["id", "brand", "model", "years", "listings"];
+
+/// `STATUS` is shown as one row per field.
+pub const STATUS_COLUMNS: &[&str] = &["field", "value"];
+
+/// A response as rows of text cells under named columns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub columns: &'static [&'static str],
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// The table as CSV with a header line, quoted as in RFC 4180.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let header = self.columns.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        for row in std::iter::once(&header).chain(&self.rows) {
+            let cells: Vec<String> = row.iter().map(|cell| csv_cell(cell)).collect();
+            csv.push_str(&cells.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+}
+
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+fn item_row(item: &Item, conversion: Option<&Conversion>) -> Vec<String> {
+    vec![
+        item.id.clone(),
+        item.alias.to_string(),
+        item.name.clone(),
+        item.category.clone(),
+        item.price.clone(),
+        conversion.and_then(|c| c.prices.get(&item.id)).map(|p| p.to_string()).unwrap_or_default(),
+        item.seller.clone(),
+        item.seller_verified.to_string(),
+        item.seller_away.to_string(),
+        item.created_at.to_string(),
+        item.revision.to_string(),
+        item.model.clone().unwrap_or_default(),
+        item.shipping.to_string(),
+        item.bundle.join(";"),
+        item.description.clone(),
+    ]
+}
+
+fn model_row(model: &Model) -> Vec<String> {
+    vec![
+        model.id.clone(),
+        model.brand.clone(),
+        model.model.clone(),
+        model.years.clone().unwrap_or_default(),
+        model.listings.to_string(),
+    ]
+}
+
+fn status_rows(status: &ServerStatus) -> Vec<Vec<String>> {
+    let fields = [
+        ("protocol_version", status.protocol_version.to_string()),
+        ("address", status.address.clone()),
+        ("other_addresses", status.other_addresses.join(";")),
+        ("uptime_secs", status.uptime_secs.to_string()),
+        ("items", status.items.to_string()),
+        ("categories", status.categories.to_string()),
+        ("sessions", status.sessions.to_string()),
+        ("catalog_root", status.catalog_root.clone()),
+        ("tlog_size", status.tlog_size.to_string()),
+        ("tlog_head", status.tlog_head.clone()),
+        ("capabilities", status.capabilities.join(";")),
+        ("pass_key", status.pass_key.clone()),
+    ];
+    fields.into_iter().map(|(field, value)| vec![field.to_string(), value]).collect()
+}
+
+impl Response {
+    /// The response as a table, for responses that are lists of listings,
+    /// categories or models, or `STATUS`. Others have no tabular form.
+    pub fn table(&self) -> Option<Table> {
+        self.table_converted(None)
+    }
+
+    fn table_converted(&self, conversion: Option<&Conversion>) -> Option<Table> {
+        let items = |items: &[Item]| Table {
+            columns: ITEM_COLUMNS,
+            rows: items.iter().map(|item| item_row(item, conversion)).collect(),
+        };
+        Some(match self {
+            Response::Items(list)
+            | Response::SearchResults(list)
+            | Response::PriceFiltered { items: list, .. }
+            | Response::Export(list) => items(list),
+            Response::Item(item) => items(std::slice::from_ref(&**item)),
+            Response::Converted(inner, conversion) => return inner.table_converted(Some(conversion)),
+            Response::Categories(categories) => Table {
+                columns: CATEGORY_COLUMNS,
+                rows: categories.iter().map(|category| vec![category.clone()]).collect(),
+            },
+            Response::Models(models) => Table {
+                columns: MODEL_COLUMNS,
+                rows: models.iter().map(model_row).collect(),
+            },
+            Response::Status(status) => Table {
+                columns: STATUS_COLUMNS,
+                rows: status_rows(status),
+            },
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,132 @@
+//! Public transparency log of listing lifecycle events.
+//!
+//! Every time a listing goes live, changes, is taken down or comes back, the
+//! server appends an entry chained to the one before it. `TLOG RANGE` serves
+//! the log to anyone, so third parties can audit takedowns and notice
+//! listings that quietly vanish without a removal entry. Entries name the
+//! listing and whether the seller or an operator acted, never who.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Chain value preceding the first entry.
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Most entries one `TLOG RANGE` returns. Longer ranges are cut short and
+/// picked up with another request from the last `seq` + 1.
+pub const MAX_RANGE: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlogEvent {
+    /// The listing went live, either when sold or at its publication time.
+    Added,
+    Updated,
+    /// Moved to the trash, from where it can still be restored.
+    Removed,
+    Restored,
+    /// Dropped from the trash for good.
+    Purged,
+}
+
+/// Who caused an event, without identifying them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TlogActor {
+    Seller,
+    Operator,
+    /// The server itself, e.g. publishing on schedule or purging the trash.
+    Server,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlogEntry {
+    pub seq: u64,
+    pub at: u64,
+    pub event: TlogEvent,
+    pub id: String,
+    pub actor: TlogActor,
+    /// The listing's Merkle leaf hash after the event (see [`crate::merkle`]),
+    /// hex encoded. Empty for removals and purges.
+    pub leaf: String,
+    /// Hash of the previous entry, or [`GENESIS`].
+    pub prev: String,
+    /// Hash over every other field.
+    pub hash: String,
+}
+
+impl TlogEvent {
+    pub fn name(self) -> &'static str {
+        match self {
+            TlogEvent::Added => "added",
+            TlogEvent::Updated => "updated",
+            TlogEvent::Removed => "removed",
+            TlogEvent::Restored => "restored",
+            TlogEvent::Purged => "purged",
+        }
+    }
+}
+
+impl TlogActor {
+    pub fn name(self) -> &'static str {
+        match self {
+            TlogActor::Seller => "seller",
+            TlogActor::Operator => "operator",
+            TlogActor::Server => "server",
+        }
+    }
+}
+
+impl TlogEntry {
+    /// The chain hash of this entry, computed from every field but `hash`.
+    pub fn digest(&self) -> String {
+        let seq = self.seq.to_string();
+        let at = self.at.to_string();
+        let mut hasher = Sha256::new();
+        hasher.update(b"nym-bazaar tlog v1\n");
+        // Length-prefix each field so values can't bleed into their neighbours
+        for field in [
+            seq.as_str(),
+            at.as_str(),
+            self.event.name(),
+            self.id.as_str(),
+            self.actor.name(),
+            self.leaf.as_str(),
+            self.prev.as_str(),
+        ] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Checks that `entries` are consecutive, each chained to the one before and
+/// the first to `prev` (the hash of the entry before the range, or [`GENESIS`]).
+/// Returns the hash of the last entry, to check the next range against, or
+/// `None` if the chain is broken.
+pub fn verify_chain(entries: &[TlogEntry], prev: &str) -> Option<String> {
+    let mut prev = prev.to_string();
+    let mut seq = None;
+    for entry in entries {
+        let consecutive = seq.is_none_or(|seq: u64| entry.seq == seq + 1);
+        if !consecutive || entry.prev != prev || entry.digest() != entry.hash {
+            return None;
+        }
+        seq = Some(entry.seq);
+        prev = entry.hash.clone();
+    }
+    Some(prev)
+}
+
+impl fmt::Display for TlogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hash = self.hash.get(..16).unwrap_or(&self.hash);
+        write!(f, "{} {} {} {} {} {}", self.seq, self.at, hash, self.event.name(), self.id, self.actor.name())?;
+        if !self.leaf.is_empty() {
+            write!(f, " {}", self.leaf.get(..16).unwrap_or(&self.leaf))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Resumable uploads, for data too big to send reliably in one request line.
+//!
+//! `UPLOAD BEGIN <size> <sha256>` declares the data up front and returns an
+//! upload id. The data is then sent in numbered chunks of [`CHUNK_SIZE`] bytes
+//! with `UPLOAD CHUNK`, in any order, and `UPLOAD STATUS` tells which chunks the
+//! server is still missing, so a client that lost its connection picks up where
+//! it left off. Once every chunk is in, the server checks the whole against the
+//! declared hash. A complete upload is used in place of inline data by
+//! `PUTIMAGE <id> --upload <upload-id>` or `BATCH --upload <upload-id>`.
+
+use serde::{Deserialize, Serialize};
+
+/// Bytes per `UPLOAD CHUNK`, before base64 encoding. Every chunk but the last is exactly this long.
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// How many chunks an upload of `size` bytes is sent in.
+pub fn chunk_count(size: u64) -> usize {
+    (size.div_ceil(CHUNK_SIZE as u64) as usize).max(1)
+}
+
+/// Length of chunk `chunk` of an upload of `size` bytes, or `None` past the last chunk.
+pub fn chunk_len(size: u64, chunk: usize) -> Option<usize> {
+    let start = (chunk as u64).checked_mul(CHUNK_SIZE as u64)?;
+    (chunk < chunk_count(size)).then(|| (size - start.min(size)).min(CHUNK_SIZE as u64) as usize)
+}
+
+/// Where an upload stands, as returned by every `UPLOAD` command but `ABORT`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub id: String,
+    pub size: u64,
+    /// Hex SHA-256 of the whole upload.
+    pub hash: String,
+    pub chunks: usize,
+    /// Chunks not received yet, in order. Empty once the upload is complete.
+    pub missing: Vec<usize>,
+}
+
+impl UploadStatus {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
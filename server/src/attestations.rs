@@ -0,0 +1,221 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::{signing, Attestation, AuthenticatorInfo, BazaarError};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Authenticator {
+    /// The name as added, keeping its case.
+    name: String,
+    public_key: String,
+    added_at: u64,
+    #[serde(default)]
+    removed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredAttestation {
+    /// ULID of the listing attested.
+    item: String,
+    authenticator: String,
+    statement: String,
+    attested_at: u64,
+    signature: String,
+    #[serde(default)]
+    revoked_at: Option<u64>,
+}
+
+impl StoredAttestation {
+    fn public(&self, id: &str) -> Attestation {
+        Attestation {
+            id: id.to_string(),
+            authenticator: self.authenticator.clone(),
+            statement: self.statement.clone(),
+            attested_at: self.attested_at,
+            signature: self.signature.clone(),
+            revoked_at: self.revoked_at,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Attestations {
+    /// Keyed by lowercased name.
+    authenticators: BTreeMap<String, Authenticator>,
+    attestations: BTreeMap<String, StoredAttestation>,
+}
+
+/// Authenticators the operator trusts and the attestations they signed,
+/// persisted in `<data_dir>/attestations.json`. Revoked attestations and
+/// removed authenticators are kept, so buyers can see that an attestation
+/// was withdrawn and a revoked signature can't be submitted again.
+pub struct AttestationStore {
+    path: PathBuf,
+    state: Mutex<Attestations>,
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Failed to update attestations: {:#}", e);
+    BazaarError::Internal
+}
+
+impl AttestationStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let state = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read attestations {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid attestations {}", path.display()))?
+        } else {
+            Attestations::default()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+        })
+    }
+
+    fn save(&self, state: &Attestations) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace attestations {}", self.path.display()))
+    }
+
+    /// Trusts `name`'s attestations signed with `public_key`. Adding a removed
+    /// authenticator again trusts it again, with the new key; its revoked
+    /// attestations stay revoked.
+    pub fn add_authenticator(&self, name: &str, public_key: &str) -> Result<AuthenticatorInfo, BazaarError> {
+        if !signing::is_public_key(public_key) {
+            return Err(BazaarError::InvalidCommand);
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let authenticator = Authenticator {
+            name: name.to_string(),
+            public_key: public_key.to_string(),
+            added_at: unix_now(),
+            removed: false,
+        };
+        state.authenticators.insert(name.to_lowercase(), authenticator.clone());
+        self.save(&state).map_err(failed)?;
+        Ok(info(&authenticator))
+    }
+
+    /// Stops trusting `name` and revokes every attestation it made, returning
+    /// how many were revoked.
+    pub fn remove_authenticator(&self, name: &str) -> Result<usize, BazaarError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let authenticator = state
+            .authenticators
+            .get_mut(&name.to_lowercase())
+            .filter(|authenticator| !authenticator.removed)
+            .ok_or(BazaarError::AuthenticatorNotFound)?;
+        authenticator.removed = true;
+        let name = authenticator.name.clone();
+
+        let now = unix_now();
+        let mut revoked = 0;
+        for attestation in state.attestations.values_mut() {
+            if attestation.authenticator == name && attestation.revoked_at.is_none() {
+                attestation.revoked_at = Some(now);
+                revoked += 1;
+            }
+        }
+        self.save(&state).map_err(failed)?;
+        Ok(revoked)
+    }
+
+    pub fn authenticators(&self) -> Vec<AuthenticatorInfo> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.authenticators.values().map(info).collect()
+    }
+
+    /// Records `authenticator`'s `statement` about listing `item` (a ULID),
+    /// provided the signature checks out, returning the attestation's id.
+    /// Submitting the same attestation again returns the id it already has.
+    pub fn attest(&self, item: &str, authenticator: &str, statement: &str, signature: &str) -> Result<String, BazaarError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let trusted = state
+            .authenticators
+            .get(&authenticator.to_lowercase())
+            .filter(|trusted| !trusted.removed && trusted.name == authenticator)
+            .ok_or(BazaarError::AuthenticatorNotFound)?;
+        signing::verify(&trusted.public_key, &signing::attestation_payload(item, authenticator, statement), signature)?;
+
+        let signature = signature.to_lowercase();
+        if let Some((id, existing)) = state.attestations.iter().find(|(_, existing)| existing.signature == signature) {
+            return match existing.revoked_at {
+                Some(_) => Err(BazaarError::AttestationRevoked),
+                None => Ok(id.clone()),
+            };
+        }
+
+        let mut id = [0u8; 8];
+        rand::rngs::OsRng.fill_bytes(&mut id);
+        let id = hex::encode(id);
+        state.attestations.insert(
+            id.clone(),
+            StoredAttestation {
+                item: item.to_string(),
+                authenticator: authenticator.to_string(),
+                statement: statement.to_string(),
+                attested_at: unix_now(),
+                signature,
+                revoked_at: None,
+            },
+        );
+        self.save(&state).map_err(failed)?;
+        Ok(id)
+    }
+
+    /// Revokes attestation `id`. With a signature it has to be its
+    /// authenticator's over [`signing::revocation_payload`]; without one the
+    /// caller must already be trusted to revoke anything. Returns the
+    /// authenticator's name.
+    pub fn revoke(&self, id: &str, signature: Option<&str>) -> Result<String, BazaarError> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let Attestations { authenticators, attestations } = &mut *state;
+        let attestation = attestations.get_mut(id).ok_or(BazaarError::AttestationNotFound)?;
+        if let Some(signature) = signature {
+            let public_key = &authenticators
+                .get(&attestation.authenticator.to_lowercase())
+                .ok_or(BazaarError::AuthenticatorNotFound)?
+                .public_key;
+            signing::verify(public_key, &signing::revocation_payload(id), signature)?;
+        }
+        if attestation.revoked_at.is_some() {
+            return Err(BazaarError::AttestationRevoked);
+        }
+        attestation.revoked_at = Some(unix_now());
+        let authenticator = attestation.authenticator.clone();
+        self.save(&state).map_err(failed)?;
+        Ok(authenticator)
+    }
+
+    /// Attestations about listing `item` (a ULID), oldest first, revoked ones included.
+    pub fn for_item(&self, item: &str) -> Vec<Attestation> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut found: Vec<Attestation> = state
+            .attestations
+            .iter()
+            .filter(|(_, stored)| stored.item == item)
+            .map(|(id, stored)| stored.public(id))
+            .collect();
+        found.sort_by_key(|attestation| attestation.attested_at);
+        found
+    }
+}
+
+fn info(authenticator: &Authenticator) -> AuthenticatorInfo {
+    AuthenticatorInfo {
+        name: authenticator.name.clone(),
+        public_key: authenticator.public_key.clone(),
+        added_at: authenticator.added_at,
+        removed: authenticator.removed,
+    }
+}
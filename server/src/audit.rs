@@ -0,0 +1,159 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::AuditRecord;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Entries kept in memory for `AUDIT TAIL`.
+pub const TAIL_LEN: usize = 100;
+
+/// Chain value preceding the first entry.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log. `hash` covers every other field, including the
+/// previous entry's hash, so editing or dropping a line breaks the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    seq: u64,
+    timestamp: u64,
+    actor: String,
+    action: String,
+    detail: String,
+    prev: String,
+    hash: String,
+}
+
+impl Entry {
+    fn digest(&self) -> String {
+        let seq = self.seq.to_string();
+        let timestamp = self.timestamp.to_string();
+        let mut hasher = Sha256::new();
+        // Length-prefix each field so values can't bleed into their neighbours
+        for field in [&seq, &timestamp, &self.actor, &self.action, &self.detail, &self.prev] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+struct State {
+    file: File,
+    seq: u64,
+    last_hash: String,
+    tail: VecDeque<Entry>,
+}
+
+/// Append-only, hash-chained record of mutating and operator commands, stored
+/// as JSON lines in `<data_dir>/audit.log`.
+pub struct AuditLog {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl AuditLog {
+    /// Opens the log, checking the chain of any existing entries. A broken chain
+    /// means the file was edited and is refused rather than extended.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut seq = 0;
+        let mut last_hash = GENESIS.to_string();
+        let mut tail = VecDeque::with_capacity(TAIL_LEN);
+
+        if path.exists() {
+            let file = File::open(path).with_context(|| format!("Failed to read audit log {}", path.display()))?;
+            for (n, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                let entry: Entry = serde_json::from_str(&line)
+                    .with_context(|| format!("Audit log {} line {} is malformed", path.display(), n + 1))?;
+                if entry.seq != seq + 1 || entry.prev != last_hash || entry.digest() != entry.hash {
+                    bail!("Audit log {} fails verification at line {}", path.display(), n + 1);
+                }
+                seq = entry.seq;
+                last_hash = entry.hash.clone();
+                if tail.len() == TAIL_LEN {
+                    tail.pop_front();
+                }
+                tail.push_back(entry);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(State {
+                file,
+                seq,
+                last_hash,
+                tail,
+            }),
+        })
+    }
+
+    /// Appends an entry. Failures are logged rather than returned, since the
+    /// action being recorded has already happened.
+    pub fn record(&self, actor: &str, action: &str, detail: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entry = Entry {
+            seq: state.seq + 1,
+            timestamp: crate::catalog::unix_now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            detail: detail.to_string(),
+            prev: state.last_hash.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.digest();
+
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to encode audit entry: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(state.file, "{}", line).and_then(|_| state.file.flush()) {
+            tracing::error!(path = %self.path.display(), "Failed to write audit log: {}", e);
+            return;
+        }
+
+        state.seq = entry.seq;
+        state.last_hash = entry.hash.clone();
+        if state.tail.len() == TAIL_LEN {
+            state.tail.pop_front();
+        }
+        state.tail.push_back(entry);
+    }
+
+    /// The most recent `count` entries, oldest first.
+    pub fn tail(&self, count: usize) -> Vec<AuditRecord> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let skip = state.tail.len().saturating_sub(count);
+        state
+            .tail
+            .iter()
+            .skip(skip)
+            .map(|entry| AuditRecord {
+                seq: entry.seq,
+                timestamp: entry.timestamp,
+                actor: entry.actor.clone(),
+                action: entry.action.clone(),
+                detail: entry.detail.clone(),
+                hash: entry.hash.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Short, non-reversible identifier for an admin key, used as the actor in audit entries.
+pub fn key_fingerprint(key: &str) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..4])
+}
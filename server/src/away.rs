@@ -0,0 +1,93 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::BazaarError;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Away {
+    since: u64,
+    until: Option<u64>,
+}
+
+impl Away {
+    fn is_over(&self, now: u64) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+}
+
+/// Sellers who set `AWAY`, by lowercased name, persisted in `<data_dir>/away.json`.
+/// A seller who gave an end time is back once it passes, without doing anything.
+pub struct AwayStore {
+    path: PathBuf,
+    sellers: Mutex<BTreeMap<String, Away>>,
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Failed to update away sellers: {:#}", e);
+    BazaarError::Internal
+}
+
+impl AwayStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let sellers = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read away sellers {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid away sellers {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sellers: Mutex::new(sellers),
+        })
+    }
+
+    fn save(&self, sellers: &BTreeMap<String, Away>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(sellers)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace away sellers {}", self.path.display()))
+    }
+
+    /// Marks `seller` away, until `until` if given.
+    pub fn set(&self, seller: &str, until: Option<u64>) -> Result<(), BazaarError> {
+        let mut sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        let now = unix_now();
+        sellers.retain(|_, away| !away.is_over(now));
+        sellers.insert(seller.to_lowercase(), Away { since: now, until });
+        self.save(&sellers).map_err(failed)
+    }
+
+    pub fn clear(&self, seller: &str) -> Result<(), BazaarError> {
+        let mut sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        if sellers.remove(&seller.to_lowercase()).is_some() {
+            self.save(&sellers).map_err(failed)?;
+        }
+        Ok(())
+    }
+
+    /// `Some(until)` if `seller` is away.
+    pub fn get(&self, seller: &str) -> Option<Option<u64>> {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers
+            .get(&seller.to_lowercase())
+            .filter(|away| !away.is_over(unix_now()))
+            .map(|away| away.until)
+    }
+
+    /// Every seller away right now, by lowercased name, with their end time.
+    pub fn current(&self) -> HashMap<String, Option<u64>> {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        let now = unix_now();
+        sellers
+            .iter()
+            .filter(|(_, away)| !away.is_over(now))
+            .map(|(seller, away)| (seller.clone(), away.until))
+            .collect()
+    }
+}
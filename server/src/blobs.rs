@@ -0,0 +1,155 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::media::{self, ImageSize};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Blob {
+    size: u64,
+    /// Listings the blob is attached to.
+    refs: u32,
+    stored_at: u64,
+}
+
+/// Listing images stored once per content hash under `<data_dir>/blobs`, so a
+/// photo used by several listings is kept once. Blobs are reference counted and
+/// removed by [`BlobStore::collect`] once no listing uses them.
+pub struct BlobStore {
+    dir: PathBuf,
+    blobs: Mutex<BTreeMap<String, Blob>>,
+}
+
+impl BlobStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create blob directory {}", dir.display()))?;
+        let index = dir.join("index.json");
+        let blobs = if index.exists() {
+            let contents = std::fs::read_to_string(&index)
+                .with_context(|| format!("Failed to read blob index {}", index.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid blob index {}", index.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            blobs: Mutex::new(blobs),
+        })
+    }
+
+    fn save(&self, blobs: &BTreeMap<String, Blob>) -> Result<()> {
+        let index = self.dir.join("index.json");
+        let tmp = index.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(blobs)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &index).with_context(|| format!("Failed to replace blob index {}", index.display()))
+    }
+
+    /// Blobs are spread over subdirectories by the first byte of their hash.
+    fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(&hash[..2]).join(hash)
+    }
+
+    /// Stores `data` with one reference to it, returning its hash. Data that is
+    /// already stored only gains the reference.
+    pub fn put(&self, data: &[u8]) -> Result<String> {
+        let hash = media::hash(data);
+        let mut blobs = self.blobs.lock().unwrap_or_else(|e| e.into_inner());
+        match blobs.get_mut(&hash) {
+            Some(blob) => blob.refs += 1,
+            None => {
+                let path = self.path(&hash);
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, data).with_context(|| format!("Failed to write {}", tmp.display()))?;
+                std::fs::rename(&tmp, &path).with_context(|| format!("Failed to store blob {}", path.display()))?;
+                blobs.insert(
+                    hash.clone(),
+                    Blob {
+                        size: data.len() as u64,
+                        refs: 1,
+                        stored_at: unix_now(),
+                    },
+                );
+            },
+        }
+        self.save(&blobs)?;
+        Ok(hash)
+    }
+
+    /// Drops one reference to a blob. The blob itself stays until the next collection.
+    pub fn release(&self, hash: &str) -> Result<()> {
+        let mut blobs = self.blobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(blob) = blobs.get_mut(hash) {
+            blob.refs = blob.refs.saturating_sub(1);
+            self.save(&blobs)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps a scaled-down version of a stored blob next to it. Scaled versions
+    /// aren't counted separately; they go when the blob goes.
+    pub fn put_scaled(&self, hash: &str, size: ImageSize, data: &[u8]) -> Result<()> {
+        let path = self.scaled_path(hash, size);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("Failed to store {}", path.display()))
+    }
+
+    /// Reads a scaled version of a blob, or `None` if there isn't one.
+    pub fn read_scaled(&self, hash: &str, size: ImageSize) -> Result<Option<Vec<u8>>> {
+        let path = self.scaled_path(hash, size);
+        match std::fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn scaled_path(&self, hash: &str, size: ImageSize) -> PathBuf {
+        self.path(hash).with_extension(size.name())
+    }
+
+    /// Reads a whole blob, or `None` if it isn't stored.
+    pub fn read(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        if !self.blobs.lock().unwrap_or_else(|e| e.into_inner()).contains_key(hash) {
+            return Ok(None);
+        }
+        let path = self.path(hash);
+        let data = std::fs::read(&path).with_context(|| format!("Failed to read blob {}", path.display()))?;
+        Ok(Some(data))
+    }
+
+    /// Resets reference counts to `refs`, counted from the catalog, then deletes
+    /// blobs nothing refers to that were stored more than `grace_secs` ago.
+    /// Recounting corrects for listings purged from the trash or replaced by a
+    /// catalog reload. Returns how many blobs were deleted.
+    pub fn collect(&self, refs: &HashMap<String, u32>, grace_secs: u64) -> Result<usize> {
+        let mut blobs = self.blobs.lock().unwrap_or_else(|e| e.into_inner());
+        let cutoff = unix_now().saturating_sub(grace_secs);
+        let mut unused = Vec::new();
+        for (hash, blob) in blobs.iter_mut() {
+            blob.refs = refs.get(hash).copied().unwrap_or_default();
+            if blob.refs == 0 && blob.stored_at < cutoff {
+                unused.push(hash.clone());
+            }
+        }
+        for hash in &unused {
+            let path = self.path(hash);
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::warn!("Failed to delete blob {}: {}", path.display(), e);
+            }
+            for &size in ImageSize::SCALED {
+                let _ = std::fs::remove_file(self.scaled_path(hash, size));
+            }
+            blobs.remove(hash);
+        }
+        self.save(&blobs)?;
+        Ok(unused.len())
+    }
+}
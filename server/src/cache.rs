@@ -0,0 +1,52 @@
+use crate::metrics::Metrics;
+use bazaar_protocol::{BazaarError, Response};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+/// Recently built LIST/GET responses, before currency conversion.
+///
+/// Entries must only be inserted while holding the catalog read lock, and the
+/// cache is cleared whenever the write lock is taken, so a cached response never
+/// outlives the catalog state it was built from.
+pub struct ResponseCache {
+    entries: Option<Mutex<LruCache<String, Response>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl ResponseCache {
+    /// A cache holding up to `capacity` responses; 0 disables caching.
+    pub fn new(capacity: usize, metrics: Arc<Metrics>) -> Self {
+        Self {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+            metrics,
+        }
+    }
+
+    /// Returns the cached response for `key`, or builds and caches it. Errors aren't cached.
+    pub fn get_or_insert(
+        &self,
+        key: String,
+        build: impl FnOnce() -> Result<Response, BazaarError>,
+    ) -> Result<Response, BazaarError> {
+        let Some(entries) = &self.entries else {
+            return build();
+        };
+
+        if let Some(response) = entries.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            self.metrics.cache_hits.inc();
+            return Ok(response.clone());
+        }
+        self.metrics.cache_misses.inc();
+
+        let response = build()?;
+        entries.lock().unwrap_or_else(|e| e.into_inner()).put(key, response.clone());
+        Ok(response)
+    }
+
+    pub fn clear(&self) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use crate::storage::Record;
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::merkle::{self, InclusionProof};
+use bazaar_protocol::{BazaarError, Change, HistoryEvent, Item, ItemField};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use ulid::{Generator, Ulid};
+
+/// Listings keyed by ULID, so iteration runs oldest first. Each listing also gets
+/// a short numeric alias for typing into interactive clients.
+///
+/// Every change is recorded as an event in the listing's history, which is kept
+/// until the listing is purged.
+#[derive(Default)]
+pub struct Catalog {
+    items: BTreeMap<String, Item>,
+    /// Deleted listings with the Unix time they were deleted, kept until purged.
+    trash: HashMap<String, (Item, u64)>,
+    history: HashMap<String, Vec<HistoryEvent>>,
+    aliases: HashMap<u64, String>,
+    next_alias: u64,
+    /// Featured listings with the Unix time they were featured.
+    featured: HashMap<String, u64>,
+    /// Listings waiting to go live, with the Unix time they are published at.
+    scheduled: HashMap<String, (Item, u64)>,
+}
+
+impl Catalog {
+    /// Builds a catalog, handing out aliases in creation order.
+    pub fn from_items(items: impl IntoIterator<Item = Item>) -> Self {
+        let mut sorted: Vec<Item> = items.into_iter().collect();
+        sorted.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut catalog = Catalog::default();
+        for item in sorted {
+            catalog.insert(item);
+        }
+        catalog
+    }
+
+    /// Rebuilds a catalog from stored records, keeping their aliases and history.
+    pub fn from_records(records: Vec<Record>) -> Self {
+        let mut catalog = Catalog::default();
+        for record in records {
+            catalog.add_record(record);
+        }
+        catalog
+    }
+
+    /// Replaces one listing (by ULID) with its stored record, or drops it when
+    /// storage has none, e.g. after another server changed it.
+    pub fn set_record(&mut self, id: &str, record: Option<Record>) {
+        self.items.remove(id);
+        self.trash.remove(id);
+        self.scheduled.remove(id);
+        self.featured.remove(id);
+        self.history.remove(id);
+        self.aliases.retain(|_, aliased| aliased != id);
+        if let Some(record) = record {
+            self.add_record(record);
+        }
+    }
+
+    fn add_record(&mut self, record: Record) {
+        let id = record.item.id.clone();
+        self.next_alias = self.next_alias.max(record.item.alias);
+        self.aliases.insert(record.item.alias, id.clone());
+        self.history.insert(id.clone(), record.history);
+        if let Some(featured_at) = record.featured_at {
+            self.featured.insert(id.clone(), featured_at);
+        }
+        if let Some(deleted_at) = record.deleted_at {
+            self.trash.insert(id, (record.item, deleted_at));
+        } else if let Some(publish_at) = record.publish_at {
+            self.scheduled.insert(id, (record.item, publish_at));
+        } else {
+            self.items.insert(id, record.item);
+        }
+    }
+
+    /// The storage record for a listing by ULID, live, scheduled or trashed.
+    pub fn record_of(&self, id: &str) -> Option<Record> {
+        let (item, deleted_at, publish_at) = match (self.items.get(id), self.scheduled.get(id), self.trash.get(id)) {
+            (Some(item), _, _) => (item, None, None),
+            (None, Some((item, at)), _) => (item, None, Some(*at)),
+            (None, None, Some((item, at))) => (item, Some(*at), None),
+            (None, None, None) => return None,
+        };
+        Some(Record {
+            item: item.clone(),
+            deleted_at,
+            history: self.history.get(id).cloned().unwrap_or_default(),
+            featured_at: self.featured.get(id).copied(),
+            publish_at,
+        })
+    }
+
+    /// Storage records for every listing, live, scheduled or trashed.
+    pub fn records(&self) -> Vec<Record> {
+        self.items
+            .keys()
+            .chain(self.scheduled.keys())
+            .chain(self.trash.keys())
+            .filter_map(|id| self.record_of(id))
+            .collect()
+    }
+
+    /// Builds a catalog from another bazaar's export, keeping its aliases so
+    /// listings are numbered the same on the mirror.
+    pub fn mirrored(items: Vec<Item>) -> Self {
+        let mut catalog = Catalog::default();
+        for item in items {
+            catalog.next_alias = catalog.next_alias.max(item.alias);
+            catalog.aliases.insert(item.alias, item.id.clone());
+            catalog.record(&item.id, item.created_at, item.revision, Change::Listed { price: item.price.clone() });
+            catalog.items.insert(item.id.clone(), item);
+        }
+        catalog
+    }
+
+    /// Adds a listing and assigns it the next alias, unless storage already
+    /// handed it one.
+    pub fn insert(&mut self, mut item: Item) -> &Item {
+        self.assign_alias(&mut item);
+        self.aliases.insert(item.alias, item.id.clone());
+        self.record(&item.id, item.created_at, item.revision, Change::Listed { price: item.price.clone() });
+        self.items.entry(item.id.clone()).or_insert(item)
+    }
+
+    /// Adds a listing that stays hidden until `publish_at`, assigning its alias now
+    /// so the seller can refer to it.
+    pub fn schedule(&mut self, mut item: Item, publish_at: u64) -> &Item {
+        self.assign_alias(&mut item);
+        self.aliases.insert(item.alias, item.id.clone());
+        &self.scheduled.entry(item.id.clone()).or_insert((item, publish_at)).0
+    }
+
+    fn assign_alias(&mut self, item: &mut Item) {
+        if item.alias == 0 {
+            item.alias = self.next_alias + 1;
+        }
+        self.next_alias = self.next_alias.max(item.alias);
+    }
+
+    /// When the next scheduled listing is due, if any are waiting.
+    pub fn next_publication(&self) -> Option<u64> {
+        self.scheduled.values().map(|(_, at)| *at).min()
+    }
+
+    /// Puts scheduled listings due by `now` live, returning their ULIDs. A published
+    /// listing counts as created when it went live, so it shows up under `NEW`.
+    pub fn publish_due(&mut self, now: u64) -> Vec<String> {
+        let due: Vec<String> = self
+            .scheduled
+            .iter()
+            .filter(|(_, (_, at))| *at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &due {
+            if let Some((mut item, at)) = self.scheduled.remove(id) {
+                item.created_at = at;
+                self.record(id, at, item.revision, Change::Listed { price: item.price.clone() });
+                self.items.insert(id.clone(), item);
+            }
+        }
+        due
+    }
+
+    /// Sets one field of a live or scheduled listing, provided `revision` is
+    /// still current.
+    pub fn update(&mut self, id: &str, revision: u64, field: ItemField, value: String) -> Result<&Item, BazaarError> {
+        let id = self.resolve(id).ok_or(BazaarError::ItemNotFound)?;
+        let item = match self.items.get_mut(&id) {
+            Some(item) => item,
+            None => self.scheduled.get_mut(&id).map(|(item, _)| item).ok_or(BazaarError::ItemNotFound)?,
+        };
+        if item.revision != revision {
+            return Err(BazaarError::RevisionConflict);
+        }
+
+        let old = match field {
+            ItemField::Name => std::mem::replace(&mut item.name, value.clone()),
+            ItemField::Category => std::mem::replace(&mut item.category, value.clone()),
+            ItemField::Price => std::mem::replace(&mut item.price, value.clone()),
+            ItemField::Description => std::mem::replace(&mut item.description, value.clone()),
+            ItemField::Shipping => std::mem::replace(&mut item.shipping, value.parse()?).to_string(),
+            ItemField::Model => {
+                let model = Some(value.clone()).filter(|model| !model.is_empty());
+                std::mem::replace(&mut item.model, model).unwrap_or_default()
+            },
+        };
+        item.revision += 1;
+        // The signature was over the old revision
+        item.signature = None;
+
+        let revision = item.revision;
+        self.record(&id, unix_now(), revision, Change::Updated { field, old, new: value });
+        Ok(self.items.get(&id).unwrap_or_else(|| &self.scheduled[&id].0))
+    }
+
+    /// A listing's ULID and its changes, oldest first. Deleted listings keep their
+    /// history until purged.
+    pub fn history(&self, id: &str) -> Option<(&str, &[HistoryEvent])> {
+        let (id, events) = self.history.get_key_value(&self.resolve(id)?)?;
+        Some((id, events))
+    }
+
+    fn record(&mut self, id: &str, timestamp: u64, revision: u64, change: Change) {
+        self.history
+            .entry(id.to_string())
+            .or_default()
+            .push(HistoryEvent { timestamp, revision, change });
+    }
+
+    /// Maps a ULID (case-insensitive) or alias to the ULID the listing is stored under.
+    fn resolve(&self, id: &str) -> Option<String> {
+        match id.parse::<u64>() {
+            Ok(alias) => self.aliases.get(&alias).cloned(),
+            Err(_) => Some(id.to_uppercase()),
+        }
+    }
+
+    /// Looks a listing up by ULID or alias.
+    pub fn get(&self, id: &str) -> Option<&Item> {
+        self.items.get(&self.resolve(id)?)
+    }
+
+    /// Seller of a listing, including scheduled listings and listings in the trash.
+    pub fn seller_of(&self, id: &str) -> Option<&str> {
+        let id = self.resolve(id)?;
+        let item = self
+            .items
+            .get(&id)
+            .or_else(|| self.scheduled.get(&id).map(|(item, _)| item))
+            .or_else(|| self.trash.get(&id).map(|(item, _)| item))?;
+        Some(&item.seller)
+    }
+
+    /// Moves a listing to the trash, returning its ULID. Deleting a scheduled
+    /// listing cancels it; restoring it puts it live straight away.
+    pub fn delete(&mut self, id: &str) -> Option<String> {
+        let id = self.resolve(id)?;
+        let item = match self.items.remove(&id) {
+            Some(item) => item,
+            None => self.scheduled.remove(&id)?.0,
+        };
+        let now = unix_now();
+        self.record(&id, now, item.revision, Change::Deleted);
+        self.trash.insert(id.clone(), (item, now));
+        Some(id)
+    }
+
+    /// Whether a live listing is part of a live bundle.
+    pub fn in_bundle(&self, id: &str) -> bool {
+        self.resolve(id).is_some_and(|id| self.items.values().any(|item| item.bundle.contains(&id)))
+    }
+
+    /// Whether a trashed bundle is made of a listing that isn't live.
+    pub fn has_missing_parts(&self, id: &str) -> bool {
+        let Some((bundle, _)) = self.resolve(id).and_then(|id| self.trash.get(&id)) else {
+            return false;
+        };
+        bundle.bundle.iter().any(|part| !self.items.contains_key(part))
+    }
+
+    /// Brings a listing back from the trash, returning its ULID.
+    pub fn restore(&mut self, id: &str) -> Option<String> {
+        let id = self.resolve(id)?;
+        let (item, _) = self.trash.remove(&id)?;
+        self.record(&id, unix_now(), item.revision, Change::Restored);
+        self.items.insert(id.clone(), item);
+        Some(id)
+    }
+
+    /// Permanently drops listings deleted before `cutoff`, returning their ULIDs.
+    pub fn purge(&mut self, cutoff: u64) -> Vec<String> {
+        let mut purged = Vec::new();
+        self.trash.retain(|id, (_, deleted_at)| {
+            let keep = *deleted_at >= cutoff;
+            if !keep {
+                purged.push(id.clone());
+            }
+            keep
+        });
+        let items = &self.items;
+        let trash = &self.trash;
+        let scheduled = &self.scheduled;
+        let kept = |id: &String| items.contains_key(id) || trash.contains_key(id) || scheduled.contains_key(id);
+        self.aliases.retain(|_, id| kept(id));
+        self.history.retain(|id, _| kept(id));
+        self.featured.retain(|id, _| kept(id));
+        purged
+    }
+
+    /// Features a live listing, returning its ULID. Featuring it again moves it to the front.
+    pub fn feature(&mut self, id: &str) -> Option<String> {
+        let id = self.resolve(id).filter(|id| self.items.contains_key(id))?;
+        self.featured.insert(id.clone(), unix_now());
+        Some(id)
+    }
+
+    /// Attaches a seller signature to a live listing, returning its ULID.
+    pub fn sign(&mut self, id: &str, signature: String) -> Option<String> {
+        let id = self.resolve(id)?;
+        self.items.get_mut(&id)?.signature = Some(signature);
+        Some(id)
+    }
+
+    /// Adds an image hash to a live listing, returning the listing. The same image
+    /// isn't attached twice.
+    pub fn attach_media(&mut self, id: &str, hash: &str, max: usize) -> Result<&Item, BazaarError> {
+        let id = self.resolve(id).ok_or(BazaarError::ItemNotFound)?;
+        let item = self.items.get_mut(&id).ok_or(BazaarError::ItemNotFound)?;
+        if !item.media.iter().any(|existing| existing == hash) {
+            if item.media.len() >= max {
+                return Err(BazaarError::LimitReached);
+            }
+            item.media.push(hash.to_string());
+        }
+        Ok(&self.items[&id])
+    }
+
+    /// Removes an image hash from a live listing, returning the listing.
+    pub fn detach_media(&mut self, id: &str, hash: &str) -> Result<&Item, BazaarError> {
+        let id = self.resolve(id).ok_or(BazaarError::ItemNotFound)?;
+        let item = self.items.get_mut(&id).ok_or(BazaarError::ItemNotFound)?;
+        let index = item.media.iter().position(|existing| existing == hash).ok_or(BazaarError::MediaNotFound)?;
+        item.media.remove(index);
+        Ok(&self.items[&id])
+    }
+
+    /// How many listings, live, scheduled or trashed, use each image.
+    pub fn media_refs(&self) -> HashMap<String, u32> {
+        let mut refs = HashMap::new();
+        let items = self
+            .items
+            .values()
+            .chain(self.scheduled.values().map(|(item, _)| item))
+            .chain(self.trash.values().map(|(item, _)| item));
+        for hash in items.flat_map(|item| &item.media) {
+            *refs.entry(hash.clone()).or_default() += 1;
+        }
+        refs
+    }
+
+    /// Stops featuring a listing, returning its ULID if it was featured.
+    pub fn unfeature(&mut self, id: &str) -> Option<String> {
+        let id = self.resolve(id)?;
+        self.featured.remove(&id).map(|_| id)
+    }
+
+    /// Live featured listings, most recently featured first. Featured listings in
+    /// the trash stay featured and come back with a restore.
+    pub fn featured(&self) -> Vec<&Item> {
+        let mut featured: Vec<(&u64, &Item)> = self
+            .featured
+            .iter()
+            .filter_map(|(id, at)| Some((at, self.items.get(id)?)))
+            .collect();
+        featured.sort_by(|(a, _), (b, _)| b.cmp(a));
+        featured.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Merkle root over the live listings, hex encoded.
+    pub fn merkle_root(&self) -> String {
+        let leaves: Vec<_> = self.items.values().map(merkle::leaf_hash).collect();
+        hex::encode(merkle::root(&leaves))
+    }
+
+    /// Inclusion proof for a live listing against [`Catalog::merkle_root`].
+    pub fn prove(&self, id: &str) -> Option<InclusionProof> {
+        let id = self.resolve(id)?;
+        let index = self.items.keys().position(|key| *key == id)?;
+        let leaves: Vec<_> = self.items.values().map(merkle::leaf_hash).collect();
+        merkle::prove(&id, &leaves, index)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Item> {
+        self.items.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Whether any listing, including scheduled and trashed ones, is under
+    /// `seller`, ignoring case as seller names do.
+    pub fn has_seller(&self, seller: &str) -> bool {
+        self.items.values().any(|item| item.seller.eq_ignore_ascii_case(seller))
+            || self.scheduled.values().any(|(item, _)| item.seller.eq_ignore_ascii_case(seller))
+            || self.trash.values().any(|(item, _)| item.seller.eq_ignore_ascii_case(seller))
+    }
+
+    /// Live and scheduled listings by `seller`.
+    pub fn active_listings(&self, seller: &str) -> usize {
+        let live = self.items.values().filter(|item| item.seller == seller).count();
+        live + self.scheduled.values().filter(|(item, _)| item.seller == seller).count()
+    }
+}
+
+/// Hands out ULIDs that stay strictly increasing even within the same millisecond.
+pub struct IdGenerator {
+    generator: Mutex<Generator>,
+}
+
+impl IdGenerator {
+    pub fn new() -> Self {
+        Self {
+            generator: Mutex::new(Generator::new()),
+        }
+    }
+
+    pub fn generate(&self) -> Ulid {
+        let mut generator = self.generator.lock().unwrap_or_else(|e| e.into_inner());
+        // Only fails if more than 2^80 IDs are requested within one millisecond
+        generator.generate().unwrap_or_else(|_| Ulid::new())
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Seconds since the epoch at which a ULID was generated.
+pub fn created_at(id: &Ulid) -> u64 {
+    id.timestamp_ms() / 1000
+}
+
+/// A listing as written in a catalog file. Leave `id` out to have one generated,
+/// but note that generated IDs change every time the file is loaded.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogEntry {
+    id: Option<String>,
+    name: String,
+    category: String,
+    description: String,
+    price: String,
+    seller: String,
+}
+
+/// Layout of a catalog import file:
+///
+/// ```toml
+/// [[items]]
+/// id = "01HZX3N5Q8R2V4W6Y8A0C2E4G6"
+/// name = "Nintendo NES"
+/// category = "gaming"
+/// description = "Original Nintendo Entertainment System from 1985."
+/// price = "$150"
+/// seller = "RetroGamer"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    #[serde(default)]
+    items: Vec<CatalogEntry>,
+}
+
+/// Reads a catalog file.
+pub fn load(path: &Path, ids: &IdGenerator) -> Result<Catalog> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read catalog {}", path.display()))?;
+    let file: CatalogFile =
+        toml::from_str(&contents).with_context(|| format!("Invalid catalog {}", path.display()))?;
+
+    let mut items = HashMap::new();
+    for entry in file.items {
+        let id = match &entry.id {
+            Some(id) => Ulid::from_str(id)
+                .with_context(|| format!("Catalog {} has an invalid ULID '{}'", path.display(), id))?,
+            None => ids.generate(),
+        };
+        let item = Item::new(
+            id.to_string(),
+            created_at(&id),
+            entry.name,
+            entry.category,
+            entry.description,
+            entry.price,
+            entry.seller,
+        );
+        if let Some(previous) = items.insert(item.id.clone(), item) {
+            bail!("Catalog {} has duplicate item id {}", path.display(), previous.id);
+        }
+    }
+    Ok(Catalog::from_items(items.into_values()))
+}
+
+/// Built-in listings served when no catalog file is configured.
+pub fn sample_items() -> Catalog {
+    let item = |id: &str, name: &str, category: &str, description: &str, price: &str, seller: &str| {
+        let id = Ulid::from_str(id).expect("sample item IDs are valid ULIDs");
+        Item::new(
+            id.to_string(),
+            created_at(&id),
+            name.to_string(),
+            category.to_string(),
+            description.to_string(),
+            price.to_string(),
+            seller.to_string(),
+        )
+    };
+
+    Catalog::from_items([
+        item(
+            "01JB2Q5X4N0000000000000001",
+            "Nintendo NES",
+            "gaming",
+            "Original Nintendo Entertainment System from 1985. Good condition with controllers.",
+            "$150",
+            "RetroGamer",
+        ),
+        item(
+            "01JB2Q5X4N0000000000000002",
+            "Yamaha DX7",
+            "synthesizer",
+            "Classic FM synthesizer from 1983. The quintessential 80s synth sound.",
+            "$800",
+            "SynthWave",
+        ),
+    ])
+}
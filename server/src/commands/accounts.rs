@@ -0,0 +1,179 @@
+//! Seller accounts: API tokens, registration and moderation, and away notices.
+
+use super::Builtin;
+use crate::policy::Role;
+use crate::{catalog, BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{AwayCommand, BazaarError, Command, Response, SellerCommand, TokenCommand, TokenScope};
+use tracing::{error, info};
+
+pub struct TokenHandler;
+
+#[async_trait]
+impl Builtin for TokenHandler {
+    const NAME: &'static str = "TOKEN";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Token(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        // Admins manage every token, sellers only their own and never with
+        // scopes or a lifetime beyond the token they authenticated with
+        let own = match &state.seller {
+            _ if state.is_admin() => None,
+            Some(session) => Some(session),
+            None => return Err(BazaarError::Forbidden),
+        };
+        let actor = state.actor.as_deref().unwrap_or_default();
+
+        match command {
+            TokenCommand::Create { seller, scopes, mut expires_secs } => {
+                if let Some(session) = own {
+                    if session.seller != seller || !scopes.iter().all(|scope| session.scopes.contains(scope)) {
+                        return Err(BazaarError::Forbidden);
+                    }
+                    if let Some(expires_at) = session.expires_at {
+                        let left = expires_at.saturating_sub(catalog::unix_now());
+                        expires_secs = Some(expires_secs.map_or(left, |secs| secs.min(left)));
+                    }
+                }
+                if server.sellers.is_impersonation(&seller) {
+                    return Err(BazaarError::SellerNameTaken);
+                }
+                let (info, token) = server.tokens.create(&seller, scopes, expires_secs).map_err(|e| {
+                    error!("Failed to create API token: {:#}", e);
+                    BazaarError::Internal
+                })?;
+                server.audit.record(actor, "TOKEN CREATE", &format!("{} for {}", info.id, info.seller));
+                Ok(Response::TokenCreated { info, token })
+            },
+            TokenCommand::Revoke { id } => {
+                if own.is_some_and(|session| server.tokens.seller_of(&id).as_ref() != Some(&session.seller)) {
+                    return Err(BazaarError::TokenNotFound);
+                }
+                let revoked = server.tokens.revoke(&id).map_err(|e| {
+                    error!("Failed to revoke API token: {:#}", e);
+                    BazaarError::Internal
+                })?;
+                if !revoked {
+                    return Err(BazaarError::TokenNotFound);
+                }
+                server.audit.record(actor, "TOKEN REVOKE", &id);
+                Ok(Response::Ok)
+            },
+            TokenCommand::List => {
+                let mut tokens = server.tokens.list();
+                if let Some(session) = own {
+                    tokens.retain(|token| token.seller == session.seller);
+                }
+                Ok(Response::Tokens(tokens))
+            },
+        }
+    }
+}
+
+pub struct SellerHandler;
+
+#[async_trait]
+impl Builtin for SellerHandler {
+    const NAME: &'static str = "SELLER";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Seller(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        match command {
+            SellerCommand::Register { name, public_key, signature, credential } => {
+                // Only spend the credential on a claim that will go through, and
+                // not at all on registering again
+                if server.sellers.check_claim(&name, &public_key, &signature)? && !state.is_admin() {
+                    // A name already listing in the catalog goes to whoever the
+                    // operator gave a token for it, not the first key to ask
+                    let vouched = state.seller.as_ref().is_some_and(|session| session.seller.eq_ignore_ascii_case(&name));
+                    if !vouched && server.items.read().await.has_seller(&name) {
+                        return Err(BazaarError::SellerNameTaken);
+                    }
+                    server.credentials.check(credential.as_deref()).await?;
+                }
+                let info = server.sellers.register(&name, &public_key, &signature)?;
+                server.audit.record(state.actor.as_deref().unwrap_or("anonymous"), "SELLER REGISTER", &info.name);
+                Ok(Response::Seller(info))
+            },
+            SellerCommand::Info { name } => {
+                let mut info = server.sellers.get(&name).ok_or(BazaarError::SellerNotFound)?;
+                let config = server.config();
+                if config.seller_tiers.enabled {
+                    info.tier = config.seller_tiers.tier_for(Some(&info)).map(|tier| tier.name.clone());
+                }
+                Ok(Response::Seller(info))
+            },
+            SellerCommand::Verify { name } => server.set_seller_verified(state, &name, true).await,
+            SellerCommand::Unverify { name } => server.set_seller_verified(state, &name, false).await,
+            SellerCommand::Ban { name, expires_secs } => {
+                let actor = state.require_role(Role::Moderator)?;
+                let until = expires_secs.map(|secs| catalog::unix_now().saturating_add(secs));
+                let info = server.sellers.ban(&name, until)?;
+                let detail = match until {
+                    Some(until) => format!("{} until {}", info.name, until),
+                    None => info.name.clone(),
+                };
+                server.audit.record(actor, "SELLER BAN", &detail);
+                Ok(Response::Seller(info))
+            },
+            SellerCommand::Unban { name } => {
+                let actor = state.require_role(Role::Moderator)?;
+                let info = server.sellers.unban(&name)?;
+                server.audit.record(actor, "SELLER UNBAN", &info.name);
+                Ok(Response::Seller(info))
+            },
+            SellerCommand::Strike { name } => {
+                let actor = state.require_role(Role::Moderator)?;
+                let moderation = server.config().moderation.clone();
+                let info = server.sellers.strike(&name, moderation.strikes_to_ban, moderation.strike_ban_secs)?;
+                server.audit.record(actor, "SELLER STRIKE", &info.name);
+                if info.banned && info.strikes == 0 {
+                    info!(seller = %info.name, "Seller banned after repeated strikes");
+                }
+                Ok(Response::Seller(info))
+            },
+            SellerCommand::Revoke { name } => {
+                let actor = state.require_role(Role::Moderator)?;
+                let revoked = server.tokens.revoke_seller(&name).map_err(|e| {
+                    error!("Failed to revoke API tokens: {:#}", e);
+                    BazaarError::Internal
+                })?;
+                server.audit.record(actor, "SELLER REVOKE", &format!("{} ({} tokens)", name, revoked));
+                Ok(Response::Ok)
+            },
+        }
+    }
+}
+
+pub struct AwayHandler;
+
+#[async_trait]
+impl Builtin for AwayHandler {
+    const NAME: &'static str = "AWAY";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Away(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let seller = state.seller.as_ref().map(|session| session.seller.clone()).ok_or(BazaarError::Forbidden)?;
+        let actor = state.authorize(TokenScope::Update, &seller)?;
+        match command {
+            AwayCommand::On { for_secs } => {
+                let until = for_secs.map(|secs| catalog::unix_now().saturating_add(secs));
+                server.away.set(&seller, until)?;
+                server.audit.record(&actor, "AWAY", &until.map_or("on".to_string(), |until| format!("until {}", until)));
+            },
+            AwayCommand::Off => {
+                server.away.clear(&seller)?;
+                server.audit.record(&actor, "AWAY", "off");
+            },
+            AwayCommand::Status => {},
+        }
+        let until = server.away.get(&seller);
+        Ok(Response::Away { seller, away: until.is_some(), until: until.flatten() })
+    }
+}
@@ -0,0 +1,105 @@
+//! Running the server: status, reloads, the audit log and announcements.
+
+use super::Builtin;
+use crate::policy::Role;
+use crate::{audit, BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{BazaarError, Command, PROTOCOL_VERSION, Response, ServerStatus};
+use std::collections::BTreeSet;
+use tracing::{error, info};
+
+/// Audit entries returned by `AUDIT TAIL` without a count.
+const DEFAULT_AUDIT_TAIL: usize = 20;
+
+pub struct StatusHandler;
+
+#[async_trait]
+impl Builtin for StatusHandler {
+    const NAME: &'static str = "STATUS";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        let config = server.config();
+        let (tlog_size, tlog_head) = server.tlog.head();
+        let items = server.items.read().await;
+        let categories: BTreeSet<&str> = items
+            .values()
+            .map(|item| item.category.as_str())
+            .collect();
+
+        Ok(Response::Status(ServerStatus {
+            protocol_version: PROTOCOL_VERSION,
+            address: server.addresses[0].clone(),
+            other_addresses: server.addresses[1..].to_vec(),
+            uptime_secs: server.started_at.elapsed().as_secs(),
+            items: items.len(),
+            categories: categories.len(),
+            sessions: server.metrics.active_connections.get().max(0) as usize,
+            catalog_root: items.merkle_root(),
+            tlog_size,
+            tlog_head,
+            capabilities: config.capabilities(),
+            pass_key: if config.passes.required { server.passes.public_key() } else { String::new() },
+        }))
+    }
+}
+
+pub struct ReloadHandler;
+
+#[async_trait]
+impl Builtin for ReloadHandler {
+    const NAME: &'static str = "RELOAD";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Reload { catalog } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let actor = state.require_role(Role::Admin)?;
+
+        match server.reload(catalog).await {
+            Ok(items) => {
+                let catalog = catalog && server.config().catalog.is_some();
+                server.audit.record(actor, "RELOAD", if catalog { "config and catalog" } else { "config" });
+                Ok(Response::Reloaded { items })
+            },
+            Err(e) => {
+                error!("Reload failed: {:#}", e);
+                Err(BazaarError::ReloadFailed)
+            }
+        }
+    }
+}
+
+pub struct AuditHandler;
+
+#[async_trait]
+impl Builtin for AuditHandler {
+    const NAME: &'static str = "AUDIT";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::AuditTail { count } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        state.require_role(Role::Moderator)?;
+
+        let count = count.unwrap_or(DEFAULT_AUDIT_TAIL).min(audit::TAIL_LEN);
+        Ok(Response::AuditTail(server.audit.tail(count)))
+    }
+}
+
+pub struct AnnounceHandler;
+
+#[async_trait]
+impl Builtin for AnnounceHandler {
+    const NAME: &'static str = "ANNOUNCE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Announce { text } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let actor = state.require_role(Role::Admin)?;
+        let seq = server.news.announce(&text)?;
+        server.audit.record(actor, "ANNOUNCE", &format!("#{} {}", seq, text));
+        info!(seq, "Announcement published");
+        Ok(Response::Announced { seq })
+    }
+}
@@ -0,0 +1,235 @@
+//! Reading the catalog: listings, searches, categories, models and the mirror export.
+
+use super::{partial_response, Builtin};
+use crate::{catalog, search, BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{BazaarError, Command, Item, Response};
+use rand::seq::IteratorRandom;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// How far back `NEW` looks without a duration.
+const DEFAULT_NEW_WINDOW: Duration = Duration::from_secs(24 * 3600);
+
+pub struct HeadHandler;
+
+#[async_trait]
+impl Builtin for HeadHandler {
+    const NAME: &'static str = "HEAD";
+
+    async fn run(&self, _server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        Ok(Response::Ok)
+    }
+}
+
+pub struct HelpHandler;
+
+#[async_trait]
+impl Builtin for HelpHandler {
+    const NAME: &'static str = "HELP";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        Ok(Response::Help(server.commands.usage()))
+    }
+}
+
+pub struct ListHandler;
+
+#[async_trait]
+impl Builtin for ListHandler {
+    const NAME: &'static str = "LIST";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::List { category, currency, price } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let category_filter = category.map(|c| c.to_lowercase());
+        let key = format!("LIST {}", category_filter.as_deref().unwrap_or(""));
+
+        let items = server.items.read().await;
+        let response = server.cache.get_or_insert(key, || {
+            let filtered_items: Vec<Item> = items
+                .values()
+                .filter(|item| {
+                    if let Some(ref cat) = category_filter {
+                        item.category.to_lowercase() == *cat
+                    } else {
+                        true
+                    }
+                })
+                .cloned()
+                .collect();
+            Ok(Response::Items(filtered_items))
+        })?;
+
+        let response = match (price, response) {
+            (Some(range), Response::Items(items)) => {
+                let price_of = server.price_reader(currency.as_deref())?;
+                Response::PriceFiltered {
+                    items: items.into_iter().filter(|item| price_of(item).is_some_and(|p| range.contains(p))).collect(),
+                    range,
+                    currency: currency.clone(),
+                }
+            },
+            (_, response) => response,
+        };
+        server.convert(response, currency)
+    }
+}
+
+pub struct GetHandler;
+
+#[async_trait]
+impl Builtin for GetHandler {
+    const NAME: &'static str = "GET";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Get { id, currency, range } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let items = server.items.read().await;
+        let response = server.cache.get_or_insert(format!("GET {}", id.to_uppercase()), || {
+            let mut item = items.get(&id).ok_or(BazaarError::ItemNotFound)?.clone();
+            item.seller_verified = server.sellers.is_verified(&item.seller);
+            Ok(Response::Item(Box::new(item)))
+        })?;
+
+        let mut response = server.convert(response, currency)?;
+        if let Response::Item(item) = &mut response {
+            item.questions = server.questions.for_item(&item.id, true);
+            item.attestations = server.attestations.for_item(&item.id);
+        }
+        match range {
+            Some(range) => partial_response(&response, state, range),
+            None => Ok(response),
+        }
+    }
+}
+
+pub struct SearchHandler;
+
+#[async_trait]
+impl Builtin for SearchHandler {
+    const NAME: &'static str = "SEARCH";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Search { query, currency } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if !server.config().features.search {
+            return Err(BazaarError::FeatureDisabled);
+        }
+
+        let price_of = server.price_reader(currency.as_deref())?;
+        let items = server.items.read().await;
+        let results = search::search(items.values(), &query, price_of);
+        server.convert(Response::SearchResults(results), currency)
+    }
+}
+
+pub struct NewHandler;
+
+#[async_trait]
+impl Builtin for NewHandler {
+    const NAME: &'static str = "NEW";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::New { since_secs } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let since = since_secs.unwrap_or(DEFAULT_NEW_WINDOW.as_secs());
+        let cutoff = catalog::unix_now().saturating_sub(since);
+
+        let items = server.items.read().await;
+        let mut fresh: Vec<Item> = items.values().filter(|item| item.created_at >= cutoff).cloned().collect();
+        fresh.sort_by_key(|item| std::cmp::Reverse(item.created_at));
+        Ok(Response::Items(fresh))
+    }
+}
+
+pub struct CategoriesHandler;
+
+#[async_trait]
+impl Builtin for CategoriesHandler {
+    const NAME: &'static str = "CATEGORIES";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        let items = server.items.read().await;
+        let categories: BTreeSet<String> = items
+            .values()
+            .map(|item| item.category.clone())
+            .collect();
+
+        Ok(Response::Categories(categories.into_iter().collect()))
+    }
+}
+
+pub struct ModelsHandler;
+
+#[async_trait]
+impl Builtin for ModelsHandler {
+    const NAME: &'static str = "MODELS";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Models { query } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let mut models = server.models().search(query.as_deref().unwrap_or_default());
+        let items = server.items.read().await;
+        for model in &mut models {
+            model.listings = items.values().filter(|item| item.model.as_ref() == Some(&model.id)).count();
+        }
+        Ok(Response::Models(models))
+    }
+}
+
+pub struct FeaturedHandler;
+
+#[async_trait]
+impl Builtin for FeaturedHandler {
+    const NAME: &'static str = "FEATURED";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        let items = server.items.read().await;
+        Ok(Response::Items(items.featured().into_iter().cloned().collect()))
+    }
+}
+
+pub struct RandomHandler;
+
+#[async_trait]
+impl Builtin for RandomHandler {
+    const NAME: &'static str = "RANDOM";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Random { category } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let items = server.items.read().await;
+        let pick = items
+            .values()
+            .filter(|item| category.as_ref().is_none_or(|c| item.category.eq_ignore_ascii_case(c)))
+            .choose(&mut rand::thread_rng())
+            .ok_or(BazaarError::ItemNotFound)?;
+        Ok(Response::Item(Box::new(pick.clone())))
+    }
+}
+
+pub struct ExportHandler;
+
+#[async_trait]
+impl Builtin for ExportHandler {
+    const NAME: &'static str = "EXPORT";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Export { range } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let items = server.items.read().await;
+        let response = Response::Export(items.values().cloned().collect());
+        match range {
+            Some(range) => partial_response(&response, state, range),
+            None => Ok(response),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Talking to sellers and other buyers: questions, news, dead drops and saved searches.
+
+use super::Builtin;
+use crate::filters::FilterChain;
+use crate::{search, BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{BazaarError, Command, DropCommand, ItemField, Response, SavedSearchCommand, SearchQuery, TokenScope};
+use tracing::{error, info};
+
+pub struct AskHandler;
+
+#[async_trait]
+impl Builtin for AskHandler {
+    const NAME: &'static str = "ASK";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Ask { id, question } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let config = server.config().questions.clone();
+        if !config.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let item = server.items.read().await.get(&id).ok_or(BazaarError::ItemNotFound)?.id.clone();
+        let question = FilterChain::from_config(&server.config().filters).apply(ItemField::Description, question)?;
+        let id = server.questions.ask(&item, &question, config.max_pending_per_listing)?;
+        info!(item = %item, question = %id, "Question asked");
+        Ok(Response::Asked { id })
+    }
+}
+
+pub struct AnswerHandler;
+
+#[async_trait]
+impl Builtin for AnswerHandler {
+    const NAME: &'static str = "ANSWER";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Answer { question_id, answer } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if !server.config().questions.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let item = server.questions.item_of(&question_id).ok_or(BazaarError::QuestionNotFound)?;
+        let owner = server.items.read().await.get(&item).ok_or(BazaarError::ItemNotFound)?.seller.clone();
+        let actor = state.authorize(TokenScope::Update, &owner)?;
+        let answer = FilterChain::from_config(&server.config().filters).apply(ItemField::Description, answer)?;
+        server.questions.answer(&question_id, &answer)?;
+        server.audit.record(&actor, "ANSWER", &format!("{} {}", item, question_id));
+        Ok(Response::Ok)
+    }
+}
+
+pub struct QuestionsHandler;
+
+#[async_trait]
+impl Builtin for QuestionsHandler {
+    const NAME: &'static str = "QUESTIONS";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Questions { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if !server.config().questions.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let item = server.items.read().await.get(&id).cloned().ok_or(BazaarError::ItemNotFound)?;
+        state.authorize(TokenScope::Update, &item.seller)?;
+        Ok(Response::Questions { id: item.id.clone(), questions: server.questions.for_item(&item.id, false) })
+    }
+}
+
+pub struct NewsHandler;
+
+#[async_trait]
+impl Builtin for NewsHandler {
+    const NAME: &'static str = "NEWS";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        Ok(Response::News { public_key: server.news.public_key(), announcements: server.news.recent() })
+    }
+}
+
+pub struct DropHandler;
+
+#[async_trait]
+impl Builtin for DropHandler {
+    const NAME: &'static str = "DROP";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Drop(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        match command {
+            DropCommand::Put { id, ciphertext } => {
+                let owner = state.identity()?;
+                server.drops.put(&owner, &id, &ciphertext)?;
+                Ok(Response::Ok)
+            },
+            DropCommand::Get { id } => {
+                let (ciphertext, posted_at) = server.drops.get(&id).ok_or(BazaarError::DropNotFound)?;
+                Ok(Response::Drop { id, ciphertext, posted_at })
+            },
+        }
+    }
+}
+
+pub struct SavedSearchHandler;
+
+#[async_trait]
+impl Builtin for SavedSearchHandler {
+    const NAME: &'static str = "SAVEDSEARCH";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::SavedSearch(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let owner = state.identity()?;
+        let failed = |e: anyhow::Error| {
+            error!("Failed to update saved searches: {:#}", e);
+            BazaarError::Internal
+        };
+
+        match command {
+            SavedSearchCommand::Add { name, query } => {
+                if !server.saved_searches.add(&owner, &name, &query).map_err(failed)? {
+                    return Err(BazaarError::LimitReached);
+                }
+                Ok(Response::Ok)
+            },
+            SavedSearchCommand::Run { name, new_only } => {
+                if !server.config().features.search {
+                    return Err(BazaarError::FeatureDisabled);
+                }
+                let (query, last_run) = server
+                    .saved_searches
+                    .start_run(&owner, &name)
+                    .map_err(failed)?
+                    .ok_or(BazaarError::SavedSearchNotFound)?;
+                let query: SearchQuery = query.parse()?;
+                // With --new, only listings that appeared since the previous run
+                let since = if new_only { last_run.unwrap_or(0) } else { 0 };
+
+                let items = server.items.read().await;
+                let fresh = items.values().filter(|item| item.created_at >= since);
+                Ok(Response::SearchResults(search::search(fresh, &query, server.price_reader(None)?)))
+            },
+            SavedSearchCommand::List => Ok(Response::SavedSearches(server.saved_searches.list(&owner))),
+            SavedSearchCommand::Delete { name } => {
+                if !server.saved_searches.remove(&owner, &name).map_err(failed)? {
+                    return Err(BazaarError::SavedSearchNotFound);
+                }
+                Ok(Response::Ok)
+            },
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Listing images and the chunked uploads they can come in as.
+
+use super::{partial, Builtin};
+use crate::{BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{media, BazaarError, Command, ImageData, Response, TokenScope, UploadCommand};
+use tracing::{error, info, warn};
+
+pub struct PutImageHandler;
+
+#[async_trait]
+impl Builtin for PutImageHandler {
+    const NAME: &'static str = "PUTIMAGE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::PutImage { id, data } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let config = server.config().media.clone();
+        if !config.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let (data, upload) = match data {
+            ImageData::Inline(data) => (media::decode(&data).ok_or(BazaarError::InvalidMedia)?, None),
+            ImageData::Upload(upload) => (server.uploads.read(&upload)?, Some(upload)),
+        };
+        if data.is_empty() || data.len() > config.max_image_bytes {
+            return Err(BazaarError::InvalidMedia);
+        }
+
+        if let Some(ipfs) = &server.ipfs {
+            // Checked before pinning so strangers can't pin data, and the
+            // catalog isn't locked while the node is busy
+            let owner = server.items.read().await.get(&id).ok_or(BazaarError::ItemNotFound)?.seller.clone();
+            state.authorize(TokenScope::Update, &owner)?;
+            let size = data.len();
+            let cid = ipfs.pin(data).await.map_err(|e| {
+                error!("Failed to pin image: {:#}", e);
+                BazaarError::Internal
+            })?;
+
+            let mut items = server.write_catalog().await;
+            let owner = items.get(&id).ok_or(BazaarError::ItemNotFound)?.seller.clone();
+            let actor = state.authorize(TokenScope::Update, &owner)?;
+            let item = items.attach_media(&id, &cid, config.max_per_listing)?;
+            let (id, media) = (item.id.clone(), item.media.clone());
+            server.persist(&mut items, &id).await?;
+            server.audit.record(&actor, "PUTIMAGE", &format!("{} {} ({} bytes)", id, cid, size));
+            info!(id = %id, cid = %cid, "Image pinned");
+            server.finish_upload(upload);
+            return Ok(Response::Media { id, media });
+        }
+
+        let mut items = server.write_catalog().await;
+        let item = items.get(&id).ok_or(BazaarError::ItemNotFound)?;
+        let actor = state.authorize(TokenScope::Update, &item.seller)?;
+        let hash = media::hash(&data);
+        if !item.media.contains(&hash) {
+            server.blobs.put(&data).map_err(|e| {
+                error!("Failed to store image: {:#}", e);
+                BazaarError::Internal
+            })?;
+            if let Err(e) = items.attach_media(&id, &hash, config.max_per_listing) {
+                let _ = server.blobs.release(&hash);
+                return Err(e);
+            }
+            server.store_thumbnails(&hash, data.clone()).await;
+        }
+        let item = items.get(&id).ok_or(BazaarError::ItemNotFound)?;
+        let (id, media) = (item.id.clone(), item.media.clone());
+        server.persist(&mut items, &id).await?;
+        server.audit.record(&actor, "PUTIMAGE", &format!("{} {} ({} bytes)", id, hash, data.len()));
+        info!(id = %id, hash = %hash, "Image attached");
+        server.finish_upload(upload);
+        Ok(Response::Media { id, media })
+    }
+}
+
+pub struct GetImageHandler;
+
+#[async_trait]
+impl Builtin for GetImageHandler {
+    const NAME: &'static str = "GETIMAGE";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::GetImage { hash, chunk, size, range } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if !server.config().media.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let data = server.read_image(&hash, size).await?;
+        if let Some(range) = range {
+            return partial(&data, range);
+        }
+        let chunks = data.len().div_ceil(media::CHUNK_SIZE).max(1);
+        let start = chunk.checked_mul(media::CHUNK_SIZE).filter(|_| chunk < chunks).ok_or(BazaarError::InvalidCommand)?;
+        let end = (start + media::CHUNK_SIZE).min(data.len());
+        Ok(Response::Image { hash, chunk, chunks, data: media::encode(&data[start..end]) })
+    }
+}
+
+pub struct DelImageHandler;
+
+#[async_trait]
+impl Builtin for DelImageHandler {
+    const NAME: &'static str = "DELIMAGE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::DelImage { id, hash } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if !server.config().media.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        let mut items = server.write_catalog().await;
+        let owner = items.get(&id).ok_or(BazaarError::ItemNotFound)?.seller.clone();
+        let actor = state.authorize(TokenScope::Update, &owner)?;
+        let item = items.detach_media(&id, &hash)?;
+        let (id, media) = (item.id.clone(), item.media.clone());
+        if let Err(e) = server.blobs.release(&hash) {
+            // The next collection recounts references anyway
+            warn!("Failed to release image {}: {:#}", hash, e);
+        }
+        server.persist(&mut items, &id).await?;
+        server.audit.record(&actor, "DELIMAGE", &format!("{} {}", id, hash));
+        Ok(Response::Media { id, media })
+    }
+}
+
+pub struct UploadHandler;
+
+#[async_trait]
+impl Builtin for UploadHandler {
+    const NAME: &'static str = "UPLOAD";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Upload(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let config = server.config().uploads.clone();
+        if !config.enabled {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        match command {
+            UploadCommand::Begin { size, hash } => {
+                Ok(Response::Upload(server.uploads.begin(size, &hash, config.max_bytes, config.max_open)?))
+            },
+            UploadCommand::Chunk { id, chunk, data } => {
+                let data = media::decode(&data).ok_or(BazaarError::InvalidCommand)?;
+                Ok(Response::Upload(server.uploads.chunk(&id, chunk, &data)?))
+            },
+            UploadCommand::Status { id } => Ok(Response::Upload(server.uploads.status(&id)?)),
+            UploadCommand::Abort { id } => {
+                server.uploads.remove(&id)?;
+                Ok(Response::Ok)
+            },
+        }
+    }
+}
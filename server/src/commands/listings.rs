@@ -0,0 +1,334 @@
+//! Adding and changing listings, including bundles, signatures and featuring.
+
+use super::Builtin;
+use crate::filters::FilterChain;
+use crate::hooks::Event;
+use crate::policy::Role;
+use crate::catalog::{self, Catalog};
+use crate::{BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::tlog::TlogEvent;
+use bazaar_protocol::{signing, BazaarError, Command, Item, ItemField, NewListing, Response, TokenScope};
+use tracing::info;
+
+pub struct SellHandler;
+
+#[async_trait]
+impl Builtin for SellHandler {
+    const NAME: &'static str = "SELL";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Sell(mut listing) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let actor = state.authorize(TokenScope::Sell, &listing.seller)?;
+        // Before the credential check, so a rejected listing doesn't spend it
+        let filters = FilterChain::from_config(&server.config().filters);
+        listing.name = filters.apply(ItemField::Name, listing.name)?;
+        listing.description = filters.apply(ItemField::Description, listing.description)?;
+        // Numbered before the catalog is locked, as shared storage is a round trip away
+        let id = server.ids.generate();
+        let alias = server.allocate_alias().await?;
+        let (mut items, duplicate_of) = server.admit_listing(state, &actor, &listing).await?;
+        let item = Item {
+            alias,
+            shipping: listing.shipping,
+            ..Item::new(
+                id.to_string(),
+                catalog::created_at(&id),
+                listing.name,
+                listing.category,
+                listing.description,
+                listing.price,
+                listing.seller,
+            )
+        };
+
+        // A publication time that has already passed just lists the item now
+        let (item, response) = match listing.publish_at.filter(|at| *at > catalog::unix_now()) {
+            Some(publish_at) => {
+                let item = items.schedule(item, publish_at);
+                let response = Response::Scheduled { id: item.id.clone(), alias: item.alias, publish_at, duplicate_of };
+                (item, response)
+            },
+            None => {
+                let item = items.insert(item);
+                server.tlog.record(TlogEvent::Added, &item.id, state.tlog_actor(), Some(item));
+                (item, Response::Created { id: item.id.clone(), alias: item.alias, duplicate_of })
+            },
+        };
+        let detail = format!("{} {} {}", item.id, item.price, item.name);
+        let event = Event::new("listing_created", &item.id, &actor, Some(item));
+        server.persist(&mut items, &id.to_string()).await?;
+        server.audit.record(&actor, "SELL", &detail);
+        server.hooks.emit(event);
+        server.metrics.items.set(items.len() as i64);
+        info!(id = %id, "Item listed");
+        Ok(response)
+    }
+}
+
+pub struct BundleHandler;
+
+#[async_trait]
+impl Builtin for BundleHandler {
+    const NAME: &'static str = "BUNDLE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::BundleCreate { items: parts, price, name, credential } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        // Admitted like any other new listing, from what its parts are now
+        let listing = {
+            let items = server.items.read().await;
+            let (seller, components) = bundle_components(&items, &parts)?;
+            NewListing {
+                category: components[0].category.clone(),
+                seller,
+                price,
+                name: match name {
+                    Some(name) => FilterChain::from_config(&server.config().filters).apply(ItemField::Name, name)?,
+                    None => components.iter().map(|item| item.name.as_str()).collect::<Vec<_>>().join(" + "),
+                },
+                description: String::new(),
+                credential,
+                publish_at: None,
+                shipping: components[0].shipping.clone(),
+            }
+        };
+        let actor = state.authorize(TokenScope::Sell, &listing.seller)?;
+
+        let id = server.ids.generate();
+        let alias = server.allocate_alias().await?;
+        let (mut items, duplicate_of) = server.admit_listing(state, &actor, &listing).await?;
+        // The parts may have changed while the catalog was unlocked
+        let (seller, components) = bundle_components(&items, &parts)?;
+        if seller != listing.seller {
+            return Err(BazaarError::InvalidCommand);
+        }
+        let item = Item {
+            alias,
+            shipping: listing.shipping,
+            bundle: components.iter().map(|item| item.id.clone()).collect(),
+            ..Item::new(
+                id.to_string(),
+                catalog::created_at(&id),
+                listing.name,
+                listing.category,
+                listing.description,
+                listing.price,
+                listing.seller,
+            )
+        };
+        let item = items.insert(item);
+        server.tlog.record(TlogEvent::Added, &item.id, state.tlog_actor(), Some(item));
+        let response = Response::Created { id: item.id.clone(), alias: item.alias, duplicate_of };
+        let detail = format!("{} {} {}", item.id, item.price, item.bundle.join(","));
+        let event = Event::new("listing_created", &item.id, &actor, Some(item));
+        server.persist(&mut items, &id.to_string()).await?;
+        server.audit.record(&actor, "BUNDLE", &detail);
+        server.hooks.emit(event);
+        server.metrics.items.set(items.len() as i64);
+        info!(id = %id, "Bundle listed");
+        Ok(response)
+    }
+}
+
+/// The live listings a bundle of `parts` is made of, each once, and the seller
+/// they belong to. A bundle is one seller's lot of at least two of their own
+/// listings, none of them bundles themselves.
+fn bundle_components<'a>(items: &'a Catalog, parts: &[String]) -> Result<(String, Vec<&'a Item>), BazaarError> {
+    let mut components: Vec<&Item> = Vec::new();
+    for part in parts {
+        let item = items.get(part).ok_or(BazaarError::ItemNotFound)?;
+        if !item.bundle.is_empty() {
+            return Err(BazaarError::InvalidCommand);
+        }
+        if !components.iter().any(|component| component.id == item.id) {
+            components.push(item);
+        }
+    }
+    match components.as_slice() {
+        [first, rest @ ..] if !rest.is_empty() && rest.iter().all(|item| item.seller == first.seller) => {
+            Ok((first.seller.clone(), components))
+        },
+        _ => Err(BazaarError::InvalidCommand),
+    }
+}
+
+pub struct UpdateHandler;
+
+#[async_trait]
+impl Builtin for UpdateHandler {
+    const NAME: &'static str = "UPDATE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Update { id, revision, field, mut value } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if field == ItemField::Model && !value.is_empty() {
+            value = server.models().get(&value).ok_or(BazaarError::ModelNotFound)?.id.clone();
+        }
+        let mut items = server.write_catalog().await;
+        let owner = items.seller_of(&id).ok_or(BazaarError::ItemNotFound)?;
+        let actor = state.authorize(TokenScope::Update, owner)?;
+        if field == ItemField::Price {
+            server.check_tier(state, &items, owner, Some(&value), 0)?;
+        }
+        let value = FilterChain::from_config(&server.config().filters).apply(field, value)?;
+        let detail = format!("rev {} {}={}", revision + 1, field.name(), value);
+        let item = match items.update(&id, revision, field, value) {
+            Ok(item) => item,
+            Err(BazaarError::RevisionConflict) => {
+                info!(id = %id, expected = revision, "Rejecting stale update");
+                return Err(BazaarError::RevisionConflict);
+            },
+            Err(e) => return Err(e),
+        };
+        let (id, revision) = (item.id.clone(), item.revision);
+        server.persist(&mut items, &id).await?;
+        let event = Event::new("listing_updated", &id, &actor, items.get(&id));
+        // A scheduled listing goes into the transparency log when it is published
+        if items.get(&id).is_some() {
+            server.tlog.record(TlogEvent::Updated, &id, state.tlog_actor(), items.get(&id));
+        }
+        server.audit.record(&actor, "UPDATE", &format!("{} {}", id, detail));
+        server.hooks.emit(event);
+        info!(id = %id, revision, field = field.name(), "Item updated");
+        Ok(Response::Updated { id, revision })
+    }
+}
+
+pub struct DeleteHandler;
+
+#[async_trait]
+impl Builtin for DeleteHandler {
+    const NAME: &'static str = "DELETE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Delete { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let mut items = server.write_catalog().await;
+        let owner = items.seller_of(&id).ok_or(BazaarError::ItemNotFound)?;
+        let actor = state.authorize(TokenScope::Delete, owner)?;
+        // A bundle has to go before the listings it is made of
+        if items.in_bundle(&id) {
+            return Err(BazaarError::InBundle);
+        }
+        let id = items.delete(&id).ok_or(BazaarError::ItemNotFound)?;
+        server.persist(&mut items, &id).await?;
+        server.metrics.items.set(items.len() as i64);
+        server.audit.record(&actor, "DELETE", &id);
+        server.tlog.record(TlogEvent::Removed, &id, state.tlog_actor(), None);
+        server.hooks.emit(Event::new("listing_deleted", &id, &actor, None));
+        info!(id = %id, "Item moved to trash");
+        Ok(Response::Deleted { id, restore_secs: server.config().trash_retention_secs })
+    }
+}
+
+pub struct RestoreHandler;
+
+#[async_trait]
+impl Builtin for RestoreHandler {
+    const NAME: &'static str = "RESTORE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Restore { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let mut items = server.write_catalog().await;
+        let owner = items.seller_of(&id).ok_or(BazaarError::ItemNotFound)?;
+        let actor = state.authorize(TokenScope::Delete, owner)?;
+        server.check_tier(state, &items, owner, None, 1)?;
+        // A bundle only comes back once the listings it is made of are live
+        if items.has_missing_parts(&id) {
+            return Err(BazaarError::ItemNotFound);
+        }
+        let id = items.restore(&id).ok_or(BazaarError::ItemNotFound)?;
+        server.persist(&mut items, &id).await?;
+        server.metrics.items.set(items.len() as i64);
+        server.audit.record(&actor, "RESTORE", &id);
+        server.tlog.record(TlogEvent::Restored, &id, state.tlog_actor(), items.get(&id));
+        server.hooks.emit(Event::new("listing_restored", &id, &actor, None));
+        info!(id = %id, "Item restored from trash");
+        Ok(Response::Restored { id })
+    }
+}
+
+pub struct HistoryHandler;
+
+#[async_trait]
+impl Builtin for HistoryHandler {
+    const NAME: &'static str = "HISTORY";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::History { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let items = server.items.read().await;
+        let (id, events) = items.history(&id).ok_or(BazaarError::ItemNotFound)?;
+        Ok(Response::History { id: id.to_string(), events: events.to_vec() })
+    }
+}
+
+pub struct SignHandler;
+
+#[async_trait]
+impl Builtin for SignHandler {
+    const NAME: &'static str = "SIGN";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Sign { id, signature } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let mut items = server.write_catalog().await;
+        let item = items.get(&id).ok_or(BazaarError::ItemNotFound)?;
+        // Anyone may submit it, the signature itself proves it came from the seller
+        let public_key = server.sellers.public_key(&item.seller).ok_or(BazaarError::SellerNotFound)?;
+        signing::verify(&public_key, &signing::listing_payload(item), &signature)?;
+        let seller = item.seller.clone();
+        let id = items.sign(&id, signature).ok_or(BazaarError::ItemNotFound)?;
+        server.persist(&mut items, &id).await?;
+        server.audit.record(&seller, "SIGN", &id);
+        Ok(Response::Ok)
+    }
+}
+
+pub struct FeatureHandler;
+
+#[async_trait]
+impl Builtin for FeatureHandler {
+    const NAME: &'static str = "FEATURE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Feature { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let actor = state.require_role(Role::Admin)?;
+        let mut items = server.write_catalog().await;
+        let id = items.feature(&id).ok_or(BazaarError::ItemNotFound)?;
+        server.persist(&mut items, &id).await?;
+        server.audit.record(actor, "FEATURE", &id);
+        Ok(Response::Ok)
+    }
+}
+
+pub struct UnfeatureHandler;
+
+#[async_trait]
+impl Builtin for UnfeatureHandler {
+    const NAME: &'static str = "UNFEATURE";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Unfeature { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let actor = state.require_role(Role::Admin)?;
+        let mut items = server.write_catalog().await;
+        let id = items.unfeature(&id).ok_or(BazaarError::ItemNotFound)?;
+        server.persist(&mut items, &id).await?;
+        server.audit.record(actor, "UNFEATURE", &id);
+        Ok(Response::Ok)
+    }
+}
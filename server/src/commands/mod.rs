@@ -0,0 +1,207 @@
+//! Command handlers. Every keyword the server answers, built in or added by an
+//! operator, is a [`CommandHandler`] in the [`CommandRegistry`]. The built-in
+//! ones are grouped by topic in the modules below, one handler per keyword.
+
+mod accounts;
+mod admin;
+mod browse;
+mod community;
+mod images;
+mod listings;
+mod plugins;
+mod session;
+mod transparency;
+
+use crate::policy::Role;
+use crate::{BazaarServer, ConnectionState};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bazaar_protocol::{media, BazaarError, ByteRange, Command, Response};
+use tracing::{error, info};
+
+/// Answers one command keyword.
+#[async_trait]
+pub trait CommandHandler: Send + Sync {
+    /// The keyword, e.g. `PICKUP`. Matched case-insensitively and used as the
+    /// name in `[policy]` lists and metrics.
+    fn name(&self) -> &str;
+
+    /// Lines shown for this command by `HELP`.
+    fn usage(&self) -> Vec<String>;
+
+    /// Runs a request line whose first word is [`CommandHandler::name`]. Use
+    /// `bazaar_protocol::command::tokenize` to split it the way built-in commands are.
+    /// The connection's role has already been checked against the policy.
+    async fn handle(&self, server: &BazaarServer, state: &mut ConnectionState, line: &str) -> Result<Response, BazaarError>;
+}
+
+/// A command defined by the protocol crate. The handler parses the line with
+/// [`Command::parse`], applies the checks every built-in write goes through, and
+/// passes the parsed command to [`Builtin::run`].
+#[async_trait]
+trait Builtin: Send + Sync {
+    /// The keyword, as [`Command::name`] gives it.
+    const NAME: &'static str;
+
+    /// Runs a command parsed from a line starting with [`Builtin::NAME`].
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError>;
+}
+
+#[async_trait]
+impl<T: Builtin> CommandHandler for T {
+    fn name(&self) -> &str {
+        T::NAME
+    }
+
+    fn usage(&self) -> Vec<String> {
+        Command::USAGE
+            .iter()
+            .filter(|usage| usage.split_whitespace().next() == Some(T::NAME))
+            .map(|usage| usage.to_string())
+            .collect()
+    }
+
+    async fn handle(&self, server: &BazaarServer, state: &mut ConnectionState, line: &str) -> Result<Response, BazaarError> {
+        let command = Command::parse(line)?;
+        if command.is_write() && server.config().mirror.upstream.is_some() {
+            return Err(BazaarError::ReadOnly);
+        }
+        // Revocation, expiry and bans apply to connections that authenticated
+        // with the token before them too
+        if let (true, Some(session)) = (command.is_write(), &state.seller) {
+            match server.tokens.session(&session.token_id) {
+                Some(session) if server.sellers.is_banned(&session.seller) => return Err(BazaarError::Banned),
+                Some(session) => state.seller = Some(session),
+                None => {
+                    info!(token = %session.token_id, "Token revoked or expired since the connection authenticated");
+                    state.sign_out();
+                },
+            }
+        }
+        // Operators don't pay for writes, and failed writes are free
+        let pays = command.is_write() && server.config().passes.required && state.role < Role::Moderator;
+        // Taken up front, so concurrent writes on one session can't share a pass
+        if pays && !server.spend_pass(state) {
+            return Err(BazaarError::PassRequired);
+        }
+        let response = self.run(server, state, command).await;
+        if pays && response.is_err() {
+            server.refund_pass(state);
+        }
+        response
+    }
+}
+
+/// The bytes of `data` in `range`.
+fn partial(data: &[u8], range: ByteRange) -> Result<Response, BazaarError> {
+    let bytes = range.slice(data).ok_or(BazaarError::InvalidCommand)?;
+    Ok(Response::Partial {
+        offset: range.offset,
+        total: data.len() as u64,
+        data: media::encode(bytes),
+    })
+}
+
+/// The bytes in `range` of `response` as the connection would get it whole.
+fn partial_response(response: &Response, state: &ConnectionState, range: ByteRange) -> Result<Response, BazaarError> {
+    let payload = response.encode(state.encoding, state.lang).map_err(|e| {
+        error!("Failed to encode response: {}", e);
+        BazaarError::Internal
+    })?;
+    partial(&payload, range)
+}
+
+/// The commands the server answers, in the order `HELP` lists them.
+pub struct CommandRegistry {
+    handlers: Vec<Box<dyn CommandHandler>>,
+}
+
+impl CommandRegistry {
+    /// Adds a command. Keywords must be unique, so a handler can't replace a built-in one.
+    pub fn register(&mut self, handler: Box<dyn CommandHandler>) -> Result<()> {
+        let name = handler.name();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("Command name '{}' must be a single word", name);
+        }
+        if self.get(name).is_some() {
+            bail!("Command {} is registered twice", name);
+        }
+        self.handlers.push(handler);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn CommandHandler> {
+        self.handlers
+            .iter()
+            .find(|handler| handler.name().eq_ignore_ascii_case(name))
+            .map(|handler| handler.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.handlers.iter().map(|handler| handler.name())
+    }
+
+    /// Every usage line, for `HELP`.
+    pub fn usage(&self) -> Vec<String> {
+        self.handlers.iter().flat_map(|handler| handler.usage()).collect()
+    }
+}
+
+/// The built-in commands, in the order of [`Command::USAGE`], followed by those
+/// from [`plugins::register`].
+pub fn registry() -> Result<CommandRegistry> {
+    let builtins: Vec<Box<dyn CommandHandler>> = vec![
+        Box::new(browse::HeadHandler),
+        Box::new(browse::HelpHandler),
+        Box::new(browse::ListHandler),
+        Box::new(browse::GetHandler),
+        Box::new(browse::SearchHandler),
+        Box::new(browse::NewHandler),
+        Box::new(browse::CategoriesHandler),
+        Box::new(browse::ModelsHandler),
+        Box::new(browse::FeaturedHandler),
+        Box::new(listings::FeatureHandler),
+        Box::new(listings::UnfeatureHandler),
+        Box::new(browse::RandomHandler),
+        Box::new(admin::StatusHandler),
+        Box::new(session::AuthHandler),
+        Box::new(admin::ReloadHandler),
+        Box::new(session::LangHandler),
+        Box::new(listings::SellHandler),
+        Box::new(listings::BundleHandler),
+        Box::new(listings::UpdateHandler),
+        Box::new(listings::DeleteHandler),
+        Box::new(listings::RestoreHandler),
+        Box::new(admin::AuditHandler),
+        Box::new(listings::HistoryHandler),
+        Box::new(accounts::TokenHandler),
+        Box::new(community::SavedSearchHandler),
+        Box::new(accounts::SellerHandler),
+        Box::new(listings::SignHandler),
+        Box::new(images::PutImageHandler),
+        Box::new(images::GetImageHandler),
+        Box::new(images::DelImageHandler),
+        Box::new(community::AskHandler),
+        Box::new(community::AnswerHandler),
+        Box::new(community::QuestionsHandler),
+        Box::new(transparency::ProofHandler),
+        Box::new(transparency::TlogHandler),
+        Box::new(community::DropHandler),
+        Box::new(session::PassHandler),
+        Box::new(accounts::AwayHandler),
+        Box::new(community::NewsHandler),
+        Box::new(admin::AnnounceHandler),
+        Box::new(transparency::AttestHandler),
+        Box::new(transparency::AuthenticatorHandler),
+        Box::new(images::UploadHandler),
+        Box::new(browse::ExportHandler),
+        Box::new(session::HelloHandler),
+        Box::new(session::BatchHandler),
+    ];
+    let mut registry = CommandRegistry { handlers: Vec::new() };
+    for handler in builtins {
+        registry.register(handler)?;
+    }
+    plugins::register(&mut registry)?;
+    Ok(registry)
+}
@@ -0,0 +1,163 @@
+//! Setting up a connection: authentication, language, `HELLO` sessions and write passes.
+
+use super::Builtin;
+use crate::policy::Role;
+use crate::{audit, BazaarServer, ConnectionState, Credential};
+use async_trait::async_trait;
+use bazaar_protocol::{BazaarError, Command, Compression, PROTOCOL_VERSION, PassCommand, Response};
+use std::time::Duration;
+use tracing::{info, warn};
+
+pub struct AuthHandler;
+
+#[async_trait]
+impl Builtin for AuthHandler {
+    const NAME: &'static str = "AUTH";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Auth { key } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        if let Some(role) = server.config().role_for_key(&key) {
+            let actor = format!("{}:{}", role.name(), audit::key_fingerprint(&key));
+            info!(actor = %actor, "Authenticated");
+            server.audit.record(&actor, "AUTH", "ok");
+            state.role = role;
+            state.actor = Some(actor);
+            state.credential = Some(Credential::Key(key));
+            Ok(Response::Ok)
+        } else if let Some(session) = server.tokens.verify(&key) {
+            if server.sellers.is_banned(&session.seller) {
+                server.audit.record(&format!("seller:{}/{}", session.seller, session.token_id), "AUTH", "banned");
+                return Err(BazaarError::Banned);
+            }
+            let actor = format!("seller:{}/{}", session.seller, session.token_id);
+            info!(actor = %actor, "Seller authenticated with API token");
+            server.audit.record(&actor, "AUTH", "token");
+            state.role = Role::Seller;
+            state.actor = Some(actor);
+            state.credential = Some(Credential::Token(session.token_id.clone()));
+            state.seller = Some(session);
+            Ok(Response::Ok)
+        } else {
+            state.failed_auths += 1;
+            warn!(attempts = state.failed_auths, "Failed authentication");
+            server.audit.record("anonymous", "AUTH", "failed");
+            Err(BazaarError::Unauthorized)
+        }
+    }
+}
+
+pub struct LangHandler;
+
+#[async_trait]
+impl Builtin for LangHandler {
+    const NAME: &'static str = "LANG";
+
+    async fn run(&self, _server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Lang { lang } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        state.lang = lang;
+        Ok(Response::Language(lang))
+    }
+}
+
+pub struct HelloHandler;
+
+#[async_trait]
+impl Builtin for HelloHandler {
+    const NAME: &'static str = "HELLO";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Hello { compress, encoding, session } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let idle = Duration::from_secs(server.config().limits.session_idle_secs);
+        match session.and_then(|token| Some((server.sessions.resume(&token, idle)?, token))) {
+            Some((saved, token)) => {
+                *state = server.resume_session(saved);
+                state.session = Some(token);
+            },
+            // A connection holding passes keeps the session they are in
+            None if server.has_session(state) => {},
+            None => state.session = Some(server.open_session(state)),
+        }
+
+        // Take the client's most preferred scheme that this build supports
+        state.compression = None;
+        if server.config().compression.enabled {
+            state.compression = compress.into_iter().find(|c| Compression::supported().contains(c));
+        }
+        state.encoding = encoding.unwrap_or_default();
+        Ok(Response::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            compression: state.compression,
+            encoding: state.encoding,
+            session: state.session.clone().unwrap_or_default(),
+            capabilities: server.config().capabilities(),
+        })
+    }
+}
+
+pub struct PassHandler;
+
+#[async_trait]
+impl Builtin for PassHandler {
+    const NAME: &'static str = "PASS";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Pass(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let config = server.config().passes.clone();
+        if !config.required {
+            return Err(BazaarError::FeatureDisabled);
+        }
+        match command {
+            PassCommand::Challenge => Ok(Response::PassChallenge {
+                challenge: server.passes.challenge()?,
+                difficulty: config.difficulty,
+                batch: config.batch,
+                key: server.passes.public_key(),
+            }),
+            PassCommand::Issue { challenge, solution, blinded } => {
+                if blinded.len() > config.batch {
+                    return Err(BazaarError::BatchTooLarge);
+                }
+                let signed = server.passes.issue(&challenge, solution, config.difficulty, &blinded)?;
+                Ok(Response::PassesIssued(signed))
+            },
+            PassCommand::Redeem { passes } => {
+                // Passes are held by a session, so open one for connections
+                // without one, or whose session idled out
+                if !server.has_session(state) {
+                    state.session = Some(server.open_session(state));
+                }
+                server.passes.redeem(&passes).await?;
+                let token = state.session.as_deref().unwrap_or_default();
+                let balance = server
+                    .sessions
+                    .update(token, |saved| {
+                        saved.passes = saved.passes.saturating_add(passes.len() as u32);
+                        saved.passes
+                    })
+                    .ok_or(BazaarError::Internal)?;
+                Ok(Response::PassBalance(balance))
+            },
+        }
+    }
+}
+
+/// `BATCH` is collected by the connection loop and never gets this far; the
+/// handler gives it a place in `HELP` and `[policy]`.
+pub struct BatchHandler;
+
+#[async_trait]
+impl Builtin for BatchHandler {
+    const NAME: &'static str = "BATCH";
+
+    async fn run(&self, _server: &BazaarServer, _state: &mut ConnectionState, _command: Command) -> Result<Response, BazaarError> {
+        Err(BazaarError::InvalidCommand)
+    }
+}
@@ -0,0 +1,97 @@
+//! Checking up on listings: inclusion proofs, the transparency log and attestations.
+
+use super::Builtin;
+use crate::policy::Role;
+use crate::{BazaarServer, ConnectionState};
+use async_trait::async_trait;
+use bazaar_protocol::{AttestCommand, AuthenticatorCommand, BazaarError, Command, Response};
+use tracing::info;
+
+pub struct ProofHandler;
+
+#[async_trait]
+impl Builtin for ProofHandler {
+    const NAME: &'static str = "PROOF";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Proof { id } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        let items = server.items.read().await;
+        Ok(Response::Proof(items.prove(&id).ok_or(BazaarError::ItemNotFound)?))
+    }
+}
+
+pub struct TlogHandler;
+
+#[async_trait]
+impl Builtin for TlogHandler {
+    const NAME: &'static str = "TLOG";
+
+    async fn run(&self, server: &BazaarServer, _state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::TlogRange { from, to } = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        Ok(Response::Tlog(server.tlog.range(from, to)))
+    }
+}
+
+pub struct AttestHandler;
+
+#[async_trait]
+impl Builtin for AttestHandler {
+    const NAME: &'static str = "ATTEST";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Attest(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        match command {
+            AttestCommand::Add { id, authenticator, signature, statement } => {
+                // Anyone may submit it, the signature itself proves it came from the authenticator
+                let item = server.items.read().await.get(&id).map(|item| item.id.clone()).ok_or(BazaarError::ItemNotFound)?;
+                let id = server.attestations.attest(&item, &authenticator, &statement, &signature)?;
+                server.audit.record(&authenticator, "ATTEST", &format!("{} {}", item, id));
+                info!(id = %item, authenticator = %authenticator, "Listing attested");
+                Ok(Response::Attested { id })
+            },
+            AttestCommand::Revoke { id, signature } => {
+                let actor = match &signature {
+                    Some(_) => None,
+                    None => Some(state.require_role(Role::Moderator)?.to_string()),
+                };
+                let authenticator = server.attestations.revoke(&id, signature.as_deref())?;
+                server.audit.record(actor.as_deref().unwrap_or(&authenticator), "ATTEST REVOKE", &id);
+                Ok(Response::Ok)
+            },
+        }
+    }
+}
+
+pub struct AuthenticatorHandler;
+
+#[async_trait]
+impl Builtin for AuthenticatorHandler {
+    const NAME: &'static str = "AUTHENTICATOR";
+
+    async fn run(&self, server: &BazaarServer, state: &mut ConnectionState, command: Command) -> Result<Response, BazaarError> {
+        let Command::Authenticator(command) = command else {
+            return Err(BazaarError::InvalidCommand);
+        };
+        match command {
+            AuthenticatorCommand::Add { name, public_key } => {
+                let actor = state.require_role(Role::Admin)?;
+                let info = server.attestations.add_authenticator(&name, &public_key)?;
+                server.audit.record(actor, "AUTHENTICATOR ADD", &format!("{} {}", info.name, info.public_key));
+                Ok(Response::Authenticators(vec![info]))
+            },
+            AuthenticatorCommand::Remove { name } => {
+                let actor = state.require_role(Role::Admin)?;
+                let revoked = server.attestations.remove_authenticator(&name)?;
+                server.audit.record(actor, "AUTHENTICATOR REMOVE", &format!("{} ({} attestations revoked)", name, revoked));
+                Ok(Response::Ok)
+            },
+            AuthenticatorCommand::List => Ok(Response::Authenticators(server.attestations.authenticators())),
+        }
+    }
+}
@@ -0,0 +1,757 @@
+use crate::policy::{PolicyConfig, Role};
+use crate::tiers::TiersConfig;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Config file read when `--config` isn't given, if it exists.
+pub const DEFAULT_CONFIG_PATH: &str = "bazaar.toml";
+
+/// Admin keys shorter than this are rejected at startup.
+const MIN_ADMIN_KEY_LEN: usize = 16;
+
+/// Server settings loaded from `bazaar.toml`. Every field has a default, so an
+/// empty (or missing) file yields a working local setup.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Loopback address the bazaar listens on and the NymProxyServer forwards to.
+    /// Port 0 picks a free port at startup.
+    pub bind: String,
+    /// Permit `bind` on a non-loopback interface, exposing the bazaar outside the mixnet.
+    pub allow_public_bind: bool,
+    /// Directory for server state.
+    pub data_dir: PathBuf,
+    /// Catalog file new storage is seeded from, also re-imported on reload; new
+    /// storage gets the sample items without one.
+    pub catalog: Option<PathBuf>,
+    /// Secrets granting access to operator commands.
+    pub admin_keys: Vec<String>,
+    /// How long shutdown waits for open connections to finish before closing them.
+    pub shutdown_grace_secs: u64,
+    /// How long deleted listings can be restored before they are purged.
+    pub trash_retention_secs: u64,
+    pub nym: NymConfig,
+    pub limits: LimitsConfig,
+    pub features: FeaturesConfig,
+    pub log: LogConfig,
+    pub metrics: MetricsConfig,
+    pub rates: RatesConfig,
+    pub credentials: CredentialsConfig,
+    pub passes: PassesConfig,
+    pub moderation: ModerationConfig,
+    pub duplicates: DuplicatesConfig,
+    pub media: MediaConfig,
+    pub uploads: UploadsConfig,
+    pub questions: QuestionsConfig,
+    pub models: ModelsConfig,
+    pub seller_tiers: TiersConfig,
+    pub policy: PolicyConfig,
+    pub mirror: MirrorConfig,
+    pub storage: StorageConfig,
+    pub cache: CacheConfig,
+    pub compression: CompressionConfig,
+    /// Content filters SELL and UPDATE text goes through, in order.
+    pub filters: Vec<FilterConfig>,
+    pub hooks: HooksConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NymConfig {
+    /// Nym client storage; defaults to `<data_dir>/nym`. Keep it stable to keep the same nym address.
+    pub config_dir: Option<PathBuf>,
+    /// Network env file for non-mainnet deployments.
+    pub env_path: Option<String>,
+    pub transport: Transport,
+    /// Storage for further nym identities serving the same bazaar, each with its
+    /// own address. Clients can fall back to another address if one gateway
+    /// misbehaves, and addresses can be rotated by adding a directory and later
+    /// dropping an old one.
+    pub extra_config_dirs: Vec<PathBuf>,
+    /// Identity key of the gateway to register new identities with, instead of
+    /// a random one. Identities keep the gateway they first registered with.
+    pub gateway: Option<String>,
+    /// Send loop cover traffic, so an observer can't tell when the server is busy.
+    pub cover_traffic: bool,
+    /// Send packets at a steady Poisson rate rather than as soon as they're ready.
+    pub poisson_traffic: bool,
+    /// Average delay each mix node adds per packet; the SDK's default if unset.
+    /// Lower is faster and easier to correlate.
+    pub average_packet_delay_ms: Option<u64>,
+}
+
+impl Default for NymConfig {
+    fn default() -> Self {
+        Self {
+            config_dir: None,
+            env_path: None,
+            transport: Transport::default(),
+            extra_config_dirs: Vec::new(),
+            gateway: None,
+            cover_traffic: true,
+            poisson_traffic: true,
+            average_packet_delay_ms: None,
+        }
+    }
+}
+
+impl NymConfig {
+    /// Whether any setting is changed that only a direct client can apply.
+    pub fn tunes_client(&self) -> bool {
+        self.gateway.is_some() || !self.cover_traffic || !self.poisson_traffic || self.average_packet_delay_ms.is_some()
+    }
+}
+
+/// How requests reach the server from the mixnet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Through `NymProxyServer` to the TCP listener on `bind`. Works with every client.
+    #[default]
+    Proxy,
+    /// Straight from a `MixnetClient`, replying over SURBs. Needs clients run with `--direct`.
+    Direct,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Connections served at once; further connections are turned away.
+    pub max_connections: usize,
+    /// Connections that send no complete command for this long are closed.
+    pub idle_timeout_secs: u64,
+    /// Longest command line accepted, in bytes.
+    pub max_command_len: usize,
+    /// Clients that don't accept a response within this long are dropped.
+    pub write_timeout_secs: u64,
+    /// Most commands accepted in one `BATCH`.
+    pub max_batch_len: usize,
+    /// Sessions opened with `HELLO` can be resumed until unused for this long.
+    pub session_idle_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    /// Allow full-text SEARCH, which scans the whole catalog per request.
+    pub search: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `tracing` filter directive, e.g. `info` or `nymbazaar_server=debug,warn`.
+    pub level: String,
+    /// Emit one JSON object per line instead of human-readable text.
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Serve Prometheus metrics at `http://<bind>/metrics`.
+    pub enabled: bool,
+    pub bind: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateSource {
+    /// Currency conversion is disabled.
+    None,
+    /// Rates maintained by hand in `rates.file`.
+    File,
+    /// Rates fetched as JSON from `rates.url`.
+    Http,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RatesConfig {
+    pub source: RateSource,
+    pub file: Option<PathBuf>,
+    pub url: Option<String>,
+    /// Proxy for fetching rates, e.g. `socks5h://127.0.0.1:9050`.
+    pub proxy: Option<String>,
+    pub refresh_secs: u64,
+    /// Rates older than this are flagged as stale in responses.
+    pub stale_after_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifierKind {
+    /// No credential is required.
+    None,
+    /// Credentials are checked by running `credentials.command`.
+    Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialsConfig {
+    /// How anonymous credentials presented with SELL, BUNDLE and SELLER REGISTER are verified.
+    pub verifier: VerifierKind,
+    /// Verifier program; receives the credential on stdin and exits 0 if it is valid.
+    pub command: Option<PathBuf>,
+    pub args: Vec<String>,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PassesConfig {
+    /// Whether writes below the moderator role each spend a blind pass.
+    pub required: bool,
+    /// Leading zero bits a `PASS ISSUE` solution needs.
+    pub difficulty: u32,
+    /// Most passes signed per solved challenge.
+    pub batch: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModerationConfig {
+    /// `SELLER STRIKE`s that ban a seller automatically.
+    pub strikes_to_ban: u32,
+    /// How long an automatic ban lasts.
+    pub strike_ban_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateAction {
+    /// Listings are accepted without a check.
+    Off,
+    /// The listing is accepted and the response names the listing it resembles.
+    Warn,
+    /// The listing is refused with `duplicate_listing`.
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    /// What happens to a SELL that resembles one of the seller's own listings.
+    pub same_seller: DuplicateAction,
+    /// Whether a SELL resembling another seller's listing is flagged in the audit log.
+    pub flag_cross_seller: bool,
+    /// Simhash bits two listings may differ in and still count as duplicates.
+    pub max_distance: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub enabled: bool,
+    /// Largest image accepted by `PUTIMAGE`, before base64 encoding.
+    pub max_image_bytes: usize,
+    /// Most images attached to one listing.
+    pub max_per_listing: usize,
+    pub backend: MediaBackend,
+    /// HTTP RPC address of the IPFS node the ipfs backend pins to.
+    pub ipfs_api: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaBackend {
+    /// Images are kept in `<data_dir>/blobs` and served with `GETIMAGE`.
+    Local,
+    /// Images are pinned to an IPFS node and listings only carry their CIDs;
+    /// clients fetch them out-of-band.
+    Ipfs,
+}
+
+impl MediaConfig {
+    /// Longest request line a `PUTIMAGE` of the largest image makes.
+    pub fn max_upload_line(&self) -> usize {
+        self.max_image_bytes.div_ceil(3) * 4 + 128
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadsConfig {
+    pub enabled: bool,
+    /// Largest upload that can be declared with `UPLOAD BEGIN`.
+    pub max_bytes: u64,
+    /// Unfinished and unused uploads kept at once, across all clients.
+    pub max_open: usize,
+    /// Uploads are deleted this long after they were begun, used or not.
+    pub ttl_secs: u64,
+}
+
+impl UploadsConfig {
+    /// Longest request line an `UPLOAD CHUNK` makes.
+    pub fn max_chunk_line() -> usize {
+        bazaar_protocol::upload::CHUNK_SIZE.div_ceil(3) * 4 + 128
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuestionsConfig {
+    pub enabled: bool,
+    /// Unanswered questions a listing takes before `ASK` is refused.
+    pub max_pending_per_listing: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModelsConfig {
+    /// Reference table of gear listings can be linked to, in place of the
+    /// built-in one; read at startup and on reload.
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// Nym address of the bazaar to mirror. When set, the catalog is pulled from
+    /// it and write commands are refused.
+    pub upstream: Option<String>,
+    /// How often the catalog is pulled.
+    pub interval_secs: u64,
+    /// Key to AUTH with upstream, for bazaars that don't let anonymous clients EXPORT.
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Listings only live as long as the process.
+    Memory,
+    /// Embedded sled database (needs the storage-sled feature).
+    Sled,
+    /// Shared PostgreSQL database (needs the storage-postgres feature). Only
+    /// listings are shared; everything else stays under each server's `data_dir`.
+    Postgres,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Database location; defaults to a file or directory under `data_dir`.
+    pub path: Option<PathBuf>,
+    /// Connection string for the postgres backend, e.g. `postgres://bazaar@localhost/bazaar`.
+    pub url: Option<String>,
+    /// Size of the postgres connection pool.
+    pub max_connections: u32,
+    /// Reload the catalog from storage this often, for stores shared by several
+    /// servers. 0 disables it.
+    pub refresh_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// LIST/GET responses kept in memory; 0 disables the cache.
+    pub capacity: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompressionConfig {
+    /// Offer response compression to clients that ask for it with `HELLO`.
+    pub enabled: bool,
+    /// Responses smaller than this are always sent uncompressed.
+    pub threshold_bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// WebAssembly modules notified of listing changes (needs the wasm-hooks feature).
+    pub scripts: Vec<PathBuf>,
+    /// Instructions each script may run per event before it is stopped.
+    pub fuel: u64,
+    /// Linear memory each script instance may grow to.
+    pub max_memory_bytes: usize,
+    /// Scripts run at once; further events wait their turn.
+    pub max_running: usize,
+}
+
+/// One `[[filters]]` entry, selected by its `kind`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FilterConfig {
+    /// Reject listings containing any of `words`.
+    BannedWords { words: Vec<String> },
+    /// Reject names and descriptions over these many characters.
+    MaxLength {
+        #[serde(default = "default_max_name_len")]
+        name: usize,
+        #[serde(default = "default_max_description_len")]
+        description: usize,
+    },
+    /// Remove links from listing text.
+    StripUrls,
+}
+
+fn default_max_name_len() -> usize {
+    120
+}
+
+fn default_max_description_len() -> usize {
+    4000
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:0".to_string(),
+            allow_public_bind: false,
+            data_dir: PathBuf::from("bazaar-data"),
+            catalog: None,
+            admin_keys: Vec::new(),
+            shutdown_grace_secs: 10,
+            trash_retention_secs: 7 * 24 * 3600,
+            nym: NymConfig::default(),
+            limits: LimitsConfig::default(),
+            features: FeaturesConfig::default(),
+            log: LogConfig::default(),
+            metrics: MetricsConfig::default(),
+            rates: RatesConfig::default(),
+            credentials: CredentialsConfig::default(),
+            passes: PassesConfig::default(),
+            moderation: ModerationConfig::default(),
+            duplicates: DuplicatesConfig::default(),
+            media: MediaConfig::default(),
+            uploads: UploadsConfig::default(),
+            questions: QuestionsConfig::default(),
+            models: ModelsConfig::default(),
+            seller_tiers: TiersConfig::default(),
+            policy: PolicyConfig::default(),
+            mirror: MirrorConfig::default(),
+            storage: StorageConfig::default(),
+            cache: CacheConfig::default(),
+            compression: CompressionConfig::default(),
+            filters: Vec::new(),
+            hooks: HooksConfig::default(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 256,
+            idle_timeout_secs: 300,
+            max_command_len: 4096,
+            write_timeout_secs: 30,
+            max_batch_len: 32,
+            session_idle_secs: 1800,
+        }
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self { search: true }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            json: false,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: "127.0.0.1:9100".to_string(),
+        }
+    }
+}
+
+impl Default for RatesConfig {
+    fn default() -> Self {
+        Self {
+            source: RateSource::None,
+            file: None,
+            url: None,
+            proxy: None,
+            refresh_secs: 3600,
+            stale_after_secs: 6 * 3600,
+        }
+    }
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        Self {
+            verifier: VerifierKind::None,
+            command: None,
+            args: Vec::new(),
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl Default for PassesConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            difficulty: 22,
+            batch: 32,
+        }
+    }
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            strikes_to_ban: 3,
+            strike_ban_secs: 7 * 24 * 3600,
+        }
+    }
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        Self {
+            same_seller: DuplicateAction::Warn,
+            flag_cross_seller: true,
+            max_distance: 10,
+        }
+    }
+}
+
+impl Default for QuestionsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_pending_per_listing: 20,
+        }
+    }
+}
+
+impl Default for UploadsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_bytes: 4 * 1024 * 1024,
+            max_open: 64,
+            ttl_secs: 24 * 3600,
+        }
+    }
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_image_bytes: 512 * 1024,
+            max_per_listing: 8,
+            backend: MediaBackend::Local,
+            ipfs_api: "http://127.0.0.1:5001".to_string(),
+        }
+    }
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            upstream: None,
+            interval_secs: 300,
+            key: None,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::Memory,
+            path: None,
+            url: None,
+            max_connections: 8,
+            refresh_secs: 0,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { capacity: 1024 }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_bytes: bazaar_protocol::codec::COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            scripts: Vec::new(),
+            fuel: 10_000_000,
+            max_memory_bytes: 16 * 1024 * 1024,
+            max_running: 4,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `path`, or `bazaar.toml` if no path is given. Only an explicitly
+    /// requested file has to exist; otherwise defaults are used.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => (Path::new(DEFAULT_CONFIG_PATH), false),
+        };
+
+        if !required && !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn nym_config_dir(&self) -> PathBuf {
+        self.nym
+            .config_dir
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("nym"))
+    }
+
+    /// Storage for every nym identity the server runs, the primary one first.
+    pub fn nym_config_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(self.nym_config_dir())
+            .chain(self.nym.extra_config_dirs.iter().cloned())
+            .collect()
+    }
+
+    /// The role `key` grants, checking admin keys and then policy keys. Every key
+    /// is compared without short-circuiting on the first mismatching byte.
+    pub fn role_for_key(&self, key: &str) -> Option<Role> {
+        let admin = self.admin_keys.iter().fold(false, |found, admin_key| {
+            found | constant_time_eq(admin_key.as_bytes(), key.as_bytes())
+        });
+        let role = self.policy.keys.iter().fold(None, |found, role_key| {
+            if constant_time_eq(role_key.key.as_bytes(), key.as_bytes()) {
+                Some(role_key.role)
+            } else {
+                found
+            }
+        });
+        if admin {
+            Some(Role::Admin)
+        } else {
+            role
+        }
+    }
+
+    /// Subsystems to advertise in `STATUS` and `HELLO`, so clients can hide
+    /// what this server doesn't offer.
+    pub fn capabilities(&self) -> Vec<String> {
+        use bazaar_protocol::capability::*;
+        let media = self.media.enabled;
+        let local_media = media && self.media.backend == MediaBackend::Local;
+        [
+            (SEARCH, self.features.search),
+            (IMAGES, media),
+            (IPFS, media && self.media.backend == MediaBackend::Ipfs),
+            (THUMBNAILS, local_media && cfg!(feature = "thumbnails")),
+            (UPLOADS, media && self.uploads.enabled),
+            (QUESTIONS, self.questions.enabled),
+            (CURRENCY, self.rates.source != RateSource::None),
+            (PASSES, self.passes.required),
+            (CREDENTIALS, self.credentials.verifier != VerifierKind::None),
+            (COMPRESSION, self.compression.enabled),
+            (MIRROR, self.mirror.upstream.is_some()),
+            (SELLER_TIERS, self.seller_tiers.enabled),
+            (MODELS, true),
+            (BUNDLES, true),
+            (ATTESTATIONS, true),
+            (NEWS, true),
+            (SIGNING, true),
+            (AWAY, true),
+            (SAVED_SEARCHES, true),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect()
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let bind: SocketAddr = self
+            .bind
+            .parse()
+            .with_context(|| format!("bind must be an ip:port address, got '{}'", self.bind))?;
+        if !bind.ip().is_loopback() && !self.allow_public_bind {
+            bail!("bind address {} is not loopback; set allow_public_bind to expose the bazaar directly", bind);
+        }
+        if self.limits.max_connections == 0 {
+            bail!("limits.max_connections must be at least 1");
+        }
+        if self.limits.idle_timeout_secs == 0 || self.limits.write_timeout_secs == 0 || self.limits.session_idle_secs == 0 {
+            bail!("limits timeouts must be at least 1 second");
+        }
+        if self.passes.batch == 0 || self.passes.difficulty > 64 {
+            bail!("passes.batch must be at least 1 and passes.difficulty at most 64");
+        }
+        if self.moderation.strikes_to_ban == 0 {
+            bail!("moderation.strikes_to_ban must be at least 1");
+        }
+        if self.uploads.max_bytes == 0 || self.uploads.ttl_secs == 0 {
+            bail!("uploads.max_bytes and uploads.ttl_secs must be at least 1");
+        }
+        if self.rates.refresh_secs == 0 {
+            bail!("rates.refresh_secs must be at least 1");
+        }
+        if self.limits.max_command_len < 64 {
+            bail!("limits.max_command_len must be at least 64 bytes");
+        }
+        if self.admin_keys.iter().any(|k| k.len() < MIN_ADMIN_KEY_LEN) {
+            bail!("admin keys must be at least {} characters long", MIN_ADMIN_KEY_LEN);
+        }
+        if self.policy.keys.iter().any(|k| k.key.len() < MIN_ADMIN_KEY_LEN) {
+            bail!("policy keys must be at least {} characters long", MIN_ADMIN_KEY_LEN);
+        }
+        self.policy.validate()?;
+        self.seller_tiers.validate()?;
+        let mut dirs = self.nym_config_dirs();
+        dirs.sort();
+        dirs.dedup();
+        if dirs.len() != self.nym_config_dirs().len() {
+            bail!("nym.extra_config_dirs must all differ from each other and from nym.config_dir");
+        }
+        // The proxy server builds its mixnet client internally and only takes an env file
+        if self.nym.transport == Transport::Proxy && self.nym.tunes_client() {
+            bail!("nym.gateway, cover_traffic, poisson_traffic and average_packet_delay_ms need nym.transport = \"direct\"");
+        }
+        if self.hooks.max_memory_bytes == 0 || self.hooks.max_running == 0 {
+            bail!("hooks.max_memory_bytes and hooks.max_running must be at least 1");
+        }
+        if self.mirror.upstream.is_some() && self.mirror.interval_secs == 0 {
+            bail!("mirror.interval_secs must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
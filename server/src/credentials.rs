@@ -0,0 +1,116 @@
+use crate::config::{CredentialsConfig, VerifierKind};
+use crate::spent::SpentLog;
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bazaar_protocol::BazaarError;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Checks anonymous credentials (zk-nyms) presented with write commands.
+#[async_trait]
+pub trait CredentialVerifier: Send + Sync {
+    /// Whether `credential` is a valid, unspent credential.
+    async fn verify(&self, credential: &str) -> Result<bool>;
+}
+
+/// Hands each credential to an operator-supplied program on stdin, e.g. a wrapper
+/// around the Nym ecash verification tooling. Exit status 0 means valid.
+pub struct CommandVerifier {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl CredentialVerifier for CommandVerifier {
+    async fn verify(&self, credential: &str) -> Result<bool> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to run credential verifier {}", self.program.display()))?;
+
+        let mut stdin = child.stdin.take().context("Credential verifier has no stdin")?;
+        stdin.write_all(credential.as_bytes()).await?;
+        drop(stdin);
+
+        let status = tokio::time::timeout(self.timeout, child.wait())
+            .await
+            .context("Credential verifier timed out")??;
+        Ok(status.success())
+    }
+}
+
+/// Gates write commands on a valid credential when a verifier is configured.
+/// Accepted credentials are remembered in `<data_dir>/credentials_spent.log`
+/// so each one is only good for one action, across restarts too.
+pub struct CredentialGate {
+    verifier: Option<Box<dyn CredentialVerifier>>,
+    spent: SpentLog,
+}
+
+impl CredentialGate {
+    pub fn from_config(config: &CredentialsConfig, data_dir: &Path) -> Result<Self> {
+        let verifier: Option<Box<dyn CredentialVerifier>> = match config.verifier {
+            VerifierKind::None => None,
+            VerifierKind::Command => {
+                let Some(program) = config.command.clone() else {
+                    bail!("credentials.command is required for the command verifier");
+                };
+                Some(Box::new(CommandVerifier {
+                    program,
+                    args: config.args.clone(),
+                    timeout: Duration::from_secs(config.timeout_secs),
+                }))
+            },
+        };
+
+        Ok(Self {
+            verifier,
+            spent: SpentLog::open(&data_dir.join("credentials_spent.log"))?,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.verifier.is_some()
+    }
+
+    /// Succeeds if no verifier is configured, or if `credential` verifies and hasn't been used before.
+    pub async fn check(&self, credential: Option<&str>) -> Result<(), BazaarError> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(());
+        };
+        let credential = credential.ok_or(BazaarError::CredentialRequired)?;
+
+        let digest: [u8; 32] = Sha256::digest(credential.as_bytes()).into();
+        if self.spent.contains(&digest) {
+            return Err(BazaarError::InvalidCredential);
+        }
+
+        match verifier.verify(credential).await {
+            Ok(true) => {},
+            Ok(false) => return Err(BazaarError::InvalidCredential),
+            Err(e) => {
+                tracing::error!("Credential verification failed: {:#}", e);
+                return Err(BazaarError::Internal);
+            },
+        }
+
+        // Another connection may have spent the same credential while we were verifying it
+        match self.spent.spend(&[digest]).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BazaarError::InvalidCredential),
+            Err(e) => {
+                tracing::error!("Failed to record a spent credential: {:#}", e);
+                Err(BazaarError::Internal)
+            },
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::Acceptor;
+use crate::config::NymConfig;
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::codec::FrameDecoder;
+use nym_sdk::mixnet::{
+    AnonymousSenderTag, DebugConfig, MixnetClient, MixnetClientBuilder, MixnetClientSender, MixnetMessageSender,
+    StoragePaths,
+};
+use nym_sdk::NymNetworkDetails;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Bytes buffered between a session and its connection handler in each direction.
+const SESSION_BUFFER: usize = 64 * 1024;
+
+/// Requests queued for a session before further ones are dropped.
+const SESSION_QUEUE: usize = 32;
+
+/// Connects the server's mixnet client, keeping its keys in `config_dir` so the
+/// nym address stays the same across restarts.
+pub async fn connect(config_dir: &Path, nym: &NymConfig) -> Result<MixnetClient> {
+    std::fs::create_dir_all(config_dir)
+        .with_context(|| format!("Failed to create nym config directory {}", config_dir.display()))?;
+    let paths = StoragePaths::new_from_dir(config_dir).context("Invalid nym config directory")?;
+    let mut builder = MixnetClientBuilder::new_with_default_storage(paths)
+        .await?
+        .debug_config(debug_config(nym));
+    // main has loaded the env file by now
+    if nym.env_path.is_some() {
+        builder = builder.network_details(NymNetworkDetails::new_from_env());
+    }
+    if let Some(gateway) = &nym.gateway {
+        builder = builder.request_gateway(gateway.clone());
+    }
+    let client = builder
+        .build()?
+        .connect_to_mixnet()
+        .await
+        .context("Failed to connect to the mixnet")?;
+    Ok(client)
+}
+
+/// The SDK's traffic settings with the latency knobs from `[nym]` applied.
+fn debug_config(nym: &NymConfig) -> DebugConfig {
+    let mut debug = DebugConfig::default();
+    debug.cover_traffic.disable_loop_cover_traffic_stream = !nym.cover_traffic;
+    debug.traffic.disable_main_poisson_packet_distribution = !nym.poisson_traffic;
+    if let Some(delay) = nym.average_packet_delay_ms {
+        debug.traffic.average_packet_delay = Duration::from_millis(delay);
+    }
+    debug
+}
+
+/// Sets the `KEY=value` lines of a network env file as environment variables, the
+/// way the proxy server does, for [`NymNetworkDetails::new_from_env`] to read.
+/// Call it before starting the runtime: setting variables while other threads
+/// may read them is unsound.
+pub fn load_env(path: &Path) -> Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read nym env file {}", path.display()))?;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("Invalid line in nym env file {}: {}", path.display(), line);
+        };
+        std::env::set_var(key.trim(), value.trim().trim_matches('"'));
+    }
+    Ok(())
+}
+
+/// Serves requests arriving straight over the mixnet until shutdown, then hands
+/// the client back so it can be disconnected once open sessions are done.
+///
+/// Each anonymous sender gets a session that runs through the same connection
+/// handler as proxied connections, fed the bytes of every message the sender
+/// sends. Replies go back over the sender's SURBs, one whole response frame per
+/// message, so the client never has to reassemble frames from out-of-order
+/// pieces. Senders that include no SURBs can't be answered and are ignored.
+pub async fn serve(mut client: MixnetClient, acceptor: Arc<Acceptor>) -> MixnetClient {
+    let sender = client.split_sender();
+    let mut sessions: HashMap<AnonymousSenderTag, mpsc::Sender<Vec<u8>>> = HashMap::new();
+
+    loop {
+        let messages = tokio::select! {
+            messages = client.wait_for_messages() => match messages {
+                Some(messages) => messages,
+                None => {
+                    warn!("Mixnet client stopped delivering messages");
+                    break;
+                },
+            },
+            _ = acceptor.shutdown.cancelled() => break,
+        };
+
+        sessions.retain(|_, session| !session.is_closed());
+        for message in messages {
+            let Some(tag) = message.sender_tag else {
+                debug!("Ignoring a request sent without reply SURBs");
+                continue;
+            };
+            let session = sessions
+                .entry(tag)
+                .or_insert_with(|| open_session(tag, sender.clone(), &acceptor));
+            if session.try_send(message.message).is_err() {
+                debug!("Session is not keeping up, dropping request");
+            }
+        }
+    }
+    client
+}
+
+/// Starts a connection handler for `tag` and returns where to send its requests.
+/// The session ends when the handler closes the connection, e.g. when it idles out.
+fn open_session(tag: AnonymousSenderTag, sender: MixnetClientSender, acceptor: &Acceptor) -> mpsc::Sender<Vec<u8>> {
+    let (session, connection) = tokio::io::duplex(SESSION_BUFFER);
+    acceptor.accept(connection);
+    let (mut reader, mut writer) = tokio::io::split(session);
+    let (requests, mut incoming) = mpsc::channel::<Vec<u8>>(SESSION_QUEUE);
+    let (closed, mut on_close) = tokio::sync::oneshot::channel::<()>();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                request = incoming.recv() => match request {
+                    Some(request) if writer.write_all(&request).await.is_ok() => {},
+                    _ => break,
+                },
+                _ = &mut on_close => break,
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut decoder = FrameDecoder::new();
+        let mut buf = vec![0; SESSION_BUFFER];
+        loop {
+            match decoder.next_encoded_frame() {
+                Ok(Some(frame)) => {
+                    if let Err(e) = sender.send_reply(tag, frame).await {
+                        warn!("Failed to send a reply over the mixnet: {}", e);
+                    }
+                    continue;
+                },
+                Ok(None) => {},
+                Err(_) => break,
+            }
+            match reader.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => decoder.extend(&buf[..n]),
+            }
+        }
+        // Stops the request side too, which marks the session closed
+        let _ = closed.send(());
+    });
+
+    requests
+}
@@ -0,0 +1,90 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::BazaarError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Drops are deleted this long after they were last posted.
+pub const RETENTION_SECS: u64 = 30 * 24 * 3600;
+
+/// Drops kept per owner.
+pub const MAX_PER_OWNER: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeadDrop {
+    owner: String,
+    ciphertext: String,
+    posted_at: u64,
+}
+
+/// Encrypted delivery instructions, persisted in `<data_dir>/drops.json`. The
+/// server can't read them; only the owner may replace one.
+pub struct DropStore {
+    path: PathBuf,
+    drops: Mutex<BTreeMap<String, DeadDrop>>,
+}
+
+impl DropStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let drops = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read dead drops {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid dead drops {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            drops: Mutex::new(drops),
+        })
+    }
+
+    fn save(&self, drops: &BTreeMap<String, DeadDrop>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(drops)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace dead drops {}", self.path.display()))
+    }
+
+    /// Posts or replaces a drop. Returns when it was posted.
+    pub fn put(&self, owner: &str, id: &str, ciphertext: &str) -> Result<u64, BazaarError> {
+        let mut drops = self.drops.lock().unwrap_or_else(|e| e.into_inner());
+        let now = unix_now();
+        drops.retain(|_, drop| drop.posted_at + RETENTION_SECS > now);
+
+        match drops.get(id) {
+            Some(existing) if existing.owner != owner => return Err(BazaarError::Forbidden),
+            Some(_) => {},
+            None if drops.values().filter(|drop| drop.owner == owner).count() >= MAX_PER_OWNER => {
+                return Err(BazaarError::LimitReached);
+            },
+            None => {},
+        }
+        drops.insert(
+            id.to_string(),
+            DeadDrop {
+                owner: owner.to_string(),
+                ciphertext: ciphertext.to_string(),
+                posted_at: now,
+            },
+        );
+        self.save(&drops).map_err(|e| {
+            tracing::error!("Failed to update dead drops: {:#}", e);
+            BazaarError::Internal
+        })?;
+        Ok(now)
+    }
+
+    /// The ciphertext of a drop that hasn't expired, and when it was posted.
+    pub fn get(&self, id: &str) -> Option<(String, u64)> {
+        let drops = self.drops.lock().unwrap_or_else(|e| e.into_inner());
+        drops
+            .get(id)
+            .filter(|drop| drop.posted_at + RETENTION_SECS > unix_now())
+            .map(|drop| (drop.ciphertext.clone(), drop.posted_at))
+    }
+}
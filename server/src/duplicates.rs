@@ -0,0 +1,56 @@
+use bazaar_protocol::Item;
+use sha2::{Digest, Sha256};
+
+/// Words per shingle. Pairs keep some word order while a changed word in a short
+/// listing still only touches a couple of shingles.
+const SHINGLE_WORDS: usize = 2;
+
+/// A 64-bit simhash of a listing's name and description. Listings whose hashes
+/// differ in only a few bits have mostly the same text, ignoring case,
+/// punctuation and spacing.
+pub fn simhash(name: &str, description: &str) -> u64 {
+    let text = format!("{} {}", name, description).to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut weights = [0i32; 64];
+    for shingle in words.windows(SHINGLE_WORDS.min(words.len()).max(1)) {
+        let digest = Sha256::digest(shingle.join(" ").as_bytes());
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        let hash = u64::from_le_bytes(bytes);
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// Listings that look like copies of a new one, split by whether `seller` posted them.
+#[derive(Default)]
+pub struct Duplicates<'a> {
+    pub same_seller: Option<&'a Item>,
+    pub other_sellers: Vec<&'a Item>,
+}
+
+/// Looks through `items` for listings within `max_distance` bits of the new
+/// listing's simhash.
+pub fn find<'a>(items: impl Iterator<Item = &'a Item>, seller: &str, name: &str, description: &str, max_distance: u32) -> Duplicates<'a> {
+    let hash = simhash(name, description);
+    let mut found = Duplicates::default();
+    for item in items.filter(|item| (simhash(&item.name, &item.description) ^ hash).count_ones() <= max_distance) {
+        if item.seller == seller {
+            found.same_seller.get_or_insert(item);
+        } else {
+            found.other_sellers.push(item);
+        }
+    }
+    found
+}
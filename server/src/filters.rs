@@ -0,0 +1,102 @@
+use crate::config::FilterConfig;
+use bazaar_protocol::{BazaarError, ItemField};
+use std::collections::HashSet;
+
+/// Checks listing text from SELL and UPDATE against an operator's listing policy.
+pub trait ContentFilter: Send + Sync {
+    /// Returns `text` from `field`, possibly rewritten, or `ContentRejected`.
+    fn filter(&self, field: ItemField, text: String) -> Result<String, BazaarError>;
+}
+
+/// Rejects text containing any of a list of words, ignoring case. Only whole
+/// words match, so banning "ass" leaves "class" alone.
+pub struct BannedWords {
+    words: HashSet<String>,
+}
+
+impl BannedWords {
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl ContentFilter for BannedWords {
+    fn filter(&self, _field: ItemField, text: String) -> Result<String, BazaarError> {
+        let banned = text
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|word| self.words.contains(&word.to_lowercase()));
+        if banned {
+            return Err(BazaarError::ContentRejected);
+        }
+        Ok(text)
+    }
+}
+
+/// Rejects names and descriptions longer than a number of characters.
+pub struct MaxLength {
+    pub name: usize,
+    pub description: usize,
+}
+
+impl ContentFilter for MaxLength {
+    fn filter(&self, field: ItemField, text: String) -> Result<String, BazaarError> {
+        let max = match field {
+            ItemField::Name => self.name,
+            ItemField::Description => self.description,
+            _ => return Ok(text),
+        };
+        if text.chars().count() > max {
+            return Err(BazaarError::ContentRejected);
+        }
+        Ok(text)
+    }
+}
+
+/// Removes links, so listings can't send buyers off to clearnet sites.
+pub struct StripUrls;
+
+impl ContentFilter for StripUrls {
+    fn filter(&self, _field: ItemField, text: String) -> Result<String, BazaarError> {
+        let kept: Vec<&str> = text.split(' ').filter(|word| !is_url(word)).collect();
+        Ok(kept.join(" ").trim().to_string())
+    }
+}
+
+fn is_url(word: &str) -> bool {
+    let word = word.to_lowercase();
+    ["http://", "https://", "ftp://", "www."].iter().any(|prefix| word.starts_with(prefix))
+}
+
+/// The filters from `[[filters]]`, run in the order they are configured.
+pub struct FilterChain {
+    filters: Vec<Box<dyn ContentFilter>>,
+}
+
+impl FilterChain {
+    pub fn from_config(config: &[FilterConfig]) -> Self {
+        let filters = config
+            .iter()
+            .map(|filter| -> Box<dyn ContentFilter> {
+                match filter {
+                    FilterConfig::BannedWords { words } => Box::new(BannedWords::new(words)),
+                    FilterConfig::MaxLength { name, description } => Box::new(MaxLength {
+                        name: *name,
+                        description: *description,
+                    }),
+                    FilterConfig::StripUrls => Box::new(StripUrls),
+                }
+            })
+            .collect();
+        Self { filters }
+    }
+
+    /// Runs `text` through every filter. Prices and categories aren't free text and pass as-is.
+    pub fn apply(&self, field: ItemField, text: String) -> Result<String, BazaarError> {
+        if !matches!(field, ItemField::Name | ItemField::Description) {
+            return Ok(text);
+        }
+        self.filters.iter().try_fold(text, |text, filter| filter.filter(field, text))
+    }
+}
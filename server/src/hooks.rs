@@ -0,0 +1,180 @@
+use crate::catalog::unix_now;
+use crate::config::HooksConfig;
+use anyhow::Result;
+use bazaar_protocol::Item;
+use serde::Serialize;
+
+/// Something that happened on the marketplace, as handed to hook scripts.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// `listing_created`, `listing_updated`, `listing_deleted` or `listing_restored`.
+    pub event: &'static str,
+    pub id: String,
+    pub actor: String,
+    /// The listing after the change, for creations and updates.
+    pub item: Option<Item>,
+    pub at: u64,
+}
+
+impl Event {
+    pub fn new(event: &'static str, id: &str, actor: &str, item: Option<&Item>) -> Self {
+        Self {
+            event,
+            id: id.to_string(),
+            actor: actor.to_string(),
+            item: item.cloned(),
+            at: unix_now(),
+        }
+    }
+}
+
+/// Operator scripts notified of marketplace events, sandboxed in WebAssembly.
+///
+/// A script is a module exporting `memory`, `alloc(len: i32) -> i32` and
+/// `on_event(ptr: i32, len: i32)`. Each event is written into the script's memory
+/// as JSON and passed to `on_event`. Scripts can't touch the filesystem or network;
+/// the only import they get is `bazaar.log(ptr: i32, len: i32)`, which writes a
+/// line to the server log. Every call gets a fresh instance with a `hooks.fuel`
+/// instruction budget and at most `hooks.max_memory_bytes` of memory, and at
+/// most `hooks.max_running` run at once.
+pub struct Hooks {
+    #[cfg(feature = "wasm-hooks")]
+    runtime: Option<std::sync::Arc<wasm::Runtime>>,
+    #[cfg(feature = "wasm-hooks")]
+    running: std::sync::Arc<tokio::sync::Semaphore>,
+}
+
+impl Hooks {
+    pub fn from_config(config: &HooksConfig) -> Result<Self> {
+        #[cfg(feature = "wasm-hooks")]
+        {
+            let runtime = if config.scripts.is_empty() {
+                None
+            } else {
+                Some(std::sync::Arc::new(wasm::Runtime::load(config)?))
+            };
+            let running = std::sync::Arc::new(tokio::sync::Semaphore::new(config.max_running));
+            Ok(Self { runtime, running })
+        }
+        #[cfg(not(feature = "wasm-hooks"))]
+        {
+            if !config.scripts.is_empty() {
+                anyhow::bail!("hooks.scripts needs a server built with the wasm-hooks feature");
+            }
+            Ok(Self {})
+        }
+    }
+
+    /// Runs every script on `event` in the background. Script failures are logged
+    /// and never affect the command that caused the event.
+    pub fn emit(&self, event: Event) {
+        #[cfg(feature = "wasm-hooks")]
+        if let Some(runtime) = &self.runtime {
+            let (runtime, running) = (runtime.clone(), self.running.clone());
+            tokio::spawn(async move {
+                let Ok(_permit) = running.acquire_owned().await else {
+                    return;
+                };
+                let _ = tokio::task::spawn_blocking(move || runtime.run(&event)).await;
+            });
+        }
+        #[cfg(not(feature = "wasm-hooks"))]
+        let _ = event;
+    }
+}
+
+#[cfg(feature = "wasm-hooks")]
+mod wasm {
+    use super::Event;
+    use crate::config::HooksConfig;
+    use anyhow::{Context, Result};
+    use tracing::{info, warn};
+    use wasmtime::{Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+    /// Longest line a script can log; the rest is cut off.
+    const MAX_LOG_LINE: usize = 4096;
+
+    /// What a running script instance carries.
+    struct Instance {
+        /// The path the script was loaded from, for logging.
+        name: String,
+        limits: StoreLimits,
+    }
+
+    pub struct Runtime {
+        engine: Engine,
+        linker: Linker<Instance>,
+        /// Each script with the path it was loaded from, for logging.
+        scripts: Vec<(String, Module)>,
+        fuel: u64,
+        max_memory_bytes: usize,
+    }
+
+    impl Runtime {
+        pub fn load(config: &HooksConfig) -> Result<Self> {
+            let engine = Engine::new(Config::new().consume_fuel(true))?;
+            let mut linker = Linker::new(&engine);
+            linker.func_wrap("bazaar", "log", |mut caller: Caller<'_, Instance>, ptr: i32, len: i32| {
+                let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                    return;
+                };
+                let (Ok(start), Ok(len)) = (usize::try_from(ptr), usize::try_from(len)) else {
+                    return;
+                };
+                let end = start.saturating_add(len.min(MAX_LOG_LINE));
+                if let Some(line) = memory.data(&caller).get(start..end) {
+                    info!(script = %caller.data().name, "{}", String::from_utf8_lossy(line));
+                }
+            })?;
+
+            let scripts = config
+                .scripts
+                .iter()
+                .map(|path| {
+                    let module = Module::from_file(&engine, path)
+                        .with_context(|| format!("Failed to load hook script {}", path.display()))?;
+                    Ok((path.display().to_string(), module))
+                })
+                .collect::<Result<_>>()?;
+
+            Ok(Self {
+                engine,
+                linker,
+                scripts,
+                fuel: config.fuel,
+                max_memory_bytes: config.max_memory_bytes,
+            })
+        }
+
+        pub fn run(&self, event: &Event) {
+            let payload = match serde_json::to_vec(event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Failed to encode hook event: {}", e);
+                    return;
+                },
+            };
+            for (name, module) in &self.scripts {
+                if let Err(e) = self.call(name, module, &payload) {
+                    warn!(script = %name, event = event.event, "Hook script failed: {:#}", e);
+                }
+            }
+        }
+
+        fn call(&self, name: &str, module: &Module, payload: &[u8]) -> Result<()> {
+            let limits = StoreLimitsBuilder::new().memory_size(self.max_memory_bytes).instances(1).build();
+            let mut store = Store::new(&self.engine, Instance { name: name.to_string(), limits });
+            store.limiter(|instance| &mut instance.limits);
+            store.set_fuel(self.fuel)?;
+            let instance = self.linker.instantiate(&mut store, module)?;
+            let memory = instance.get_memory(&mut store, "memory").context("Script exports no memory")?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+            let on_event = instance.get_typed_func::<(i32, i32), ()>(&mut store, "on_event")?;
+
+            let len = i32::try_from(payload.len()).context("Event too large")?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as usize, payload)?;
+            on_event.call(&mut store, (ptr, len))
+        }
+    }
+}
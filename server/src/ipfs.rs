@@ -0,0 +1,121 @@
+use crate::catalog::unix_now;
+use anyhow::{bail, Context, Result};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Deserialize)]
+struct Added {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Pins listing images to an IPFS node through its HTTP RPC API, for the ipfs
+/// media backend. Listings carry the CIDs and clients fetch the images from
+/// IPFS themselves.
+///
+/// Pins made here are recorded in `<data_dir>/ipfs_pins.json` with when they
+/// were made, so [`IpfsPinner::collect`] only ever removes its own pins.
+pub struct IpfsPinner {
+    client: reqwest::Client,
+    api: reqwest::Url,
+    path: PathBuf,
+    pins: Mutex<BTreeMap<String, u64>>,
+}
+
+impl IpfsPinner {
+    pub fn open(api: &str, path: &Path) -> Result<Self> {
+        let api = reqwest::Url::parse(api).with_context(|| format!("Invalid media.ipfs_api '{}'", api))?;
+        let pins = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read IPFS pins {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid IPFS pins {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(60)).build()?,
+            api,
+            path: path.to_path_buf(),
+            pins: Mutex::new(pins),
+        })
+    }
+
+    fn save(&self, pins: &BTreeMap<String, u64>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(pins)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace IPFS pins {}", self.path.display()))
+    }
+
+    async fn call(&self, method: &str, query: &[(&str, &str)], form: Option<Form>) -> Result<reqwest::Response> {
+        let url = self.api.join(&format!("api/v0/{}", method))?;
+        // The RPC API only takes POST
+        let mut request = self.client.post(url).query(query);
+        if let Some(form) = form {
+            request = request.multipart(form);
+        }
+        let response = request.send().await.with_context(|| format!("IPFS {} failed", method))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            bail!("IPFS {} failed with {}: {}", method, status, response.text().await.unwrap_or_default().trim());
+        }
+        Ok(response)
+    }
+
+    /// Adds and pins `data`, returning its CID (version 1, so it is lowercase
+    /// and the same for the same bytes on any node).
+    pub async fn pin(&self, data: Vec<u8>) -> Result<String> {
+        let form = Form::new().part("file", Part::bytes(data));
+        let added: Added = self
+            .call("add", &[("pin", "true"), ("cid-version", "1"), ("quiet", "true")], Some(form))
+            .await?
+            .json()
+            .await
+            .context("Invalid response from IPFS add")?;
+
+        let mut pins = self.pins.lock().unwrap_or_else(|e| e.into_inner());
+        pins.insert(added.hash.clone(), unix_now());
+        self.save(&pins)?;
+        Ok(added.hash)
+    }
+
+    /// Unpins CIDs pinned more than `grace_secs` ago that no listing refers to
+    /// in `refs`. Pins the node fails to remove are kept for the next run.
+    /// Returns how many were removed.
+    pub async fn collect(&self, refs: &HashMap<String, u32>, grace_secs: u64) -> Result<usize> {
+        let cutoff = unix_now().saturating_sub(grace_secs);
+        let unused: Vec<String> = self
+            .pins
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(cid, pinned_at)| !refs.contains_key(*cid) && **pinned_at < cutoff)
+            .map(|(cid, _)| cid.clone())
+            .collect();
+
+        let mut removed = Vec::new();
+        for cid in unused {
+            match self.call("pin/rm", &[("arg", &cid)], None).await {
+                Ok(_) => removed.push(cid),
+                Err(e) if format!("{:#}", e).contains("not pinned") => removed.push(cid),
+                Err(e) => tracing::warn!("Failed to unpin {}: {:#}", cid, e),
+            }
+        }
+
+        let mut pins = self.pins.lock().unwrap_or_else(|e| e.into_inner());
+        for cid in &removed {
+            // Pinned again while the node was being called
+            if pins.get(cid).is_some_and(|pinned_at| *pinned_at < cutoff) {
+                pins.remove(cid);
+            }
+        }
+        self.save(&pins)?;
+        Ok(removed.len())
+    }
+}
@@ -1,230 +1,1442 @@
-use anyhow::Result;
+mod attestations;
+mod audit;
+mod away;
+mod blobs;
+mod cache;
+mod catalog;
+mod commands;
+mod config;
+mod credentials;
+mod direct;
+mod drops;
+mod duplicates;
+mod filters;
+mod hooks;
+mod ipfs;
+mod metrics;
+mod models;
+mod passes;
+mod mirror;
+mod news;
+mod policy;
+mod questions;
+mod rates;
+mod scheduler;
+mod saved_searches;
+mod sellers;
+mod sessions;
+mod search;
+mod spent;
+mod storage;
+mod thumbnails;
+mod tiers;
+mod tlog;
+mod tokens;
+mod uploads;
+
+use anyhow::{Context, Result};
+use bazaar_protocol::codec::{self, LineDecoder};
+use bazaar_protocol::tlog::{TlogActor, TlogEvent};
+use bazaar_protocol::{
+    media::ImageSize, BazaarError, Compression, Conversion, Encoding, Item, Lang, NewListing, TokenScope, Price, Response,
+};
+use clap::Parser;
+use attestations::AttestationStore;
+use audit::AuditLog;
+use away::AwayStore;
+use blobs::BlobStore;
+use cache::ResponseCache;
+use catalog::{Catalog, IdGenerator};
+use commands::CommandRegistry;
+use config::{Config, DuplicateAction, MediaBackend, Transport, UploadsConfig};
+use credentials::CredentialGate;
+use drops::DropStore;
+use hooks::Hooks;
+use ipfs::IpfsPinner;
+use metrics::Metrics;
+use news::NewsStore;
+use models::ModelTable;
+use passes::PassIssuer;
+use policy::Role;
+use questions::QuestionStore;
+use rates::RateService;
+use scheduler::{Every, Scheduler};
+use saved_searches::SavedSearchStore;
+use sellers::SellerRegistry;
+use sessions::SessionStore;
+use storage::Storage;
+use tlog::TransparencyLog;
+use tokens::{SellerSession, TokenStore};
+use uploads::UploadStore;
+use nym_sdk::mixnet::MixnetClient;
 use nym_sdk::tcp_proxy;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, RwLockWriteGuard, Semaphore};
 use tokio::signal;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser, Clone)]
+#[clap(name = "nymbazaar-server", about = "NymBazaar marketplace server on the NYM mixnet")]
+struct Args {
+    /// Config file path (defaults to ./bazaar.toml when present)
+    #[clap(long, env = "BAZAAR_CONFIG")]
+    config: Option<PathBuf>,
+    
+    /// Loopback address the bazaar listens on
+    #[clap(long, env = "BAZAAR_BIND")]
+    bind: Option<String>,
+    
+    /// Directory for server state
+    #[clap(long, env = "BAZAAR_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    
+    /// Nym client config directory (keeps the server's nym address stable)
+    #[clap(long, env = "BAZAAR_NYM_CONFIG_DIR")]
+    nym_config_dir: Option<PathBuf>,
+    
+    /// Nym network env file
+    #[clap(long, env = "BAZAAR_NYM_ENV")]
+    nym_env: Option<String>,
+    
+    /// Identity key of the gateway to register with (direct transport only)
+    #[clap(long, env = "BAZAAR_NYM_GATEWAY")]
+    nym_gateway: Option<String>,
+    
+    /// Take requests straight off the mixnet instead of through the TCP proxy
+    #[clap(long, env = "BAZAAR_DIRECT")]
+    direct: bool,
+    
+    /// Maximum number of concurrent connections
+    #[clap(long, env = "BAZAAR_MAX_CONNECTIONS")]
+    max_connections: Option<usize>,
+    
+    /// Additional admin keys (comma separated in the environment)
+    #[clap(long = "admin-key", env = "BAZAAR_ADMIN_KEYS", value_delimiter = ',')]
+    admin_keys: Vec<String>,
+    
+    /// Log filter, e.g. `debug` or `nymbazaar_server=trace,warn`
+    #[clap(long, env = "BAZAAR_LOG_LEVEL")]
+    log_level: Option<String>,
+    
+    /// Write logs as JSON lines
+    #[clap(long, env = "BAZAAR_LOG_JSON")]
+    log_json: bool,
+    
+    /// Serve Prometheus metrics on this address (enables the endpoint)
+    #[clap(long, env = "BAZAAR_METRICS_BIND")]
+    metrics_bind: Option<String>,
+    
+    /// Serve a read-only mirror of the bazaar at this nym address
+    #[clap(long, env = "BAZAAR_MIRROR")]
+    mirror: Option<String>,
+}
+
+impl Args {
+    /// Loads the config file and layers command line flags and environment variables over it.
+    fn load_config(&self) -> Result<Config> {
+        let mut config = Config::load(self.config.as_deref())?;
+        self.clone().apply(&mut config);
+        config.validate()?;
+        Ok(config)
+    }
+    
+    fn apply(self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+        if let Some(data_dir) = self.data_dir {
+            config.data_dir = data_dir;
+        }
+        if let Some(dir) = self.nym_config_dir {
+            config.nym.config_dir = Some(dir);
+        }
+        if let Some(env) = self.nym_env {
+            config.nym.env_path = Some(env);
+        }
+        if let Some(gateway) = self.nym_gateway {
+            config.nym.gateway = Some(gateway);
+        }
+        if self.direct {
+            config.nym.transport = Transport::Direct;
+        }
+        if let Some(max) = self.max_connections {
+            config.limits.max_connections = max;
+        }
+        config.admin_keys.extend(self.admin_keys);
+        if let Some(level) = self.log_level {
+            config.log.level = level;
+        }
+        if self.log_json {
+            config.log.json = true;
+        }
+        if let Some(bind) = self.metrics_bind {
+            config.metrics.enabled = true;
+            config.metrics.bind = bind;
+        }
+        if let Some(upstream) = self.mirror {
+            config.mirror.upstream = Some(upstream);
+        }
+    }
+}
+
+fn init_logging(config: &config::LogConfig) -> Result<()> {
+    let filter = EnvFilter::try_new(&config.level)
+        .with_context(|| format!("Invalid log level '{}'", config.level))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    
+    if config.json {
+        builder.json().init();
+    } else {
+        builder.init();
+    }
+    
+    Ok(())
+}
+
+/// Failed AUTH attempts tolerated on one connection before it is closed.
+const MAX_AUTH_FAILURES: u32 = 3;
+
+/// How often images no listing uses are looked for and deleted.
+const BLOB_COLLECT_INTERVAL: Duration = Duration::from_secs(3600);
 
-struct Item {
-    id: String,
-    name: String,
-    category: String,
-    description: String,
-    price: String,
-    seller: String,
+/// Unused images younger than this are kept, in case they are attached again.
+const BLOB_GRACE_SECS: u64 = 3600;
+
+/// How often the trash is checked for listings past their retention period.
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often scheduled listings are checked for publication. Drops go live within this of their time.
+const PUBLISH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often idle sessions are cleared out.
+const SESSION_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often uploads past `uploads.ttl_secs` are deleted.
+const UPLOAD_EXPIRY_INTERVAL: Duration = Duration::from_secs(600);
+
+/// What authenticated a connection, kept so a resumed session can be checked
+/// again rather than trusted.
+#[derive(Clone)]
+enum Credential {
+    /// An admin or `[policy]` key.
+    Key(String),
+    /// A seller API token, by id.
+    Token(String),
+}
+
+/// What a session opened with `HELLO` carries over to the connections that
+/// resume it.
+#[derive(Default, Clone)]
+struct SavedSession {
+    credential: Option<Credential>,
+    failed_auths: u32,
+    lang: Lang,
+    /// Writes paid for with `PASS REDEEM` and not made yet. Only kept here, so
+    /// every connection on the session draws on the one balance.
+    passes: u32,
+}
+
+/// Per-connection state carried between commands.
+#[derive(Default)]
+struct ConnectionState {
+    role: Role,
+    /// Audit actor this connection authenticated as.
+    actor: Option<String>,
+    /// Seller API token this connection authenticated with.
+    seller: Option<SellerSession>,
+    credential: Option<Credential>,
+    failed_auths: u32,
+    lang: Lang,
+    /// Response compression agreed with `HELLO`.
+    compression: Option<Compression>,
+    /// Response encoding chosen with `HELLO`.
+    encoding: Encoding,
+    /// Token of the session opened with `HELLO`, or by `PASS REDEEM` to hold
+    /// the passes.
+    session: Option<String>,
+}
+
+impl ConnectionState {
+    fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+    
+    /// Records what resuming this connection's session picks up. The pass
+    /// balance is left alone, it only changes in the session itself.
+    fn save(&self, saved: &mut SavedSession) {
+        saved.credential = self.credential.clone();
+        saved.failed_auths = self.failed_auths;
+        saved.lang = self.lang;
+    }
+    
+    /// Drops what the connection authenticated as, leaving it anonymous.
+    fn sign_out(&mut self) {
+        self.role = Role::Anonymous;
+        self.actor = None;
+        self.seller = None;
+        self.credential = None;
+    }
+    
+    /// How the transparency log describes changes made by this connection.
+    fn tlog_actor(&self) -> TlogActor {
+        if self.role >= Role::Moderator {
+            TlogActor::Operator
+        } else {
+            TlogActor::Seller
+        }
+    }
+    
+    /// The actor of a connection holding at least `role`, or `Forbidden`.
+    fn require_role(&self, role: Role) -> Result<&str, BazaarError> {
+        match &self.actor {
+            Some(actor) if self.role >= role => Ok(actor),
+            _ => Err(BazaarError::Forbidden),
+        }
+    }
+    
+    /// Who per-user data such as saved searches belongs to: the seller for token
+    /// sessions, so it survives token rotation, otherwise the authenticated actor.
+    fn identity(&self) -> Result<String, BazaarError> {
+        match (&self.seller, &self.actor) {
+            (Some(session), _) => Ok(format!("seller:{}", session.seller)),
+            (None, Some(actor)) => Ok(actor.clone()),
+            (None, None) => Err(BazaarError::Unauthorized),
+        }
+    }
+    
+    /// The actor allowed to act on `owner`'s listings within `scope`: an admin,
+    /// a moderator taking listings down or putting them back, or a token for that
+    /// seller carrying the scope.
+    fn authorize(&self, scope: TokenScope, owner: &str) -> Result<String, BazaarError> {
+        let moderating = self.role == Role::Moderator && scope == TokenScope::Delete;
+        if let (Some(actor), true) = (&self.actor, self.is_admin() || moderating) {
+            return Ok(actor.clone());
+        }
+        match &self.seller {
+            Some(session) if session.seller == owner && session.scopes.contains(&scope) => {
+                Ok(format!("seller:{}/{}", session.seller, session.token_id))
+            },
+            _ => Err(BazaarError::Forbidden),
+        }
+    }
+}
+
+/// The catalog a new store starts with: the catalog file, the sample items, or
+/// nothing at all for a mirror that hasn't synced yet.
+fn initial_catalog(config: &Config, ids: &IdGenerator) -> Result<Catalog> {
+    match (&config.mirror.upstream, &config.catalog) {
+        (Some(_), _) => Ok(Catalog::default()),
+        (None, Some(path)) => catalog::load(path, ids),
+        (None, None) => Ok(catalog::sample_items()),
+    }
 }
 
 struct BazaarServer {
-    items: Arc<RwLock<HashMap<String, Item>>>,
+    items: Arc<RwLock<Catalog>>,
+    cache: ResponseCache,
+    ids: IdGenerator,
+    storage: Box<dyn Storage>,
+    audit: AuditLog,
+    tlog: TransparencyLog,
+    tokens: TokenStore,
+    saved_searches: SavedSearchStore,
+    sellers: SellerRegistry,
+    drops: DropStore,
+    questions: QuestionStore,
+    away: AwayStore,
+    attestations: AttestationStore,
+    news: NewsStore,
+    blobs: BlobStore,
+    /// Set when images are pinned to IPFS instead of kept in `blobs`.
+    ipfs: Option<IpfsPinner>,
+    passes: PassIssuer,
+    uploads: UploadStore,
+    sessions: SessionStore<SavedSession>,
+    commands: CommandRegistry,
+    credentials: CredentialGate,
+    hooks: Hooks,
+    /// Reference table of gear, replaced on reload.
+    models: std::sync::RwLock<Arc<ModelTable>>,
+    config: std::sync::RwLock<Arc<Config>>,
+    args: Args,
+    metrics: Arc<Metrics>,
+    rates: Arc<RateService>,
+    /// Nym addresses the bazaar is reachable at, the primary one first.
+    addresses: Vec<String>,
+    started_at: Instant,
 }
 
 impl BazaarServer {
-    fn new() -> Self {
-        let mut items = HashMap::new();
-        
-        // Sample items
-        items.insert("1".to_string(), Item {
-            id: "1".to_string(),
-            name: "Nintendo NES".to_string(),
-            category: "gaming".to_string(),
-            description: "Original Nintendo Entertainment System from 1985. Good condition with controllers.".to_string(),
-            price: "$150".to_string(),
-            seller: "RetroGamer".to_string(),
-        });
+    async fn new(config: Config, args: Args, metrics: Arc<Metrics>, addresses: Vec<String>) -> Result<Self> {
+        let rates = Arc::new(RateService::from_config(&config.rates)?);
         
-        items.insert("2".to_string(), Item {
-            id: "2".to_string(),
-            name: "Yamaha DX7".to_string(),
-            category: "synthesizer".to_string(),
-            description: "Classic FM synthesizer from 1983. The quintessential 80s synth sound.".to_string(),
-            price: "$800".to_string(),
-            seller: "SynthWave".to_string(),
-        });
+        let ids = IdGenerator::new();
+        let storage = storage::open(&config.storage, &config.data_dir).await?;
+        let records = storage.load().await.context("Failed to load the catalog from storage")?;
+        // Only a new store is seeded; one emptied on purpose stays empty
+        let items = if records.is_empty() && !storage.is_initialized().await? {
+            let items = initial_catalog(&config, &ids)?;
+            storage.replace_all(&items.records()).await?;
+            match (&config.mirror.upstream, &config.catalog) {
+                (Some(_), _) => {},
+                (None, Some(path)) => info!("Storage seeded from catalog {}", path.display()),
+                (None, None) => info!("Storage seeded with sample items"),
+            }
+            items
+        } else {
+            Catalog::from_records(records)
+        };
+        storage.mark_initialized().await?;
         
-        // Add more items here...
+        metrics.items.set(items.len() as i64);
+        let audit = AuditLog::open(&config.data_dir.join("audit.log"))?;
+        let tlog = TransparencyLog::open(&config.data_dir.join("tlog.log"))?;
+        let tokens = TokenStore::open(&config.data_dir.join("tokens.json"))?;
+        let saved_searches = SavedSearchStore::open(&config.data_dir.join("saved_searches.json"))?;
+        let sellers = SellerRegistry::open(&config.data_dir.join("sellers.json"))?;
+        let drops = DropStore::open(&config.data_dir.join("drops.json"))?;
+        let questions = QuestionStore::open(&config.data_dir.join("questions.json"))?;
+        let away = AwayStore::open(&config.data_dir.join("away.json"))?;
+        let attestations = AttestationStore::open(&config.data_dir.join("attestations.json"))?;
+        let news = NewsStore::open(&config.data_dir)?;
+        let blobs = BlobStore::open(&config.data_dir.join("blobs"))?;
+        let ipfs = match config.media.backend {
+            MediaBackend::Local => None,
+            MediaBackend::Ipfs => Some(IpfsPinner::open(&config.media.ipfs_api, &config.data_dir.join("ipfs_pins.json"))?),
+        };
+        let passes = PassIssuer::open(&config.data_dir)?;
+        let uploads = UploadStore::open(&config.data_dir.join("uploads"))?;
+        let credentials = CredentialGate::from_config(&config.credentials, &config.data_dir)?;
+        let hooks = Hooks::from_config(&config.hooks)?;
+        let models = ModelTable::load(config.models.file.as_deref())?;
         
-        BazaarServer {
+        Ok(BazaarServer {
             items: Arc::new(RwLock::new(items)),
+            cache: ResponseCache::new(config.cache.capacity, metrics.clone()),
+            ids,
+            storage,
+            audit,
+            tlog,
+            tokens,
+            saved_searches,
+            sellers,
+            drops,
+            questions,
+            away,
+            attestations,
+            news,
+            blobs,
+            ipfs,
+            passes,
+            uploads,
+            sessions: SessionStore::default(),
+            commands: commands::registry()?,
+            credentials,
+            hooks,
+            models: std::sync::RwLock::new(Arc::new(models)),
+            config: std::sync::RwLock::new(Arc::new(config)),
+            args,
+            metrics,
+            rates,
+            addresses,
+            started_at: Instant::now(),
+        })
+    }
+    
+    /// Locks the catalog for changes. Every change goes through here so cached
+    /// responses are dropped before anything is modified.
+    async fn write_catalog(&self) -> RwLockWriteGuard<'_, Catalog> {
+        let items = self.items.write().await;
+        self.cache.clear();
+        items
+    }
+    
+    /// The active configuration. Connections snapshot it, so a reload applies to new commands.
+    fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    fn models(&self) -> Arc<ModelTable> {
+        self.models.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    
+    /// Re-reads the config file (and the catalog file, if asked and one is set)
+    /// without touching the mixnet identity. Without a catalog file the listings
+    /// in storage are left alone. A store shared with other servers is never
+    /// replaced by the file; asking for the catalog re-reads the store instead.
+    /// Listener, logging and connection-limit settings only take effect after a
+    /// restart.
+    async fn reload(&self, reload_catalog: bool) -> Result<usize> {
+        let config = self.args.load_config()?;
+        let models = ModelTable::load(config.models.file.as_deref())?;
+        let current = self.config();
+        
+        if config.bind != current.bind
+            || config.data_dir != current.data_dir
+            || config.nym_config_dir() != current.nym_config_dir()
+            || config.nym != current.nym
+            || config.limits.max_connections != current.limits.max_connections
+            || config.log.level != current.log.level
+            || config.metrics.bind != current.metrics.bind
+            || config.credentials != current.credentials
+            || config.mirror.upstream != current.mirror.upstream
+            || config.mirror.interval_secs != current.mirror.interval_secs
+            || config.storage != current.storage
+            || config.hooks != current.hooks
+            || config.media.backend != current.media.backend
+            || config.media.ipfs_api != current.media.ipfs_api
+        {
+            warn!("bind, data_dir, nym, max_connections, log, metrics, credentials, mirror, storage, hooks and media backend settings only change on restart");
+        }
+        
+        let mut items = self.write_catalog().await;
+        // A mirror's catalog only ever comes from upstream
+        let reload_catalog = reload_catalog && config.mirror.upstream.is_none();
+        let catalog_file = config.catalog.clone().filter(|_| reload_catalog && !self.storage.is_shared());
+        if let Some(path) = &catalog_file {
+            let catalog = catalog::load(path, &self.ids)?;
+            self.storage.replace_all(&catalog.records()).await?;
+            self.record_replacement(&items, &catalog);
+            *items = catalog;
+            self.metrics.items.set(items.len() as i64);
+        } else if reload_catalog && self.storage.is_shared() {
+            // Replacing the table would drop what the other servers listed. Their
+            // changes are in their own transparency logs, as on a refresh
+            *items = Catalog::from_records(self.storage.load().await.context("Failed to reload the catalog from storage")?);
+            self.metrics.items.set(items.len() as i64);
+        }
+        
+        *self.models.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(models);
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+        info!(catalog = catalog_file.is_some(), items = items.len(), "Configuration reloaded");
+        Ok(items.len())
+    }
+    
+    /// Logs how replacing the catalog wholesale changes the live listings.
+    fn record_replacement(&self, old: &Catalog, new: &Catalog) {
+        for item in old.values().filter(|item| new.get(&item.id).is_none()) {
+            self.tlog.record(TlogEvent::Removed, &item.id, TlogActor::Operator, None);
+        }
+        for item in new.values() {
+            match old.get(&item.id) {
+                None => self.tlog.record(TlogEvent::Added, &item.id, TlogActor::Operator, Some(item)),
+                Some(previous) if previous != item => {
+                    self.tlog.record(TlogEvent::Updated, &item.id, TlogActor::Operator, Some(item))
+                },
+                Some(_) => {},
+            }
+        }
+    }
+    
+    /// Drops trashed listings whose retention period is over.
+    async fn purge_trash(self: Arc<Self>) {
+        let cutoff = catalog::unix_now().saturating_sub(self.config().trash_retention_secs);
+        let purged = self.write_catalog().await.purge(cutoff);
+        for id in &purged {
+            match self.storage.remove(id).await {
+                Ok(()) => {},
+                // Restored or changed by another server; the next refresh brings it back
+                Err(e) if e.is::<storage::Conflict>() => info!(id = %id, "Purged item was changed in storage by another server"),
+                Err(e) => error!(id = %id, "Failed to remove purged item from storage: {:#}", e),
+            }
+            self.tlog.record(TlogEvent::Purged, id, TlogActor::Server, None);
+        }
+        if !purged.is_empty() {
+            info!(purged = purged.len(), "Purged deleted items");
+            self.audit.record("server", "PURGE", &purged.join(" "));
         }
     }
     
-    async fn handle_command(&self, command: &str) -> String {
-        let parts: Vec<&str> = command.trim().split_whitespace().collect();
+    /// Deletes an upload once it has been used. A leftover would only wait for expiry.
+    fn finish_upload(&self, upload: Option<String>) {
+        if let Some(upload) = upload {
+            let _ = self.uploads.remove(&upload);
+        }
+    }
+    
+    /// Makes the scaled-down versions of an image for `GETIMAGE --size`. Failing
+    /// to store them only costs bandwidth later, so errors are just logged.
+    async fn store_thumbnails(&self, hash: &str, data: Vec<u8>) {
+        let scaled = tokio::task::spawn_blocking(move || thumbnails::generate(&data)).await.unwrap_or_default();
+        for (size, data) in scaled {
+            if let Err(e) = self.blobs.put_scaled(hash, size, &data) {
+                warn!("Failed to store {} version of image {}: {:#}", size.name(), hash, e);
+            }
+        }
+    }
+    
+    /// An image at `size`. Scaled versions missing for images stored before the
+    /// server could make them are made on first request.
+    async fn read_image(&self, hash: &str, size: ImageSize) -> Result<Vec<u8>, BazaarError> {
+        let failed = |e: anyhow::Error| {
+            error!("Failed to read image: {:#}", e);
+            BazaarError::Internal
+        };
+        if size == ImageSize::Full {
+            return self.blobs.read(hash).map_err(failed)?.ok_or(BazaarError::MediaNotFound);
+        }
+        if let Some(scaled) = self.blobs.read_scaled(hash, size).map_err(failed)? {
+            return Ok(scaled);
+        }
+        let original = self.blobs.read(hash).map_err(failed)?.ok_or(BazaarError::MediaNotFound)?;
+        self.store_thumbnails(hash, original).await;
+        // Not an image, or no thumbnails in this build
+        self.blobs.read_scaled(hash, size).map_err(failed)?.ok_or(BazaarError::MediaNotFound)
+    }
+    
+    /// Deletes stored images no listing uses any more.
+    async fn collect_blobs(self: Arc<Self>) {
+        let refs = self.items.read().await.media_refs();
+        match self.blobs.collect(&refs, BLOB_GRACE_SECS) {
+            Ok(0) => {},
+            Ok(deleted) => info!(deleted, "Deleted unused images"),
+            Err(e) => error!("Failed to collect unused images: {:#}", e),
+        }
+        if let Some(ipfs) = &self.ipfs {
+            match ipfs.collect(&refs, BLOB_GRACE_SECS).await {
+                Ok(0) => {},
+                Ok(unpinned) => info!(unpinned, "Unpinned unused images from IPFS"),
+                Err(e) => error!("Failed to unpin unused images: {:#}", e),
+            }
+        }
+    }
+    
+    /// Puts scheduled listings live whose publication time has come.
+    async fn publish_scheduled(self: Arc<Self>) {
+        let now = catalog::unix_now();
+        // Only take the write lock, which empties the cache, when something is due
+        if self.items.read().await.next_publication().is_none_or(|at| at > now) {
+            return;
+        }
         
-        match parts.get(0).map(|s| s.to_uppercase()).as_deref() {
-            Some("HEAD") => "OK\n".to_string(),
-            
-            Some("LIST") => {
-                let category_filter = parts.get(1).map(|s| s.to_lowercase());
-                
-                let items = self.items.read().await;
-                let filtered_items: Vec<&Item> = items
-                    .values()
-                    .filter(|item| {
-                        if let Some(ref cat) = category_filter {
-                            item.category.to_lowercase() == *cat
-                        } else {
-                            true
-                        }
-                    })
-                    .collect();
-                
-                if filtered_items.is_empty() {
-                    return "No items found\n".to_string();
-                }
-                
-                let mut response = String::new();
-                for item in filtered_items {
-                    response.push_str(&format!("{}. {} - {}\n", item.id, item.name, item.price));
-                }
-                
-                response
+        let mut items = self.write_catalog().await;
+        let published = items.publish_due(now);
+        for id in &published {
+            // Keep going on failure, the listing is live in memory either way
+            let _ = self.persist(&mut items, id).await;
+            self.tlog.record(TlogEvent::Added, id, TlogActor::Server, items.get(id));
+            info!(id = %id, "Scheduled item published");
+        }
+        self.metrics.items.set(items.len() as i64);
+        self.audit.record("scheduler", "PUBLISH", &published.join(" "));
+    }
+    
+    /// Reloads the catalog from storage, so changes made by other servers sharing
+    /// the store show up here.
+    async fn refresh_from_storage(self: Arc<Self>) {
+        // Locked first, so no write lands between the load and the swap
+        let mut items = self.write_catalog().await;
+        match self.storage.load().await {
+            Ok(records) => {
+                *items = Catalog::from_records(records);
+                self.metrics.items.set(items.len() as i64);
+                debug!("Catalog refreshed from storage");
             },
-            
-            Some("GET") if parts.len() > 1 => {
-                let id = parts[1];
-                let items = self.items.read().await;
-                
-                if let Some(item) = items.get(id) {
-                    format!(
-                        "ID: {}\nName: {}\nCategory: {}\nPrice: {}\nSeller: {}\n\n{}\n",
-                        item.id, item.name, item.category, item.price, item.seller, item.description
-                    )
-                } else {
-                    format!("Item with ID {} not found\n", id)
-                }
+            Err(e) => warn!("Failed to refresh the catalog from storage: {:#}", e),
+        }
+    }
+    
+    /// The state of a connection resuming a saved session. The credential it
+    /// authenticated with is checked again, so a key dropped by a reload, a
+    /// revoked or expired token or a ban leaves the connection anonymous.
+    fn resume_session(&self, saved: SavedSession) -> ConnectionState {
+        let mut state = ConnectionState {
+            failed_auths: saved.failed_auths,
+            lang: saved.lang,
+            ..Default::default()
+        };
+        match saved.credential {
+            Some(Credential::Key(key)) => match self.config().role_for_key(&key) {
+                Some(role) => {
+                    state.role = role;
+                    state.actor = Some(format!("{}:{}", role.name(), audit::key_fingerprint(&key)));
+                    state.credential = Some(Credential::Key(key));
+                },
+                None => info!("Resumed session's key is no longer accepted"),
             },
-            
-            Some("SEARCH") if parts.len() > 1 => {
-                let term = parts[1].to_lowercase();
-                let items = self.items.read().await;
-                
-                let results: Vec<&Item> = items
-                    .values()
-                    .filter(|item| {
-                        item.name.to_lowercase().contains(&term) ||
-                        item.description.to_lowercase().contains(&term) ||
-                        item.category.to_lowercase().contains(&term)
-                    })
-                    .collect();
-                
-                if results.is_empty() {
-                    return "No items found matching your search\n".to_string();
-                }
-                
-                let mut response = String::new();
-                for item in results {
-                    response.push_str(&format!("{}. {} - {}\n", item.id, item.name, item.price));
-                }
-                
-                response
+            Some(Credential::Token(id)) => match self.tokens.session(&id) {
+                Some(session) if !self.sellers.is_banned(&session.seller) => {
+                    state.role = Role::Seller;
+                    state.actor = Some(format!("seller:{}/{}", session.seller, session.token_id));
+                    state.credential = Some(Credential::Token(id));
+                    state.seller = Some(session);
+                },
+                _ => info!(token = %id, "Resumed session's token is revoked, expired or its seller banned"),
             },
-            
-            Some("CATEGORIES") => {
-                let items = self.items.read().await;
-                let mut categories = HashSet::new();
-                
-                for item in items.values() {
-                    categories.insert(&item.category);
+            None => {},
+        }
+        state
+    }
+    
+    /// Starts a session for `state`, returning its token.
+    fn open_session(&self, state: &ConnectionState) -> String {
+        let mut saved = SavedSession::default();
+        state.save(&mut saved);
+        self.sessions.create(saved)
+    }
+    
+    /// Whether the connection's session is still open.
+    fn has_session(&self, state: &ConnectionState) -> bool {
+        state.session.as_ref().is_some_and(|token| self.sessions.update(token, |_| ()).is_some())
+    }
+    
+    /// Takes one pass from the connection's session, if it has any left.
+    fn spend_pass(&self, state: &ConnectionState) -> bool {
+        let Some(token) = &state.session else {
+            return false;
+        };
+        let spent = self.sessions.update(token, |saved| {
+            saved.passes = saved.passes.checked_sub(1)?;
+            Some(())
+        });
+        spent.flatten().is_some()
+    }
+    
+    /// Gives back a pass taken for a write that failed.
+    fn refund_pass(&self, state: &ConnectionState) {
+        if let Some(token) = &state.session {
+            self.sessions.update(token, |saved| saved.passes = saved.passes.saturating_add(1));
+        }
+    }
+    
+    /// Sets or clears a seller's verified badge for `SELLER VERIFY`/`UNVERIFY`.
+    async fn set_seller_verified(&self, state: &ConnectionState, name: &str, verified: bool) -> Result<Response, BazaarError> {
+        let actor = state.require_role(Role::Admin)?;
+        // Cached GET responses carry the old badge, so hold off readers until it changes
+        let _items = self.write_catalog().await;
+        let name = self.sellers.set_verified(name, verified)?;
+        self.audit.record(actor, if verified { "SELLER VERIFY" } else { "SELLER UNVERIFY" }, &name);
+        Ok(Response::Ok)
+    }
+    
+    /// Checks a new listing against the catalog per `[duplicates]`. Returns the
+    /// seller's own listing it resembles when that only warrants a warning;
+    /// resemblance to other sellers' listings goes to the audit log as `flag_as`,
+    /// if given.
+    fn check_duplicates(&self, items: &Catalog, flag_as: Option<&str>, listing: &NewListing) -> Result<Option<String>, BazaarError> {
+        let config = self.config().duplicates.clone();
+        if config.same_seller == DuplicateAction::Off && !config.flag_cross_seller {
+            return Ok(None);
+        }
+        
+        let (seller, name) = (&listing.seller, &listing.name);
+        let found = duplicates::find(items.values(), seller, name, &listing.description, config.max_distance);
+        if let (true, Some(actor)) = (config.flag_cross_seller, flag_as) {
+            for other in &found.other_sellers {
+                warn!(seller = %seller, other = %other.id, "New listing resembles another seller's listing");
+                self.audit.record(actor, "DUPLICATE", &format!("{} resembles {} by {}", name, other.id, other.seller));
+            }
+        }
+        match (config.same_seller, found.same_seller) {
+            (DuplicateAction::Reject, Some(own)) => {
+                info!(seller = %seller, duplicate_of = %own.id, "Rejecting duplicate listing");
+                Err(BazaarError::DuplicateListing)
+            },
+            (DuplicateAction::Warn, Some(own)) => Ok(Some(own.id.clone())),
+            _ => Ok(None),
+        }
+    }
+    
+    /// Admits a new listing: checks it for duplicates and against the seller's
+    /// tier, spends its credential, and locks the catalog for it to go in.
+    ///
+    /// The credential is verified without the lock, which a slow verifier would
+    /// otherwise hold for everyone, so the tier cap and the seller's own
+    /// duplicates are checked again once it is taken. Returns the locked catalog
+    /// and the seller's listing the new one resembles, when that only warrants
+    /// a warning.
+    async fn admit_listing(&self, state: &ConnectionState, actor: &str, listing: &NewListing) -> Result<(RwLockWriteGuard<'_, Catalog>, Option<String>), BazaarError> {
+        {
+            let items = self.items.read().await;
+            self.check_duplicates(&items, Some(actor), listing)?;
+            self.check_tier(state, &items, &listing.seller, Some(&listing.price), 1)?;
+        }
+        // Operators list without a credential
+        if !state.is_admin() {
+            self.credentials.check(listing.credential.as_deref()).await?;
+        }
+        let items = self.write_catalog().await;
+        let duplicate_of = self.check_duplicates(&items, None, listing)?;
+        self.check_tier(state, &items, &listing.seller, Some(&listing.price), 1)?;
+        Ok((items, duplicate_of))
+    }
+    
+    /// Checks that `seller` may add `adding` listings, or list at `price`, under
+    /// their tier. Operators aren't limited.
+    fn check_tier(&self, state: &ConnectionState, items: &Catalog, seller: &str, price: Option<&str>, adding: usize) -> Result<(), BazaarError> {
+        let config = self.config();
+        if !config.seller_tiers.enabled || state.is_admin() {
+            return Ok(());
+        }
+        let registration = self.sellers.get(seller).filter(|info| info.name == seller);
+        let tier = config.seller_tiers.tier_for(registration.as_ref()).ok_or(BazaarError::LimitReached)?;
+        if tier.max_active_listings.is_some_and(|max| items.active_listings(seller) + adding > max) {
+            info!(seller = %seller, tier = %tier.name, "Rejecting listing over the tier's listing cap");
+            return Err(BazaarError::LimitReached);
+        }
+        if price.is_some_and(|price| !tier.allows_price(price, self.rates.table().as_deref())) {
+            info!(seller = %seller, tier = %tier.name, "Rejecting price over the tier's cap");
+            return Err(BazaarError::LimitReached);
+        }
+        Ok(())
+    }
+    
+    /// Writes the current state of one listing (by ULID) through to storage. If
+    /// another server sharing the store changed it first, the listing is reread
+    /// from storage and the change fails as stale.
+    async fn persist(&self, items: &mut Catalog, id: &str) -> Result<(), BazaarError> {
+        let result = match items.record_of(id) {
+            Some(record) => self.storage.put(&record).await,
+            None => self.storage.remove(id).await,
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) if e.is::<storage::Conflict>() => {
+                info!(id = %id, "Listing changed in storage by another server, rereading it");
+                match self.storage.get(id).await {
+                    Ok(record) => items.set_record(id, record),
+                    Err(e) => error!(id = %id, "Failed to reread item from storage: {:#}", e),
                 }
-                
-                let mut response = String::from("Available categories:\n");
-                for category in categories {
-                    response.push_str(&format!("- {}\n", category));
+                Err(BazaarError::RevisionConflict)
+            },
+            Err(e) => {
+                error!(id = %id, "Failed to write item to storage: {:#}", e);
+                Err(BazaarError::Internal)
+            },
+        }
+    }
+    
+    /// An alias for a new listing from storage shared with other servers, or 0
+    /// to have the catalog number it.
+    async fn allocate_alias(&self) -> Result<u64, BazaarError> {
+        match self.storage.allocate_alias().await {
+            Ok(alias) => Ok(alias.unwrap_or(0)),
+            Err(e) => {
+                error!("Failed to allocate an alias from storage: {:#}", e);
+                Err(BazaarError::Internal)
+            },
+        }
+    }
+    
+    /// Reads listing prices in minor units of `currency`, converted at the current
+    /// rates, or of each listing's own currency when none is asked for. Prices
+    /// that don't parse or can't be converted read as `None`.
+    fn price_reader(&self, currency: Option<&str>) -> Result<impl Fn(&Item) -> Option<u64>, BazaarError> {
+        let target = match currency {
+            Some(code) => {
+                let table = self.rates.table().ok_or(BazaarError::RatesUnavailable)?;
+                if !table.rates.contains_key(code) {
+                    return Err(BazaarError::UnsupportedCurrency);
                 }
-                
-                response
+                Some((table, code.to_string()))
             },
-            
-            _ => "Invalid command. Available commands:\nHEAD\nLIST [category]\nGET <id>\nSEARCH <term>\nCATEGORIES\n".to_string(),
+            None => None,
+        };
+        
+        Ok(move |item: &Item| {
+            let price: Price = item.price.parse().ok()?;
+            match &target {
+                Some((table, code)) => Some(price.convert(code, table.rate(&price.currency, code)?).minor),
+                None => Some(price.minor),
+            }
+        })
+    }
+    
+    /// Wraps a listing response with prices converted into `currency`, if one was requested.
+    fn convert(&self, response: Response, currency: Option<String>) -> Result<Response, BazaarError> {
+        let Some(currency) = currency else {
+            return Ok(response);
+        };
+        
+        let table = self.rates.table().ok_or(BazaarError::RatesUnavailable)?;
+        if !table.rates.contains_key(&currency) {
+            return Err(BazaarError::UnsupportedCurrency);
+        }
+        
+        let items: Vec<&Item> = match &response {
+            Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => {
+                items.iter().collect()
+            },
+            Response::Item(item) => vec![&**item],
+            _ => Vec::new(),
+        };
+        let prices = items
+            .into_iter()
+            .filter_map(|item| {
+                let price: Price = item.price.parse().ok()?;
+                let rate = table.rate(&price.currency, &currency)?;
+                Some((item.id.clone(), price.convert(&currency, rate)))
+            })
+            .collect();
+        
+        let conversion = Conversion {
+            currency,
+            prices,
+            rates_age_secs: table.fetched_at.elapsed().unwrap_or_default().as_secs(),
+            stale: self.rates.is_stale(&table),
+        };
+        Ok(Response::Converted(Box::new(response), conversion))
+    }
+    
+    /// Marks the listings in a response whose sellers are `away`. Exports are
+    /// left alone, since mirrors store what they get.
+    fn mark_away(&self, response: &mut Response, away: &HashMap<String, Option<u64>>) {
+        if away.is_empty() {
+            return;
         }
+        let items = match response {
+            Response::Items(items) | Response::SearchResults(items) | Response::PriceFiltered { items, .. } => items.iter_mut().collect(),
+            Response::Item(item) => vec![&mut **item],
+            Response::Converted(response, _) => return self.mark_away(response, away),
+            _ => Vec::new(),
+        };
+        for item in items {
+            if let Some(until) = away.get(&item.seller.to_lowercase()) {
+                item.seller_away = true;
+                item.seller_back_at = *until;
+            }
+        }
+    }
+    
+    async fn handle_command(&self, state: &mut ConnectionState, line: &str) -> Response {
+        let started = Instant::now();
+        let keyword = line.split_whitespace().next().unwrap_or("");
+        let (name, mut response) = match self.commands.get(keyword) {
+            Some(handler) if !self.config().policy.allows(state.role, handler.name()) => {
+                (handler.name(), Response::Error(BazaarError::Forbidden))
+            },
+            Some(handler) => (handler.name(), handler.handle(self, state, line).await.unwrap_or_else(Response::Error)),
+            None => ("INVALID", Response::Error(BazaarError::InvalidCommand)),
+        };
+        
+        self.mark_away(&mut response, &self.away.current());
+        
+        let outcome = if matches!(response, Response::Error(_)) { "error" } else { "ok" };
+        self.metrics.commands.with_label_values(&[name, outcome]).inc();
+        self.metrics
+            .command_latency
+            .with_label_values(&[name])
+            .observe(started.elapsed().as_secs_f64());
+        
+        response
     }
+    
 }
 
-async fn handle_connection(mut socket: tokio::net::TcpStream, server: Arc<BazaarServer>) {
-    let mut buffer = vec![0u8; 4096];
+/// Writes a final error frame to a connection that is about to be closed.
+async fn send_error<S: AsyncWrite + Unpin>(
+    socket: &mut S,
+    error: BazaarError,
+    encoding: Encoding,
+    write_timeout: Duration,
+) -> Result<()> {
+    let frame = Response::Error(error).encode(encoding, Lang::En)?;
+    tokio::time::timeout(write_timeout, codec::write_frame(socket, &frame)).await??;
+    Ok(())
+}
+
+/// Runs one request line, logging it under a span named after the command.
+async fn run_command(server: &BazaarServer, state: &mut ConnectionState, request: &str) -> Response {
+    let name = request.split_whitespace().next().unwrap_or("").to_uppercase();
+    let span = info_span!("command", command = %name);
+    
+    async {
+        // Arguments can identify what a user is looking for, so they stay at debug level.
+        // AUTH arguments are secrets and are never logged.
+        if name == "AUTH" {
+            debug!("Received command");
+        } else {
+            debug!(request = %request, "Received command");
+        }
+        let response = server.handle_command(state, request).await;
+        if let Response::Error(e) = &response {
+            info!(code = e.code(), error = e.message(), "Command failed");
+        }
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+/// Runs the lines of a `BATCH` in order and gathers their replies into one response.
+async fn run_batch(server: &BazaarServer, state: &mut ConnectionState, lines: Vec<String>, max_len: usize) -> Response {
+    if !server.config().policy.allows(state.role, "BATCH") {
+        return Response::Error(BazaarError::Forbidden);
+    }
+    if lines.len() > max_len {
+        return Response::Error(BazaarError::BatchTooLarge);
+    }
+    
+    let mut responses = Vec::with_capacity(lines.len());
+    for line in lines {
+        let name = line.split_whitespace().next().unwrap_or("");
+        let response = if state.failed_auths >= MAX_AUTH_FAILURES {
+            // Don't let a batch carry more AUTH guesses than a connection gets
+            Response::Error(BazaarError::Unauthorized)
+        } else if name.eq_ignore_ascii_case("HELLO") {
+            // The batch reply is encoded after every command ran, so HELLO can't switch it midway
+            Response::Error(BazaarError::InvalidCommand)
+        } else {
+            run_command(server, state, &line).await
+        };
+        responses.push(response);
+    }
+    Response::Batch(responses)
+}
+
+/// Where requests come in from the mixnet, per `nym.transport`, with one proxy
+/// or client for each identity.
+enum Frontend {
+    Proxy {
+        listener: TcpListener,
+        proxies: Vec<(tokio::sync::mpsc::Sender<()>, tokio::task::JoinHandle<()>)>,
+    },
+    Direct(Vec<MixnetClient>),
+}
+
+/// Hands new connections to [`handle_connection`], turning them away once
+/// `limits.max_connections` are open.
+struct Acceptor {
+    server: Arc<BazaarServer>,
+    slots: Arc<Semaphore>,
+    next_id: AtomicU64,
+    connections: TaskTracker,
+    shutdown: CancellationToken,
+}
+
+impl Acceptor {
+    fn accept<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(&self, mut socket: S) {
+        let connection_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let span = info_span!("connection", id = connection_id);
+        
+        let Ok(slot) = self.slots.clone().try_acquire_owned() else {
+            span.in_scope(|| warn!("Connection limit reached, rejecting client"));
+            self.server.metrics.rejected_connections.inc();
+            // Don't let a slow client stall the accept loop while it's being turned away
+            let write_timeout = Duration::from_secs(self.server.config().limits.write_timeout_secs);
+            tokio::spawn(async move {
+                let _ = send_error(&mut socket, BazaarError::ServerBusy, Encoding::Text, write_timeout).await;
+            });
+            return;
+        };
+        let server = self.server.clone();
+        let shutdown = self.shutdown.clone();
+        self.connections.spawn(async move {
+            debug!("Connection opened");
+            server.metrics.active_connections.inc();
+            let metrics = server.metrics.clone();
+            handle_connection(socket, server, shutdown).await;
+            metrics.active_connections.dec();
+            drop(slot);
+        }.instrument(span));
+    }
+}
+
+/// Whether a request line carries upload data, and may be longer than other commands.
+fn is_upload(request: &str) -> bool {
+    let request = request.trim_start();
+    ["PUTIMAGE ", "UPLOAD CHUNK "]
+        .iter()
+        .any(|prefix| request.get(..prefix.len()).is_some_and(|command| command.eq_ignore_ascii_case(prefix)))
+}
+
+/// The upload id of a `BATCH --upload <upload-id>` request.
+fn batch_upload(request: &str) -> Option<String> {
+    match request.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [batch, flag, id] if batch.eq_ignore_ascii_case("BATCH") && flag.eq_ignore_ascii_case("--upload") => Some(id.to_lowercase()),
+        _ => None,
+    }
+}
+
+/// Runs an uploaded `BATCH`, one command per line of the upload.
+async fn run_uploaded_batch(server: &BazaarServer, state: &mut ConnectionState, id: &str, max_len: usize) -> Response {
+    if !server.config().uploads.enabled {
+        return Response::Error(BazaarError::FeatureDisabled);
+    }
+    let data = match server.uploads.read(id) {
+        Ok(data) => data,
+        Err(e) => return Response::Error(e),
+    };
+    let Ok(text) = String::from_utf8(data) else {
+        return Response::Error(BazaarError::InvalidCommand);
+    };
+    let max_command_len = server.config().limits.max_command_len;
+    let lines: Vec<String> = text.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect();
+    if lines.iter().any(|line| line.len() > max_command_len) {
+        return Response::Error(BazaarError::CommandTooLong);
+    }
+    let response = run_batch(server, state, lines, max_len).await;
+    if !matches!(response, Response::Error(_)) {
+        server.finish_upload(Some(id.to_string()));
+    }
+    response
+}
+
+/// Serves one client until it disconnects or the server shuts down. Shutdown is
+/// only observed between commands, so a command in progress always gets its
+/// response written out.
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut socket: S,
+    server: Arc<BazaarServer>,
+    shutdown: CancellationToken,
+) {
+    let limits = server.config().limits.clone();
+    // Image uploads and upload chunks are the only requests allowed past max_command_len
+    let max_upload_line = server.config().media.max_upload_line().max(UploadsConfig::max_chunk_line());
+    let mut decoder = LineDecoder::with_max_len(limits.max_command_len.max(max_upload_line));
+    let idle_timeout = Duration::from_secs(limits.idle_timeout_secs);
+    let write_timeout = Duration::from_secs(limits.write_timeout_secs);
+    let compression_threshold = server.config().compression.threshold_bytes;
+    let mut state = ConnectionState::default();
+    // Lines of a BATCH collected until its END
+    let mut batch: Option<Vec<String>> = None;
     
     loop {
-        match socket.read(&mut buffer).await {
-            Ok(0) => {
-                println!("Connection closed by client");
+        let request = tokio::select! {
+            request = tokio::time::timeout(idle_timeout, codec::read_line(&mut socket, &mut decoder)) => request,
+            _ = shutdown.cancelled() => {
+                debug!("Closing connection for shutdown");
+                break;
+            }
+        };
+        
+        let request = match request {
+            Ok(request) => request,
+            Err(_) => {
+                debug!("Closing idle connection");
+                let _ = send_error(&mut socket, BazaarError::IdleTimeout, state.encoding, write_timeout).await;
+                break;
+            }
+        };
+        
+        match request {
+            Ok(None) => {
+                debug!("Connection closed by client");
+                break;
+            },
+            Ok(Some(request)) if request.len() > limits.max_command_len && !is_upload(&request) => {
+                info!("Rejecting over-long command");
+                let _ = send_error(&mut socket, BazaarError::CommandTooLong, state.encoding, write_timeout).await;
                 break;
             },
-            Ok(n) => {
-                let request = String::from_utf8_lossy(&buffer[..n]);
-                println!("Command: {}", request.trim());
+            Ok(Some(request)) => {
+                let response = match batch.as_mut() {
+                    Some(lines) if !request.trim().eq_ignore_ascii_case("END") => {
+                        // Keep one line past the limit so the batch can be refused at END
+                        if lines.len() <= limits.max_batch_len {
+                            lines.push(request);
+                        }
+                        continue;
+                    },
+                    Some(_) => {
+                        let lines = batch.take().unwrap_or_default();
+                        run_batch(&server, &mut state, lines, limits.max_batch_len).await
+                    },
+                    None if request.trim().eq_ignore_ascii_case("BATCH") => {
+                        batch = Some(Vec::new());
+                        continue;
+                    },
+                    None => match batch_upload(&request) {
+                        Some(id) => run_uploaded_batch(&server, &mut state, &id, limits.max_batch_len).await,
+                        None => run_command(&server, &mut state, &request).await,
+                    },
+                };
+                if let Some(token) = &state.session {
+                    server.sessions.update(token, |saved| state.save(saved));
+                }
                 
-                let response = server.handle_command(&request).await;
+                let payload = response.encode(state.encoding, state.lang).unwrap_or_else(|e| {
+                    error!("Failed to encode response: {}", e);
+                    Response::Error(BazaarError::Internal).to_string().into_bytes()
+                });
+                let write = codec::write_frame_compressed(
+                    &mut socket,
+                    &payload,
+                    state.compression,
+                    compression_threshold,
+                );
+                match tokio::time::timeout(write_timeout, write).await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(e)) => {
+                        warn!("Write error: {}", e);
+                        break;
+                    },
+                    Err(_) => {
+                        warn!("Client stopped reading, dropping connection");
+                        break;
+                    }
+                }
                 
-                if let Err(e) = socket.write_all(response.as_bytes()).await {
-                    eprintln!("Write error: {}", e);
+                if state.failed_auths >= MAX_AUTH_FAILURES {
+                    warn!("Too many failed authentication attempts, closing connection");
                     break;
                 }
             },
+            // The line decoder only reports invalid data for over-long lines
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                info!("Rejecting over-long command");
+                let _ = send_error(&mut socket, BazaarError::CommandTooLong, state.encoding, write_timeout).await;
+                break;
+            },
             Err(e) => {
-                eprintln!("Read error: {}", e);
+                warn!("Read error: {}", e);
                 break;
             }
         }
     }
+    
+    if let Err(e) = socket.shutdown().await {
+        debug!("Error closing connection: {}", e);
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let config_dir = std::env::args().nth(1).expect("Config directory not provided");
-    let env_path = std::env::args().nth(2);
+/// Resolves on ctrl-c, or on SIGTERM so service managers get a clean shutdown too.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
     
-    let tcp_addr = "127.0.0.1:8000";
+    #[cfg(not(unix))]
+    signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let config = args.load_config()?;
+    // Sets environment variables, which is only safe before the runtime starts its threads
+    if let (Transport::Direct, Some(env_path)) = (config.nym.transport, &config.nym.env_path) {
+        direct::load_env(Path::new(env_path))?;
+    }
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the async runtime")?
+        .block_on(serve(args, config))
+}
+
+/// Runs the server until it is shut down.
+async fn serve(args: Args, config: Config) -> Result<()> {
+    init_logging(&config.log)?;
     
-    // Create NymProxyServer
-    let mut proxy_server = tcp_proxy::NymProxyServer::new(tcp_addr, &config_dir, env_path).await?;
-    let server_address = proxy_server.nym_address();
+    std::fs::create_dir_all(&config.data_dir)
+        .with_context(|| format!("Failed to create data directory {}", config.data_dir.display()))?;
+    let metrics = Arc::new(Metrics::new()?);
     
-    println!("NymBazaar server starting on NYM mixnet");
-    println!("Server address: {}", server_address);
+    // One mixnet identity per config dir, all serving the same bazaar
+    let mut addresses = Vec::new();
+    let frontend = match config.nym.transport {
+        Transport::Proxy => {
+            // Bind first so the proxies are pointed at the port actually in use
+            let listener = TcpListener::bind(&config.bind).await?;
+            let local_addr = listener.local_addr()?.to_string();
+            debug!(address = %local_addr, "Listening for proxied connections");
+            
+            let mut proxies = Vec::new();
+            for config_dir in config.nym_config_dirs() {
+                let config_dir = config_dir.to_string_lossy();
+                let mut proxy_server = tcp_proxy::NymProxyServer::new(&local_addr, &config_dir, config.nym.env_path.clone()).await?;
+                addresses.push(proxy_server.nym_address().to_string());
+                
+                // Run proxy server
+                let proxy_shutdown = proxy_server.disconnect_signal();
+                let proxy_metrics = metrics.clone();
+                let proxy_task = tokio::spawn(async move {
+                    if let Err(e) = proxy_server.run_with_shutdown().await {
+                        proxy_metrics.proxy_errors.inc();
+                        error!("Proxy error: {}", e);
+                    }
+                }.instrument(info_span!("proxy", identity = proxies.len())));
+                proxies.push((proxy_shutdown, proxy_task));
+            }
+            Frontend::Proxy { listener, proxies }
+        },
+        Transport::Direct => {
+            let mut clients = Vec::new();
+            for config_dir in config.nym_config_dirs() {
+                let client = direct::connect(&config_dir, &config.nym).await?;
+                addresses.push(client.nym_address().to_string());
+                clients.push(client);
+            }
+            Frontend::Direct(clients)
+        },
+    };
+    
+    info!("NymBazaar server starting on NYM mixnet");
+    for address in &addresses {
+        info!(address = %address, "Server address: {}", address);
+    }
+    
+    if config.metrics.enabled {
+        let bind = config.metrics.bind.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(&bind, metrics).await {
+                error!("Metrics endpoint error: {}", e);
+            }
+        });
+    }
     
-    // Run proxy server
-    let proxy_task = tokio::spawn(async move {
-        if let Err(e) = proxy_server.run_with_shutdown().await {
-            eprintln!("Proxy error: {}", e);
+    // Handle shutdown
+    let shutdown = CancellationToken::new();
+    let connections = TaskTracker::new();
+    let scheduler = Scheduler::new(shutdown.clone());
+    
+    // Create bazaar server
+    let bazaar_server = Arc::new(BazaarServer::new(config.clone(), args, metrics.clone(), addresses).await?);
+    if config.mirror.upstream.is_some() {
+        info!("Marketplace running as a read-only mirror");
+        scheduler.once("mirror-start", Duration::ZERO, mirror::start(bazaar_server.clone(), scheduler.clone()));
+    }
+    
+    let server = bazaar_server.clone();
+    scheduler.every("purge-trash", Every::new(TRASH_PURGE_INTERVAL), move || server.clone().purge_trash());
+    let server = bazaar_server.clone();
+    scheduler.every("collect-blobs", Every::new(BLOB_COLLECT_INTERVAL).skip_first(), move || server.clone().collect_blobs());
+    let server = bazaar_server.clone();
+    scheduler.every("publish-scheduled", Every::new(PUBLISH_CHECK_INTERVAL), move || server.clone().publish_scheduled());
+    
+    let server = bazaar_server.clone();
+    scheduler.every("expire-sessions", Every::new(SESSION_EXPIRY_INTERVAL), move || {
+        let idle = Duration::from_secs(server.config().limits.session_idle_secs);
+        let expired = server.sessions.expire(idle);
+        async move {
+            if expired > 0 {
+                debug!(expired, "Expired idle sessions");
+            }
         }
     });
     
-    // Create bazaar server
-    let bazaar_server = Arc::new(BazaarServer::new());
-    println!("Marketplace initialized with sample items");
+    let server = bazaar_server.clone();
+    scheduler.every("expire-uploads", Every::new(UPLOAD_EXPIRY_INTERVAL), move || {
+        let ttl = server.config().uploads.ttl_secs;
+        let expired = server.uploads.expire(ttl);
+        async move {
+            match expired {
+                Ok(0) => {},
+                Ok(expired) => debug!(expired, "Deleted expired uploads"),
+                Err(e) => error!("Failed to delete expired uploads: {:#}", e),
+            }
+        }
+    });
     
-    // Create TCP server
-    let listener = TcpListener::bind(tcp_addr).await?;
+    if config.storage.refresh_secs > 0 {
+        // The catalog was just loaded, so the first refresh can wait
+        let every = Every::new(Duration::from_secs(config.storage.refresh_secs)).skip_first();
+        let server = bazaar_server.clone();
+        scheduler.every("refresh-storage", every, move || server.clone().refresh_from_storage());
+    }
     
-    // Handle shutdown
-    let shutdown = Arc::new(tokio::sync::Notify::new());
-    let shutdown_clone = shutdown.clone();
+    if bazaar_server.credentials.is_enabled() {
+        info!("SELL, BUNDLE and SELLER REGISTER require an anonymous credential");
+    }
+    if config.passes.required {
+        info!("Writes require a blind pass (difficulty {})", config.passes.difficulty);
+    }
+    
+    if bazaar_server.rates.is_enabled() {
+        let rates = bazaar_server.rates.clone();
+        let every = Every::new(rates.refresh_every()).jitter(rates.refresh_every() / 10);
+        scheduler.every("refresh-rates", every, move || rates.clone().refresh());
+    }
+    
+    #[cfg(unix)]
+    {
+        let server = bazaar_server.clone();
+        tokio::spawn(async move {
+            let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+                .expect("Failed to listen for SIGHUP");
+            while hangup.recv().await.is_some() {
+                info!("SIGHUP received, reloading configuration and catalog file");
+                match server.reload(true).await {
+                    Ok(_) => {
+                        let catalog = server.config().catalog.is_some();
+                        server.audit.record("sighup", "RELOAD", if catalog { "config and catalog" } else { "config" });
+                    },
+                    Err(e) => error!("Reload failed, keeping the previous configuration: {:#}", e),
+                }
+            }
+        });
+    }
     
+    let signal_token = shutdown.clone();
     tokio::spawn(async move {
-        signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
-        shutdown_clone.notify_one();
+        shutdown_signal().await;
+        signal_token.cancel();
+    });
+    
+    let acceptor = Arc::new(Acceptor {
+        server: bazaar_server.clone(),
+        slots: Arc::new(Semaphore::new(config.limits.max_connections)),
+        next_id: AtomicU64::new(1),
+        connections: connections.clone(),
+        shutdown: shutdown.clone(),
     });
     
     // Accept connections
-    loop {
-        tokio::select! {
-            Ok((socket, _)) = listener.accept() => {
-                let server_ref = bazaar_server.clone();
-                tokio::spawn(async move {
-                    handle_connection(socket, server_ref).await;
-                });
-            },
-            _ = shutdown.notified() => {
-                println!("Server shutting down...");
-                break;
+    let frontend = match frontend {
+        Frontend::Proxy { listener, proxies } => {
+            loop {
+                tokio::select! {
+                    Ok((socket, _)) = listener.accept() => acceptor.accept(socket),
+                    _ = shutdown.cancelled() => break,
+                }
             }
-        }
+            Frontend::Proxy { listener, proxies }
+        },
+        Frontend::Direct(clients) => {
+            let serving: Vec<_> = clients
+                .into_iter()
+                .map(|client| tokio::spawn(direct::serve(client, acceptor.clone())))
+                .collect();
+            let mut clients = Vec::new();
+            for serve in serving {
+                clients.extend(serve.await.ok());
+            }
+            Frontend::Direct(clients)
+        },
+    };
+    info!("Server shutting down...");
+    
+    // Stop accepting, then give open connections time to finish their current command
+    connections.close();
+    let grace = Duration::from_secs(config.shutdown_grace_secs);
+    if tokio::time::timeout(grace, connections.wait()).await.is_err() {
+        warn!("{} connections still open after {:?}, closing anyway", connections.len(), grace);
+    }
+    scheduler.wait(grace).await;
+    
+    // Closes the mixnet client so the gateway sees a clean disconnect
+    match frontend {
+        Frontend::Proxy { listener, proxies } => {
+            drop(listener);
+            for (proxy_shutdown, proxy_task) in proxies {
+                if proxy_shutdown.send(()).await.is_ok() && tokio::time::timeout(grace, proxy_task).await.is_err() {
+                    warn!("Mixnet proxy did not stop within {:?}", grace);
+                }
+            }
+        },
+        Frontend::Direct(clients) => {
+            for client in clients {
+                client.disconnect().await;
+            }
+        },
     }
     
-    proxy_task.abort();
-    println!("Server shutdown complete");
+    info!("Server shutdown complete");
     Ok(())
 }
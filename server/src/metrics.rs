@@ -0,0 +1,114 @@
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info};
+
+/// Prometheus collectors for the bazaar. Always collected; only exported over
+/// HTTP when `[metrics] enabled = true`.
+pub struct Metrics {
+    registry: Registry,
+    pub commands: IntCounterVec,
+    pub command_latency: HistogramVec,
+    pub active_connections: IntGauge,
+    pub rejected_connections: IntCounter,
+    pub items: IntGauge,
+    pub proxy_errors: IntCounter,
+    pub cache_hits: IntCounter,
+    pub cache_misses: IntCounter,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("nymbazaar".to_string()), None)?;
+
+        let commands = IntCounterVec::new(
+            Opts::new("commands_total", "Commands handled, by command and outcome"),
+            &["command", "outcome"],
+        )?;
+        let command_latency = HistogramVec::new(
+            HistogramOpts::new("command_duration_seconds", "Time spent handling a command")
+                .buckets(vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0]),
+            &["command"],
+        )?;
+        let active_connections = IntGauge::new("active_connections", "Open client connections")?;
+        let rejected_connections = IntCounter::new(
+            "rejected_connections_total",
+            "Connections turned away because of limits",
+        )?;
+        let items = IntGauge::new("items", "Listings in the catalog")?;
+        let proxy_errors = IntCounter::new("proxy_errors_total", "Errors reported by the mixnet proxy")?;
+        let cache_hits = IntCounter::new("cache_hits_total", "LIST/GET responses served from the cache")?;
+        let cache_misses = IntCounter::new("cache_misses_total", "LIST/GET responses built from the catalog")?;
+
+        registry.register(Box::new(commands.clone()))?;
+        registry.register(Box::new(command_latency.clone()))?;
+        registry.register(Box::new(active_connections.clone()))?;
+        registry.register(Box::new(rejected_connections.clone()))?;
+        registry.register(Box::new(items.clone()))?;
+        registry.register(Box::new(proxy_errors.clone()))?;
+        registry.register(Box::new(cache_hits.clone()))?;
+        registry.register(Box::new(cache_misses.clone()))?;
+
+        Ok(Self {
+            registry,
+            commands,
+            command_latency,
+            active_connections,
+            rejected_connections,
+            items,
+            proxy_errors,
+            cache_hits,
+            cache_misses,
+        })
+    }
+
+    /// Renders all collectors in the Prometheus text exposition format.
+    pub fn render(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Serves `GET /metrics` on `bind`. Intended for a loopback address scraped by a
+/// local Prometheus; anything else gets a 404.
+pub async fn serve(bind: &str, metrics: Arc<Metrics>) -> Result<()> {
+    let listener = TcpListener::bind(bind).await?;
+    info!("Metrics available at http://{}/metrics", bind);
+
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 1024];
+            let n = match socket.read(&mut buffer).await {
+                Ok(n) => n,
+                Err(e) => {
+                    debug!("Metrics read error: {}", e);
+                    return;
+                }
+            };
+
+            let request = String::from_utf8_lossy(&buffer[..n]);
+            let response = match (request.starts_with("GET /metrics "), metrics.render()) {
+                (true, Ok(body)) => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                (true, Err(_)) => "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                (false, _) => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            };
+
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                debug!("Metrics write error: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,152 @@
+use crate::catalog::Catalog;
+use crate::scheduler::{Every, Scheduler};
+use crate::BazaarServer;
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::codec::{self, FrameDecoder};
+use bazaar_protocol::error::parse_error;
+use bazaar_protocol::{Command, Compression, Encoding, Item, Response};
+use nym_sdk::{mixnet::Recipient, tcp_proxy::NymProxyClient};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, error, info, warn};
+
+/// How long the proxy client waits on the mixnet before giving up on a request.
+const PROXY_TIMEOUT_SECS: u64 = 60;
+
+/// Sends one command and returns the response payload, failing on `ERR` replies.
+async fn request(stream: &mut TcpStream, decoder: &mut FrameDecoder, command: &Command) -> Result<Vec<u8>> {
+    codec::write_request(stream, command).await?;
+    let Some(frame) = codec::read_frame(stream, decoder).await? else {
+        bail!("Upstream closed the connection");
+    };
+    if let Some((code, message)) = parse_error(&String::from_utf8_lossy(&frame)) {
+        bail!("Upstream refused {}: {} {}", command.name(), code, message);
+    }
+    Ok(frame)
+}
+
+/// Pulls the full catalog from the upstream bazaar through the local proxy client,
+/// along with the catalog root upstream publishes in `STATUS` if it has one.
+async fn pull(port: u16, key: Option<&str>) -> Result<(Vec<Item>, Option<String>)> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))
+        .await
+        .context("Failed to reach the mirror proxy")?;
+    let mut decoder = FrameDecoder::new();
+
+    request(&mut stream, &mut decoder, &Command::Head).await?;
+    if let Some(key) = key {
+        request(&mut stream, &mut decoder, &Command::Auth { key: key.to_string() }).await?;
+    }
+
+    // Exports are large, so ask for compact binary frames; older upstreams refuse
+    // HELLO and send the JSON text export instead
+    let hello = Command::Hello {
+        compress: Compression::supported().to_vec(),
+        encoding: Some(Encoding::Cbor),
+        session: None,
+    };
+    let encoding = match request(&mut stream, &mut decoder, &hello).await {
+        Ok(_) => Encoding::Cbor,
+        Err(e) => {
+            debug!("Upstream declined HELLO: {:#}", e);
+            Encoding::Text
+        },
+    };
+
+    let export = request(&mut stream, &mut decoder, &Command::Export { range: None }).await?;
+    if encoding == Encoding::Text {
+        let items = serde_json::from_slice(&export).context("Upstream sent an invalid export")?;
+        return Ok((items, None));
+    }
+    let items = match Response::decode(&export, encoding).context("Upstream sent an invalid export")? {
+        Response::Export(items) => items,
+        Response::Error(e) => bail!("Upstream refused EXPORT: {}", e),
+        _ => bail!("Upstream sent an unexpected reply to EXPORT"),
+    };
+
+    let status = request(&mut stream, &mut decoder, &Command::Status).await?;
+    let root = match Response::decode(&status, encoding) {
+        Ok(Response::Status(status)) if !status.catalog_root.is_empty() => Some(status.catalog_root),
+        _ => None,
+    };
+    Ok((items, root))
+}
+
+/// Starts the proxy client to the configured upstream and schedules catalog syncs
+/// for the life of the server.
+pub async fn start(server: Arc<BazaarServer>, scheduler: Scheduler) {
+    let config = server.config();
+    let Some(upstream) = config.mirror.upstream.clone() else {
+        return;
+    };
+
+    let recipient = match Recipient::try_from_base58_string(&upstream) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            error!("Invalid mirror upstream address: {}", e);
+            return;
+        }
+    };
+
+    // Let the OS pick a loopback port for the proxy client to listen on
+    let port = match TcpListener::bind("127.0.0.1:0").await.and_then(|l| l.local_addr()) {
+        Ok(addr) => addr.port(),
+        Err(e) => {
+            error!("Failed to pick a port for the mirror proxy: {}", e);
+            return;
+        }
+    };
+
+    let proxy = match NymProxyClient::new(
+        recipient,
+        "127.0.0.1",
+        &port.to_string(),
+        PROXY_TIMEOUT_SECS,
+        config.nym.env_path.clone(),
+        1,
+    )
+    .await
+    {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            error!("Failed to start the mirror proxy: {}", e);
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        if let Err(e) = proxy.run().await {
+            error!("Mirror proxy error: {}", e);
+        }
+    });
+
+    info!(upstream = %upstream, "Mirroring upstream bazaar");
+    let interval = Duration::from_secs(config.mirror.interval_secs);
+    // Upstream sees the mirror's requests arrive, so don't make them clockwork
+    let every = Every::new(interval).jitter(interval / 10);
+    scheduler.every("mirror-sync", every, move || sync(server.clone(), port));
+}
+
+/// Replaces the catalog with upstream's. A failed pull keeps the previous catalog.
+async fn sync(server: Arc<BazaarServer>, port: u16) {
+    let key = server.config().mirror.key.clone();
+    match pull(port, key.as_deref()).await {
+        Ok((items, upstream_root)) => {
+            let count = items.len();
+            let catalog = Catalog::mirrored(items);
+            // Upstream can't keep its export and its published root consistent while
+            // showing different catalogs to different users
+            if upstream_root.is_some_and(|root| root != catalog.merkle_root()) {
+                warn!("Upstream's export doesn't match the catalog root it publishes; it may be serving different catalogs to different users");
+            }
+            let mut current = server.write_catalog().await;
+            if let Err(e) = server.storage.replace_all(&catalog.records()).await {
+                warn!("Failed to store the mirrored catalog: {:#}", e);
+            }
+            *current = catalog;
+            server.metrics.items.set(count as i64);
+            info!(items = count, "Catalog synced from upstream");
+        },
+        Err(e) => warn!("Mirror sync failed, serving the previous catalog: {:#}", e),
+    }
+}
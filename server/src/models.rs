@@ -0,0 +1,81 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::Model;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Reference table used when `models.file` isn't set.
+const BUILTIN: &str = include_str!("../models.toml");
+
+/// Most models `MODELS` answers with.
+pub const MAX_RESULTS: usize = 50;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelFile {
+    #[serde(default)]
+    model: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelEntry {
+    brand: String,
+    model: String,
+    years: Option<String>,
+}
+
+/// The curated table of known gear (see `bazaar_protocol::model`), kept in
+/// a TOML file of `[[model]]` entries:
+///
+/// ```toml
+/// [[model]]
+/// brand = "Roland"
+/// model = "TR-808"
+/// years = "1980-1983"
+/// ```
+pub struct ModelTable {
+    models: BTreeMap<String, Model>,
+}
+
+impl ModelTable {
+    /// Reads `path`, or the built-in table without one.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read models file {}", path.display()))?;
+                Self::parse(&contents).with_context(|| format!("Invalid models file {}", path.display()))
+            },
+            None => Self::parse(BUILTIN).context("Invalid built-in models table"),
+        }
+    }
+
+    fn parse(contents: &str) -> Result<Self> {
+        let file: ModelFile = toml::from_str(contents)?;
+        let mut models = BTreeMap::new();
+        for entry in file.model {
+            let model = Model::new(&entry.brand, &entry.model, entry.years);
+            if model.id.is_empty() {
+                bail!("Model '{} {}' has no letters or digits", entry.brand, entry.model);
+            }
+            if let Some(existing) = models.insert(model.id.clone(), model) {
+                bail!("Model {} is listed twice", existing.id);
+            }
+        }
+        Ok(Self { models })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Model> {
+        self.models.get(&id.to_lowercase())
+    }
+
+    /// Models matching `query` (see [`Model::matches`]), by brand and model
+    /// name, at most [`MAX_RESULTS`].
+    pub fn search(&self, query: &str) -> Vec<Model> {
+        let mut found: Vec<Model> = self.models.values().filter(|model| model.matches(query)).cloned().collect();
+        found.sort_by_cached_key(|model| (model.brand.to_lowercase(), model.model.to_lowercase()));
+        found.truncate(MAX_RESULTS);
+        found
+    }
+}
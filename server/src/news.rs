@@ -0,0 +1,104 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::{signing, Announcement, BazaarError};
+use ed25519_dalek::{Signer, SigningKey};
+use rand::RngCore;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Announcements kept; older ones are dropped as new ones are published.
+const MAX_KEPT: usize = 50;
+
+/// Announcements `NEWS` answers with.
+const SHOWN: usize = 10;
+
+/// Operator announcements for `NEWS`, signed with the bazaar's announcement
+/// key so they can be checked wherever they are relayed.
+///
+/// The key lives in `<data_dir>/announce.key` and the announcements in
+/// `<data_dir>/news.json`. Deleting the key makes clients that pinned the old
+/// one reject every later announcement.
+pub struct NewsStore {
+    key: SigningKey,
+    path: PathBuf,
+    announcements: Mutex<Vec<Announcement>>,
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Failed to publish announcement: {:#}", e);
+    BazaarError::Internal
+}
+
+impl NewsStore {
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let key_path = data_dir.join("announce.key");
+        let key = if key_path.exists() {
+            let contents = std::fs::read_to_string(&key_path)
+                .with_context(|| format!("Failed to read announcement key {}", key_path.display()))?;
+            let mut bytes = [0; 32];
+            hex::decode_to_slice(contents.trim(), &mut bytes)
+                .with_context(|| format!("Invalid announcement key {}", key_path.display()))?;
+            SigningKey::from_bytes(&bytes)
+        } else {
+            let mut bytes = [0; 32];
+            rand::rngs::OsRng.fill_bytes(&mut bytes);
+            std::fs::write(&key_path, hex::encode(bytes))
+                .with_context(|| format!("Failed to write announcement key {}", key_path.display()))?;
+            SigningKey::from_bytes(&bytes)
+        };
+
+        let path = data_dir.join("news.json");
+        let announcements = if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read announcements {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid announcements {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            key,
+            path,
+            announcements: Mutex::new(announcements),
+        })
+    }
+
+    fn save(&self, announcements: &[Announcement]) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(announcements)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace announcements {}", self.path.display()))
+    }
+
+    /// The hex ed25519 key announcements are signed with.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Signs and publishes `text`, returning its sequence number.
+    pub fn announce(&self, text: &str) -> Result<u64, BazaarError> {
+        let mut announcements = self.announcements.lock().unwrap_or_else(|e| e.into_inner());
+        let seq = announcements.last().map_or(1, |last| last.seq + 1);
+        let published_at = unix_now();
+        let signature = self.key.sign(&signing::announcement_payload(seq, published_at, text));
+        announcements.push(Announcement {
+            seq,
+            published_at,
+            text: text.to_string(),
+            signature: hex::encode(signature.to_bytes()),
+        });
+        if announcements.len() > MAX_KEPT {
+            let excess = announcements.len() - MAX_KEPT;
+            announcements.drain(..excess);
+        }
+        self.save(&announcements).map_err(failed)?;
+        Ok(seq)
+    }
+
+    /// The latest announcements, newest first.
+    pub fn recent(&self) -> Vec<Announcement> {
+        let announcements = self.announcements.lock().unwrap_or_else(|e| e.into_inner());
+        announcements.iter().rev().take(SHOWN).cloned().collect()
+    }
+}
@@ -0,0 +1,177 @@
+use crate::spent::SpentLog;
+use anyhow::{Context, Result};
+use bazaar_protocol::pass::{check_solution, Pass, PassKey};
+use bazaar_protocol::BazaarError;
+use rand::RngCore;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a client has to solve a challenge.
+const CHALLENGE_TTL: Duration = Duration::from_secs(600);
+
+/// Challenges handed out and not yet solved, across all connections.
+const MAX_OPEN_CHALLENGES: usize = 10_000;
+
+/// Issues and redeems blind write passes (see `bazaar_protocol::pass`).
+///
+/// The signing key lives in `<data_dir>/pass.key` and spent passes in
+/// `<data_dir>/passes_spent.log`, so passes stay good, and stay spent, across
+/// restarts. Deleting the key invalidates every pass issued so far.
+pub struct PassIssuer {
+    key: PassKey,
+    challenges: Mutex<HashMap<String, Instant>>,
+    spent: SpentLog,
+}
+
+impl PassIssuer {
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let key_path = data_dir.join("pass.key");
+        let key = if key_path.exists() {
+            let contents = std::fs::read_to_string(&key_path)
+                .with_context(|| format!("Failed to read pass key {}", key_path.display()))?;
+            let mut bytes = [0; 32];
+            hex::decode_to_slice(contents.trim(), &mut bytes)
+                .ok()
+                .and_then(|_| PassKey::from_bytes(bytes))
+                .with_context(|| format!("Invalid pass key {}", key_path.display()))?
+        } else {
+            let mut bytes = [0; 64];
+            rand::rngs::OsRng.fill_bytes(&mut bytes);
+            let key = PassKey::from_random(&bytes);
+            std::fs::write(&key_path, hex::encode(key.to_bytes()))
+                .with_context(|| format!("Failed to write pass key {}", key_path.display()))?;
+            key
+        };
+
+        Ok(Self {
+            key,
+            challenges: Mutex::new(HashMap::new()),
+            spent: SpentLog::open(&data_dir.join("passes_spent.log"))?,
+        })
+    }
+
+    /// The public key passes are signed with, in hex.
+    pub fn public_key(&self) -> String {
+        self.key.public_key()
+    }
+
+    /// Hands out a new challenge for `PASS ISSUE`.
+    pub fn challenge(&self) -> Result<String, BazaarError> {
+        let mut challenges = self.challenges.lock().unwrap_or_else(|e| e.into_inner());
+        challenges.retain(|_, issued| issued.elapsed() < CHALLENGE_TTL);
+        if challenges.len() >= MAX_OPEN_CHALLENGES {
+            return Err(BazaarError::ServerBusy);
+        }
+
+        let mut challenge = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut challenge);
+        let challenge = hex::encode(challenge);
+        challenges.insert(challenge.clone(), Instant::now());
+        Ok(challenge)
+    }
+
+    /// Signs `blinded` passes if `solution` solves an open challenge. Each
+    /// challenge is good for one batch, solved or not.
+    pub fn issue(&self, challenge: &str, solution: u64, difficulty: u32, blinded: &[String]) -> Result<Vec<String>, BazaarError> {
+        let open = self
+            .challenges
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(challenge)
+            .is_some_and(|issued| issued.elapsed() < CHALLENGE_TTL);
+        if !open || !check_solution(challenge, difficulty, solution) {
+            return Err(BazaarError::InvalidPass);
+        }
+        blinded.iter().map(|blinded| self.key.sign_blinded(blinded)).collect()
+    }
+
+    /// Spends `passes`, all or none. Fails if any pass is forged, already spent
+    /// or given twice.
+    pub async fn redeem(&self, passes: &[Pass]) -> Result<(), BazaarError> {
+        if !passes.iter().all(|pass| self.key.verify(pass)) {
+            return Err(BazaarError::InvalidPass);
+        }
+        let ids: Vec<[u8; 32]> = passes.iter().map(Pass::spend_id).collect();
+        match self.spent.spend(&ids).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BazaarError::InvalidPass),
+            Err(e) => {
+                tracing::error!("Failed to record spent passes: {:#}", e);
+                Err(BazaarError::Internal)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bazaar_protocol::pass::{solve, BlindedPass};
+    use std::path::PathBuf;
+
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nymbazaar-passes-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn issue(issuer: &PassIssuer, seeds: &[u8]) -> Vec<Pass> {
+        let challenge = issuer.challenge().unwrap();
+        let requests: Vec<BlindedPass> = seeds.iter().map(|seed| BlindedPass::new([*seed; 32], &[*seed; 64])).collect();
+        let blinded: Vec<String> = requests.iter().map(BlindedPass::blinded).collect();
+        let signed = issuer.issue(&challenge, solve(&challenge, 4), 4, &blinded).unwrap();
+        requests
+            .iter()
+            .zip(&signed)
+            .map(|(request, signed)| request.finish(signed, &issuer.public_key()).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn passes_are_spent_once() {
+        let dir = data_dir("once");
+        let issuer = PassIssuer::open(&dir).unwrap();
+        let passes = issue(&issuer, &[1, 2, 3]);
+
+        issuer.redeem(&passes[..1]).await.unwrap();
+        assert_eq!(issuer.redeem(&passes[..1]).await, Err(BazaarError::InvalidPass));
+        // Given twice in one batch
+        assert_eq!(issuer.redeem(&[passes[1].clone(), passes[1].clone()]).await, Err(BazaarError::InvalidPass));
+        // All or none: the unspent pass in a rejected batch stays good
+        assert_eq!(issuer.redeem(&passes[..2]).await, Err(BazaarError::InvalidPass));
+        issuer.redeem(&passes[1..]).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn passes_stay_spent_across_restarts() {
+        let dir = data_dir("restart");
+        let passes = {
+            let issuer = PassIssuer::open(&dir).unwrap();
+            let passes = issue(&issuer, &[1, 2]);
+            issuer.redeem(&passes[..1]).await.unwrap();
+            passes
+        };
+
+        let issuer = PassIssuer::open(&dir).unwrap();
+        assert_eq!(issuer.redeem(&passes[..1]).await, Err(BazaarError::InvalidPass));
+        issuer.redeem(&passes[1..]).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn challenges_are_good_for_one_batch() {
+        let dir = data_dir("challenge");
+        let issuer = PassIssuer::open(&dir).unwrap();
+        let challenge = issuer.challenge().unwrap();
+        let blinded = [BlindedPass::new([1; 32], &[1; 64]).blinded()];
+        let solution = solve(&challenge, 4);
+
+        issuer.issue(&challenge, solution, 4, &blinded).unwrap();
+        assert_eq!(issuer.issue(&challenge, solution, 4, &blinded), Err(BazaarError::InvalidPass));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
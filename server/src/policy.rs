@@ -0,0 +1,118 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// Who a connection acts as. Roles are ordered: each one may also run every
+/// command allowed to the roles below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Connections that haven't authenticated.
+    #[default]
+    Anonymous,
+    /// Holders of a buyer invite key.
+    Buyer,
+    /// Connections authenticated with a seller API token.
+    Seller,
+    /// Holders of a moderator key; may delete and restore any listing.
+    Moderator,
+    /// Holders of an admin key.
+    Admin,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Anonymous => "anonymous",
+            Role::Buyer => "buyer",
+            Role::Seller => "seller",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+/// A key that grants a role other than admin, e.g. a buyer invite.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleKey {
+    pub key: String,
+    pub role: Role,
+}
+
+/// Commands each role may run, by wire name (`"LIST"`, `"TOKEN"`, ...). `"*"`
+/// allows everything. Commands still check ownership and scopes on top of this.
+///
+/// ```toml
+/// [policy]
+/// anonymous = ["HEAD", "HELLO", "AUTH"]         # invite-only: nothing visible before AUTH
+/// buyer = ["HELP", "LIST", "GET", "SEARCH", "CATEGORIES", "HISTORY", "LANG", "STATUS"]
+/// seller = []                           # read-only: nobody but admins can list
+///
+/// [[policy.keys]]
+/// key = "invite-for-alice-0123"
+/// role = "buyer"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub anonymous: Vec<String>,
+    pub buyer: Vec<String>,
+    pub seller: Vec<String>,
+    pub moderator: Vec<String>,
+    pub admin: Vec<String>,
+    pub keys: Vec<RoleKey>,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        Self {
+            anonymous: names(&[
+                "HEAD", "HELP", "LIST", "GET", "SEARCH", "CATEGORIES", "MODELS", "STATUS", "AUTH", "LANG", "HISTORY",
+                "EXPORT", "HELLO", "BATCH", "NEW", "FEATURED", "RANDOM", "SELLER", "DROP", "SIGN", "PROOF", "TLOG", "PASS", "GETIMAGE",
+                "ASK", "ATTEST", "AUTHENTICATOR", "NEWS",
+            ]),
+            buyer: names(&["SAVEDSEARCH"]),
+            seller: names(&["SELL", "UPDATE", "DELETE", "RESTORE", "PUTIMAGE", "DELIMAGE", "UPLOAD", "ANSWER", "QUESTIONS", "AWAY", "BUNDLE", "TOKEN"]),
+            moderator: names(&["AUDIT"]),
+            admin: names(&["*"]),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl PolicyConfig {
+    fn commands(&self, role: Role) -> &[String] {
+        match role {
+            Role::Anonymous => &self.anonymous,
+            Role::Buyer => &self.buyer,
+            Role::Seller => &self.seller,
+            Role::Moderator => &self.moderator,
+            Role::Admin => &self.admin,
+        }
+    }
+
+    /// Whether `role` may run the command named `command`.
+    pub fn allows(&self, role: Role, command: &str) -> bool {
+        [Role::Anonymous, Role::Buyer, Role::Seller, Role::Moderator, Role::Admin]
+            .into_iter()
+            .filter(|r| *r <= role)
+            .flat_map(|r| self.commands(r))
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(command))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let commands = crate::commands::registry()?;
+        let known: Vec<&str> = commands.names().collect();
+        let lists = [&self.anonymous, &self.buyer, &self.seller, &self.moderator, &self.admin];
+        for name in lists.into_iter().flatten() {
+            if name != "*" && !known.iter().any(|k| k.eq_ignore_ascii_case(name)) {
+                bail!("policy names unknown command '{}'", name);
+            }
+        }
+        if self.keys.iter().any(|k| k.role == Role::Seller) {
+            bail!("policy.keys can't grant the seller role; sellers authenticate with API tokens");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::{BazaarError, Question};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredQuestion {
+    /// ULID of the listing asked about.
+    item: String,
+    question: String,
+    asked_at: u64,
+    #[serde(default)]
+    answer: Option<String>,
+    #[serde(default)]
+    answered_at: Option<u64>,
+}
+
+impl StoredQuestion {
+    fn public(&self, id: &str) -> Question {
+        Question {
+            id: id.to_string(),
+            question: self.question.clone(),
+            asked_at: self.asked_at,
+            answer: self.answer.clone(),
+            answered_at: self.answered_at,
+        }
+    }
+}
+
+/// Questions about listings and their sellers' answers, persisted in
+/// `<data_dir>/questions.json`. Askers aren't recorded; only answered
+/// questions are shown with a listing.
+pub struct QuestionStore {
+    path: PathBuf,
+    questions: Mutex<BTreeMap<String, StoredQuestion>>,
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Failed to update questions: {:#}", e);
+    BazaarError::Internal
+}
+
+impl QuestionStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let questions = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read questions {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid questions {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            questions: Mutex::new(questions),
+        })
+    }
+
+    fn save(&self, questions: &BTreeMap<String, StoredQuestion>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(questions)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace questions {}", self.path.display()))
+    }
+
+    /// Records a question about listing `item`, returning its id. A listing
+    /// takes at most `max_pending` unanswered questions at a time.
+    pub fn ask(&self, item: &str, question: &str, max_pending: usize) -> Result<String, BazaarError> {
+        let mut questions = self.questions.lock().unwrap_or_else(|e| e.into_inner());
+        let pending = questions
+            .values()
+            .filter(|stored| stored.item == item && stored.answer.is_none())
+            .count();
+        if pending >= max_pending {
+            return Err(BazaarError::LimitReached);
+        }
+
+        let mut id = [0u8; 8];
+        rand::rngs::OsRng.fill_bytes(&mut id);
+        let id = hex::encode(id);
+        questions.insert(
+            id.clone(),
+            StoredQuestion {
+                item: item.to_string(),
+                question: question.to_string(),
+                asked_at: unix_now(),
+                answer: None,
+                answered_at: None,
+            },
+        );
+        self.save(&questions).map_err(failed)?;
+        Ok(id)
+    }
+
+    /// The listing a question is about.
+    pub fn item_of(&self, id: &str) -> Option<String> {
+        let questions = self.questions.lock().unwrap_or_else(|e| e.into_inner());
+        questions.get(id).map(|stored| stored.item.clone())
+    }
+
+    /// Answers a question, or replaces its answer.
+    pub fn answer(&self, id: &str, answer: &str) -> Result<(), BazaarError> {
+        let mut questions = self.questions.lock().unwrap_or_else(|e| e.into_inner());
+        let stored = questions.get_mut(id).ok_or(BazaarError::QuestionNotFound)?;
+        stored.answer = Some(answer.to_string());
+        stored.answered_at = Some(unix_now());
+        self.save(&questions).map_err(failed)
+    }
+
+    /// Questions about listing `item`, oldest first, optionally only the answered ones.
+    pub fn for_item(&self, item: &str, answered_only: bool) -> Vec<Question> {
+        let questions = self.questions.lock().unwrap_or_else(|e| e.into_inner());
+        let mut found: Vec<Question> = questions
+            .iter()
+            .filter(|(_, stored)| stored.item == item && (!answered_only || stored.answer.is_some()))
+            .map(|(id, stored)| stored.public(id))
+            .collect();
+        found.sort_by_key(|question| question.asked_at);
+        found
+    }
+}
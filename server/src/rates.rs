@@ -0,0 +1,175 @@
+use crate::config::{RateSource, RatesConfig};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bazaar_protocol::price::normalize_currency;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Exchange rates relative to a base currency: `rates["EUR"]` is how many euros one unit of `base` buys.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateTable {
+    pub base: String,
+    pub rates: HashMap<String, f64>,
+    #[serde(skip, default = "SystemTime::now")]
+    pub fetched_at: SystemTime,
+}
+
+impl RateTable {
+    fn normalize(mut self) -> Result<Self> {
+        self.base = normalize_currency(&self.base).context("Rate table has an invalid base currency")?;
+        let mut rates = HashMap::new();
+        for (code, rate) in self.rates {
+            let Some(code) = normalize_currency(&code) else {
+                bail!("Rate table has an invalid currency code '{}'", code);
+            };
+            if !rate.is_finite() || rate <= 0.0 {
+                bail!("Rate for {} must be a positive number", code);
+            }
+            rates.insert(code, rate);
+        }
+        rates.insert(self.base.clone(), 1.0);
+        self.rates = rates;
+        Ok(self)
+    }
+
+    /// Units of `to` that one unit of `from` buys, if both currencies are known.
+    pub fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        Some(self.rates.get(to)? / self.rates.get(from)?)
+    }
+}
+
+/// A source of exchange rates.
+#[async_trait]
+pub trait RateProvider: Send + Sync {
+    async fn fetch(&self) -> Result<RateTable>;
+}
+
+/// Rates maintained by hand in a TOML file:
+///
+/// ```toml
+/// base = "USD"
+///
+/// [rates]
+/// EUR = 0.92
+/// GBP = 0.79
+/// ```
+pub struct FileRates {
+    path: PathBuf,
+}
+
+#[async_trait]
+impl RateProvider for FileRates {
+    async fn fetch(&self) -> Result<RateTable> {
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .with_context(|| format!("Failed to read rates file {}", self.path.display()))?;
+        let table: RateTable = toml::from_str(&contents)
+            .with_context(|| format!("Invalid rates file {}", self.path.display()))?;
+        table.normalize()
+    }
+}
+
+/// Rates fetched as JSON (`{"base": "USD", "rates": {"EUR": 0.92}}`) from an HTTP
+/// endpoint, optionally through the operator's own proxy so the lookup doesn't
+/// reveal the server's IP address.
+pub struct HttpRates {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl HttpRates {
+    fn new(url: String, proxy: Option<&str>) -> Result<Self> {
+        let mut builder = reqwest::Client::builder().timeout(Duration::from_secs(60));
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy).context("Invalid rates proxy")?);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            url,
+        })
+    }
+}
+
+#[async_trait]
+impl RateProvider for HttpRates {
+    async fn fetch(&self) -> Result<RateTable> {
+        let table: RateTable = self
+            .client
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid rates response")?;
+        table.normalize()
+    }
+}
+
+/// Caches the latest rate table and refreshes it in the background.
+pub struct RateService {
+    provider: Option<Box<dyn RateProvider>>,
+    table: RwLock<Option<Arc<RateTable>>>,
+    refresh: Duration,
+    stale_after: Duration,
+}
+
+impl RateService {
+    pub fn from_config(config: &RatesConfig) -> Result<Self> {
+        let provider: Option<Box<dyn RateProvider>> = match config.source {
+            RateSource::None => None,
+            RateSource::File => Some(Box::new(FileRates {
+                path: config.file.clone().context("rates.file is required for the file source")?,
+            })),
+            RateSource::Http => Some(Box::new(HttpRates::new(
+                config.url.clone().context("rates.url is required for the http source")?,
+                config.proxy.as_deref(),
+            )?)),
+        };
+
+        Ok(Self {
+            provider,
+            table: RwLock::new(None),
+            refresh: Duration::from_secs(config.refresh_secs),
+            stale_after: Duration::from_secs(config.stale_after_secs),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some()
+    }
+
+    /// The cached table, if one has been fetched.
+    pub fn table(&self) -> Option<Arc<RateTable>> {
+        self.table.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_stale(&self, table: &RateTable) -> bool {
+        table.fetched_at.elapsed().unwrap_or_default() > self.stale_after
+    }
+
+    /// How often [`RateService::refresh`] should run.
+    pub fn refresh_every(&self) -> Duration {
+        self.refresh
+    }
+
+    /// Fetches the latest rates. A failed fetch keeps the previous table, which
+    /// eventually gets flagged as stale.
+    pub async fn refresh(self: Arc<Self>) {
+        let Some(provider) = &self.provider else {
+            return;
+        };
+
+        match provider.fetch().await {
+            Ok(table) => {
+                info!(base = %table.base, currencies = table.rates.len(), "Exchange rates updated");
+                *self.table.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(table));
+            },
+            Err(e) => warn!("Failed to update exchange rates: {:#}", e),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::{SavedSearchInfo, SearchQuery};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Saved searches kept per user.
+pub const MAX_PER_USER: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedSearch {
+    query: String,
+    created_at: u64,
+    last_run_at: Option<u64>,
+}
+
+/// Searches users keep on the server, persisted in `<data_dir>/saved_searches.json`
+/// and keyed by the identity the user authenticated as.
+pub struct SavedSearchStore {
+    path: PathBuf,
+    searches: Mutex<BTreeMap<String, BTreeMap<String, SavedSearch>>>,
+}
+
+impl SavedSearchStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let searches = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read saved searches {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid saved searches {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            searches: Mutex::new(searches),
+        })
+    }
+
+    fn save(&self, searches: &BTreeMap<String, BTreeMap<String, SavedSearch>>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(searches)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace saved searches {}", self.path.display()))
+    }
+
+    /// Saves or replaces a search. Returns false if the user already has
+    /// [`MAX_PER_USER`] other searches.
+    pub fn add(&self, owner: &str, name: &str, query: &SearchQuery) -> Result<bool> {
+        let mut searches = self.searches.lock().unwrap_or_else(|e| e.into_inner());
+        let mine = searches.entry(owner.to_string()).or_default();
+        if mine.len() >= MAX_PER_USER && !mine.contains_key(name) {
+            return Ok(false);
+        }
+        mine.insert(
+            name.to_string(),
+            SavedSearch {
+                query: query.to_string(),
+                created_at: unix_now(),
+                last_run_at: None,
+            },
+        );
+        self.save(&searches)?;
+        Ok(true)
+    }
+
+    /// Looks up a search for running and marks it as run now. Returns the query
+    /// and when it was last run before this.
+    pub fn start_run(&self, owner: &str, name: &str) -> Result<Option<(String, Option<u64>)>> {
+        let mut searches = self.searches.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(search) = searches.get_mut(owner).and_then(|mine| mine.get_mut(name)) else {
+            return Ok(None);
+        };
+        let previous = search.last_run_at.replace(unix_now());
+        let query = search.query.clone();
+        self.save(&searches)?;
+        Ok(Some((query, previous)))
+    }
+
+    pub fn list(&self, owner: &str) -> Vec<SavedSearchInfo> {
+        let searches = self.searches.lock().unwrap_or_else(|e| e.into_inner());
+        searches
+            .get(owner)
+            .into_iter()
+            .flatten()
+            .map(|(name, search)| SavedSearchInfo {
+                name: name.clone(),
+                query: search.query.clone(),
+                created_at: search.created_at,
+                last_run_at: search.last_run_at,
+            })
+            .collect()
+    }
+
+    /// Deletes a search. Returns false if there is no such search.
+    pub fn remove(&self, owner: &str, name: &str) -> Result<bool> {
+        let mut searches = self.searches.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(mine) = searches.get_mut(owner) else {
+            return Ok(false);
+        };
+        if mine.remove(name).is_none() {
+            return Ok(false);
+        }
+        if mine.is_empty() {
+            searches.remove(owner);
+        }
+        self.save(&searches)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,120 @@
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tracing::{debug, info_span, warn, Instrument};
+
+/// When a periodic job runs.
+#[derive(Debug, Clone, Copy)]
+pub struct Every {
+    period: Duration,
+    jitter: Duration,
+    delay: Duration,
+}
+
+impl Every {
+    /// Every `period`, starting straight away.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            jitter: Duration::ZERO,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Waits up to `jitter` longer before each run, so the timing of outgoing
+    /// requests doesn't give the server away.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Skips the run at startup, first running one period in.
+    pub fn skip_first(mut self) -> Self {
+        self.delay = self.period;
+        self
+    }
+}
+
+/// Background jobs that run on timers for the life of the server.
+///
+/// Jobs are checked for shutdown between runs, never during one, so a job
+/// stopped at shutdown always finishes what it was doing.
+#[derive(Clone)]
+pub struct Scheduler {
+    shutdown: CancellationToken,
+    jobs: TaskTracker,
+}
+
+impl Scheduler {
+    pub fn new(shutdown: CancellationToken) -> Self {
+        Self {
+            shutdown,
+            jobs: TaskTracker::new(),
+        }
+    }
+
+    /// Runs `job` on the `every` schedule until shutdown. Runs never overlap; a run
+    /// that takes longer than the period delays the next one.
+    pub fn every<F, Fut>(&self, name: &'static str, every: Every, mut job: F)
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+        self.jobs.spawn(
+            async move {
+                let mut wait = every.delay;
+                loop {
+                    if stopped(&shutdown, wait + jittered(every.jitter)).await {
+                        break;
+                    }
+                    job().await;
+                    wait = every.period;
+                }
+                debug!("Job stopped");
+            }
+            .instrument(info_span!("job", name)),
+        );
+    }
+
+    /// Runs `job` once after `delay`, unless the server shuts down first.
+    pub fn once<Fut>(&self, name: &'static str, delay: Duration, job: Fut)
+    where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let shutdown = self.shutdown.clone();
+        self.jobs.spawn(
+            async move {
+                if !stopped(&shutdown, delay).await {
+                    job.await;
+                }
+            }
+            .instrument(info_span!("job", name)),
+        );
+    }
+
+    /// Waits up to `grace` for running jobs to finish after shutdown was signalled.
+    pub async fn wait(&self, grace: Duration) {
+        self.jobs.close();
+        if tokio::time::timeout(grace, self.jobs.wait()).await.is_err() {
+            warn!("{} background jobs still running after {:?}, stopping anyway", self.jobs.len(), grace);
+        }
+    }
+}
+
+/// Sleeps for `wait`, returning early with `true` if the server shuts down.
+async fn stopped(shutdown: &CancellationToken, wait: Duration) -> bool {
+    tokio::select! {
+        _ = shutdown.cancelled() => true,
+        _ = tokio::time::sleep(wait) => false,
+    }
+}
+
+fn jittered(jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return Duration::ZERO;
+    }
+    rand::thread_rng().gen_range(Duration::ZERO..jitter)
+}
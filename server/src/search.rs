@@ -0,0 +1,39 @@
+use bazaar_protocol::{Item, SearchQuery, SortKey};
+use std::cmp::Reverse;
+
+/// Runs a `SEARCH` query over `items`. `price_of` gives a listing's price in
+/// minor units of the currency the query's price range is written in, or `None`
+/// if the price can't be read; such listings never match a price range and
+/// sort last by price.
+pub fn search<'a>(
+    items: impl Iterator<Item = &'a Item>,
+    query: &SearchQuery,
+    price_of: impl Fn(&Item) -> Option<u64>,
+) -> Vec<Item> {
+    let terms: Vec<String> = query.terms.iter().map(|t| t.to_lowercase()).collect();
+
+    let mut results: Vec<(Option<u64>, &Item)> = items
+        .filter(|item| {
+            let text = format!("{}\n{}\n{}", item.name, item.description, item.category).to_lowercase();
+            terms.iter().all(|term| text.contains(term.as_str()))
+        })
+        .filter(|item| query.category.as_ref().is_none_or(|c| item.category.eq_ignore_ascii_case(c)))
+        .filter(|item| query.seller.as_ref().is_none_or(|s| item.seller.eq_ignore_ascii_case(s)))
+        .filter(|item| query.model.as_ref().is_none_or(|m| item.model.as_ref() == Some(m)))
+        .map(|item| (price_of(item), item))
+        .filter(|(price, _)| match &query.price {
+            Some(range) => price.is_some_and(|p| range.contains(p)),
+            None => true,
+        })
+        .collect();
+
+    match query.sort {
+        Some(SortKey::Price) => results.sort_by_key(|(price, _)| (price.is_none(), *price)),
+        Some(SortKey::PriceDesc) => results.sort_by_key(|(price, _)| (price.is_none(), Reverse(*price))),
+        Some(SortKey::Newest) => results.sort_by_key(|(_, item)| Reverse(item.created_at)),
+        Some(SortKey::Oldest) => results.sort_by_key(|(_, item)| item.created_at),
+        Some(SortKey::Name) => results.sort_by_cached_key(|(_, item)| item.name.to_lowercase()),
+        None => {},
+    }
+    results.into_iter().map(|(_, item)| item.clone()).collect()
+}
@@ -0,0 +1,203 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::command::registration_message;
+use bazaar_protocol::{signing, BazaarError, SellerInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Registration {
+    /// The name as registered, keeping its case.
+    name: String,
+    public_key: String,
+    registered_at: u64,
+    verified: bool,
+    #[serde(default)]
+    strikes: u32,
+    #[serde(default)]
+    banned: bool,
+    #[serde(default)]
+    banned_until: Option<u64>,
+}
+
+impl Registration {
+    fn is_banned(&self) -> bool {
+        self.banned && self.banned_until.is_none_or(|until| until > unix_now())
+    }
+}
+
+/// Seller names claimed with an ed25519 key, persisted in `<data_dir>/sellers.json`.
+/// Names are unique ignoring case, so `Alice` can't be shadowed by `alice`.
+pub struct SellerRegistry {
+    path: PathBuf,
+    sellers: Mutex<BTreeMap<String, Registration>>,
+}
+
+impl SellerRegistry {
+    pub fn open(path: &Path) -> Result<Self> {
+        let sellers = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read seller registry {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid seller registry {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sellers: Mutex::new(sellers),
+        })
+    }
+
+    fn save(&self, sellers: &BTreeMap<String, Registration>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(sellers)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace seller registry {}", self.path.display()))
+    }
+
+    /// Claims `name` for the key that signed [`registration_message`]. Registering
+    /// again with the same key is a no-op.
+    pub fn register(&self, name: &str, public_key: &str, signature: &str) -> Result<SellerInfo, BazaarError> {
+        signing::verify(public_key, &registration_message(name), signature)?;
+
+        let mut sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        let public_key = public_key.to_lowercase();
+        if let Some(existing) = sellers.get(&name.to_lowercase()) {
+            if existing.public_key != public_key || existing.name != name {
+                return Err(BazaarError::SellerNameTaken);
+            }
+            return Ok(info(existing));
+        }
+
+        let registration = Registration {
+            name: name.to_string(),
+            public_key,
+            registered_at: unix_now(),
+            verified: false,
+            strikes: 0,
+            banned: false,
+            banned_until: None,
+        };
+        sellers.insert(name.to_lowercase(), registration.clone());
+        self.save(&sellers).map_err(failed)?;
+        Ok(info(&registration))
+    }
+
+    /// Checks that [`register`](Self::register) would accept the claim, without
+    /// making it. Returns whether `name` is new, rather than already the key's.
+    pub fn check_claim(&self, name: &str, public_key: &str, signature: &str) -> Result<bool, BazaarError> {
+        signing::verify(public_key, &registration_message(name), signature)?;
+
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        match sellers.get(&name.to_lowercase()) {
+            Some(existing) if existing.public_key != public_key.to_lowercase() || existing.name != name => Err(BazaarError::SellerNameTaken),
+            Some(_) => Ok(false),
+            None => Ok(true),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<SellerInfo> {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers.get(&name.to_lowercase()).map(info)
+    }
+
+    /// The key `name` is registered to, if it is registered exactly as spelled.
+    pub fn public_key(&self, name: &str) -> Option<String> {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers
+            .get(&name.to_lowercase())
+            .filter(|seller| seller.name == name)
+            .map(|seller| seller.public_key.clone())
+    }
+
+    /// Whether `name` is registered exactly as spelled and an operator verified it.
+    pub fn is_verified(&self, name: &str) -> bool {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers.get(&name.to_lowercase()).is_some_and(|seller| seller.verified && seller.name == name)
+    }
+
+    /// Whether `name` differs only in case from a name someone else registered.
+    pub fn is_impersonation(&self, name: &str) -> bool {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers.get(&name.to_lowercase()).is_some_and(|seller| seller.name != name)
+    }
+
+    /// Sets or clears the verified badge. Returns the name as registered.
+    pub fn set_verified(&self, name: &str, verified: bool) -> Result<String, BazaarError> {
+        let mut sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        let seller = sellers.get_mut(&name.to_lowercase()).ok_or(BazaarError::SellerNotFound)?;
+        seller.verified = verified;
+        let name = seller.name.clone();
+        self.save(&sellers).map_err(failed)?;
+        Ok(name)
+    }
+
+    /// Whether `name` is under a ban that hasn't run out.
+    pub fn is_banned(&self, name: &str) -> bool {
+        let sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        sellers.get(&name.to_lowercase()).is_some_and(Registration::is_banned)
+    }
+
+    /// Bans `name` until `until`, or for good without one. Replaces any earlier ban.
+    pub fn ban(&self, name: &str, until: Option<u64>) -> Result<SellerInfo, BazaarError> {
+        self.update(name, |seller| {
+            seller.banned = true;
+            seller.banned_until = until;
+        })
+    }
+
+    pub fn unban(&self, name: &str) -> Result<SellerInfo, BazaarError> {
+        self.update(name, |seller| {
+            seller.banned = false;
+            seller.banned_until = None;
+        })
+    }
+
+    /// Adds a strike. The `threshold`th strike bans `name` for `ban_secs` and
+    /// starts the count over; a longer ban already in place is kept.
+    pub fn strike(&self, name: &str, threshold: u32, ban_secs: u64) -> Result<SellerInfo, BazaarError> {
+        self.update(name, |seller| {
+            seller.strikes += 1;
+            if seller.strikes < threshold {
+                return;
+            }
+            seller.strikes = 0;
+            let until = unix_now().saturating_add(ban_secs);
+            if !seller.is_banned() || seller.banned_until.is_some_and(|current| current < until) {
+                seller.banned = true;
+                seller.banned_until = Some(until);
+            }
+        })
+    }
+
+    fn update(&self, name: &str, change: impl FnOnce(&mut Registration)) -> Result<SellerInfo, BazaarError> {
+        let mut sellers = self.sellers.lock().unwrap_or_else(|e| e.into_inner());
+        let seller = sellers.get_mut(&name.to_lowercase()).ok_or(BazaarError::SellerNotFound)?;
+        change(seller);
+        let info = info(seller);
+        self.save(&sellers).map_err(failed)?;
+        Ok(info)
+    }
+}
+
+fn info(registration: &Registration) -> SellerInfo {
+    SellerInfo {
+        name: registration.name.clone(),
+        public_key: registration.public_key.clone(),
+        registered_at: registration.registered_at,
+        verified: registration.verified,
+        strikes: registration.strikes,
+        banned: registration.is_banned(),
+        banned_until: registration.banned_until.filter(|_| registration.is_banned()),
+        tier: None,
+    }
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Failed to update seller registry: {:#}", e);
+    BazaarError::Internal
+}
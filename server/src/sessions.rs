@@ -0,0 +1,62 @@
+use rand::RngCore;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Connection state kept under a random token, so a client whose mixnet
+/// connection drops can pick up where it left off with `HELLO --session`.
+/// Sessions live in memory only and end after `limits.session_idle_secs`
+/// without use.
+pub struct SessionStore<T> {
+    sessions: Mutex<HashMap<String, (T, Instant)>>,
+}
+
+impl<T> Default for SessionStore<T> {
+    fn default() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> SessionStore<T> {
+    /// Starts a session holding `state` and returns its token.
+    pub fn create(&self, state: T) -> String {
+        let mut token = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut token);
+        let token = hex::encode(token);
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        sessions.insert(token.clone(), (state, Instant::now()));
+        token
+    }
+
+    /// The state saved under `token`, unless the session idled out.
+    pub fn resume(&self, token: &str, idle: Duration) -> Option<T> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let (state, last_used) = sessions.get_mut(token)?;
+        if last_used.elapsed() > idle {
+            sessions.remove(token);
+            return None;
+        }
+        *last_used = Instant::now();
+        Some(state.clone())
+    }
+
+    /// Changes the state saved under `token` in place, so connections sharing
+    /// the session never overwrite each other's changes. Sessions that already
+    /// ended stay ended and yield `None`.
+    pub fn update<R>(&self, token: &str, change: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let (state, last_used) = sessions.get_mut(token)?;
+        *last_used = Instant::now();
+        Some(change(state))
+    }
+
+    /// Ends sessions unused for longer than `idle`, returning how many ended.
+    pub fn expire(&self, idle: Duration) -> usize {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let before = sessions.len();
+        sessions.retain(|_, (_, last_used)| last_used.elapsed() <= idle);
+        before - sessions.len()
+    }
+}
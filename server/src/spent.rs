@@ -0,0 +1,84 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Digests of single-use tokens that have been spent, such as passes and
+/// credentials. Kept in memory and appended to a file, one hex digest per
+/// line, so they stay spent across restarts and spending one costs the same
+/// however many came before.
+pub struct SpentLog {
+    file: Arc<Mutex<File>>,
+    spent: Mutex<HashSet<[u8; 32]>>,
+}
+
+impl SpentLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut spent = HashSet::new();
+        if path.exists() {
+            let file = File::open(path).with_context(|| format!("Failed to read spent log {}", path.display()))?;
+            for (n, line) in BufReader::new(file).lines().enumerate() {
+                let mut digest = [0; 32];
+                // Only a write that failed, and so spent nothing, leaves a broken line
+                if hex::decode_to_slice(line?.trim(), &mut digest).is_ok() {
+                    spent.insert(digest);
+                } else {
+                    warn!("Skipping malformed line {} of spent log {}", n + 1, path.display());
+                }
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open spent log {}", path.display()))?;
+
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            spent: Mutex::new(spent),
+        })
+    }
+
+    pub fn contains(&self, digest: &[u8; 32]) -> bool {
+        self.spent.lock().unwrap_or_else(|e| e.into_inner()).contains(digest)
+    }
+
+    /// Spends `digests`, all or none. Returns false if any was spent before or
+    /// is given twice.
+    pub async fn spend(&self, digests: &[[u8; 32]]) -> Result<bool> {
+        {
+            let mut spent = self.spent.lock().unwrap_or_else(|e| e.into_inner());
+            let new: HashSet<&[u8; 32]> = digests.iter().collect();
+            if new.len() != digests.len() || new.iter().any(|digest| spent.contains(*digest)) {
+                return Ok(false);
+            }
+            spent.extend(digests);
+        }
+
+        let file = self.file.clone();
+        let lines: String = digests.iter().map(|digest| format!("{}\n", hex::encode(digest))).collect();
+        let written = tokio::task::spawn_blocking(move || {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            file.write_all(lines.as_bytes())?;
+            file.sync_data()
+        })
+        .await;
+        match written {
+            Ok(Ok(())) => Ok(true),
+            failed => {
+                // Not on disk, so not spent: a restart would accept them again
+                let mut spent = self.spent.lock().unwrap_or_else(|e| e.into_inner());
+                for digest in digests {
+                    spent.remove(digest);
+                }
+                match failed {
+                    Ok(Err(e)) => Err(e).context("Failed to append to the spent log"),
+                    _ => bail!("Spent log writer panicked"),
+                }
+            },
+        }
+    }
+}
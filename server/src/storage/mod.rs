@@ -0,0 +1,133 @@
+//! Persistence for the catalog. The catalog stays in memory and every change is
+//! written through to the configured backend, one record per listing.
+
+#[cfg(feature = "storage-postgres")]
+mod postgres_store;
+#[cfg(feature = "storage-sled")]
+mod sled_store;
+
+use crate::config::{StorageBackend, StorageConfig};
+use anyhow::Result;
+use async_trait::async_trait;
+use bazaar_protocol::{HistoryEvent, Item};
+use serde::{Deserialize, Serialize};
+
+/// Everything stored about one listing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub item: Item,
+    /// Unix time the listing was moved to the trash, if it was.
+    pub deleted_at: Option<u64>,
+    pub history: Vec<HistoryEvent>,
+    /// Unix time an operator featured the listing, if they did.
+    #[serde(default)]
+    pub featured_at: Option<u64>,
+    /// Unix time a scheduled listing goes live; set only until it does.
+    #[serde(default)]
+    pub publish_at: Option<u64>,
+}
+
+/// A write refused because another server sharing the store changed the
+/// listing since this one last read it.
+#[derive(Debug)]
+pub struct Conflict;
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the listing was changed in storage by another server")
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Every stored listing, live or trashed.
+    async fn load(&self) -> Result<Vec<Record>>;
+    /// The stored record for one listing.
+    async fn get(&self, id: &str) -> Result<Option<Record>>;
+    /// Inserts or replaces the record for `record.item.id`. Stores shared by
+    /// several servers fail with [`Conflict`] if the stored record changed
+    /// since this server last loaded or wrote it.
+    async fn put(&self, record: &Record) -> Result<()>;
+    /// Deletes the record for `id`. Shared stores fail with [`Conflict`], as
+    /// [`put`](Self::put) does, if it changed since this server last saw it.
+    async fn remove(&self, id: &str) -> Result<()>;
+    /// Replaces all stored listings, e.g. after a catalog reload or mirror sync.
+    async fn replace_all(&self, records: &[Record]) -> Result<()>;
+    /// Whether the store was set up by an earlier start, so having no listings
+    /// means they were deleted rather than never added.
+    async fn is_initialized(&self) -> Result<bool>;
+    async fn mark_initialized(&self) -> Result<()>;
+    /// An alias for a new listing from the store, for stores shared by several
+    /// servers; `None` lets the catalog number listings itself.
+    async fn allocate_alias(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    /// Whether other servers write to the store too, so it must not be
+    /// replaced with one server's copy of the catalog.
+    fn is_shared(&self) -> bool {
+        false
+    }
+}
+
+/// Keeps nothing: the catalog lives only as long as the process.
+pub struct MemoryStorage;
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load(&self) -> Result<Vec<Record>> {
+        Ok(Vec::new())
+    }
+
+    async fn get(&self, _id: &str) -> Result<Option<Record>> {
+        Ok(None)
+    }
+
+    async fn put(&self, _record: &Record) -> Result<()> {
+        Ok(())
+    }
+
+    async fn remove(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn replace_all(&self, _records: &[Record]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn is_initialized(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn mark_initialized(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens the backend selected in `[storage]`.
+pub async fn open(config: &StorageConfig, data_dir: &std::path::Path) -> Result<Box<dyn Storage>> {
+    match config.backend {
+        StorageBackend::Memory => Ok(Box::new(MemoryStorage)),
+        #[cfg(feature = "storage-sled")]
+        StorageBackend::Sled => {
+            let path = config.path.clone().unwrap_or_else(|| data_dir.join("catalog.sled"));
+            Ok(Box::new(sled_store::SledStorage::open(&path)?))
+        },
+        #[cfg(not(feature = "storage-sled"))]
+        StorageBackend::Sled => {
+            let _ = data_dir;
+            anyhow::bail!("storage.backend = \"sled\" needs a server built with the storage-sled feature")
+        },
+        #[cfg(feature = "storage-postgres")]
+        StorageBackend::Postgres => {
+            use anyhow::Context;
+            let url = config.url.as_deref().context("storage.url is required for the postgres backend")?;
+            Ok(Box::new(postgres_store::PostgresStorage::connect(url, config.max_connections).await?))
+        },
+        #[cfg(not(feature = "storage-postgres"))]
+        StorageBackend::Postgres => {
+            anyhow::bail!("storage.backend = \"postgres\" needs a server built with the storage-postgres feature")
+        },
+    }
+}
@@ -0,0 +1,197 @@
+use super::{Conflict, Record, Storage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Moves the alias sequence past every alias in use, so listings numbered
+/// before it existed, or by a catalog import, aren't numbered again.
+const SYNC_ALIASES: &str = "SELECT setval('bazaar_aliases', GREATEST(nextval('bazaar_aliases'),
+     COALESCE(MAX((record->'item'->>'alias')::BIGINT), 0) + 1), false) FROM bazaar_items";
+
+/// Records stored as JSONB rows keyed by ULID. Several server processes can
+/// share one database; each reloads the catalog every `storage.refresh_secs`.
+///
+/// Every write gives the row a new version, and a server only writes a row
+/// at the version it last saw, so a change based on a stale copy fails with
+/// [`Conflict`] instead of overwriting another server's. Aliases come from
+/// the `bazaar_aliases` sequence so servers don't hand out the same one.
+///
+/// Only the catalog is shared. Tokens, sellers, questions, passes, drops, the
+/// audit and transparency logs and the rest stay in each server's `data_dir`.
+pub struct PostgresStorage {
+    pool: PgPool,
+    /// The version of each row as this server last read or wrote it.
+    versions: Mutex<HashMap<String, i64>>,
+}
+
+impl PostgresStorage {
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(url)
+            .await
+            .context("Failed to connect to PostgreSQL")?;
+
+        for statement in [
+            "CREATE SEQUENCE IF NOT EXISTS bazaar_item_versions",
+            "CREATE SEQUENCE IF NOT EXISTS bazaar_aliases",
+            "CREATE TABLE IF NOT EXISTS bazaar_items (id TEXT PRIMARY KEY, record JSONB NOT NULL)",
+            "ALTER TABLE bazaar_items ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT nextval('bazaar_item_versions')",
+            "CREATE TABLE IF NOT EXISTS bazaar_meta (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            SYNC_ALIASES,
+        ] {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .with_context(|| format!("Failed to set up the database: {}", statement))?;
+        }
+
+        Ok(Self { pool, versions: Mutex::new(HashMap::new()) })
+    }
+
+    fn versions(&self) -> std::sync::MutexGuard<'_, HashMap<String, i64>> {
+        self.versions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn load(&self) -> Result<Vec<Record>> {
+        let rows: Vec<(Json<Record>, i64)> = sqlx::query_as("SELECT record, version FROM bazaar_items")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut versions = self.versions();
+        versions.clear();
+        Ok(rows
+            .into_iter()
+            .map(|(Json(record), version)| {
+                versions.insert(record.item.id.clone(), version);
+                record
+            })
+            .collect())
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Record>> {
+        let row: Option<(Json<Record>, i64)> = sqlx::query_as("SELECT record, version FROM bazaar_items WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let mut versions = self.versions();
+        match row {
+            Some((Json(record), version)) => {
+                versions.insert(id.to_string(), version);
+                Ok(Some(record))
+            },
+            None => {
+                versions.remove(id);
+                Ok(None)
+            },
+        }
+    }
+
+    async fn put(&self, record: &Record) -> Result<()> {
+        let id = &record.item.id;
+        let seen = self.versions().get(id).copied();
+        let written: Option<(i64,)> = match seen {
+            // New to this server, so it must be new to the store too
+            None => {
+                sqlx::query_as(
+                    "INSERT INTO bazaar_items (id, record) VALUES ($1, $2)
+                     ON CONFLICT (id) DO NOTHING RETURNING version",
+                )
+                .bind(id)
+                .bind(Json(record))
+                .fetch_optional(&self.pool)
+                .await?
+            },
+            Some(version) => {
+                sqlx::query_as(
+                    "UPDATE bazaar_items SET record = $2, version = nextval('bazaar_item_versions')
+                     WHERE id = $1 AND version = $3 RETURNING version",
+                )
+                .bind(id)
+                .bind(Json(record))
+                .bind(version)
+                .fetch_optional(&self.pool)
+                .await?
+            },
+        };
+        let Some((version,)) = written else {
+            return Err(Conflict.into());
+        };
+        self.versions().insert(id.clone(), version);
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        let seen = self.versions().get(id).copied();
+        let removed = match seen {
+            Some(version) => {
+                sqlx::query("DELETE FROM bazaar_items WHERE id = $1 AND version = $2")
+                    .bind(id)
+                    .bind(version)
+                    .execute(&self.pool)
+                    .await?
+                    .rows_affected()
+                    > 0
+            },
+            None => false,
+        };
+        if !removed {
+            // Gone already, e.g. purged by another server, is what was asked for
+            let stored: Option<(i64,)> = sqlx::query_as("SELECT version FROM bazaar_items WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+            if stored.is_some() {
+                return Err(Conflict.into());
+            }
+        }
+        self.versions().remove(id);
+        Ok(())
+    }
+
+    async fn replace_all(&self, records: &[Record]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM bazaar_items").execute(&mut *tx).await?;
+        let mut versions = HashMap::new();
+        for record in records {
+            let (version,): (i64,) = sqlx::query_as("INSERT INTO bazaar_items (id, record) VALUES ($1, $2) RETURNING version")
+                .bind(&record.item.id)
+                .bind(Json(record))
+                .fetch_one(&mut *tx)
+                .await?;
+            versions.insert(record.item.id.clone(), version);
+        }
+        sqlx::query(SYNC_ALIASES).execute(&mut *tx).await?;
+        tx.commit().await?;
+        *self.versions() = versions;
+        Ok(())
+    }
+
+    async fn is_initialized(&self) -> Result<bool> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT value FROM bazaar_meta WHERE key = 'initialized'")
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    async fn mark_initialized(&self) -> Result<()> {
+        sqlx::query("INSERT INTO bazaar_meta (key, value) VALUES ('initialized', 'true') ON CONFLICT (key) DO NOTHING")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn allocate_alias(&self) -> Result<Option<u64>> {
+        let (alias,): (i64,) = sqlx::query_as("SELECT nextval('bazaar_aliases')").fetch_one(&self.pool).await?;
+        Ok(Some(alias as u64))
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,76 @@
+use super::{Record, Storage};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::path::Path;
+
+/// Key in the `meta` tree set once the store has been seeded.
+const INITIALIZED: &[u8] = b"initialized";
+
+/// Records stored as JSON values keyed by ULID in an embedded sled database.
+/// Pure Rust, so the server still builds as a single static binary.
+pub struct SledStorage {
+    db: sled::Db,
+    /// Facts about the store itself, kept apart from the listings.
+    meta: sled::Tree,
+}
+
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let db = sled::open(path).with_context(|| format!("Failed to open sled database {}", path.display()))?;
+        let meta = db.open_tree("meta")?;
+        Ok(Self { db, meta })
+    }
+}
+
+#[async_trait]
+impl Storage for SledStorage {
+    async fn load(&self) -> Result<Vec<Record>> {
+        self.db
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    async fn get(&self, id: &str) -> Result<Option<Record>> {
+        match self.db.get(id.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, record: &Record) -> Result<()> {
+        self.db.insert(record.item.id.as_bytes(), serde_json::to_vec(record)?)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        self.db.remove(id.as_bytes())?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn replace_all(&self, records: &[Record]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for key in self.db.iter().keys() {
+            batch.remove(key?);
+        }
+        for record in records {
+            batch.insert(record.item.id.as_bytes(), serde_json::to_vec(record)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn is_initialized(&self) -> Result<bool> {
+        Ok(self.meta.contains_key(INITIALIZED)?)
+    }
+
+    async fn mark_initialized(&self) -> Result<()> {
+        self.meta.insert(INITIALIZED, &[])?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use bazaar_protocol::media::ImageSize;
+
+/// Largest width or height of an image that is scaled down. Bigger ones are
+/// served at full size only, so a crafted upload can't make decoding eat memory.
+#[cfg(feature = "thumbnails")]
+const MAX_SOURCE_SIDE: u32 = 8192;
+
+/// Scales an uploaded image down to every [`ImageSize::SCALED`] size, as JPEG.
+/// Returns nothing for data that isn't an image in a supported format, or for
+/// servers built without the thumbnails feature.
+pub fn generate(data: &[u8]) -> Vec<(ImageSize, Vec<u8>)> {
+    #[cfg(feature = "thumbnails")]
+    {
+        match scale(data) {
+            Ok(scaled) => scaled,
+            Err(e) => {
+                tracing::debug!("Not making thumbnails: {}", e);
+                Vec::new()
+            },
+        }
+    }
+    #[cfg(not(feature = "thumbnails"))]
+    {
+        let _ = data;
+        Vec::new()
+    }
+}
+
+#[cfg(feature = "thumbnails")]
+fn scale(data: &[u8]) -> image::ImageResult<Vec<(ImageSize, Vec<u8>)>> {
+    use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+    use std::io::Cursor;
+
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_SIDE);
+    limits.max_image_height = Some(MAX_SOURCE_SIDE);
+    reader.limits(limits);
+    let image = reader.decode()?;
+
+    let mut scaled = Vec::new();
+    for &size in ImageSize::SCALED {
+        let side = size.max_side().unwrap_or(u32::MAX);
+        // JPEG has no alpha channel
+        let thumbnail = DynamicImage::ImageRgb8(image.thumbnail(side, side).to_rgb8());
+        let mut jpeg = Cursor::new(Vec::new());
+        thumbnail.write_to(&mut jpeg, ImageFormat::Jpeg)?;
+        scaled.push((size, jpeg.into_inner()));
+    }
+    Ok(scaled)
+}
@@ -0,0 +1,154 @@
+use crate::catalog::unix_now;
+use crate::rates::RateTable;
+use anyhow::{bail, Result};
+use bazaar_protocol::{Price, SellerInfo};
+use serde::Deserialize;
+
+/// Caps on what sellers may list that rise with their standing, so a new
+/// seller who disappears with buyers' money can't take much with them.
+///
+/// A seller is in the last tier whose requirements they meet. Standing is
+/// read from the seller registry; names nobody registered only ever meet the
+/// first tier, which therefore can't have requirements.
+///
+/// ```toml
+/// [seller_tiers]
+/// enabled = true
+///
+/// [[seller_tiers.tiers]]
+/// name = "new"
+/// max_active_listings = 10
+/// max_price = "500 USD"
+///
+/// [[seller_tiers.tiers]]
+/// name = "established"
+/// min_age_days = 30
+/// max_strikes = 0
+/// max_active_listings = 100
+/// max_price = "5000 USD"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TiersConfig {
+    pub enabled: bool,
+    /// Lowest tier first.
+    pub tiers: Vec<SellerTier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SellerTier {
+    pub name: String,
+    /// Days since the seller name was registered.
+    #[serde(default)]
+    pub min_age_days: u64,
+    /// Whether an operator has to have verified the seller.
+    #[serde(default)]
+    pub verified: bool,
+    /// Most strikes the seller may have on record.
+    #[serde(default)]
+    pub max_strikes: Option<u32>,
+    /// Live and scheduled listings the seller may have at once; unlimited without one.
+    #[serde(default)]
+    pub max_active_listings: Option<usize>,
+    /// Highest price a listing may have, e.g. `500 USD`. Listings in other
+    /// currencies are compared at the current rates; prices that can't be
+    /// compared are refused.
+    #[serde(default)]
+    pub max_price: Option<String>,
+}
+
+impl Default for TiersConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tiers: vec![
+                SellerTier {
+                    name: "new".to_string(),
+                    min_age_days: 0,
+                    verified: false,
+                    max_strikes: None,
+                    max_active_listings: Some(10),
+                    max_price: Some("500 USD".to_string()),
+                },
+                SellerTier {
+                    name: "established".to_string(),
+                    min_age_days: 30,
+                    verified: false,
+                    max_strikes: Some(0),
+                    max_active_listings: Some(100),
+                    max_price: Some("5000 USD".to_string()),
+                },
+                SellerTier {
+                    name: "trusted".to_string(),
+                    min_age_days: 180,
+                    verified: true,
+                    max_strikes: Some(0),
+                    max_active_listings: None,
+                    max_price: None,
+                },
+            ],
+        }
+    }
+}
+
+impl SellerTier {
+    fn has_requirements(&self) -> bool {
+        self.min_age_days > 0 || self.verified || self.max_strikes.is_some()
+    }
+
+    fn admits(&self, seller: Option<&SellerInfo>) -> bool {
+        let Some(seller) = seller else {
+            return !self.has_requirements();
+        };
+        let age_days = unix_now().saturating_sub(seller.registered_at) / 86_400;
+        age_days >= self.min_age_days
+            && (!self.verified || seller.verified)
+            && self.max_strikes.is_none_or(|max| seller.strikes <= max)
+    }
+
+    /// Whether `price` is at most this tier's `max_price`, converting with
+    /// `rates` when the currencies differ.
+    pub fn allows_price(&self, price: &str, rates: Option<&RateTable>) -> bool {
+        let Some(max) = self.max_price.as_deref().and_then(|max| max.parse::<Price>().ok()) else {
+            return true;
+        };
+        let Ok(price) = price.parse::<Price>() else {
+            return false;
+        };
+        if price.currency == max.currency {
+            return price.minor <= max.minor;
+        }
+        rates
+            .and_then(|table| table.rate(&price.currency, &max.currency))
+            .is_some_and(|rate| price.convert(&max.currency, rate).minor <= max.minor)
+    }
+}
+
+impl TiersConfig {
+    /// The tier of `seller`, given their registration if they have one.
+    pub fn tier_for(&self, seller: Option<&SellerInfo>) -> Option<&SellerTier> {
+        self.tiers.iter().rev().find(|tier| tier.admits(seller))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        match self.tiers.first() {
+            None => bail!("seller_tiers.tiers must list at least one tier"),
+            Some(first) if first.has_requirements() => {
+                bail!("the first of seller_tiers.tiers can't have requirements; unregistered sellers are in it")
+            },
+            Some(_) => {},
+        }
+        for tier in &self.tiers {
+            if let Some(max_price) = &tier.max_price {
+                if max_price.parse::<Price>().is_err() {
+                    bail!("seller tier '{}' has an invalid max_price '{}'", tier.name, max_price);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use bazaar_protocol::merkle::leaf_hash;
+use bazaar_protocol::tlog::{TlogActor, TlogEntry, TlogEvent, GENESIS, MAX_RANGE};
+use bazaar_protocol::Item;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+struct State {
+    file: File,
+    entries: Vec<TlogEntry>,
+}
+
+/// Public, hash-chained log of listing lifecycle events, stored as JSON lines
+/// in `<data_dir>/tlog.log` and served by `TLOG RANGE`. Unlike the audit log it
+/// is kept whole in memory, since any range can be asked for.
+pub struct TransparencyLog {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl TransparencyLog {
+    /// Opens the log, checking the chain of any existing entries. A broken chain
+    /// means the file was edited and is refused rather than extended.
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut entries: Vec<TlogEntry> = Vec::new();
+
+        if path.exists() {
+            let file = File::open(path)
+                .with_context(|| format!("Failed to read transparency log {}", path.display()))?;
+            for (n, line) in BufReader::new(file).lines().enumerate() {
+                let entry: TlogEntry = serde_json::from_str(&line?)
+                    .with_context(|| format!("Transparency log {} line {} is malformed", path.display(), n + 1))?;
+                let prev = entries.last().map_or(GENESIS, |last| &last.hash);
+                if entry.seq != n as u64 + 1 || entry.prev != prev || entry.digest() != entry.hash {
+                    bail!("Transparency log {} fails verification at line {}", path.display(), n + 1);
+                }
+                entries.push(entry);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open transparency log {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(State { file, entries }),
+        })
+    }
+
+    /// Appends an event for listing `id`. `item` is the listing after the event,
+    /// for events that leave it live. Failures are logged rather than returned,
+    /// since the change being recorded has already happened.
+    pub fn record(&self, event: TlogEvent, id: &str, actor: TlogActor, item: Option<&Item>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entry = TlogEntry {
+            seq: state.entries.len() as u64 + 1,
+            at: crate::catalog::unix_now(),
+            event,
+            id: id.to_string(),
+            actor,
+            leaf: item.map(|item| hex::encode(leaf_hash(item))).unwrap_or_default(),
+            prev: state.entries.last().map_or(GENESIS.to_string(), |last| last.hash.clone()),
+            hash: String::new(),
+        };
+        entry.hash = entry.digest();
+
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("Failed to encode transparency log entry: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = writeln!(state.file, "{}", line).and_then(|_| state.file.flush()) {
+            tracing::error!(path = %self.path.display(), "Failed to write transparency log: {}", e);
+            return;
+        }
+        state.entries.push(entry);
+    }
+
+    /// Entries `from` through `to` inclusive, at most [`MAX_RANGE`] of them.
+    pub fn range(&self, from: u64, to: u64) -> Vec<TlogEntry> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // Entry `seq` sits at index `seq - 1`
+        let start = from.max(1) - 1;
+        let end = to.min(start.saturating_add(MAX_RANGE)).min(state.entries.len() as u64);
+        if start >= end {
+            return Vec::new();
+        }
+        state.entries[start as usize..end as usize].to_vec()
+    }
+
+    /// The number of entries and the hash of the last one.
+    pub fn head(&self) -> (u64, String) {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let hash = state.entries.last().map_or(GENESIS.to_string(), |last| last.hash.clone());
+        (state.entries.len() as u64, hash)
+    }
+}
@@ -0,0 +1,179 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::{TokenInfo, TokenScope};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Prefix that makes tokens easy to spot in scripts and secret scanners.
+const TOKEN_PREFIX: &str = "bzt";
+
+/// A stored token. Only a hash of the secret is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TokenRecord {
+    seller: String,
+    scopes: Vec<TokenScope>,
+    created_at: u64,
+    expires_at: Option<u64>,
+    revoked: bool,
+    secret_hash: String,
+}
+
+impl TokenRecord {
+    fn info(&self, id: &str) -> TokenInfo {
+        TokenInfo {
+            id: id.to_string(),
+            seller: self.seller.clone(),
+            scopes: self.scopes.clone(),
+            created_at: self.created_at,
+            expires_at: self.expires_at,
+            revoked: self.revoked,
+        }
+    }
+
+    /// The session the token grants, unless it was revoked or has expired.
+    fn session(&self, id: &str) -> Option<SellerSession> {
+        if self.revoked || self.expires_at.is_some_and(|at| at <= unix_now()) {
+            return None;
+        }
+        Some(SellerSession {
+            token_id: id.to_string(),
+            seller: self.seller.clone(),
+            scopes: self.scopes.clone(),
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+/// A token that passed verification.
+#[derive(Debug, Clone)]
+pub struct SellerSession {
+    pub token_id: String,
+    pub seller: String,
+    pub scopes: Vec<TokenScope>,
+    pub expires_at: Option<u64>,
+}
+
+/// Seller API tokens (`bzt_<id>_<secret>`), persisted in `<data_dir>/tokens.json`.
+/// Tokens let scripts act for one seller within their scopes without an admin key.
+/// Admins mint them for any seller, and a seller authenticated with one mints
+/// more for themselves, with no more scopes and no longer life than it has.
+pub struct TokenStore {
+    path: PathBuf,
+    tokens: Mutex<BTreeMap<String, TokenRecord>>,
+}
+
+impl TokenStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let tokens = if path.exists() {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read token store {}", path.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid token store {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    fn save(&self, tokens: &BTreeMap<String, TokenRecord>) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(tokens)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("Failed to replace token store {}", self.path.display()))
+    }
+
+    /// Mints a token, returning its details and the full secret token string.
+    pub fn create(&self, seller: &str, scopes: Vec<TokenScope>, expires_secs: Option<u64>) -> Result<(TokenInfo, String)> {
+        let mut id = [0u8; 4];
+        let mut secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut id);
+        rand::rngs::OsRng.fill_bytes(&mut secret);
+        let id = hex::encode(id);
+        let secret = hex::encode(secret);
+
+        let now = unix_now();
+        let record = TokenRecord {
+            seller: seller.to_string(),
+            scopes,
+            created_at: now,
+            expires_at: expires_secs.map(|secs| now.saturating_add(secs)),
+            revoked: false,
+            secret_hash: hash_secret(&secret),
+        };
+        let info = record.info(&id);
+
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.insert(id.clone(), record);
+        self.save(&tokens)?;
+        Ok((info, format!("{}_{}_{}", TOKEN_PREFIX, id, secret)))
+    }
+
+    /// Marks a token as revoked. Returns false if there is no such token.
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(record) = tokens.get_mut(id) else {
+            return Ok(false);
+        };
+        record.revoked = true;
+        self.save(&tokens)?;
+        Ok(true)
+    }
+
+    /// Revokes every live token of `seller`, returning how many there were.
+    pub fn revoke_seller(&self, seller: &str) -> Result<usize> {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let mut revoked = 0;
+        for record in tokens.values_mut().filter(|record| record.seller == seller && !record.revoked) {
+            record.revoked = true;
+            revoked += 1;
+        }
+        if revoked > 0 {
+            self.save(&tokens)?;
+        }
+        Ok(revoked)
+    }
+
+    /// The seller a token was minted for.
+    pub fn seller_of(&self, id: &str) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.get(id).map(|record| record.seller.clone())
+    }
+
+    pub fn list(&self) -> Vec<TokenInfo> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.iter().map(|(id, record)| record.info(id)).collect()
+    }
+
+    /// Checks a presented token string. Anything that doesn't look like a token,
+    /// is unknown, revoked or expired yields `None`.
+    pub fn verify(&self, token: &str) -> Option<SellerSession> {
+        let rest = token.strip_prefix(TOKEN_PREFIX)?.strip_prefix('_')?;
+        let (id, secret) = rest.split_once('_')?;
+
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        let record = tokens.get(id)?;
+        if !crate::config::constant_time_eq(hash_secret(secret).as_bytes(), record.secret_hash.as_bytes()) {
+            return None;
+        }
+        record.session(id)
+    }
+
+    /// The session of a token verified earlier, by id, as long as it is still
+    /// neither revoked nor expired.
+    pub fn session(&self, id: &str) -> Option<SellerSession> {
+        let tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.get(id)?.session(id)
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
@@ -0,0 +1,187 @@
+use crate::catalog::unix_now;
+use anyhow::{Context, Result};
+use bazaar_protocol::upload::{self, UploadStatus};
+use bazaar_protocol::{media, BazaarError};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Upload {
+    size: u64,
+    hash: String,
+    received: BTreeSet<usize>,
+    begun_at: u64,
+}
+
+impl Upload {
+    fn status(&self, id: &str) -> UploadStatus {
+        let chunks = upload::chunk_count(self.size);
+        UploadStatus {
+            id: id.to_string(),
+            size: self.size,
+            hash: self.hash.clone(),
+            chunks,
+            missing: (0..chunks).filter(|chunk| !self.received.contains(chunk)).collect(),
+        }
+    }
+}
+
+/// Uploads in progress (see `bazaar_protocol::upload`), kept under
+/// `<data_dir>/uploads` so they survive disconnects and restarts. Each upload's
+/// data is written straight into `<id>.part` at its chunks' offsets.
+pub struct UploadStore {
+    dir: PathBuf,
+    uploads: Mutex<BTreeMap<String, Upload>>,
+}
+
+fn failed(e: anyhow::Error) -> BazaarError {
+    tracing::error!("Upload failed: {:#}", e);
+    BazaarError::Internal
+}
+
+impl UploadStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create upload directory {}", dir.display()))?;
+        let index = dir.join("index.json");
+        let uploads = if index.exists() {
+            let contents = std::fs::read_to_string(&index)
+                .with_context(|| format!("Failed to read upload index {}", index.display()))?;
+            serde_json::from_str(&contents).with_context(|| format!("Invalid upload index {}", index.display()))?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            uploads: Mutex::new(uploads),
+        })
+    }
+
+    fn save(&self, uploads: &BTreeMap<String, Upload>) -> Result<()> {
+        let index = self.dir.join("index.json");
+        let tmp = index.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(uploads)?)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &index).with_context(|| format!("Failed to replace upload index {}", index.display()))
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.part", id))
+    }
+
+    /// Starts an upload of `size` bytes hashing to `hash`.
+    pub fn begin(&self, size: u64, hash: &str, max_bytes: u64, max_open: usize) -> Result<UploadStatus, BazaarError> {
+        if size == 0 || size > max_bytes {
+            return Err(BazaarError::InvalidCommand);
+        }
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        if uploads.len() >= max_open {
+            return Err(BazaarError::ServerBusy);
+        }
+
+        let mut id = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut id);
+        let id = hex::encode(id);
+        let path = self.path(&id);
+        std::fs::File::create(&path)
+            .and_then(|file| file.set_len(size))
+            .with_context(|| format!("Failed to create {}", path.display()))
+            .map_err(failed)?;
+        let upload = Upload {
+            size,
+            hash: hash.to_string(),
+            received: BTreeSet::new(),
+            begun_at: unix_now(),
+        };
+        let status = upload.status(&id);
+        uploads.insert(id, upload);
+        self.save(&uploads).map_err(failed)?;
+        Ok(status)
+    }
+
+    /// Stores chunk `chunk` of an upload. Sending a chunk again overwrites it.
+    /// When the last missing chunk arrives the whole upload is checked against
+    /// its hash; if it doesn't match, every chunk has to be sent again.
+    pub fn chunk(&self, id: &str, chunk: usize, data: &[u8]) -> Result<UploadStatus, BazaarError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let upload = uploads.get_mut(id).ok_or(BazaarError::UploadNotFound)?;
+        if upload::chunk_len(upload.size, chunk) != Some(data.len()) {
+            return Err(BazaarError::InvalidCommand);
+        }
+
+        let path = self.path(id);
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start((chunk * upload::CHUNK_SIZE) as u64))?;
+                file.write_all(data)
+            })
+            .with_context(|| format!("Failed to write {}", path.display()))
+            .map_err(failed)?;
+        upload.received.insert(chunk);
+
+        let status = upload.status(id);
+        let mut result = Ok(status);
+        if upload.received.len() == upload::chunk_count(upload.size) {
+            let whole = std::fs::read(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+                .map_err(failed)?;
+            if !media::verify(&upload.hash, &whole) {
+                upload.received.clear();
+                result = Err(BazaarError::UploadCorrupt);
+            }
+        }
+        self.save(&uploads).map_err(failed)?;
+        result
+    }
+
+    pub fn status(&self, id: &str) -> Result<UploadStatus, BazaarError> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.get(id).map(|upload| upload.status(id)).ok_or(BazaarError::UploadNotFound)
+    }
+
+    /// The data of a complete upload.
+    pub fn read(&self, id: &str) -> Result<Vec<u8>, BazaarError> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let upload = uploads.get(id).ok_or(BazaarError::UploadNotFound)?;
+        if !upload.status(id).is_complete() {
+            return Err(BazaarError::UploadIncomplete);
+        }
+        let path = self.path(id);
+        std::fs::read(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .map_err(failed)
+    }
+
+    /// Deletes an upload, finished or not.
+    pub fn remove(&self, id: &str) -> Result<(), BazaarError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.remove(id).ok_or(BazaarError::UploadNotFound)?;
+        let _ = std::fs::remove_file(self.path(id));
+        self.save(&uploads).map_err(failed)
+    }
+
+    /// Deletes uploads begun more than `ttl_secs` ago, returning how many.
+    pub fn expire(&self, ttl_secs: u64) -> Result<usize> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let cutoff = unix_now().saturating_sub(ttl_secs);
+        let expired: Vec<String> = uploads
+            .iter()
+            .filter(|(_, upload)| upload.begun_at < cutoff)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            uploads.remove(id);
+            let _ = std::fs::remove_file(self.path(id));
+        }
+        if !expired.is_empty() {
+            self.save(&uploads)?;
+        }
+        Ok(expired.len())
+    }
+}
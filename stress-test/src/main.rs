@@ -1,6 +1,7 @@
+use bazaar_protocol::codec::{self, FrameDecoder};
+use bazaar_protocol::Command;
 use nym_sdk::tcp_proxy;
 use nym_sdk::mixnet::Recipient;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
 use tokio::time::{Duration, Instant};
 use std::env;
@@ -71,11 +72,11 @@ async fn main() -> Result<(), anyhow::Error> {
                 match TcpStream::connect("127.0.0.1:9050").await {
                     Ok(mut stream) => {
                         // Send a simple HEAD request for maximum throughput
-                        if stream.write_all(b"HEAD").await.is_ok() {
-                            let mut buffer = [0u8; 1024];
-                            match stream.read(&mut buffer).await {
-                                Ok(n) if n > 0 => {
-                                    let response = String::from_utf8_lossy(&buffer[..n]);
+                        if codec::write_request(&mut stream, &Command::Head).await.is_ok() {
+                            let mut decoder = FrameDecoder::new();
+                            match codec::read_frame(&mut stream, &mut decoder).await {
+                                Ok(Some(frame)) => {
+                                    let response = String::from_utf8_lossy(&frame);
                                     if response.trim() == "OK" {
                                         stats.requests_succeeded.fetch_add(1, Ordering::SeqCst);
                                         let elapsed = request_start.elapsed().as_nanos() as usize;